@@ -11,10 +11,17 @@ cat <<EOF | curl http://localhost:7700/indexes/notes/settings -XPOST -d@-
   "filterableAttributes": [
     "authors",
     "date",
+    "created",
+    "modified",
+    "imported_at",
+    "permalink",
     "tags"
   ],
   "sortableAttributes": [
     "date",
+    "created",
+    "modified",
+    "imported_at",
     "weight",
     "writes",
     "views"
@@ -0,0 +1,398 @@
+use crate::date::DateRange;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use unicode_width::UnicodeWidthStr; // Provides `width()` method on String
+
+// Provides the generated 'parse()' method on Filter struct
+use pest::error::{ErrorVariant, LineColLocation};
+use pest::{iterators as pest_iterators, Parser};
+// Provides the Parser deriver, grammer autogeneration, and Rules
+use pest_derive::Parser;
+
+#[derive(Parser)]
+#[grammar = "filter.pest"]
+pub struct Filter;
+
+/// Translate an expression in the filter syntax of the filter input, e.g. `vim | !bash | >1w`,
+/// into a Meilisearch filter. Nothing is returned for input that doesn't parse, names a date
+/// that doesn't exist or a duration reaching out of range, or filters nothing, so a half-typed
+/// filter searches everything.
+pub fn translate(input: &str) -> Option<String> {
+    parse(input).ok().flatten()
+}
+
+/// Like `translate`, saying what's wrong with input that isn't a filter. The syntax:
+///
+/// - `vim` and `!bash` match notes with and without a tag
+/// - `2021-05`, `>2w` and `created:<2021` match dates, by default the `date` field, otherwise
+///   the one prefixed: `created:`, `modified:` or `imported_at:`, `imported:` for short
+/// - `field = value` compares any filterable field with `=`, `!=`, `>`, `>=`, `<` or `<=`;
+///   `tag` and `author` stand for `tags` and `authors`, and dates and durations are understood
+///   for the date fields, e.g. `date > 2023-01-01`
+/// - A date is the whole of its day, month or year: `>` is after all of it and `<` before all
+///   of it, while `>=` and `<=` take it in. `>2023` is short for `date > 2023`, and `2023`
+///   for `date = 2023`, all of it from its first second to its last.
+/// - `|` or `OR`, `+` or `AND` combine terms, AND binding tighter, and terms side by side are
+///   ANDed
+/// - `NOT` negates the term or parenthesized group after it
+pub fn parse(input: &str) -> Result<Option<String>, Report> {
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    let expr = Filter::parse(Rule::expression, input)
+        .map_err(syntax_error)?
+        .next()
+        .unwrap();
+    let filter = terms(expr.into_inner())?;
+    Ok(if filter.width() > 0 {
+        Some(filter)
+    } else {
+        None
+    })
+}
+
+/// Meilisearch filter for a sequence of terms, operators and groups
+fn terms(tokens: pest_iterators::Pairs<'_, Rule>) -> Result<String, Report> {
+    // Meilisearch filter string being built
+    let mut filter = String::new();
+    // Operator joining the next term to the ones before, AND unless one is given
+    let mut operator = " AND ";
+    let mut negate = false;
+    let mut curr_comparator: Option<Rule> = None;
+    // Which timestamp the next date or duration applies to
+    let mut curr_field = "date";
+    for token in tokens {
+        let term = match token.as_rule() {
+            Rule::not => {
+                negate = !negate;
+                continue;
+            }
+            Rule::comparator => {
+                curr_comparator = Some(token.into_inner().next().unwrap().as_rule());
+                continue;
+            }
+            Rule::date_field => {
+                curr_field = date_field(token.as_str());
+                continue;
+            }
+            Rule::operator => {
+                operator = match token.into_inner().next().unwrap().as_rule() {
+                    Rule::or => " OR ",
+                    _ => " AND ",
+                };
+                continue;
+            }
+            Rule::EOI | Rule::close => break,
+            Rule::date => {
+                let term = match token.into_inner().next() {
+                    Some(r) => {
+                        let range = DateRange::try_from(r)?;
+                        // The same as `field > date` and `field < date`, after or before all
+                        // of the day, month or year
+                        match curr_comparator {
+                            Some(Rule::gt) => {
+                                format!("{} > {}", curr_field, range.end.timestamp())
+                            }
+                            Some(_) => format!("{} < {}", curr_field, range.start.timestamp()),
+                            // The same as `field = date`, all of it
+                            None => format!(
+                                "({} >= {} AND {} <= {})",
+                                curr_field,
+                                range.start.timestamp(),
+                                curr_field,
+                                range.end.timestamp()
+                            ),
+                        }
+                    }
+                    None => format!("{} none", curr_field),
+                };
+                curr_comparator = None;
+                curr_field = "date";
+                term
+            }
+            Rule::duration => {
+                let ts = duration_timestamp(token)?;
+                let term = match curr_comparator {
+                    Some(Rule::lt) => format!("{} < {}", curr_field, ts),
+                    _ => format!("{} > {}", curr_field, ts),
+                };
+                curr_comparator = None;
+                curr_field = "date";
+                term
+            }
+            Rule::field_comparison => comparison(token)?,
+            Rule::tag => format!("tags = {}", token.as_str()),
+            Rule::not_tag => format!("tags != {}", token.into_inner().as_str()),
+            Rule::group => {
+                let group = terms(token.into_inner())?;
+                if group.is_empty() {
+                    continue;
+                }
+                format!("({})", group)
+            }
+            _ => unreachable!(),
+        };
+        if !filter.is_empty() {
+            filter.push_str(operator);
+        }
+        if negate && term.starts_with('(') {
+            filter.push_str(&format!("NOT {}", term));
+        } else if negate {
+            filter.push_str(&format!("NOT ({})", term));
+        } else {
+            filter.push_str(&term);
+        }
+        operator = " AND ";
+        negate = false;
+    }
+    Ok(filter)
+}
+
+/// Fields holding timestamps, which dates and durations are compared against
+const DATE_FIELDS: [&str; 6] = [
+    "date",
+    "created",
+    "modified",
+    "imported_at",
+    "remind_at",
+    "review_after",
+];
+
+/// Field a date prefix like `created:` applies to, one of the names `date_field` has in the
+/// grammar
+fn date_field(name: &str) -> &'static str {
+    match name {
+        "created" => "created",
+        "modified" => "modified",
+        "imported" | "imported_at" => "imported_at",
+        _ => unreachable!(),
+    }
+}
+
+/// Filter for a `field op value` comparison
+fn comparison(token: PestPair<'_>) -> Result<String, Report> {
+    let mut inner = token.into_inner();
+    let field = match inner.next().unwrap().as_str() {
+        "tag" => "tags",
+        "author" => "authors",
+        "imported" => "imported_at",
+        field => field,
+    };
+    let op = inner.next().unwrap().as_str();
+    let value = inner.next().unwrap();
+    let quoted = value.as_rule() == Rule::quoted_value;
+    let value = value.as_str().trim_matches('"');
+    if quoted {
+        return Ok(format!("{} {} \"{}\"", field, op, value));
+    }
+    if DATE_FIELDS.contains(&field) {
+        if let Some(date) = whole(Rule::date, value) {
+            let range = DateRange::try_from(date.into_inner().next().unwrap())?;
+            let (start, end) = (range.start.timestamp(), range.end.timestamp());
+            return Ok(match op {
+                ">" => format!("{} > {}", field, end),
+                ">=" => format!("{} >= {}", field, start),
+                "<" => format!("{} < {}", field, start),
+                "<=" => format!("{} <= {}", field, end),
+                "!=" => format!("({} < {} OR {} > {})", field, start, field, end),
+                _ => format!("({} >= {} AND {} <= {})", field, start, field, end),
+            });
+        }
+        if let Some(duration) = whole(Rule::duration, value) {
+            // Being within the duration is being after the time it reaches back to
+            let op = match op {
+                "=" => ">=",
+                "!=" => "<",
+                op => op,
+            };
+            return Ok(format!(
+                "{} {} {}",
+                field,
+                op,
+                duration_timestamp(duration)?
+            ));
+        }
+    }
+    if value.parse::<f64>().is_ok() {
+        Ok(format!("{} {} {}", field, op, value))
+    } else {
+        Ok(format!("{} {} \"{}\"", field, op, value))
+    }
+}
+
+/// Whether `s` on its own is read as a tag, e.g. `vim`, but not `2021`, `3d`, `2023-05` or
+/// `OR`, which are read as a date, a duration and an operator
+pub fn is_tag(s: &str) -> bool {
+    whole(Rule::tag, s).is_some()
+        && Filter::parse(Rule::expression, s)
+            .ok()
+            .and_then(|mut expr| expr.next()?.into_inner().next())
+            .is_some_and(|term| term.as_rule() == Rule::tag && term.as_str().len() == s.len())
+}
+
+/// The parse of all of `s` as `rule`, none when only part of it matches
+fn whole(rule: Rule, s: &str) -> Option<PestPair<'_>> {
+    Filter::parse(rule, s)
+        .ok()?
+        .next()
+        .filter(|pair| pair.as_str().len() == s.len())
+}
+
+/// Length of a duration written like in filters, e.g. `2w`: a number of hours, days, weeks,
+/// 30-day months or 365-day years
+pub fn parse_duration(s: &str) -> Result<Duration, Report> {
+    let token = whole(Rule::duration, s)
+        .ok_or_else(|| eyre!("Not a duration like 12h, 3d, 2w, 6m or 1y: {}", s))?;
+    let text = token.as_str().to_owned();
+    duration_secs(token)
+        .map(Duration::seconds)
+        .ok_or_else(|| eyre!("{} is too long", text))
+}
+
+/// Timestamp of the duration before now
+fn duration_timestamp(token: PestPair<'_>) -> Result<i64, Report> {
+    let text = token.as_str().to_owned();
+    // Typing digits can make any number, which mustn't overflow
+    let too_long = || eyre!("{} reaches back too far", text);
+    let secs = duration_secs(token).ok_or_else(too_long)?;
+    let ts = Local::now()
+        .checked_sub_signed(Duration::seconds(secs))
+        .ok_or_else(too_long)?;
+    Ok(ts.timestamp())
+}
+
+/// Seconds in a duration, none when there are too many to work with
+fn duration_secs(token: PestPair<'_>) -> Option<i64> {
+    let t = token.into_inner().next().unwrap();
+    let unit_secs: i64 = match t.as_rule() {
+        Rule::hour_duration => 3600,
+        Rule::day_duration => 86400,
+        Rule::week_duration => 7 * 86400,
+        Rule::month_duration => 30 * 86400,
+        Rule::year_duration => 365 * 86400,
+        _ => unreachable!(),
+    };
+    t.into_inner()
+        .next()
+        .unwrap()
+        .as_str()
+        .parse::<i64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_secs))
+        .filter(|secs| *secs <= i64::MAX / 1000)
+}
+
+/// What the parser was after where the input went wrong, in words
+fn syntax_error(e: pest::error::Error<Rule>) -> Report {
+    let column = match e.line_col {
+        LineColLocation::Pos((_, column)) | LineColLocation::Span((_, column), _) => column,
+    };
+    let message = match &e.variant {
+        ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+            let mut expected: Vec<&str> = Vec::new();
+            for name in positives.iter().map(|rule| rule_name(*rule)) {
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
+            match expected.split_last() {
+                Some((last, rest)) if !rest.is_empty() => {
+                    format!("expected {} or {}", rest.join(", "), last)
+                }
+                _ => format!("expected {}", expected.join("")),
+            }
+        }
+        ErrorVariant::ParsingError { .. } => String::from("unexpected input"),
+        ErrorVariant::CustomError { message } => message.to_owned(),
+    };
+    eyre!("{} at column {}", message, column)
+}
+
+/// Name of what a rule matches, for error messages
+fn rule_name(rule: Rule) -> &'static str {
+    match rule {
+        Rule::or | Rule::and | Rule::operator => "an operator",
+        Rule::not => "NOT",
+        Rule::group => "(",
+        Rule::close => ")",
+        Rule::date_field | Rule::comparator | Rule::date | Rule::duration => "a date",
+        Rule::field_name => "a field",
+        Rule::field_op => "a comparison like =",
+        Rule::quoted_value | Rule::bare_value => "a value",
+        Rule::EOI => "the end",
+        _ => "a tag",
+    }
+}
+
+type PestPair<'a> = pest_iterators::Pair<'a, Rule>;
+
+/// Number in the text of `pair`, which the grammar lets contain whitespace, e.g. `2 021`
+fn number<T: FromStr>(pair: PestPair<'_>) -> Result<T, Report> {
+    pair.as_str()
+        .parse()
+        .map_err(|_| eyre!("{} isn't a number", pair.as_str()))
+}
+
+/// First second of `day` in local time, the time zone the dates of notes are read in
+fn local_start(day: NaiveDate) -> Result<DateTime<Utc>, Report> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    // Where daylight saving time starts at midnight, the day starts an hour later
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|start| start.with_timezone(&Utc))
+        .ok_or_else(|| eyre!("{} has no start in the local time zone", day))
+}
+
+impl TryFrom<PestPair<'_>> for DateRange {
+    type Error = Report;
+
+    /// From the first second of the day, month or year in local time to its last
+    fn try_from(item: PestPair<'_>) -> Result<Self, Self::Error> {
+        let (first, next) = match item.as_rule() {
+            Rule::year_month_day => {
+                let mut item = item.into_inner();
+                let y = number(item.next().unwrap())?;
+                let m = number(item.next().unwrap())?;
+                let d = number(item.next().unwrap())?;
+                // The grammar lets through days like 2021-02-31 and 2021-01-0
+                let date = NaiveDate::from_ymd_opt(y, m, d)
+                    .ok_or_else(|| eyre!("No such date {}-{}-{}", y, m, d))?;
+                (Some(date), date.succ_opt())
+            }
+            Rule::year_month => {
+                let mut item = item.into_inner();
+                let y = number(item.next().unwrap())?;
+                let m = number(item.next().unwrap())?;
+                let next = match m {
+                    12 => NaiveDate::from_ymd_opt(y + 1, 1, 1),
+                    _ => NaiveDate::from_ymd_opt(y, m + 1, 1),
+                };
+                (NaiveDate::from_ymd_opt(y, m, 1), next)
+            }
+            Rule::year => {
+                let y = number(item)?;
+                (
+                    NaiveDate::from_ymd_opt(y, 1, 1),
+                    NaiveDate::from_ymd_opt(y + 1, 1, 1),
+                )
+            }
+            e => return Err(eyre!("Unexpected match item {:?}", e)),
+        };
+        let (first, next) = first
+            .zip(next)
+            .ok_or_else(|| eyre!("Dates that far out aren't supported"))?;
+        Ok(DateRange {
+            start: local_start(first)?,
+            end: local_start(next)? - Duration::seconds(1),
+        })
+    }
+}
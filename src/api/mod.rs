@@ -0,0 +1,61 @@
+use crate::document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Filter syntax of the filter input
+mod filter;
+/// Search requests, put together without any I/O
+mod request;
+/// Following the asynchronous tasks that apply changes
+pub mod task;
+/// Sending requests to a server, or answering them from memory
+mod transport;
+
+pub use filter::{
+    is_tag, parse as parse_filter, parse_duration, translate as translate_filter, Filter, Rule,
+};
+pub use request::SearchRequest;
+pub use transport::{Failover, MockTransport, Request, Transport};
+
+/// Oldest Meilisearch release the client works with, the first with the tasks API
+pub const MIN_SERVER_VERSION: &str = "0.25.0";
+/// First Meilisearch release the client isn't known to work with
+pub const MAX_SERVER_VERSION: &str = "2.0.0";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApiResponse {
+    pub hits: Vec<document::Document>,
+    /// Total number of hits, `estimatedTotalHits` since Meilisearch 0.28
+    #[serde(default, rename = "nbHits", alias = "estimatedTotalHits")]
+    pub num_hits: u32,
+    /// Whether `num_hits` is exact rather than an estimate
+    #[serde(default, rename = "exhaustiveNbHits")]
+    pub exhaustive_num_hits: bool,
+    pub query: String,
+    pub limit: u16,
+    pub offset: u32,
+    #[serde(rename = "processingTimeMs")]
+    pub processing_time_ms: u32,
+    /// Value counts per facet, present when facets were requested. `facetsDistribution`
+    /// before Meilisearch 0.28.
+    #[serde(default)]
+    #[serde(rename = "facetDistribution", alias = "facetsDistribution")]
+    pub facets_distribution: Option<HashMap<String, HashMap<String, u32>>>,
+}
+
+/// An index on the server, from `indexes`
+#[derive(Debug, Deserialize)]
+pub struct IndexInfo {
+    pub uid: String,
+    #[serde(rename = "primaryKey")]
+    pub primary_key: Option<String>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// Statistics of an index, from `indexes/:index/stats`
+#[derive(Debug, Deserialize)]
+pub struct IndexStats {
+    #[serde(rename = "numberOfDocuments")]
+    pub number_of_documents: u64,
+}
@@ -0,0 +1,146 @@
+use crate::api::filter;
+use crate::document;
+use serde::{Deserialize, Serialize};
+
+/// Body of a search request, put together without any I/O so it can be checked on its own.
+/// Start from `new` and chain the builder methods, e.g.
+/// `SearchRequest::new().query("vim").filter("!bash").page(0, 20)`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SearchRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    #[serde(rename = "q")]
+    pub query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub sort: Option<Vec<String>>,
+    /// Attributes to count the values of among the hits, sent as `facetsDistribution` to
+    /// servers before 0.28, see `query::send_search`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, alias = "facetsDistribution")]
+    pub facets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    #[serde(rename = "attributesToRetrieve")]
+    pub attributes_to_retrieve: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    #[serde(rename = "attributesToHighlight")]
+    pub attributes_to_highlight: Option<Vec<String>>,
+    /// Languages of the query, as ISO 639-3 codes like `deu`, so it's tokenized the way the
+    /// notes it should match were rather than by guessing from a few words
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub locales: Option<Vec<String>>,
+    #[serde(default)]
+    pub limit: u32,
+    /// Number of hits to skip, for paging through more of them than fit in one response
+    #[serde(skip_serializing_if = "is_zero")]
+    #[serde(default)]
+    pub offset: u32,
+    /// Also match the revisions replaced by newer ones, which searches leave out, see
+    /// `query::send_search`
+    #[serde(skip)]
+    pub all_revisions: bool,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+impl SearchRequest {
+    pub fn new() -> Self {
+        SearchRequest {
+            sort: Some(vec!["date:desc".to_owned()]),
+            limit: 10000,
+            ..Default::default()
+        }
+    }
+
+    /// Search for `query`
+    pub fn query(mut self, query: &str) -> Self {
+        self.query = Some(query.to_owned());
+        self
+    }
+
+    /// Filter with an expression in the syntax of the filter input, see `filter::translate`
+    pub fn filter(mut self, expression: &str) -> Self {
+        self.filter = filter::translate(expression);
+        self
+    }
+
+    /// Filter with an expression in Meilisearch's own syntax
+    pub fn raw_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_owned());
+        self
+    }
+
+    /// Narrow the filter down with an expression in Meilisearch's own syntax
+    pub fn and_raw_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(match self.filter.take() {
+            Some(current) => format!("({}) AND {}", current, filter),
+            None => filter.to_owned(),
+        });
+        self
+    }
+
+    /// Match every revision of the notes rather than only the latest
+    pub fn all_revisions(mut self) -> Self {
+        self.all_revisions = true;
+        self
+    }
+
+    /// Order the hits by `sort`, e.g. `date:asc`, instead of newest first
+    pub fn sort(mut self, sort: &str) -> Self {
+        self.sort = Some(vec![sort.to_owned()]);
+        self
+    }
+
+    /// Count the values of `facets` among the hits
+    pub fn facets(mut self, facets: &[&str]) -> Self {
+        self.facets = Some(facets.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Return `limit` hits after skipping `offset` of them
+    pub fn page(mut self, offset: u32, limit: u32) -> Self {
+        self.offset = offset;
+        self.limit = limit;
+        self
+    }
+
+    /// Mark the matched words in `attributes` of the hits
+    pub fn highlight(mut self, attributes: &[&str]) -> Self {
+        self.attributes_to_highlight = Some(attributes.iter().map(|a| a.to_string()).collect());
+        self
+    }
+
+    /// Tokenize the query as being in one of `locales`, left to Meilisearch when empty
+    pub fn locales(mut self, locales: &[String]) -> Self {
+        self.locales = if locales.is_empty() {
+            None
+        } else {
+            Some(locales.to_vec())
+        };
+        self
+    }
+
+    /// Leave the (potentially large) document bodies out of the hits
+    pub fn without_body(&mut self) {
+        self.attributes_to_retrieve = Some(
+            document::METADATA_FIELDS
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        );
+    }
+
+    pub fn process_filter(&mut self, input: String) {
+        if let Some(filter) = filter::translate(&input) {
+            self.filter = Some(filter);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::api::Transport;
+use crate::query;
+use color_eyre::Report;
+use eyre::{bail, Result};
+use serde::Deserialize;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Longest pause between two polls of a task, polling starts faster than this
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reply to a write request, identifying the asynchronous task that applies it, by `uid`
+/// before Meilisearch 0.28 and `taskUid` since
+#[derive(Debug, Deserialize)]
+pub struct EnqueuedTask {
+    #[serde(rename = "taskUid", alias = "uid")]
+    pub uid: u64,
+}
+
+/// Progress of a task, from `tasks/:uid`
+#[derive(Debug, Deserialize)]
+pub struct Task {
+    pub uid: u64,
+    /// enqueued, processing, then succeeded, failed or canceled
+    pub status: String,
+    /// What the task does, a name before 0.28 and an object with one after
+    #[serde(rename = "type", default)]
+    pub kind: serde_json::Value,
+    #[serde(rename = "enqueuedAt", default)]
+    pub enqueued_at: Option<String>,
+    #[serde(rename = "finishedAt", default)]
+    pub finished_at: Option<String>,
+    /// Why the task failed
+    #[serde(default)]
+    pub error: Option<TaskError>,
+    /// Counts of documents received and indexed
+    #[serde(default)]
+    pub details: Option<TaskDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskError {
+    pub message: String,
+    #[serde(default)]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskDetails {
+    #[serde(rename = "receivedDocuments", default)]
+    pub received_documents: Option<u64>,
+    #[serde(rename = "indexedDocuments", default)]
+    pub indexed_documents: Option<u64>,
+}
+
+impl Task {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.status.as_str(), "enqueued" | "processing")
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.status == "succeeded"
+    }
+
+    /// Why the task failed, if it did
+    pub fn error_message(&self) -> Option<String> {
+        if self.succeeded() {
+            return None;
+        }
+        self.error
+            .as_ref()
+            .map(|e| e.message.to_owned())
+            .or_else(|| Some(self.status.to_owned()).filter(|_| self.is_finished()))
+    }
+
+    /// Documents that were received but not indexed, which some versions report instead of
+    /// failing the whole task
+    pub fn dropped_documents(&self) -> u64 {
+        match &self.details {
+            Some(TaskDetails {
+                received_documents: Some(received),
+                indexed_documents: Some(indexed),
+            }) => received.saturating_sub(*indexed),
+            _ => 0,
+        }
+    }
+
+    /// Name of what the task does, e.g. `documentAdditionOrUpdate`
+    pub fn kind(&self) -> String {
+        match &self.kind {
+            serde_json::Value::String(kind) => kind.to_owned(),
+            serde_json::Value::Object(kind) => kind
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Look up the task `uid` on the server at `host`
+pub fn get(client: &dyn Transport, host: &Url, uid: u64) -> Result<Task, Report> {
+    query::get_json(client, &host.join(&format!("tasks/{}", uid))?)
+}
+
+/// Poll the task `uid` until it's finished, failing if it doesn't within `timeout`. A task
+/// that finished but failed is returned like any other, see `Task::error_message`.
+pub fn wait(
+    client: &dyn Transport,
+    host: &Url,
+    uid: u64,
+    timeout: Option<Duration>,
+) -> Result<Task, Report> {
+    let started = Instant::now();
+    let mut interval = Duration::from_millis(50);
+    loop {
+        let task = get(client, host, uid)?;
+        if task.is_finished() {
+            return Ok(task);
+        }
+        if let Some(timeout) = timeout {
+            if started.elapsed() >= timeout {
+                bail!("Task {} is still {} after {:?}", uid, task.status, timeout);
+            }
+        }
+        thread::sleep(interval);
+        // Short tasks are seen through quickly, long ones aren't polled needlessly often
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,256 @@
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use reqwest::header::CONTENT_TYPE;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How long a server that failed to answer is tried only after the others
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Carries requests to Meilisearch and brings back the body of the response, so searching and
+/// reading documents can run against a server or against `MockTransport`
+pub trait Transport: Send + Sync {
+    /// GET `url`
+    fn get(&self, url: &Url) -> Result<String, Report>;
+    /// POST the JSON `body` to `url`
+    fn post(&self, url: &Url, body: String) -> Result<String, Report>;
+    /// PUT the JSON `body` to `url`, e.g. partial updates of documents
+    fn put(&self, url: &Url, body: String) -> Result<String, Report>;
+    /// Server that answered the last request in place of the one it was addressed to, as when
+    /// failing over to a replica
+    fn fallback_host(&self) -> Option<Url> {
+        None
+    }
+}
+
+impl Transport for reqwest::blocking::Client {
+    fn get(&self, url: &Url) -> Result<String, Report> {
+        answer(self.get(url.as_ref()).send())
+    }
+
+    fn post(&self, url: &Url, body: String) -> Result<String, Report> {
+        answer(
+            self.post(url.as_ref())
+                .body(body)
+                .header(CONTENT_TYPE, "application/json")
+                .send(),
+        )
+    }
+
+    fn put(&self, url: &Url, body: String) -> Result<String, Report> {
+        answer(
+            self.put(url.as_ref())
+                .body(body)
+                .header(CONTENT_TYPE, "application/json")
+                .send(),
+        )
+    }
+}
+
+/// Transport sending the requests for a primary server to the first of it and its replicas
+/// that answers, so searching goes on while the primary is down. Servers that recently failed
+/// to answer are tried last. Requests for other servers go straight through.
+///
+/// Changes are only sent to the primary, and fail while it's down rather than being queued.
+/// Searches and documents answered by a replica are as of its last `replicate`, and may be
+/// behind the primary.
+pub struct Failover {
+    client: reqwest::blocking::Client,
+    /// The primary and then its replicas, in the order they're tried
+    hosts: Vec<Url>,
+    /// When each host last failed to answer
+    down: Mutex<Vec<Option<Instant>>>,
+    /// Position in `hosts` of the host that answered last
+    active: AtomicUsize,
+}
+
+impl Failover {
+    /// `hosts` are the primary and then its replicas
+    pub fn new(client: reqwest::blocking::Client, hosts: Vec<Url>) -> Failover {
+        Failover {
+            client,
+            down: Mutex::new(vec![None; hosts.len()]),
+            hosts,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Positions of the hosts in the order to try them
+    fn order(&self) -> Vec<usize> {
+        let down = self.down.lock().unwrap();
+        let (mut up, mut failed): (Vec<usize>, Vec<usize>) = (0..self.hosts.len())
+            .partition(|&i| down[i].is_none_or(|at| at.elapsed() >= RETRY_AFTER));
+        up.append(&mut failed);
+        up
+    }
+
+    /// Send the request built for `url` to each host in turn, until one answers. Servers
+    /// rejecting the request don't count as down, every host would reject it alike.
+    fn send(
+        &self,
+        url: &Url,
+        request: impl Fn(&Url) -> reqwest::blocking::RequestBuilder,
+    ) -> Result<String, Report> {
+        if url.origin() != self.hosts[0].origin() {
+            return answer(request(url).send());
+        }
+        let mut failure = None;
+        for i in self.order() {
+            let mut moved = self.hosts[i].clone();
+            moved.set_path(url.path());
+            moved.set_query(url.query());
+            match request(&moved).send() {
+                Ok(resp) if !resp.status().is_server_error() => {
+                    self.down.lock().unwrap()[i] = None;
+                    self.active.store(i, Ordering::Relaxed);
+                    return answer(Ok(resp));
+                }
+                result => {
+                    self.down.lock().unwrap()[i] = Some(Instant::now());
+                    failure = Some(result);
+                }
+            }
+        }
+        answer(failure.expect("there is always a primary"))
+    }
+}
+
+/// Body of the response, failing on errors
+fn answer(result: reqwest::Result<reqwest::blocking::Response>) -> Result<String, Report> {
+    match result {
+        Ok(resp) => {
+            if !resp.status().is_success() {
+                bail!("Request failed: {:?}", resp);
+            }
+            resp.text()
+                .map_err(|e| eyre!("resp.text() failed: {:?}", e))
+        }
+        Err(e) => bail!("Send failed: {:?}", e),
+    }
+}
+
+impl Transport for Failover {
+    fn get(&self, url: &Url) -> Result<String, Report> {
+        self.send(url, |url| self.client.get(url.as_ref()))
+    }
+
+    fn post(&self, url: &Url, body: String) -> Result<String, Report> {
+        let request = |url: &Url| {
+            self.client
+                .post(url.as_ref())
+                .body(body.clone())
+                .header(CONTENT_TYPE, "application/json")
+        };
+        // Anything posted besides searches changes the index, which only happens on the primary
+        if url.path().ends_with("/search") {
+            self.send(url, request)
+        } else {
+            answer(request(url).send())
+        }
+    }
+
+    fn put(&self, url: &Url, body: String) -> Result<String, Report> {
+        answer(
+            self.client
+                .put(url.as_ref())
+                .body(body)
+                .header(CONTENT_TYPE, "application/json")
+                .send(),
+        )
+    }
+
+    fn fallback_host(&self) -> Option<Url> {
+        match self.active.load(Ordering::Relaxed) {
+            0 => None,
+            i => Some(self.hosts[i].clone()),
+        }
+    }
+}
+
+/// A request made through `MockTransport`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub method: &'static str,
+    pub url: Url,
+    /// Body of POST requests
+    pub body: Option<String>,
+}
+
+/// Transport answering from canned responses kept in memory, for exercising searches and the
+/// interface without a server. Responses are looked up by method and URL path, ignoring the
+/// host and query string, and every request is recorded for checking afterwards.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<(&'static str, String), String>>,
+    requests: Mutex<Vec<Request>>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Answer GET requests for `path`, e.g. `/indexes/notes/documents/1`, with `body`
+    pub fn on_get(self, path: &str, body: &str) -> MockTransport {
+        self.respond("GET", path, body)
+    }
+
+    /// Answer POST requests to `path`, e.g. `/indexes/notes/search`, with `body`
+    pub fn on_post(self, path: &str, body: &str) -> MockTransport {
+        self.respond("POST", path, body)
+    }
+
+    /// Answer PUT requests to `path`, e.g. `/indexes/notes/documents`, with `body`
+    pub fn on_put(self, path: &str, body: &str) -> MockTransport {
+        self.respond("PUT", path, body)
+    }
+
+    fn respond(self, method: &'static str, path: &str, body: &str) -> MockTransport {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert((method, path.to_owned()), body.to_owned());
+        self
+    }
+
+    /// Every request made so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn answer(
+        &self,
+        method: &'static str,
+        url: &Url,
+        body: Option<String>,
+    ) -> Result<String, Report> {
+        self.requests.lock().unwrap().push(Request {
+            method,
+            url: url.clone(),
+            body,
+        });
+        self.responses
+            .lock()
+            .unwrap()
+            .get(&(method, url.path().to_owned()))
+            .cloned()
+            .ok_or_else(|| eyre!("No mock response for {} {}", method, url.path()))
+    }
+}
+
+impl Transport for MockTransport {
+    fn get(&self, url: &Url) -> Result<String, Report> {
+        self.answer("GET", url, None)
+    }
+
+    fn post(&self, url: &Url, body: String) -> Result<String, Report> {
+        self.answer("POST", url, Some(body))
+    }
+
+    fn put(&self, url: &Url, body: String) -> Result<String, Report> {
+        self.answer("PUT", url, Some(body))
+    }
+}
@@ -0,0 +1,113 @@
+use crate::date::Date;
+use crate::import::ImportReport;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Location of the import log unless overridden on the command line
+pub const DEFAULT_PATH: &str = "~/.local/share/meilizet/imports.jsonl";
+
+/// What one import or sync did to an index
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Run {
+    pub at: Date,
+    /// What was imported: the glob pattern, repository or bucket
+    pub source: String,
+    pub index: String,
+    /// Vault being synced, if it was picked by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<String>,
+    /// Documents accepted by the server
+    pub documents: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub interrupted: bool,
+    /// IDs of the documents added or replaced
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Tasks the changes were queued as
+    #[serde(default)]
+    pub tasks: Vec<u64>,
+    /// Files that couldn't be imported and batches that were rejected, and the error that
+    /// stopped the run, if one did
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+impl Run {
+    pub fn new(source: &str, index: &str, vault: Option<&str>) -> Run {
+        Run {
+            at: Date::now(),
+            source: source.to_owned(),
+            index: index.to_owned(),
+            vault: vault.map(str::to_owned),
+            documents: 0,
+            failed: 0,
+            skipped: 0,
+            interrupted: false,
+            ids: Vec::new(),
+            tasks: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Take the counts and outcomes from the report of the run
+    pub fn report(mut self, report: &ImportReport) -> Run {
+        self.documents = report.documents;
+        self.failed = report.failed;
+        self.skipped = report.skipped;
+        self.interrupted = report.interrupted;
+        self.ids = report.ids.clone();
+        self.tasks = report.tasks_queued.clone();
+        self.errors = report.errors.clone();
+        self
+    }
+
+    /// Whether anything went wrong
+    pub fn failed(&self) -> bool {
+        self.failed > 0 || self.interrupted || !self.errors.is_empty()
+    }
+}
+
+/// Append-only log of the imports run, one JSON object per line, oldest first, so what the
+/// tool did to an index can be looked up long after
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: &str) -> Self {
+        AuditLog {
+            path: PathBuf::from(shellexpand::tilde(path).as_ref()),
+        }
+    }
+
+    pub fn runs(&self) -> Result<Vec<Run>, Report> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                serde_json::from_str(l)
+                    .map_err(|e| eyre!("Failed to parse import log {}: {}", self.path.display(), e))
+            })
+            .collect()
+    }
+
+    pub fn record(&self, run: &Run) -> Result<(), Report> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(run)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use crate::document::Document;
+use std::collections::{BTreeMap, HashMap};
+
+/// Canonical author names by alias, from the `[authors]` config section
+#[derive(Clone, Debug, Default)]
+pub struct Aliases(HashMap<String, String>);
+
+impl Aliases {
+    /// Build from a map of canonical names to their aliases. Matching ignores case, and the
+    /// canonical names match themselves.
+    pub fn new(config: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut aliases = HashMap::new();
+        for (canonical, names) in config {
+            for name in names.iter().chain(std::iter::once(canonical)) {
+                aliases.insert(name.to_lowercase(), canonical.to_owned());
+            }
+        }
+        Aliases(aliases)
+    }
+
+    pub fn canonical<'a>(&'a self, author: &'a str) -> &'a str {
+        self.0
+            .get(&author.to_lowercase())
+            .map_or(author, |c| c.as_str())
+    }
+
+    /// Replace the document's author aliases with canonical names, dropping any duplicates
+    /// that leaves. Returns whether anything changed.
+    pub fn normalize(&self, doc: &mut Document) -> bool {
+        let mut authors: Vec<String> = Vec::with_capacity(doc.authors.len());
+        for author in &doc.authors {
+            let canonical = self.canonical(author);
+            if !authors.iter().any(|a| a == canonical) {
+                authors.push(canonical.to_owned());
+            }
+        }
+        if authors == doc.authors {
+            return false;
+        }
+        doc.authors = authors;
+        true
+    }
+}
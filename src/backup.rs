@@ -0,0 +1,57 @@
+use crate::date::Date;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The settings and documents of an index, saved to a file before the index is changed
+/// wholesale so whatever goes wrong it can be put back
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Backup {
+    pub index: String,
+    pub settings: Value,
+    /// As stored, every field included
+    pub documents: Vec<Value>,
+}
+
+impl Backup {
+    /// Where the backup of `index` taken by `kind`, e.g. `reindex`, goes unless told otherwise:
+    /// next to the state file at `state_path`, named after both and the time
+    pub fn default_path(state_path: &str, kind: &str, index: &str) -> PathBuf {
+        let state = PathBuf::from(shellexpand::tilde(state_path).as_ref());
+        state.with_file_name(format!(
+            "{}-{}-{}.json",
+            kind,
+            index,
+            Date::now().format("%Y%m%dT%H%M%S")
+        ))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Report> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+            .map_err(|e| eyre!("Failed to save backup {}: {}", path.display(), e))
+    }
+
+    /// Read a backup, the plain list of documents saved by `reindex` before it kept the
+    /// settings too included, with `Value::Null` for settings
+    pub fn load(path: &Path) -> Result<Backup, Report> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| eyre!("Failed to read backup {}: {}", path.display(), e))?;
+        let value: Value = serde_json::from_str(&contents)
+            .map_err(|e| eyre!("Failed to parse backup {}: {}", path.display(), e))?;
+        match value {
+            Value::Array(documents) => Ok(Backup {
+                index: String::new(),
+                settings: Value::Null,
+                documents,
+            }),
+            value => serde_json::from_value(value)
+                .map_err(|e| eyre!("Failed to parse backup {}: {}", path.display(), e)),
+        }
+    }
+}
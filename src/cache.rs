@@ -0,0 +1,145 @@
+use crate::api::SearchRequest;
+use crate::output;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use url::Url;
+
+/// `[cache]` section of the config file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Also keep responses on disk so repeated invocations of the tool can share them
+    pub disk: bool,
+    pub path: String,
+    /// Seconds a response stays valid
+    pub ttl_secs: i64,
+    /// Maximum number of responses kept
+    pub capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            disk: false,
+            path: String::from("~/.cache/meilizet/search.json"),
+            ttl_secs: 300,
+            capacity: 100,
+        }
+    }
+}
+
+impl CacheConfig {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.path).as_ref())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    /// Epoch seconds
+    stored_at: i64,
+    /// Raw response body
+    body: String,
+}
+
+/// Recent search responses keyed by the index and the full query (query, filter, sort, ...)
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SearchCache {
+    #[serde(skip)]
+    config: CacheConfig,
+    #[serde(skip)]
+    dirty: bool,
+    entries: HashMap<String, Entry>,
+}
+
+/// Cache key of a search request
+pub fn key(uri: &Url, q: &SearchRequest) -> String {
+    let revisions = if q.all_revisions {
+        " all-revisions"
+    } else {
+        ""
+    };
+    format!("{} {}{}", uri, serde_json::to_string(q).unwrap(), revisions)
+}
+
+impl SearchCache {
+    /// Create the cache, picking up the responses stored on disk if enabled
+    pub fn new(config: &CacheConfig) -> SearchCache {
+        let mut cache = if config.disk {
+            fs::read_to_string(config.path())
+                .ok()
+                .and_then(|s| serde_json::from_str::<SearchCache>(&s).ok())
+                .unwrap_or_default()
+        } else {
+            SearchCache::default()
+        };
+        cache.config = config.clone();
+        cache
+    }
+
+    /// The response body stored for `key`, unless it has expired
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries
+            .get(key)
+            .filter(|e| Utc::now().timestamp() - e.stored_at < self.config.ttl_secs)
+            .map(|e| e.body.to_owned())
+    }
+
+    pub fn insert(&mut self, key: String, body: String) {
+        let now = Utc::now().timestamp();
+        let ttl = self.config.ttl_secs;
+        self.entries.retain(|_, e| now - e.stored_at < ttl);
+        while self.entries.len() >= self.config.capacity.max(1) {
+            let oldest = match self.entries.iter().min_by_key(|(_, e)| e.stored_at) {
+                Some((k, _)) => k.to_owned(),
+                None => break,
+            };
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                stored_at: now,
+                body,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Drop every response, call after anything is written to the index
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = false;
+        invalidate(&self.config);
+    }
+
+    /// Write the responses to disk if enabled and anything changed
+    pub fn save(&self) -> std::io::Result<()> {
+        if !self.config.disk || !self.dirty {
+            return Ok(());
+        }
+        let path = self.config.path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&self)?)
+    }
+}
+
+/// Remove the on-disk cache, for commands writing to the index without a `SearchCache` of
+/// their own
+pub fn invalidate(config: &CacheConfig) {
+    let path = config.path();
+    if path.exists() {
+        if let Err(e) = fs::remove_file(&path) {
+            output::warn(format_args!(
+                "Failed to remove cache {}: {:?}",
+                path.display(),
+                e
+            ));
+        }
+    }
+}
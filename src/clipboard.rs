@@ -0,0 +1,50 @@
+use base64::Engine;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use std::env;
+use std::io::{stdout, Write};
+use std::process::{Command, Stdio};
+
+/// Clipboard programs tried in turn, the first one that runs wins
+const PROGRAMS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["pbcopy"],
+];
+
+/// Copy `text` to the system clipboard. Over SSH, or when no clipboard program is installed,
+/// the terminal is asked to do it with an OSC 52 escape, which most terminals and tmux (with
+/// `set-clipboard on`) support.
+pub fn copy(text: &str) -> Result<(), Report> {
+    if env::var_os("SSH_CONNECTION").is_none() {
+        for program in PROGRAMS {
+            if run(program, text).is_ok() {
+                return Ok(());
+            }
+        }
+    }
+    let mut out = stdout();
+    write!(
+        out,
+        "\x1b]52;c;{}\x07",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+fn run(program: &[&str], text: &str) -> Result<(), Report> {
+    let mut child = Command::new(program[0])
+        .args(&program[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(eyre!("{} failed: {}", program[0], status));
+    }
+    Ok(())
+}
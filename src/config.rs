@@ -0,0 +1,390 @@
+use crate::cache::CacheConfig;
+use crate::keys::KeyConfig;
+use crate::status::Status;
+use crate::validation::ValidationConfig;
+use crate::webhook::Webhook;
+use crate::zettel::IdScheme;
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs};
+
+/// Location of the config file unless overridden on the command line
+pub const DEFAULT_PATH: &str = "~/.config/meilizet/config.toml";
+
+/// Commented-out config file written by `config init`
+pub const TEMPLATE: &str = r#"# Server and index used unless --host, --api-key and --index, or MEILI_HOST, MEILI_API_KEY
+# and MEILIZET_INDEX, say otherwise
+# host = "http://127.0.0.1:7700"
+# api_key = "env://MEILI_MASTER_KEY"
+# index = "notes"
+
+# Servers searched in this order while the one above is down, holding copies of its indexes
+# made with `replicate`, so what they return may be behind. Changes are only ever made on the
+# one above, and fail while it's down.
+# replicas = ["http://replica.example.com:7700"]
+
+# Files imported by `import` when it isn't given any
+# paths = ["~/notes/**/*.md"]
+
+# IDs given to notes that don't have one: "uuid", or "zettel" for timestamps like
+# 202405141230. Notes named after a zettel ID get that one either way.
+# id_scheme = "uuid"
+
+# Command notes are edited with, overriding $EDITOR but not --editor
+# editor = "nvim"
+
+# date_display_format = "%Y-%m-%d %H:%M"
+
+# Languages searches are in, as ISO 639-3 codes, when the index holds notes in more than one.
+# Pair with `settings set localized-attributes` so the notes are tokenized the same way.
+# locales = ["deu", "eng"]
+
+# Theme notes are highlighted with in the preview: base16-ocean.dark, base16-eighties.dark,
+# base16-mocha.dark, base16-ocean.light, InspiredGitHub, Solarized (dark) or Solarized (light)
+# preview_theme = "Solarized (dark)"
+# attachments_dir = "~/notes/attachments"
+
+# Directory `new` writes notes to, and the file their frontmatter is made from, with {id},
+# {title}, {tags}, {datetime}, {date} and {slug} filled in
+# notes_dir = "~/notes"
+# note_template = "~/notes/.template.md"
+
+# Status of the notes in each directory that don't set one in their frontmatter: draft,
+# published or archived. Drafts are left out of the interface's searches until shown.
+# [status_defaults]
+# "~/notes/inbox" = "draft"
+
+# Keys of the interactive interface. Like the theme and locales, they're picked up by a
+# running interface when this file is saved; other settings need it started again.
+# [keys]
+# quit = "ctrl-c"
+# edit = "ctrl-e"
+# view = "ctrl-v"
+# dates = "ctrl-t"
+# next = "ctrl-n"
+# previous = "ctrl-p"
+# all_indexes = "ctrl-a"
+# next_index = "ctrl-o"
+# sequence = "ctrl-s"
+# next_link = "ctrl-l"
+# previous_link = "ctrl-h"
+# back = "ctrl-o"
+# drafts = "ctrl-d"
+
+# Note collections, each kept in an index of its own, for --vault and `sync`
+# [vaults.work]
+# path = "~/notes/work/**/*.md"
+# index = "work"
+# profile = "office"
+
+# [profiles.office]
+# host = "https://search.example.com"
+# api_key = "op://Private/Meilisearch/credential"
+# read_only = false
+"#;
+
+/// Settings loaded from the TOML config file
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// Meilisearch server, unless `--host` or `MEILI_HOST` is given
+    pub host: Option<String>,
+    /// Key of the server, unless `--api-key` or `MEILI_API_KEY` is given
+    pub api_key: Option<String>,
+    /// Index holding the notes, unless `--index` or `MEILIZET_INDEX` is given
+    pub index: Option<String>,
+    /// Servers with copies of the indexes of the main one, searched in this order while it's
+    /// down. Changes are only made on the main server, and fail while it's down.
+    #[serde(default)]
+    pub replicas: Vec<String>,
+    /// Glob patterns of the files `import` reads when it isn't given any
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Command notes are opened in, unless `--editor` is given. Unlike `$EDITOR`, which it
+    /// takes precedence over, it's only used by meilizet.
+    pub editor: Option<String>,
+    /// How IDs are generated for notes that don't have one
+    #[serde(default)]
+    pub id_scheme: IdScheme,
+    /// Languages of searches, as ISO 639-3 codes like `deu`, left to Meilisearch when empty
+    #[serde(default)]
+    pub locales: Vec<String>,
+    /// Theme the preview is highlighted with, one of syntect's defaults, `Solarized (dark)`
+    /// when unset
+    pub preview_theme: Option<String>,
+    /// Keys of the interactive interface
+    #[serde(default)]
+    pub keys: KeyConfig,
+    /// Endpoints to notify when documents are created, updated or deleted
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// strftime-style format for dates written to disk and shown to humans, e.g. `%Y-%m-%d %H:%M`
+    pub date_display_format: Option<String>,
+    /// Caching of search responses
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Connection pooling of the HTTP client
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Conventions enforced by `lint` and `import --strict`
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    /// Aliases of each author, by canonical name, e.g. `"Steve Sosik" = ["steve", "ssosik"]`
+    #[serde(default)]
+    pub authors: BTreeMap<String, Vec<String>>,
+    /// Template for the permalinks of notes, e.g. `https://notes.example.com/{year}/{slug}`, see
+    /// `permalink::render`
+    pub permalink_template: Option<String>,
+    /// Operations touching more documents than this show them and ask for confirmation first,
+    /// 10 when unset
+    pub confirm_threshold: Option<usize>,
+    /// Note collections, by name
+    #[serde(default)]
+    pub vaults: BTreeMap<String, Vault>,
+    /// Servers vaults can be kept on, by name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Directory that images referenced by relative paths in notes are kept in, e.g.
+    /// `~/notes/attachments`, the current directory when unset
+    pub attachments_dir: Option<String>,
+    /// Directory `new` writes notes to, the current directory when unset
+    pub notes_dir: Option<String>,
+    /// File the frontmatter of notes made by `new` is rendered from, see `note::render`
+    pub note_template: Option<String>,
+    /// Status of the notes that don't set one, by directory, see `status::Defaults`
+    #[serde(default)]
+    pub status_defaults: BTreeMap<String, Status>,
+}
+
+/// A note collection and the index it's kept in
+#[derive(Clone, Debug, Deserialize)]
+pub struct Vault {
+    /// Glob pattern matching the vault's files, e.g. `~/notes/work/**/*.md`
+    pub path: String,
+    /// Meilisearch index uid
+    #[serde(default = "default_index")]
+    pub index: String,
+    /// Entry in `[profiles]` for the server the index is on, `--host` when unset
+    pub profile: Option<String>,
+}
+
+fn default_index() -> String {
+    "notes".to_owned()
+}
+
+/// Connection settings for a Meilisearch server
+#[derive(Clone, Debug, Deserialize)]
+pub struct Profile {
+    pub host: String,
+    /// Refuse to modify the indexes on this server, e.g. for a shared production instance
+    #[serde(default)]
+    pub read_only: bool,
+    /// Key of the server unless `--api-key` is given, best kept out of the file with one of the
+    /// secret references `load` resolves, e.g. `op://Private/Meilisearch/credential`
+    pub api_key: Option<String>,
+}
+
+/// `[http]` section of the config file
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Idle connections kept open per host
+    pub pool_max_idle_per_host: usize,
+    /// Seconds an idle connection is kept in the pool
+    pub pool_idle_timeout_secs: u64,
+    /// Seconds between TCP keep-alive probes, 0 disables them
+    pub tcp_keepalive_secs: u64,
+    /// Seconds before giving up on a request, 0 waits however long it takes
+    pub timeout_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout_secs: 90,
+            tcp_keepalive_secs: 60,
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Config {
+    /// `attachments_dir` with `~` expanded
+    pub fn attachments_dir(&self) -> PathBuf {
+        match &self.attachments_dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// `notes_dir` with `~` expanded
+    pub fn notes_dir(&self) -> PathBuf {
+        match &self.notes_dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// The vault called `name` along with its profile, if it names one
+    pub fn vault(&self, name: &str) -> Result<(&Vault, Option<&Profile>), Report> {
+        let vault = self
+            .vaults
+            .get(name)
+            .ok_or_else(|| eyre!("No vault named {} in the config file", name))?;
+        let profile = match &vault.profile {
+            Some(profile) => Some(
+                self.profiles
+                    .get(profile)
+                    .ok_or_else(|| eyre!("Vault {} uses unknown profile {}", name, profile))?,
+            ),
+            None => None,
+        };
+        Ok((vault, profile))
+    }
+
+    /// Write `TEMPLATE` to `path`, refusing to replace an existing file unless `force` is set
+    pub fn init(path: &str, force: bool) -> Result<PathBuf, Report> {
+        let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+        if path.exists() && !force {
+            bail!(
+                "{} already exists, pass --force to replace it",
+                path.display()
+            );
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, TEMPLATE)
+            .map_err(|e| eyre!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Load the config file at `path`, falling back to the defaults when it doesn't exist
+    pub fn load(path: &str) -> Result<Config, Report> {
+        let expanded = shellexpand::tilde(path);
+        let path = Path::new(expanded.as_ref());
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let s = fs::read_to_string(path)?;
+        let mut value: toml::Value = toml::from_str(&s)
+            .map_err(|e| eyre!("Failed to parse config {}: {}", path.display(), e))?;
+        resolve_secrets(&mut value)?;
+        value
+            .try_into()
+            .map_err(|e| eyre!("Failed to parse config {}: {}", path.display(), e))
+    }
+
+    /// The config file as written, for showing: secret references are left unresolved and
+    /// API keys given in plain text are left out. Empty if there's no file.
+    pub fn redacted(path: &str) -> Result<toml::Value, Report> {
+        let expanded = shellexpand::tilde(path);
+        let path = Path::new(expanded.as_ref());
+        if !path.exists() {
+            return Ok(toml::Value::Table(Default::default()));
+        }
+        let s = fs::read_to_string(path)?;
+        let mut value: toml::Value = toml::from_str(&s)
+            .map_err(|e| eyre!("Failed to parse config {}: {}", path.display(), e))?;
+        redact_keys(&mut value);
+        Ok(value)
+    }
+}
+
+/// Replace every `api_key` that isn't a secret reference
+fn redact_keys(value: &mut toml::Value) {
+    match value {
+        toml::Value::Array(values) => values.iter_mut().for_each(redact_keys),
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    toml::Value::String(s) if key == "api_key" && !is_reference(s) => {
+                        *s = String::from("<redacted>");
+                    }
+                    value => redact_keys(value),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `s` is one of the secret references `resolve_secrets` replaces
+fn is_reference(s: &str) -> bool {
+    ["env://", "op://", "cmd://"]
+        .iter()
+        .any(|scheme| s.starts_with(scheme))
+}
+
+/// Replace every string referencing a secret with the secret, so the config file itself can be
+/// shared without leaking any:
+///
+/// - `env://NAME` reads the environment variable `NAME`
+/// - `op://vault/item/field` reads the field from 1Password with `op read`
+/// - `cmd://command` runs the command with `sh -c` and takes its output
+fn resolve_secrets(value: &mut toml::Value) -> Result<(), Report> {
+    match value {
+        toml::Value::String(s) => {
+            if let Some(secret) = resolve_secret(s)? {
+                *s = secret;
+            }
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                resolve_secrets(value)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                resolve_secrets(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The secret `reference` points at, `None` when it's a plain string
+fn resolve_secret(reference: &str) -> Result<Option<String>, Report> {
+    if let Some(name) = reference.strip_prefix("env://") {
+        return env::var(name).map(Some).map_err(|_| {
+            eyre!(
+                "Environment variable {} referenced in the config isn't set",
+                name
+            )
+        });
+    }
+    let mut cmd = if reference.starts_with("op://") {
+        let mut cmd = Command::new("op");
+        cmd.arg("read").arg(reference);
+        cmd
+    } else if let Some(command) = reference.strip_prefix("cmd://") {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    } else {
+        return Ok(None);
+    };
+    let out = cmd
+        .output()
+        .map_err(|e| eyre!("Failed to run {:?} for a config secret: {}", cmd, e))?;
+    if !out.status.success() {
+        // The reference is fine to show, it's what's in the config file
+        bail!(
+            "Reading config secret {} failed: {}",
+            reference,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    // Commands print a trailing newline which isn't part of the secret
+    Ok(Some(
+        String::from_utf8_lossy(&out.stdout)
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_owned(),
+    ))
+}
@@ -0,0 +1,49 @@
+use crate::document::Document;
+use crate::output::Table;
+use color_eyre::Report;
+use eyre::Result;
+use std::io::{self, BufRead, Write};
+
+/// Operations touching more documents than this ask first, unless configured otherwise
+pub const DEFAULT_THRESHOLD: usize = 10;
+
+/// Rows of the preview table shown before asking
+const PREVIEW_ROWS: usize = 40;
+
+/// Before `action` is applied to `docs`, show which documents it affects and have the user type
+/// "yes" when there are more than `threshold` of them. `yes` skips the question, as does
+/// having few enough documents. Returns whether to go ahead.
+pub fn bulk(action: &str, docs: &[&Document], threshold: usize, yes: bool) -> Result<bool, Report> {
+    if yes || docs.len() <= threshold {
+        return Ok(true);
+    }
+
+    let mut table = Table::new(&["ID", "TITLE", "FILENAME"]);
+    for doc in docs.iter().take(PREVIEW_ROWS) {
+        table.row(vec![
+            doc.id.to_owned(),
+            doc.title.chars().take(40).collect(),
+            doc.filename.to_owned(),
+        ]);
+    }
+    let mut out = io::stderr();
+    write!(out, "{}", table)?;
+    if docs.len() > PREVIEW_ROWS {
+        writeln!(out, "… and {} more", docs.len() - PREVIEW_ROWS)?;
+    }
+    typed_yes(&format!("{} these {} documents", action, docs.len()), false)
+}
+
+/// Have the user type "yes" to go ahead with `action`, unless `yes` was given already
+pub fn typed_yes(action: &str, yes: bool) -> Result<bool, Report> {
+    if yes {
+        return Ok(true);
+    }
+    let mut out = io::stderr();
+    write!(out, "Type 'yes' to {}: ", action)?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim() == "yes")
+}
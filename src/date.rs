@@ -1,11 +1,15 @@
-use chrono::{offset, DateTime, NaiveDateTime, Utc};
+use chrono::{offset, DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use color_eyre::Report;
 use eyre::{eyre, Result};
+use once_cell::sync::OnceCell;
 use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
 use std::str::FromStr;
-use std::{fmt, marker::PhantomData};
+use std::time::SystemTime;
 
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// Epoch seconds. Serialized as a number and deserialized from either a number or any string
+/// `FromStr` accepts, so the same type reads back every representation it's written in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct Date(i64);
 
 #[derive(Debug)]
@@ -14,22 +18,64 @@ pub struct DateRange {
     pub end: DateTime<Utc>,
 }
 
+/// strftime-style format used when displaying dates, RFC 3339 when unset
+static DISPLAY_FORMAT: OnceCell<String> = OnceCell::new();
+
+/// Set the format used when displaying dates on disk and to humans, see the
+/// `date_display_format` config setting. Only the first call has any effect.
+pub fn set_display_format(format: &str) {
+    let _ = DISPLAY_FORMAT.set(format.to_string());
+}
+
 impl Date {
     pub fn new(d: i64) -> Date {
         Date(d)
     }
+
+    pub fn now() -> Date {
+        Date(Utc::now().timestamp())
+    }
+
+    /// The time `duration` after this one, or an error if that's past the last date chrono can
+    /// represent and so could never be displayed
+    pub fn after(&self, duration: chrono::Duration) -> Result<Date> {
+        DateTime::<Utc>::from_timestamp(self.0, 0)
+            .and_then(|d| d.checked_add_signed(duration))
+            .map(|d| Date(d.timestamp()))
+            .ok_or_else(|| eyre!("{} after {} is too far out", duration, self))
+    }
+
+    /// Format in local time with a strftime-style format
+    pub fn format(&self, format: &str) -> String {
+        self.local().format(format).to_string()
+    }
+
+    fn local(&self) -> DateTime<Local> {
+        // A stored timestamp outside chrono's range shows as the nearest date it can represent
+        let datetime = DateTime::<Utc>::from_timestamp(self.0, 0).unwrap_or(if self.0 < 0 {
+            DateTime::<Utc>::MIN_UTC
+        } else {
+            DateTime::<Utc>::MAX_UTC
+        });
+
+        datetime.with_timezone(&offset::Local)
+    }
+}
+
+impl From<SystemTime> for Date {
+    fn from(t: SystemTime) -> Self {
+        Date(DateTime::<Utc>::from(t).timestamp())
+    }
 }
 
 impl fmt::Display for Date {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Create a NaiveDateTime from the timestamp
-        let naive = NaiveDateTime::from_timestamp(self.0, 0);
-
-        // Create a normal DateTime from the NaiveDateTime
-        let datetime: DateTime<Utc> = DateTime::from_utc(naive, Utc);
-
         // Format the datetime how you want
-        write!(f, "{}", datetime.with_timezone(&offset::Local).to_rfc3339())
+        let local = self.local();
+        match DISPLAY_FORMAT.get() {
+            Some(format) => write!(f, "{}", local.format(format)),
+            None => write!(f, "{}", local.to_rfc3339()),
+        }
     }
 }
 
@@ -43,61 +89,81 @@ impl FromStr for Date {
             Ok(Date::new(s.timestamp()))
         } else if let Ok(s) = s.parse::<i64>() {
             Ok(Date::new(s))
+        } else if let Some(d) = DISPLAY_FORMAT
+            .get()
+            .and_then(|format| parse_local(s, format))
+        {
+            Ok(d)
+        } else if let Some(d) = parse_local(s, "%Y-%m-%d") {
+            Ok(d)
         } else {
             Err(eyre!("❌ Failed to convert {} to str", s))
         }
     }
 }
 
-/// Support Deserializing a date from either a string or i64
-pub fn date_deserializer<'de, D>(deserializer: D) -> Result<Date, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct StringOrVec(PhantomData<Vec<String>>);
+/// Parse `s` with `format`, which may omit the time zone (local time is assumed) and the time
+/// of day (midnight is assumed)
+fn parse_local(s: &str, format: &str) -> Option<Date> {
+    if let Ok(d) = DateTime::parse_from_str(s, format) {
+        return Some(Date::new(d.timestamp()));
+    }
+    let naive = match NaiveDateTime::parse_from_str(s, format) {
+        Ok(n) => n,
+        Err(_) => NaiveDate::parse_from_str(s, format)
+            .ok()?
+            .and_hms_opt(0, 0, 0)?,
+    };
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|d| Date::new(d.timestamp()))
+}
 
-    impl<'de> de::Visitor<'de> for StringOrVec {
-        type Value = Date;
+struct DateVisitor;
 
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("Epoch seconds as i64 or RFC 3339 time string")
-        }
+impl<'de> de::Visitor<'de> for DateVisitor {
+    type Value = Date;
 
-        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(Date::from_str(value).unwrap())
-        }
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Epoch seconds as i64 or RFC 3339 time string")
+    }
 
-        fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(Date::new(value))
-        }
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Date::from_str(value).map_err(|e| E::custom(e.to_string()))
+    }
 
-        fn visit_i32<E>(self, value: i32) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(Date::new(value as i64))
-        }
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Date::new(value))
+    }
 
-        fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(Date::new(value as i64))
-        }
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Date::new(value as i64))
+    }
 
-        fn visit_u32<E>(self, value: u32) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(Date::new(value as i64))
-        }
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Date::new(value as i64))
     }
+}
 
-    deserializer.deserialize_any(StringOrVec(PhantomData))
+/// Support Deserializing a date from either a string or i64
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D>(deserializer: D) -> Result<Date, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DateVisitor)
+    }
 }
@@ -0,0 +1,76 @@
+use crate::document::{Document, SerializationType};
+use similar::{ChangeTag, TextDiff};
+use termion::color;
+
+/// How the document reads on disk, laid out the same way whatever it was parsed from so only
+/// real changes show up
+fn render(doc: &Document) -> String {
+    let mut doc = doc.clone();
+    doc.serialization_type = SerializationType::Disk;
+    doc.layout = Default::default();
+    doc.to_string()
+}
+
+/// Unified diff of the frontmatter and body between `before` and `after`, with `before` being
+/// `None` for new documents. Empty when nothing changed.
+pub fn unified(before: Option<&Document>, after: &Document, colored: bool) -> String {
+    let old = before.map(render).unwrap_or_default();
+    let new = render(after);
+    let diff = TextDiff::from_lines(&old, &new);
+    if diff.ratio() == 1.0 {
+        return String::new();
+    }
+
+    let name = if after.filename.is_empty() {
+        &after.id
+    } else {
+        &after.filename
+    };
+    let mut out = if before.is_some() {
+        format!("--- a/{}\n+++ b/{}\n", name, name)
+    } else {
+        format!("--- /dev/null\n+++ b/{}\n", name)
+    };
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        out.push_str(&paint(&hunk.header().to_string(), Line::Hunk, colored));
+        out.push('\n');
+        for change in hunk.iter_changes() {
+            let (sign, kind) = match change.tag() {
+                ChangeTag::Delete => ("-", Line::Removed),
+                ChangeTag::Insert => ("+", Line::Added),
+                ChangeTag::Equal => (" ", Line::Context),
+            };
+            let mut line = format!("{}{}", sign, change.value());
+            if change.missing_newline() {
+                line.push('\n');
+            }
+            out.push_str(&paint(&line, kind, colored));
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Line {
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+fn paint(s: &str, kind: Line, colored: bool) -> String {
+    if !colored {
+        return s.to_owned();
+    }
+    match kind {
+        Line::Hunk => format!("{}{}{}", color::Fg(color::Cyan), s, color::Fg(color::Reset)),
+        Line::Added => format!(
+            "{}{}{}",
+            color::Fg(color::Green),
+            s,
+            color::Fg(color::Reset)
+        ),
+        Line::Removed => format!("{}{}{}", color::Fg(color::Red), s, color::Fg(color::Reset)),
+        Line::Context => s.to_owned(),
+    }
+}
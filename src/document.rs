@@ -1,12 +1,14 @@
-use crate::date::{date_deserializer, Date};
+use crate::date::Date;
+use crate::layout::FrontmatterLayout;
+use crate::status::Status;
+use crate::zettel;
 use eyre::Result;
-use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind};
 use std::str::FromStr;
 use std::{fmt, fs, io, marker::PhantomData};
-use unicode_width::UnicodeWidthStr;
-use uuid_b64::UuidB64;
-use yaml_rust::YamlEmitter;
+use yaml_rust::YamlLoader;
 
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub enum SerializationType {
@@ -22,7 +24,33 @@ impl Default for SerializationType {
     }
 }
 
-// TODO add `backlink` field for hierarchical linking
+/// How the `get` subcommand prints a document
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GetFormat {
+    /// Frontmatter and body, like the file it was imported from
+    Markdown,
+    /// Every field as stored in the index, body included
+    Json,
+    /// Just the body
+    Body,
+}
+
+impl FromStr for GetFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<GetFormat, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(GetFormat::Markdown),
+            "json" => Ok(GetFormat::Json),
+            "body" => Ok(GetFormat::Body),
+            _ => Err(eyre::eyre!(
+                "Unknown format {}, expected markdown, json or body",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize)]
 pub struct Document {
     #[serde(default)]
@@ -40,30 +68,111 @@ pub struct Document {
     #[serde(skip)]
     pub serialization_type: SerializationType,
     /// Epoch seconds
-    #[serde(deserialize_with = "date_deserializer")]
     pub date: Date,
+    /// When the note was first written, from the frontmatter or else the file's creation time
+    #[serde(default)]
+    pub created: Date,
+    /// Last modification time of the source file
+    #[serde(default)]
+    pub modified: Date,
+    /// When the document was last imported into the index
+    #[serde(default)]
+    pub imported_at: Date,
     pub title: String,
     #[serde(default)]
     pub background_img: String,
     #[serde(default)]
     pub links: Vec<String>,
+    /// IDs of the notes linking to this one, worked out on import and only kept in the index
+    #[serde(default)]
+    pub backlinks: Vec<String>,
     #[serde(default)]
     pub slug: String,
+    /// Position among the notes in Luhmann-style folgezettel, e.g. `1a2b`, see `sequence`
+    #[serde(default)]
+    pub sequence: String,
+    /// Link to the note, generated from the `permalink_template` config setting on import
+    #[serde(default)]
+    pub permalink: String,
     #[serde(default)]
     pub subtitle: String,
     #[serde(default)]
     #[serde(deserialize_with = "string_or_list_string", alias = "tag")]
     pub tags: Vec<String>,
+    /// Draft, published or archived, published when unset
+    #[serde(default)]
+    pub status: Option<Status>,
+    /// When to be reminded of the note, see `due`
+    #[serde(default)]
+    pub remind_at: Option<Date>,
+    /// When the note is due to be looked over again, see `due`
+    #[serde(default)]
+    pub review_after: Option<Date>,
     #[serde(default)]
     pub weight: i32,
     #[serde(default)]
     pub writes: u16,
+    /// ID of the revision this one replaced when it was edited, see `revision`
+    #[serde(default)]
+    pub revises: String,
+    /// Whether this is the current revision of the note, unset on notes never edited in the
+    /// interface. Only kept in the index.
+    #[serde(default)]
+    pub latest: Option<bool>,
     #[serde(default)]
     pub views: i32,
     #[serde(default)]
     pub filename: String,
+    /// Frontmatter fields outside of the schema above, carried through untouched
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+    /// How the frontmatter was laid out in the file this was parsed from
+    #[serde(skip)]
+    pub layout: FrontmatterLayout,
+    /// Copy of the hit with the words the search matched marked, when it asked for that with
+    /// `attributesToHighlight`. Never stored.
+    #[serde(default, rename = "_formatted")]
+    pub formatted: Option<Formatted>,
 }
 
+/// The fields of a hit highlighted by Meilisearch, matched words wrapped in `<em>` tags
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct Formatted {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// Every stored field except `body`, for searches that fetch the body on demand
+pub const METADATA_FIELDS: &[&str] = &[
+    "id",
+    "parentid",
+    "authors",
+    "date",
+    "created",
+    "modified",
+    "imported_at",
+    "title",
+    "background_img",
+    "links",
+    "backlinks",
+    "slug",
+    "sequence",
+    "permalink",
+    "subtitle",
+    "tags",
+    "status",
+    "remind_at",
+    "review_after",
+    "weight",
+    "writes",
+    "revises",
+    "latest",
+    "views",
+    "filename",
+];
+
 #[allow(dead_code)]
 fn is_false(v: &bool) -> bool {
     *v
@@ -79,44 +188,108 @@ impl Document {
     pub fn parse_file(path: &std::path::Path) -> Result<Document, io::Error> {
         let full_path = path.to_str().unwrap();
         let s = fs::read_to_string(full_path)?;
+        let filename = path.file_name().unwrap().to_str().unwrap();
 
-        let (yaml, content) = frontmatter::parse_and_find_content(&s).unwrap();
-        match yaml {
-            Some(yaml) => {
-                let mut out_str = String::new();
-                {
-                    let mut emitter = YamlEmitter::new(&mut out_str);
-                    emitter.dump(&yaml).unwrap(); // dump the YAML object to a String
+        let mut doc = Document::parse_str(&s, filename)?;
+        if let Ok(meta) = fs::metadata(path) {
+            if let Ok(modified) = meta.modified() {
+                doc.modified = Date::from(modified);
+            }
+            if doc.created == Date::default() {
+                if let Ok(created) = meta.created().or_else(|_| meta.modified()) {
+                    doc.created = Date::from(created);
                 }
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Partial update making the stored copy of `self` into `changed`: the ID and the fields
+    /// that differ, for `PUT documents`, which leaves the fields it doesn't name as they are.
+    /// Either may be a hit missing fields that weren't retrieved, they aren't sent.
+    pub fn changes(&self, changed: &Document) -> serde_json::Value {
+        let stored = |doc: &Document| {
+            let mut doc = doc.clone();
+            doc.serialization_type = SerializationType::Storage;
+            match serde_json::to_value(&doc) {
+                Ok(serde_json::Value::Object(fields)) => fields,
+                _ => serde_json::Map::new(),
+            }
+        };
+        let before = stored(self);
+        let mut changes: serde_json::Map<_, _> = stored(changed)
+            .into_iter()
+            .filter(|(field, value)| field == "id" || before.get(field) != Some(value))
+            .collect();
+        changes.insert("id".to_owned(), serde_json::json!(changed.id));
+        serde_json::Value::Object(changes)
+    }
 
-                let mut doc: Document = match serde_yaml::from_str(&out_str) {
+    /// Parse frontmatter+markdown content as read from the file named `filename`
+    pub fn parse_str(s: &str, filename: &str) -> Result<Document, io::Error> {
+        let (yaml, content) = match split_frontmatter(s) {
+            Some((yaml, content)) => match YamlLoader::load_from_str(yaml) {
+                Ok(docs) if docs.is_empty() => (None, content),
+                Ok(_) => (Some(yaml), content),
+                Err(e) => {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("Error reading yaml {}: {}", filename, e),
+                    ))
+                }
+            },
+            None => (None, s),
+        };
+        match yaml {
+            Some(yaml) => {
+                // Straight from the text: dumped again, quoted strings that look like numbers
+                // such as `"0o0"` would lose their quotes. The opening `---` keeps the line
+                // numbers of errors those of the file.
+                let mut doc: Document = match serde_yaml::from_str(&format!("---\n{}", yaml)) {
                     Ok(d) => d,
                     Err(e) => {
-                        eprintln!("Error reading yaml {}: {:?} {}", full_path, e, out_str);
+                        eprintln!("Error reading yaml {}: {:?} {}", filename, e, yaml);
                         return Err(Error::new(
                             ErrorKind::Other,
-                            format!("Error reading yaml {}: {}", path.display(), e.to_string()),
+                            format!("Error reading yaml {}: {}", filename, e.to_string()),
                         ));
                     }
                 };
-                doc.filename = String::from(path.file_name().unwrap().to_str().unwrap());
+                doc.filename = String::from(filename);
                 doc.body = content.to_string();
-                if doc.id.width() == 0 {
-                    let uuid = UuidB64::new();
-                    doc.id = uuid.to_string();
-                    doc.parentid = uuid.to_string();
+                doc.layout = FrontmatterLayout::parse(s);
+                if doc.id.is_empty() {
+                    // A note named after its zettel ID is known by it, whatever the scheme
+                    doc.id = match zettel::from_filename(filename) {
+                        Some(id) => id.to_owned(),
+                        None => zettel::new_id(),
+                    };
+                    doc.parentid = doc.id.to_owned();
                 }
 
                 Ok(doc)
             }
             None => Err(Error::new(
                 ErrorKind::Other,
-                format!("Failed to process file {}", path.display()),
+                format!("Failed to process file {}", filename),
             )),
         }
     }
 }
 
+/// The frontmatter block at the start of `s` and the content after it. The block ends at the
+/// first line that's just `---`, not at a value ending in `---` like the `frontmatter` crate
+/// has it.
+fn split_frontmatter(s: &str) -> Option<(&str, &str)> {
+    let rest = s.strip_prefix("---\n")?;
+    let end = if rest.starts_with("---\n") {
+        0
+    } else {
+        rest.find("\n---\n")? + 1
+    };
+    Some((&rest[..end], &rest[end + 4..]))
+}
+
 /// Support Deserializing a string into a list of string of length 1
 fn string_or_list_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
@@ -152,24 +325,34 @@ where
 
 impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.serialization_type == SerializationType::Human {
-            write!(f, "{}", self.body)
-        } else {
-            let yaml = serde_yaml::to_string(&self).unwrap();
-            write!(f, "{}---\n{}", yaml, self.body)
+        match self.serialization_type {
+            SerializationType::Human => write!(f, "{}", self.body),
+            SerializationType::Disk => {
+                // Lay the fields out like the file they were read from
+                let fields = match serde_yaml::to_value(&self) {
+                    Ok(serde_yaml::Value::Mapping(m)) => m,
+                    _ => return Err(fmt::Error),
+                };
+                write!(f, "---\n{}---\n{}", self.layout.render(&fields), self.body)
+            }
+            SerializationType::Storage => {
+                let yaml = serde_yaml::to_string(&self).unwrap();
+                write!(f, "{}---\n{}", yaml, self.body)
+            }
         }
     }
 }
 
 impl From<markdown_fm_doc::Document> for Document {
     fn from(item: markdown_fm_doc::Document) -> Self {
-        let uuid = UuidB64::new();
+        let id = zettel::new_id();
         Document {
-            id: uuid.to_string(),
-            parentid: uuid.to_string(),
+            id: id.to_owned(),
+            parentid: id,
             authors: vec![item.author],
             body: item.body,
             date: Date::from_str(&item.date).unwrap(),
+            created: Date::from_str(&item.date).unwrap(),
             writes: 1,
             tags: item.tags,
             title: item.title,
@@ -186,45 +369,90 @@ impl Serialize for Document {
     where
         S: Serializer,
     {
+        // A map rather than a struct, the extra fields have names only known at runtime
         let mut s = match self.serialization_type {
-            SerializationType::Storage => serializer.serialize_struct("Document", 14)?,
-            SerializationType::Disk => serializer.serialize_struct("Document", 12)?,
+            SerializationType::Storage | SerializationType::Disk => {
+                serializer.serialize_map(None)?
+            }
             SerializationType::Human => {
                 // The Display trait implementation above handles displaying just the
                 // document body, don't need to serialize any of the doc metadata
-                return serializer.serialize_struct("Document", 0)?.end();
+                return serializer.serialize_map(Some(0))?.end();
             }
         };
 
-        s.serialize_field("title", &self.title)?;
-        if self.subtitle.width() > 0 {
-            s.serialize_field("subtitle", &self.subtitle)?;
+        s.serialize_entry("title", &self.title)?;
+        if !self.subtitle.is_empty() {
+            s.serialize_entry("subtitle", &self.subtitle)?;
         };
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("date", &self.date)?;
+            s.serialize_entry("date", &self.date)?;
+            s.serialize_entry("created", &self.created)?;
+            s.serialize_entry("modified", &self.modified)?;
+            s.serialize_entry("imported_at", &self.imported_at)?;
         } else {
-            s.serialize_field("date", &format!("{}", &self.date))?;
+            s.serialize_entry("date", &format!("{}", &self.date))?;
+            // modified and imported_at only make sense in the index
+            if self.created != Date::default() {
+                s.serialize_entry("created", &format!("{}", &self.created))?;
+            }
+        }
+        s.serialize_entry("tags", &self.tags)?;
+        if let Some(status) = &self.status {
+            s.serialize_entry("status", status)?;
+        }
+        for (name, date) in &[
+            ("remind_at", &self.remind_at),
+            ("review_after", &self.review_after),
+        ] {
+            match date {
+                Some(date) if self.serialization_type == SerializationType::Storage => {
+                    s.serialize_entry(name, date)?
+                }
+                Some(date) => s.serialize_entry(name, &format!("{}", date))?,
+                None => {}
+            }
         }
-        s.serialize_field("tags", &self.tags)?;
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("filename", &self.filename)?;
+            s.serialize_entry("filename", &self.filename)?;
         };
-        s.serialize_field("authors", &self.authors)?;
-        s.serialize_field("id", &self.id)?;
-        s.serialize_field("parentid", &self.parentid)?;
-        s.serialize_field("weight", &self.weight)?;
-        s.serialize_field("writes", &self.writes)?;
-        if self.background_img.width() > 0 {
-            s.serialize_field("background_img", &self.background_img)?;
+        s.serialize_entry("authors", &self.authors)?;
+        s.serialize_entry("id", &self.id)?;
+        s.serialize_entry("parentid", &self.parentid)?;
+        s.serialize_entry("weight", &self.weight)?;
+        s.serialize_entry("writes", &self.writes)?;
+        if self.serialization_type == SerializationType::Storage {
+            s.serialize_entry("views", &self.views)?;
+        }
+        if !self.revises.is_empty() {
+            s.serialize_entry("revises", &self.revises)?;
+        }
+        if self.serialization_type == SerializationType::Storage && self.latest.is_some() {
+            s.serialize_entry("latest", &self.latest)?;
+        }
+        if !self.background_img.is_empty() {
+            s.serialize_entry("background_img", &self.background_img)?;
         };
         if !self.links.is_empty() {
-            s.serialize_field("links", &self.links)?;
+            s.serialize_entry("links", &self.links)?;
+        };
+        if self.serialization_type == SerializationType::Storage && !self.backlinks.is_empty() {
+            s.serialize_entry("backlinks", &self.backlinks)?;
         };
-        if self.slug.width() > 0 {
-            s.serialize_field("slug", &self.slug)?;
+        if !self.slug.is_empty() {
+            s.serialize_entry("slug", &self.slug)?;
         };
+        if !self.sequence.is_empty() {
+            s.serialize_entry("sequence", &self.sequence)?;
+        };
+        if !self.permalink.is_empty() {
+            s.serialize_entry("permalink", &self.permalink)?;
+        };
+        for (key, value) in &self.extra {
+            s.serialize_entry(key, value)?;
+        }
         if self.serialization_type == SerializationType::Storage {
-            s.serialize_field("body", &self.body)?;
+            s.serialize_entry("body", &self.body)?;
         }
         s.end()
     }
@@ -0,0 +1,245 @@
+use crate::document::{Document, SerializationType};
+use crate::state::State;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Output formats supported by the `export` subcommand
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One frontmatter+markdown file per document
+    Markdown,
+    /// Anki-importable TSV of flashcards
+    Anki,
+    /// OPML outline of the parentid/link hierarchy
+    Opml,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<ExportFormat, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "anki" => Ok(ExportFormat::Anki),
+            "opml" => Ok(ExportFormat::Opml),
+            _ => Err(eyre!("Unknown export format {}", s)),
+        }
+    }
+}
+
+/// Write each document to `dir` as a frontmatter+markdown file named after its `filename`
+pub fn markdown(docs: &[Document], dir: &Path) -> Result<(), Report> {
+    fs::create_dir_all(dir)?;
+    for doc in docs {
+        let mut doc = doc.clone();
+        doc.serialization_type = SerializationType::Disk;
+        fs::write(dir.join(file_name(&doc)), doc.to_string())?;
+    }
+    Ok(())
+}
+
+/// Name of the file a note is written back to: the one it was imported from, else one made up
+/// from its slug or, failing that, its ID
+fn file_name(doc: &Document) -> String {
+    if !doc.filename.is_empty() {
+        doc.filename.to_owned()
+    } else if !doc.slug.is_empty() {
+        format!("{}.md", doc.slug)
+    } else {
+        format!("{}.md", doc.id)
+    }
+}
+
+/// Tag marking a whole note as a single card, title on the front and body on the back
+pub const FLASHCARD_TAG: &str = "flashcard";
+
+/// A question/answer pair destined for Anki
+#[derive(Debug, PartialEq)]
+pub struct Card {
+    /// Stable identity used to remember the card was already exported
+    pub key: String,
+    pub front: String,
+    pub back: String,
+    pub tags: Vec<String>,
+}
+
+/// Pull the flashcards out of a document: every `Q:`/`A:` block in the body, or the entire note
+/// when it's tagged `flashcard` and has no such blocks
+pub fn cards(doc: &Document) -> Vec<Card> {
+    let mut cards = Vec::new();
+    // (question, answer) of the block currently being collected
+    let mut current: Option<(String, Option<String>)> = None;
+
+    let mut flush = |current: &mut Option<(String, Option<String>)>| {
+        if let Some((q, Some(a))) = current.take() {
+            let (q, a) = (q.trim().to_string(), a.trim().to_string());
+            if !q.is_empty() && !a.is_empty() {
+                cards.push(Card {
+                    key: format!("{}:{}", doc.id, q),
+                    front: q,
+                    back: a,
+                    tags: doc.tags.clone(),
+                });
+            }
+        }
+    };
+
+    for line in doc.body.lines() {
+        if let Some(q) = line.strip_prefix("Q:") {
+            flush(&mut current);
+            current = Some((q.to_string(), None));
+        } else if let Some(a) = line.strip_prefix("A:") {
+            if let Some((_, answer)) = current.as_mut() {
+                *answer = Some(a.to_string());
+            }
+        } else if line.starts_with('#') {
+            // A new heading ends the block
+            flush(&mut current);
+        } else if let Some((question, answer)) = current.as_mut() {
+            let part = match answer {
+                Some(a) => a,
+                None => question,
+            };
+            part.push('\n');
+            part.push_str(line);
+        }
+    }
+    flush(&mut current);
+
+    if cards.is_empty() && doc.tags.iter().any(|t| t == FLASHCARD_TAG) {
+        cards.push(Card {
+            key: doc.id.to_owned(),
+            front: doc.title.to_owned(),
+            back: doc.body.trim().to_owned(),
+            tags: doc.tags.clone(),
+        });
+    }
+
+    cards
+}
+
+/// Anki fields are HTML, keep the TSV structure intact by escaping tabs and newlines
+fn anki_field(s: &str) -> String {
+    s.replace('\t', "    ").replace('\n', "<br>")
+}
+
+/// Write the cards not exported before to `anki.tsv` in `dir`, recording them in the state so
+/// the next export only contains new cards. Returns the number of cards written.
+pub fn anki(docs: &[Document], dir: &Path, state: &mut State) -> Result<usize, Report> {
+    fs::create_dir_all(dir)?;
+
+    let mut out = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+    let mut count = 0;
+    for card in docs.iter().flat_map(cards) {
+        if !state.anki_exported.insert(card.key.to_owned()) {
+            continue;
+        }
+        out.push_str(&format!(
+            "{}\t{}\t{}\n",
+            anki_field(&card.front),
+            anki_field(&card.back),
+            card.tags
+                .iter()
+                .map(|t| t.replace(' ', "_"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+        count += 1;
+    }
+
+    fs::write(dir.join("anki.tsv"), out)?;
+    Ok(count)
+}
+
+/// URL a note can be referenced by from other applications
+pub fn note_url(id: &str) -> String {
+    format!("meilizet://note/{}", id)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Render `doc` and, recursively, its children as nested outline elements. Links are listed as
+/// leaf entries so that link cycles can't recurse forever.
+fn opml_outline(
+    doc: &Document,
+    by_id: &HashMap<&str, &Document>,
+    children: &HashMap<&str, Vec<&Document>>,
+    visited: &mut HashSet<String>,
+    depth: usize,
+    out: &mut String,
+) {
+    let indent = "  ".repeat(depth);
+    if !visited.insert(doc.id.to_owned()) {
+        return;
+    }
+
+    out.push_str(&format!(
+        "{}<outline text=\"{}\" type=\"link\" url=\"{}\">\n",
+        indent,
+        xml_escape(&doc.title),
+        xml_escape(&note_url(&doc.id))
+    ));
+    for link in &doc.links {
+        let title = match by_id.get(link.as_str()) {
+            Some(linked) => linked.title.as_str(),
+            None => link.as_str(),
+        };
+        out.push_str(&format!(
+            "{}  <outline text=\"&#8594; {}\" type=\"link\" url=\"{}\"/>\n",
+            indent,
+            xml_escape(title),
+            xml_escape(&note_url(link))
+        ));
+    }
+    if let Some(kids) = children.get(doc.id.as_str()) {
+        for kid in kids {
+            opml_outline(kid, by_id, children, visited, depth + 1, out);
+        }
+    }
+    out.push_str(&format!("{}</outline>\n", indent));
+}
+
+/// Write the parentid hierarchy, with each note's links, to `notes.opml` in `dir`
+pub fn opml(docs: &[Document], dir: &Path) -> Result<(), Report> {
+    fs::create_dir_all(dir)?;
+
+    let by_id: HashMap<&str, &Document> = docs.iter().map(|d| (d.id.as_str(), d)).collect();
+    let mut children: HashMap<&str, Vec<&Document>> = HashMap::new();
+    let mut roots = Vec::new();
+    for doc in docs {
+        if doc.parentid != doc.id && by_id.contains_key(doc.parentid.as_str()) {
+            children.entry(doc.parentid.as_str()).or_default().push(doc);
+        } else {
+            roots.push(doc);
+        }
+    }
+
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>meilizet</title>\n  </head>\n  <body>\n",
+    );
+    let mut visited = HashSet::new();
+    for root in roots {
+        opml_outline(root, &by_id, &children, &mut visited, 2, &mut out);
+    }
+    // Notes whose parents lead round in a cycle are out of reach of every root, each cycle is
+    // outlined from the first of its notes instead
+    for doc in docs {
+        if !visited.contains(&doc.id) {
+            opml_outline(doc, &by_id, &children, &mut visited, 2, &mut out);
+        }
+    }
+    out.push_str("  </body>\n</opml>\n");
+
+    fs::write(dir.join("notes.opml"), out)?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where remote repositories are cloned to, one directory per URL
+pub const CACHE_DIR: &str = "~/.cache/meilizet/git";
+
+/// Run git, in `dir` when given, returning its trimmed output
+fn git(args: &[&str], dir: Option<&Path>) -> Result<String, Report> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    let out = cmd
+        .args(args)
+        .output()
+        .map_err(|e| eyre!("Failed to run git: {}", e))?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+}
+
+/// Local clone of the repository at `url`
+fn clone_dir(url: &str) -> PathBuf {
+    let name: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    PathBuf::from(shellexpand::tilde(CACHE_DIR).as_ref()).join(name)
+}
+
+/// Bring the local clone of `url` up to date, shallow-cloning it the first time, and return
+/// its directory along with the commit checked out
+pub fn update(url: &str) -> Result<(PathBuf, String), Report> {
+    let dir = clone_dir(url);
+    if dir.join(".git").exists() {
+        // Fetching keeps the objects of the commit imported last, so it can still be diffed
+        git(&["fetch", "--depth", "1", "origin"], Some(&dir))?;
+        git(&["reset", "--hard", "FETCH_HEAD"], Some(&dir))?;
+    } else {
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let target = dir.display().to_string();
+        git(&["clone", "--depth", "1", url, &target], None)?;
+    }
+    let head = git(&["rev-parse", "HEAD"], Some(&dir))?;
+    Ok((dir, head))
+}
+
+/// Files added or modified between two commits, `None` when they can't be compared (e.g. the
+/// clone was removed since `from` was imported). Deleted files are left in the index.
+pub fn changed_files(dir: &Path, from: &str, to: &str) -> Option<Vec<PathBuf>> {
+    let out = git(
+        &["diff", "--name-only", "--diff-filter=ACMR", from, to],
+        Some(dir),
+    )
+    .ok()?;
+    Some(out.lines().map(|file| dir.join(file)).collect())
+}
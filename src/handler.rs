@@ -0,0 +1,55 @@
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use percent_encoding::percent_decode_str;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+/// Desktop entry associating `meilizet://` URLs with `handle-url`
+const DESKTOP_FILE: &str = "meilizet-url-handler.desktop";
+
+/// ID of the note a `meilizet://note/<id>` URL points to, see `export::note_url`
+pub fn note_id(url: &str) -> Result<String, Report> {
+    let parsed = Url::parse(url).map_err(|e| eyre!("Invalid URL {}: {}", url, e))?;
+    if parsed.scheme() != "meilizet" || parsed.host_str() != Some("note") {
+        bail!("Not a meilizet://note/<id> URL: {}", url);
+    }
+    let id = parsed.path().trim_matches('/');
+    if id.is_empty() {
+        bail!("No note ID in {}", url);
+    }
+    Ok(percent_decode_str(id).decode_utf8_lossy().into_owned())
+}
+
+/// Register `exe` with the desktop as the handler of `meilizet://` URLs, returning the desktop
+/// entry written. Only freedesktop.org desktops are supported, macOS needs an app bundle.
+pub fn register(exe: &Path) -> Result<PathBuf, Report> {
+    if cfg!(target_os = "macos") {
+        bail!("Registering URL schemes on macOS requires an app bundle declaring CFBundleURLTypes");
+    }
+    let dir = PathBuf::from(shellexpand::tilde("~/.local/share/applications").as_ref());
+    fs::create_dir_all(&dir)?;
+    let entry = dir.join(DESKTOP_FILE);
+    fs::write(
+        &entry,
+        format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=meilizet\n\
+             Exec=\"{}\" handle-url %u\n\
+             Terminal=true\n\
+             NoDisplay=true\n\
+             MimeType=x-scheme-handler/meilizet;\n",
+            exe.display()
+        ),
+    )?;
+    let status = Command::new("xdg-mime")
+        .args(["default", DESKTOP_FILE, "x-scheme-handler/meilizet"])
+        .status()
+        .map_err(|e| eyre!("Failed to run xdg-mime: {}", e))?;
+    if !status.success() {
+        bail!("xdg-mime failed: {}", status);
+    }
+    Ok(entry)
+}
@@ -0,0 +1,228 @@
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Style as hStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans, Text};
+
+/// Theme used when the `preview_theme` config setting is unset
+pub const DEFAULT_THEME: &str = "Solarized (dark)";
+
+/// Highlights notes for the preview: the markdown itself, and fenced code blocks in the
+/// language named after the opening fence
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    /// `theme` is one of syntect's default themes, e.g. `base16-ocean.dark` or `InspiredGitHub`
+    pub fn new(theme: &str) -> Result<Highlighter, Report> {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let theme = match themes.remove(theme) {
+            Some(theme) => theme,
+            None => {
+                return Err(eyre!(
+                    "Unknown preview theme {}, expected one of {}",
+                    theme,
+                    themes.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            }
+        };
+        Ok(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        })
+    }
+
+    /// `text` as styled lines, ready to go in a `Paragraph`
+    pub fn markdown(&self, text: &str) -> Text<'static> {
+        let markdown = self
+            .syntaxes
+            .find_syntax_by_extension("md")
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut outer = HighlightLines::new(markdown, &self.theme);
+        // Highlighter of the fenced code block the line is in, and its fence
+        let mut block: Option<(HighlightLines, String)> = None;
+
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(text) {
+            let trimmed = line.trim_start();
+            let ranges = match &mut block {
+                Some((_, fence)) if trimmed.starts_with(fence.as_str()) => {
+                    block = None;
+                    outer.highlight(line, &self.syntaxes)
+                }
+                Some((inner, _)) => inner.highlight(line, &self.syntaxes),
+                None => {
+                    if let Some(fence) = fence(trimmed) {
+                        let language = trimmed[fence.len()..].split_whitespace().next();
+                        let syntax = self.code_syntax(language.unwrap_or_default());
+                        block = Some((HighlightLines::new(syntax, &self.theme), fence));
+                    }
+                    outer.highlight(line, &self.syntaxes)
+                }
+            };
+            lines.push(Spans::from(
+                ranges
+                    .into_iter()
+                    .map(|(style, s)| span(style, s.trim_end_matches(&['\r', '\n'][..])))
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        Text::from(lines)
+    }
+
+    /// Syntax of a code block tagged `language`, as in ```` ```rust ````, plain text when it's
+    /// not one syntect knows
+    fn code_syntax(&self, language: &str) -> &SyntaxReference {
+        let language = language.trim_start_matches('{').trim_end_matches('}');
+        if language.is_empty() {
+            return self.syntaxes.find_syntax_plain_text();
+        }
+        self.syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+}
+
+/// The fence opening a code block on this line, three or more backticks or tildes
+fn fence(line: &str) -> Option<String> {
+    let c = line.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = line.chars().take_while(|&x| x == c).count();
+    if len >= 3 {
+        Some(std::iter::repeat(c).take(len).collect())
+    } else {
+        None
+    }
+}
+
+/// The foreground and font style of a syntect style. The theme's background is left out so the
+/// preview keeps the terminal's, and selected lines can be told apart.
+fn span(style: hStyle, s: &str) -> Span<'static> {
+    let fg = style.foreground;
+    let mut tui_style = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        tui_style = tui_style.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        tui_style = tui_style.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        tui_style = tui_style.add_modifier(Modifier::UNDERLINED);
+    }
+    Span::styled(printable(s), tui_style)
+}
+
+/// Columns a tab is expanded to
+const TAB_WIDTH: usize = 4;
+
+/// `s` with tabs expanded and other control characters made spaces. The terminal moves the
+/// cursor for those while tui counts them as taking no room, which throws off everything
+/// drawn after them on the line.
+pub fn printable(s: &str) -> String {
+    if !s.contains(char::is_control) {
+        return s.to_owned();
+    }
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\t' => out.push_str(&" ".repeat(TAB_WIDTH)),
+            c if c.is_control() => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Tags Meilisearch puts around the words a search matched, in the `_formatted` copy of a hit
+const MATCH_START: &str = "<em>";
+const MATCH_END: &str = "</em>";
+
+/// Style of the words a search matched
+pub fn match_style() -> Style {
+    Style::default().fg(Color::Black).bg(Color::Yellow)
+}
+
+/// A field as highlighted by Meilisearch, split into spans with the matched words styled apart
+pub fn matches(formatted: &str) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut rest = formatted;
+    while let Some(start) = rest.find(MATCH_START) {
+        let after = &rest[start + MATCH_START.len()..];
+        let end = after.find(MATCH_END).unwrap_or(after.len());
+        if start > 0 {
+            spans.push(Span::raw(printable(&rest[..start])));
+        }
+        spans.push(Span::styled(printable(&after[..end]), match_style()));
+        rest = after.get(end + MATCH_END.len()..).unwrap_or_default();
+    }
+    if !rest.is_empty() {
+        spans.push(Span::raw(printable(rest)));
+    }
+    spans
+}
+
+/// The words marked as matches in a highlighted field, lowercased and without duplicates
+pub fn matched_terms(formatted: &str) -> Vec<String> {
+    let mut terms: Vec<String> = matches(formatted)
+        .into_iter()
+        .filter(|span| span.style == match_style())
+        .map(|span| lowercase(&span.content))
+        .filter(|term| !term.trim().is_empty())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// One lowercase character per character, so positions in the result are the same as in `s`
+fn lowercase(s: &str) -> String {
+    s.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Style every occurrence of `terms` in `text` as a match, ignoring case
+pub fn mark(text: &mut Text<'static>, terms: &[String]) {
+    if terms.is_empty() {
+        return;
+    }
+    let terms: Vec<Vec<char>> = terms.iter().map(|t| t.chars().collect()).collect();
+    for line in text.lines.iter_mut() {
+        let spans = std::mem::take(&mut line.0);
+        for span in spans {
+            let chars: Vec<char> = span.content.chars().collect();
+            let lower: Vec<char> = lowercase(&span.content).chars().collect();
+            let mut plain = 0;
+            let mut i = 0;
+            while i < lower.len() {
+                let found = terms
+                    .iter()
+                    .find(|t| !t.is_empty() && lower[i..].starts_with(t));
+                match found {
+                    Some(term) => {
+                        if plain < i {
+                            let s: String = chars[plain..i].iter().collect();
+                            line.0.push(Span::styled(s, span.style));
+                        }
+                        let s: String = chars[i..i + term.len()].iter().collect();
+                        line.0
+                            .push(Span::styled(s, span.style.patch(match_style())));
+                        i += term.len();
+                        plain = i;
+                    }
+                    None => i += 1,
+                }
+            }
+            if plain == 0 {
+                line.0.push(span);
+            } else if plain < chars.len() {
+                let s: String = chars[plain..].iter().collect();
+                line.0.push(Span::styled(s, span.style));
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::watch;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use glob::Pattern;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name of the ignore file, looked for in the root of the vault
+pub const FILE: &str = ".meilizetignore";
+
+/// One pattern of the ignore file
+pub struct Rule {
+    /// As written in the file
+    pub pattern: String,
+    /// Comment explaining it, from the end of its line or the lines right above it
+    pub reason: Option<String>,
+    glob: Pattern,
+    /// Whether it's matched against whole paths rather than each of their components
+    anchored: bool,
+    /// Whether it only matches directories, so everything in them
+    dir_only: bool,
+    /// Files it ignored so far
+    skipped: AtomicUsize,
+}
+
+/// Files left out of a vault by its ignore file, one glob pattern per line, e.g.
+///
+/// ```text
+/// # Not ready to be shared yet
+/// drafts/
+/// *.private.md  # personal notes
+/// ```
+///
+/// Patterns with a slash in them, or starting with one, are matched against the path from the
+/// root of the vault, the others against each file and directory name in it. A trailing slash
+/// makes a pattern match directories, and so everything in them. Comment lines give the reason
+/// for the patterns that follow, up to the next blank line.
+#[derive(Default)]
+pub struct Ignore {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The ignore file in the root of the vault the glob pattern `source` matches files of,
+    /// which ignores nothing when there's no such file
+    pub fn for_source(source: &str) -> Result<Ignore, Report> {
+        let source = shellexpand::tilde(source);
+        let root = watch::root(&source);
+        let path = root.join(FILE);
+        if !path.exists() {
+            return Ok(Ignore::default());
+        }
+        Ignore::parse(root, &fs::read_to_string(&path)?)
+            .map_err(|e| eyre!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    fn parse(root: PathBuf, s: &str) -> Result<Ignore, Report> {
+        let mut rules = Vec::new();
+        let mut comments: Vec<&str> = Vec::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                comments.clear();
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                comments.push(comment.trim());
+                continue;
+            }
+            let (pattern, reason) = match line.find(" #") {
+                Some(at) => (line[..at].trim(), Some(line[at + 2..].trim().to_owned())),
+                None if comments.is_empty() => (line, None),
+                None => (line, Some(comments.join(" "))),
+            };
+            let dir_only = pattern.ends_with('/');
+            let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+            let anchored = pattern.starts_with('/') || trimmed.contains('/');
+            let glob = if anchored && dir_only {
+                format!("{}/**", trimmed)
+            } else {
+                trimmed.to_owned()
+            };
+            rules.push(Rule {
+                pattern: pattern.to_owned(),
+                reason,
+                glob: Pattern::new(&glob).map_err(|e| eyre!("bad pattern {}: {}", pattern, e))?,
+                anchored,
+                dir_only,
+                skipped: AtomicUsize::new(0),
+            });
+        }
+        Ok(Ignore { root, rules })
+    }
+
+    /// The first rule ignoring `path`, counting it as skipped by that rule
+    pub fn matching(&self, path: &Path) -> Option<&Rule> {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            // Relative patterns without a directory match paths without the leading `./`
+            Err(_) if self.root == Path::new(".") => path,
+            Err(_) => return None,
+        };
+        let rule = self.rules.iter().find(|rule| {
+            if rule.anchored {
+                return rule.glob.matches_path(relative);
+            }
+            let mut names: Vec<_> = relative.components().collect();
+            if rule.dir_only {
+                // The file's own name
+                names.pop();
+            }
+            names
+                .iter()
+                .any(|name| rule.glob.matches(&name.as_os_str().to_string_lossy()))
+        })?;
+        rule.skipped.fetch_add(1, Ordering::Relaxed);
+        Some(rule)
+    }
+
+    /// A line for each rule that skipped any files, saying how many and why
+    pub fn summary(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                let skipped = rule.skipped.load(Ordering::Relaxed);
+                if skipped == 0 {
+                    return None;
+                }
+                let mut line = format!(
+                    "Skipped {} file{} due to pattern '{}'",
+                    skipped,
+                    if skipped == 1 { "" } else { "s" },
+                    rule.pattern
+                );
+                if let Some(reason) = &rule.reason {
+                    line.push_str(&format!(": {}", reason));
+                }
+                Some(line)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,206 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use pulldown_cmark::{Event, Parser, Tag};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tui::layout::Rect;
+
+/// Bytes of base64 sent per kitty graphics escape, the most the protocol allows
+const KITTY_CHUNK: usize = 4096;
+
+/// Ways of drawing images in a terminal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// Graphics protocol of kitty, also spoken by Ghostty and WezTerm. Only PNG is sent.
+    Kitty,
+    /// Inline images of iTerm2, also spoken by WezTerm
+    ITerm,
+    /// Sixel graphics, encoded by `img2sixel` from libsixel
+    Sixel,
+}
+
+impl Protocol {
+    /// Protocol the terminal understands, going by the environment as terminals can't be
+    /// queried while the interface is reading the keyboard. Set `MEILIZET_IMAGES` to `kitty`,
+    /// `iterm`, `sixel` or `none` to override, e.g. inside tmux with passthrough enabled.
+    pub fn detect() -> Option<Protocol> {
+        let var = |name| env::var(name).unwrap_or_default();
+        match var("MEILIZET_IMAGES").as_str() {
+            "kitty" => return Some(Protocol::Kitty),
+            "iterm" => return Some(Protocol::ITerm),
+            "sixel" => return Some(Protocol::Sixel),
+            "none" => return None,
+            _ => {}
+        }
+        let term = var("TERM");
+        // Multiplexers swallow the escapes unless told otherwise
+        if env::var_os("TMUX").is_some() || term.starts_with("screen") {
+            return None;
+        }
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+        {
+            Some(Protocol::Kitty)
+        } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Some(Protocol::ITerm)
+        } else if (term.starts_with("foot") || term.starts_with("mlterm"))
+            && Command::new("img2sixel").arg("--version").output().is_ok()
+        {
+            Some(Protocol::Sixel)
+        } else {
+            None
+        }
+    }
+
+    /// Draw the image at `path` in `area`, keeping its aspect ratio
+    pub fn draw(self, out: &mut impl Write, path: &Path, area: Rect) -> Result<(), Report> {
+        let data = fs::read(path).map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        let (cols, rows) = fit(png_size(&data), area);
+        write!(out, "{}", termion::cursor::Goto(area.x + 1, area.y + 1))?;
+        match self {
+            Protocol::Kitty => {
+                if png_size(&data).is_none() {
+                    bail!("Only PNG images can be shown in kitty");
+                }
+                let encoded = STANDARD.encode(&data);
+                let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    // q=2 keeps the terminal from answering on stdin, where it'd be read as keys
+                    if i == 0 {
+                        write!(out, "\x1b_Ga=T,f=100,q=2,c={},r={},m={};", cols, rows, more)?;
+                    } else {
+                        write!(out, "\x1b_Gm={};", more)?;
+                    }
+                    out.write_all(chunk)?;
+                    write!(out, "\x1b\\")?;
+                }
+            }
+            Protocol::ITerm => write!(
+                out,
+                "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                data.len(),
+                cols,
+                rows,
+                STANDARD.encode(&data)
+            )?,
+            Protocol::Sixel => {
+                let (_, cell_height) = cell_size();
+                let sixel = Command::new("img2sixel")
+                    .arg(format!("--height={}", rows as u32 * cell_height as u32))
+                    .arg(path)
+                    .output()?;
+                if !sixel.status.success() {
+                    bail!("img2sixel failed: {}", sixel.status);
+                }
+                out.write_all(&sixel.stdout)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Remove images drawn earlier. Images drawn in cells go once the cells are redrawn, kitty
+    /// keeps them on a layer of their own.
+    pub fn clear(self, out: &mut impl Write) -> Result<(), Report> {
+        if self == Protocol::Kitty {
+            write!(out, "\x1b_Ga=d,q=2\x1b\\")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Images a note refers to: the paths or URLs in its `attachments` frontmatter field, then the
+/// images embedded in its body
+pub fn references(note: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    let mut body = note;
+    if let Some(rest) = note.strip_prefix("---\n") {
+        if let Some(end) = rest.find("\n---\n") {
+            body = &rest[end + 5..];
+            if let Ok(serde_yaml::Value::Mapping(frontmatter)) = serde_yaml::from_str(&rest[..end])
+            {
+                let attachments = frontmatter.get(&serde_yaml::Value::from("attachments"));
+                if let Some(serde_yaml::Value::Sequence(attachments)) = attachments {
+                    refs.extend(
+                        attachments
+                            .iter()
+                            .filter_map(|a| a.as_str())
+                            .map(String::from),
+                    );
+                }
+            }
+        }
+    }
+    for event in Parser::new(body) {
+        if let Event::Start(Tag::Image(_, url, _)) = event {
+            refs.push(url.to_string());
+        }
+    }
+    refs.dedup();
+    refs
+}
+
+/// Local file an image reference points to, relative ones being resolved against `base`. Only
+/// image files that exist are returned, remote images aren't fetched.
+pub fn resolve(reference: &str, base: &Path) -> Option<PathBuf> {
+    if reference.contains("://") {
+        return None;
+    }
+    let path = PathBuf::from(shellexpand::tilde(reference).as_ref());
+    let path = if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    };
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let image = matches!(
+        extension.as_str(),
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp"
+    );
+    Some(path).filter(|p| image && p.is_file())
+}
+
+/// Width and height of a PNG image, from its header
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || &data[..8] != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+    let be = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+    Some((be(&data[16..20]), be(&data[20..24])))
+}
+
+/// Pixel width and height of a terminal cell, guessed when the terminal doesn't say
+fn cell_size() -> (u16, u16) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 && size.ws_xpixel > 0 && size.ws_ypixel > 0 {
+        (size.ws_xpixel / size.ws_col, size.ws_ypixel / size.ws_row)
+    } else {
+        (8, 16)
+    }
+}
+
+/// Columns and rows an image of `size` pixels takes up scaled to fit `area`, the whole area
+/// when the size isn't known
+fn fit(size: Option<(u32, u32)>, area: Rect) -> (u16, u16) {
+    let (width, height) = match size {
+        Some((w, h)) if w > 0 && h > 0 => (w as f64, h as f64),
+        _ => return (area.width, area.height),
+    };
+    let (cell_width, cell_height) = cell_size();
+    let scale = (area.width as f64 * cell_width as f64 / width)
+        .min(area.height as f64 * cell_height as f64 / height);
+    let cols = (width * scale / cell_width as f64).round() as u16;
+    let rows = (height * scale / cell_height as f64).round() as u16;
+    (cols.clamp(1, area.width), rows.clamp(1, area.height))
+}
@@ -0,0 +1,230 @@
+use crate::document::Document;
+use crate::output::Table;
+use color_eyre::Report;
+use flate2::{write::GzEncoder, Compression};
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Parsed documents allowed to queue up between the parser and the uploader
+pub const QUEUE_DEPTH: usize = 64;
+
+/// Request body for a batch of documents, encoded one document at a time as they arrive so a
+/// batch is never held in memory as documents and text at once
+pub struct Batch {
+    body: Body,
+    /// Uncompressed size of the body so far
+    pub len: usize,
+    /// Number of documents in the batch
+    pub count: usize,
+    /// Source files of the documents, for checkpointing
+    pub files: Vec<String>,
+    /// IDs of the documents, for the import log
+    pub ids: Vec<String>,
+}
+
+enum Body {
+    Plain(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+impl Batch {
+    pub fn new(compress: bool) -> Self {
+        let body = if compress {
+            Body::Gzip(GzEncoder::new(Vec::new(), Compression::default()))
+        } else {
+            Body::Plain(Vec::new())
+        };
+        Batch {
+            body,
+            len: 0,
+            count: 0,
+            files: Vec::new(),
+            ids: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), Report> {
+        match &mut self.body {
+            Body::Plain(v) => v.extend_from_slice(buf),
+            Body::Gzip(gz) => gz.write_all(buf)?,
+        }
+        self.len += buf.len();
+        Ok(())
+    }
+
+    /// Append a document parsed from `file` to the JSON array
+    pub fn push(&mut self, file: String, doc: &Document) -> Result<(), Report> {
+        let json = serde_json::to_vec(doc)?;
+        self.write(if self.count == 0 { b"[" } else { b"," })?;
+        self.write(&json)?;
+        self.count += 1;
+        self.files.push(file);
+        self.ids.push(doc.id.to_owned());
+        Ok(())
+    }
+
+    /// Close the JSON array and return the finished body
+    pub fn finish(mut self) -> Result<Vec<u8>, Report> {
+        if self.count == 0 {
+            self.write(b"[")?;
+        }
+        self.write(b"]")?;
+        Ok(match self.body {
+            Body::Plain(v) => v,
+            Body::Gzip(gz) => gz.finish()?,
+        })
+    }
+}
+
+/// Where an import spent its time, to tell whether the local parsing, the network or
+/// Meilisearch itself is the bottleneck
+#[derive(Debug)]
+pub struct ImportReport {
+    started: Instant,
+    /// Documents accepted by the server
+    pub documents: usize,
+    /// Files that couldn't be parsed or documents the server rejected
+    pub failed: usize,
+    /// Files left out because a previous run already imported them
+    pub skipped: usize,
+    /// Reading and parsing the source files, summed over the parsing threads
+    pub parsing: Duration,
+    /// Encoding documents into request bodies
+    pub serializing: Duration,
+    /// Sending requests and reading the responses
+    pub network: Duration,
+    /// Waiting for Meilisearch to process the queued tasks
+    pub tasks: Duration,
+    /// Whether ctrl-c stopped the import before every file was read
+    pub interrupted: bool,
+    /// IDs of the documents accepted by the server
+    pub ids: Vec<String>,
+    /// Tasks the documents were queued as
+    pub tasks_queued: Vec<u64>,
+    /// What went wrong with the files and batches that failed
+    pub errors: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn new() -> Self {
+        ImportReport {
+            started: Instant::now(),
+            documents: 0,
+            failed: 0,
+            skipped: 0,
+            parsing: Duration::default(),
+            serializing: Duration::default(),
+            network: Duration::default(),
+            tasks: Duration::default(),
+            interrupted: false,
+            ids: Vec::new(),
+            tasks_queued: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Default for ImportReport {
+    fn default() -> Self {
+        ImportReport::new()
+    }
+}
+
+/// Running count of an import's progress on stderr, redrawn at most ten times a second and
+/// only when stderr is a terminal
+pub struct Progress {
+    enabled: bool,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress {
+            enabled: termion::is_tty(&io::stderr()),
+            drawn: None,
+        }
+    }
+
+    pub fn update(&mut self, read: usize, sent: usize) {
+        if !self.enabled
+            || self
+                .drawn
+                .is_some_and(|t| t.elapsed() < Duration::from_millis(100))
+        {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        eprint!(
+            "\r{}{} files read, {} documents sent",
+            termion::clear::CurrentLine,
+            read,
+            sent
+        );
+    }
+
+    /// Clear the line, for the report to take its place
+    pub fn finish(&self) {
+        if self.enabled && self.drawn.is_some() {
+            eprint!("\r{}", termion::clear::CurrentLine);
+        }
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::new()
+    }
+}
+
+/// Run `f`, adding the time it took to `total`
+pub fn timed<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let ret = f();
+    *total += start.elapsed();
+    ret
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            self.documents as f64 / elapsed
+        } else {
+            0.0
+        };
+        writeln!(
+            f,
+            "Imported {} documents ({} failed, {} skipped) in {:.2}s, {:.1} docs/sec",
+            self.documents, self.failed, self.skipped, elapsed, rate
+        )?;
+        let mut table = Table::new(&["STAGE", "SECONDS", "SHARE"]);
+        for (stage, spent) in &[
+            ("parsing", self.parsing),
+            ("serializing", self.serializing),
+            ("network", self.network),
+            ("waiting on tasks", self.tasks),
+        ] {
+            let spent = spent.as_secs_f64();
+            let share = if elapsed > 0.0 {
+                100.0 * spent / elapsed
+            } else {
+                0.0
+            };
+            table.row(vec![
+                stage.to_string(),
+                format!("{:>7.2}", spent),
+                format!("{:>5.1}%", share),
+            ]);
+        }
+        write!(f, "{}", table)?;
+        if self.interrupted {
+            writeln!(
+                f,
+                "Interrupted, the documents sent so far are in the index. Run the same command \
+                 with --resume to import the rest."
+            )?;
+        }
+        Ok(())
+    }
+}
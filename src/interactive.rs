@@ -1,31 +1,50 @@
-use crate::{api, document};
-use ansi_to_tui::ansi_to_text;
+use crate::api::{Filter, Rule, Transport};
+use crate::cache::{self, SearchCache};
+use crate::clipboard;
+use crate::date::Date;
+use crate::highlight::{self, Highlighter};
+use crate::image::{self, Protocol};
+use crate::keys::{self, Keys};
+use crate::prefetch::Prefetcher;
+use crate::reload::{ConfigWatcher, Reload};
+use crate::searcher::{self, Searcher};
+use crate::textinput::{KillRing, TextInput};
+use crate::trace::Trace;
+use crate::{api, document, query, reminder, revision, sequence, status, undo};
+use chrono::{Datelike, Duration, Local, NaiveDate};
 use color_eyre::Report;
 use eyre::bail;
-use reqwest::header::CONTENT_TYPE;
+use once_cell::sync::OnceCell;
+use pest::Parser;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+use signal_hook::iterator::Signals;
+use std::collections::HashMap;
 use std::io::{stdout, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style as hStyle, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use std::sync::Arc;
+use std::time::Instant;
 use tempfile::Builder;
 use termion::{event::Key, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{
     backend::TermionBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
+use unicode_width::UnicodeWidthStr;
 use url::Url;
 
 /// TerminalApp holds the state of the application
 pub(crate) struct TerminalApp {
     /// Current value of the query_input box
-    pub(crate) query_input: String,
+    pub(crate) query_input: TextInput,
     /// Current value of the filter_input box
-    pub(crate) filter_input: String,
+    pub(crate) filter_input: TextInput,
+    /// Text killed in any of the inputs, for yanking back
+    kill_ring: KillRing,
     /// Preview window
     pub(crate) preview: String,
     /// Query Matches
@@ -34,12 +53,89 @@ pub(crate) struct TerminalApp {
     pub(crate) selected_state: ListState,
     /// Display error messages
     pub(crate) error: String,
+    /// Why the filter input isn't a filter, while it isn't one
+    filter_error: String,
     /// Display the serialized payload to send to the server
     pub(crate) debug: String,
+    /// Filterable attributes of the index, for completing field names in the filter input
+    pub(crate) fields: Vec<String>,
+    /// Every tag in the index, fetched once for completing tag values in the filter input
+    pub(crate) tags: Vec<String>,
+    /// Tags of the matches and how many of them have each, most common first, for the sidebar
+    tag_counts: Vec<(String, u32)>,
+    /// Keep track of which tag in the sidebar is selected
+    tag_state: ListState,
+    /// Tags offered in the completion popup, which is shown while this isn't empty
+    pub(crate) completions: Vec<String>,
+    /// Keep track of which completion is selected
+    pub(crate) completion_state: ListState,
+    /// Date range picker popup, when open
+    date_picker: Option<DatePicker>,
+    /// Background fetching of the documents around the selection, one per index
+    prefetchers: Vec<Prefetcher>,
+    /// Search without document bodies, fetching the body of the selected document on demand
+    lazy: bool,
+    /// Languages searches are tokenized as, see `SearchRequest::locales`
+    locales: Vec<String>,
+    /// Label and search URL of every index that can be switched to
+    indexes: Vec<(String, Url)>,
+    /// Which of `indexes` is being searched
+    index: usize,
+    /// Search every index at once instead of just the current one
+    search_all: bool,
+    /// Which of `indexes` each of the matches came from
+    match_indexes: Vec<usize>,
+    /// Title line of each match as drawn in the list, built the first time it's on screen
+    match_titles: Vec<Option<Spans<'static>>>,
+    /// Position of the first match on screen in the list
+    list_offset: usize,
+    /// When the inputs last changed, while waiting for typing to pause before searching
+    pending_search: Option<Instant>,
+    /// `search_key` of the last search started
+    last_search: String,
+    /// Page of results shown, `RESULTS_PAGE` hits per index each
+    page: u32,
+    /// Hits of the last search over every page, summed over the indexes searched
+    total_hits: u32,
+    /// Whether `total_hits` is exact rather than Meilisearch's estimate
+    exhaustive: bool,
+    /// Order the matches by folgezettel sequence instead of relevance
+    by_sequence: bool,
+    /// Include drafts in the results, which are otherwise left out when the index can filter
+    /// on `status`
+    show_drafts: bool,
     // TODO Add fields for sort expression
-    inp_idx: usize,
-    // Length here should stay in sync with the number of editable areas
-    inp_widths: [i32; 2],
+    /// Pane receiving the keyboard input
+    focus: Focus,
+    /// Lines of the preview scrolled past
+    preview_scroll: u16,
+    /// Line of the preview under the cursor while it has the focus
+    preview_cursor: usize,
+    /// Other end of the lines selected in the preview, from the line the cursor was on when
+    /// visual mode started
+    selection_anchor: Option<usize>,
+    /// Rows of the preview that fit in its pane, as of the last draw
+    preview_height: u16,
+    /// Outcome of the last copy, shown in the preview title
+    preview_status: String,
+    /// How images are drawn in this terminal, placeholders are shown instead when it can't
+    image_protocol: Option<Protocol>,
+    /// Directory relative image references are resolved against
+    attachments_dir: PathBuf,
+    /// Images the previewed note refers to
+    images: Vec<String>,
+    /// Which of `images` is shown
+    image: usize,
+    /// Image file to draw and where, as laid out by the last draw
+    image_area: Option<(PathBuf, Rect)>,
+    /// Image currently on the screen and where
+    shown_image: Option<(PathBuf, Rect)>,
+    /// Which of the selected document's links, then backlinks, is highlighted to be followed
+    link: Option<usize>,
+    /// Index and ID of the documents links were followed from, most recent last
+    link_history: Vec<(usize, String)>,
+    /// Where the changes saved from the interface are recorded, like those of the commands
+    recorder: Option<undo::Recorder>,
 }
 
 impl TerminalApp {
@@ -53,10 +149,489 @@ impl TerminalApp {
         }
     }
 
+    /// Prefetcher of the index the match at `i` came from
+    fn prefetcher(&self, i: usize) -> Option<&Prefetcher> {
+        self.match_indexes
+            .get(i)
+            .and_then(|&index| self.prefetchers.get(index))
+    }
+
     pub fn get_selected_contents(&mut self) -> String {
+        let (i, selected) = match self.selected_state.selected() {
+            Some(i) if i < self.matches.len() => (i, &self.matches[i]),
+            _ => return String::from(""),
+        };
+        // Hits may come without a body, use the full document if it was prefetched
+        if let (true, Some(prefetcher)) = (selected.body.is_empty(), self.prefetcher(i)) {
+            match prefetcher.get(&selected.id) {
+                Some(mut doc) => {
+                    doc.serialization_type = selected.serialization_type.clone();
+                    return doc.to_string();
+                }
+                None if self.lazy => return String::from("Loading…"),
+                None => {}
+            }
+        }
+        selected.to_string()
+    }
+
+    /// Fields of the selected document for the metadata pane, one line each, leaving out the
+    /// empty ones
+    fn selected_metadata(&self) -> Vec<Spans<'static>> {
+        let doc = match self
+            .selected_state
+            .selected()
+            .and_then(|i| self.matches.get(i))
+        {
+            Some(doc) => doc,
+            None => return Vec::new(),
+        };
+        // The highlighted link stands out, counting the links before the backlinks
+        let link_spans = |ids: &[String], first: usize| -> Vec<Span<'static>> {
+            let mut spans = Vec::new();
+            for (n, id) in ids.iter().enumerate() {
+                if n > 0 {
+                    spans.push(Span::raw(", "));
+                }
+                spans.push(if self.link == Some(first + n) {
+                    Span::styled(
+                        id.to_owned(),
+                        Style::default().add_modifier(Modifier::REVERSED),
+                    )
+                } else {
+                    Span::raw(id.to_owned())
+                });
+            }
+            spans
+        };
+        let mut fields = vec![
+            ("Tags", vec![Span::raw(doc.tags.join(", "))]),
+            ("Date", vec![Span::raw(doc.date.to_string())]),
+            ("Authors", vec![Span::raw(doc.authors.join(", "))]),
+            ("Weight", vec![Span::raw(doc.weight.to_string())]),
+            ("Views", vec![Span::raw(doc.views.to_string())]),
+            ("Links", link_spans(&doc.links, 0)),
+            ("Backlinks", link_spans(&doc.backlinks, doc.links.len())),
+            ("Filename", vec![Span::raw(doc.filename.to_owned())]),
+            ("ID", vec![Span::raw(doc.id.to_owned())]),
+        ];
+        fields.retain(|(_, value)| value.iter().any(|span| !span.content.is_empty()));
+        fields
+            .into_iter()
+            .map(|(label, mut value)| {
+                value.insert(
+                    0,
+                    Span::styled(
+                        format!("{:<10}", label),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                );
+                Spans::from(value)
+            })
+            .collect()
+    }
+
+    /// Position in the matches and the whole of the selected document, fetching its body if
+    /// need be
+    fn selected_document(&self) -> Option<(usize, document::Document)> {
+        let i = self.selected_state.selected()?;
+        let selected = self.matches.get(i)?;
+        if let (true, Some(prefetcher)) = (selected.body.is_empty(), self.prefetcher(i)) {
+            if let Some(mut doc) = prefetcher.fetch(&selected.id) {
+                doc.serialization_type = selected.serialization_type.clone();
+                return Some((i, doc));
+            }
+        }
+        Some((i, selected.clone()))
+    }
+
+    /// Replace the match at `i` with a new revision of `original`, as edited into `contents`,
+    /// and send both to the index they came from, the original marked as superseded. The notes
+    /// linking to the original, or linked from it, are pointed at the new revision. Returns
+    /// whether there was any change to send.
+    fn save_edited(
+        &mut self,
+        cache: &mut SearchCache,
+        client: &dyn Transport,
+        i: usize,
+        original: &document::Document,
+        contents: &str,
+    ) -> Result<bool, Report> {
+        let mut unchanged = original.clone();
+        unchanged.serialization_type = document::SerializationType::Disk;
+        if contents == unchanged.to_string() {
+            return Ok(false);
+        }
+        let edited = document::Document::parse_str(contents, &original.filename)?;
+        let mut doc = revision::revise(original, edited);
+        // Fields kept out of the file are carried over from the index
+        doc.filename = original.filename.to_owned();
+        doc.imported_at = original.imported_at.clone();
+        doc.views = original.views;
+        doc.backlinks = original.backlinks.clone();
+        doc.modified = Date::now();
+        doc.serialization_type = document::SerializationType::Storage;
+        let revision_id = doc.id.to_owned();
+        // The new revision goes first, failing after it leaves two latest revisions rather
+        // than none
+        self.store(cache, client, i, doc.clone())?;
+
+        let mut original = original.clone();
+        original.serialization_type = document::SerializationType::Storage;
+        let mut superseded = original.clone();
+        superseded.latest = Some(false);
+        let mut changes = vec![original.changes(&superseded)];
+        let mut before = vec![original.clone()];
+        let mut after = vec![superseded, doc];
+        let mut referrers: Vec<&String> =
+            original.links.iter().chain(&original.backlinks).collect();
+        referrers.sort();
+        referrers.dedup();
+        let (mut referrers_before, mut referrers_after) = (Vec::new(), Vec::new());
+        if let Some(prefetcher) = self.prefetcher(i) {
+            for id in referrers {
+                let before = match prefetcher.fetch(id) {
+                    Some(before) => before,
+                    None => continue,
+                };
+                let mut repointed = before.clone();
+                if revision::repoint(&mut repointed, &original.id, &revision_id) {
+                    changes.push(before.changes(&repointed));
+                    prefetcher.insert(repointed.clone());
+                    referrers_before.push(before);
+                    referrers_after.push(repointed);
+                }
+            }
+        }
+        for m in self.matches.iter_mut() {
+            revision::repoint(m, &original.id, &revision_id);
+        }
+        let (_, search_uri) = &self.indexes[self.match_indexes[i]];
+        client.put(
+            &search_uri.join("documents")?,
+            serde_json::to_string(&changes)?,
+        )?;
+        before.append(&mut referrers_before);
+        after.append(&mut referrers_after);
+        self.record("edit", i, before, vec![revision_id], after);
+        Ok(true)
+    }
+
+    /// Apply `change` to the selected document and send just the fields it changed to its
+    /// index, returning the document as changed. The selection may be a hit without its body
+    /// or extra fields, sending all of it would wipe them.
+    fn change_selected(
+        &mut self,
+        cache: &mut SearchCache,
+        client: &dyn Transport,
+        kind: &str,
+        change: impl FnOnce(&mut document::Document),
+    ) -> Result<Option<document::Document>, Report> {
+        let (i, mut before) = match self.selected_document() {
+            Some(selected) => selected,
+            None => return Ok(None),
+        };
+        before.serialization_type = document::SerializationType::Storage;
+        let mut doc = before.clone();
+        change(&mut doc);
+        let (_, search_uri) = &self.indexes[self.match_indexes[i]];
+        client.put(
+            &search_uri.join("documents")?,
+            serde_json::to_string(&[before.changes(&doc)])?,
+        )?;
+        self.replace_match(cache, i, doc.clone());
+        self.record(kind, i, vec![before], Vec::new(), vec![doc.clone()]);
+        Ok(Some(doc))
+    }
+
+    /// Send `doc` to the index the match at `i` came from, and have it replace the match
+    fn store(
+        &mut self,
+        cache: &mut SearchCache,
+        client: &dyn Transport,
+        i: usize,
+        doc: document::Document,
+    ) -> Result<(), Report> {
+        let (_, search_uri) = &self.indexes[self.match_indexes[i]];
+        client.post(
+            &search_uri.join("documents")?,
+            serde_json::to_string(&[&doc])?,
+        )?;
+        self.replace_match(cache, i, doc);
+        Ok(())
+    }
+
+    /// Log a change just made to the index the match at `i` came from and tell the webhooks,
+    /// see `undo::Recorder`. The change is made either way, so failing to log it is only
+    /// reported.
+    fn record(
+        &mut self,
+        kind: &str,
+        i: usize,
+        before: Vec<document::Document>,
+        created: Vec<String>,
+        after: Vec<document::Document>,
+    ) {
+        let recorder = match &self.recorder {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let (_, search_uri) = &self.indexes[self.match_indexes[i]];
+        // Search URLs end in `indexes/:uid/search`
+        let index = search_uri
+            .path_segments()
+            .and_then(|mut segments| segments.nth_back(1))
+            .unwrap_or_default();
+        if let Err(e) = recorder.record(kind, index, before, created, after) {
+            self.error = format!("Saved, but it can't be undone: {}", e);
+        }
+    }
+
+    /// Have `doc`, as just sent to the index, replace the match at `i`
+    fn replace_match(&mut self, cache: &mut SearchCache, i: usize, mut doc: document::Document) {
+        // Searches made before the change would bring back the old version
+        cache.clear();
+        // A hit without its body isn't kept in place of the whole document, it's fetched again
+        if let Some(prefetcher) = self.prefetcher(i) {
+            if !doc.body.is_empty() || prefetcher.get(&doc.id).is_some() {
+                prefetcher.insert(doc.clone());
+            }
+        }
+        doc.serialization_type = document::SerializationType::Human;
+        self.matches[i] = doc;
+        self.match_titles[i] = None;
+    }
+
+    /// Line of the list showing the match at `i`
+    fn match_title(&mut self, i: usize) -> Spans<'static> {
+        if let Some(title) = &self.match_titles[i] {
+            return title.clone();
+        }
+        let m = &self.matches[i];
+        let mut title = match &m.formatted {
+            Some(formatted) if !formatted.title.is_empty() => highlight::matches(&formatted.title),
+            _ => vec![Span::raw(highlight::printable(&m.title))],
+        };
+        if self.search_all {
+            let label = &self.indexes[self.match_indexes[i]].0;
+            title.insert(0, Span::raw(format!("[{}] ", label)));
+        }
+        let title = Spans::from(title);
+        self.match_titles[i] = Some(title.clone());
+        title
+    }
+
+    /// Positions of the matches that fit in a list `height` rows tall, scrolled as little as
+    /// possible from where it was to keep the selected match in view
+    fn visible_matches(&mut self, height: usize) -> Range<usize> {
+        let height = height.max(1);
+        if let Some(selected) = self.selected_state.selected() {
+            if selected < self.list_offset {
+                self.list_offset = selected;
+            } else if selected >= self.list_offset + height {
+                self.list_offset = selected + 1 - height;
+            }
+        }
+        let len = self.matches.len();
+        self.list_offset = self.list_offset.min(len.saturating_sub(height));
+        self.list_offset..(self.list_offset + height).min(len)
+    }
+
+    /// Whether the selected document is shown without its body, pending a prefetch
+    fn awaiting_prefetch(&self) -> bool {
         match self.selected_state.selected() {
-            Some(i) => self.matches[i].to_string(),
-            None => String::from(""),
+            Some(i) => self.lazy && self.matches.get(i).is_some_and(|m| m.body.is_empty()),
+            None => false,
+        }
+    }
+
+    /// Queue the documents next to the selection that lack a body, and the documents the
+    /// selection links to, for prefetching
+    fn prefetch_neighbors(&self) {
+        let (prefetcher, i) = match self.selected_state.selected() {
+            Some(i) if i < self.matches.len() => match self.prefetcher(i) {
+                Some(p) => (p, i),
+                None => return,
+            },
+            _ => return,
+        };
+        let lo = i.saturating_sub(PREFETCH_NEIGHBORS);
+        let hi = (i + PREFETCH_NEIGHBORS + 1).min(self.matches.len());
+        // Neighbors may be from other indexes when searching all of them
+        for j in (lo..hi).filter(|&j| self.matches[j].body.is_empty()) {
+            if let Some(p) = self.prefetcher(j) {
+                p.request(std::iter::once(self.matches[j].id.to_owned()));
+            }
+        }
+        prefetcher.request(self.matches[i].links.iter().cloned());
+    }
+
+    /// Preview the selected document from its first line
+    fn show_selected(&mut self) {
+        self.preview = self.get_selected_contents();
+        self.preview_scroll = 0;
+        self.preview_cursor = 0;
+        self.selection_anchor = None;
+        self.preview_status.clear();
+        self.images = image::references(&self.preview);
+        self.image = 0;
+        self.link = None;
+    }
+
+    /// IDs of the documents the selected one links to, then of the ones linking to it
+    fn selected_links(&self) -> Vec<String> {
+        match self
+            .selected_state
+            .selected()
+            .and_then(|i| self.matches.get(i))
+        {
+            Some(doc) => doc.links.iter().chain(&doc.backlinks).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Highlight the link `delta` places after the highlighted one, wrapping around
+    fn move_link(&mut self, delta: isize) {
+        let len = self.selected_links().len() as isize;
+        if len == 0 {
+            self.preview_status = String::from("No links");
+            return;
+        }
+        let at = match self.link {
+            Some(at) => (at as isize + delta).rem_euclid(len),
+            None if delta < 0 => len - 1,
+            None => 0,
+        };
+        self.link = Some(at as usize);
+        self.preview_status.clear();
+    }
+
+    /// Select the document the highlighted link points at, remembering where it was followed
+    /// from
+    fn follow_link(&mut self) {
+        let (i, link) = match (self.selected_state.selected(), self.link) {
+            (Some(i), Some(link)) => (i, link),
+            _ => return,
+        };
+        let id = match self.selected_links().into_iter().nth(link) {
+            Some(id) => id,
+            None => return,
+        };
+        let (index, from) = (self.match_indexes[i], self.matches[i].id.to_owned());
+        if self.select_document(index, &id) {
+            self.link_history.push((index, from));
+        }
+    }
+
+    /// Select the document the last link was followed from
+    fn link_back(&mut self) {
+        if let Some((index, id)) = self.link_history.pop() {
+            self.select_document(index, &id);
+        }
+    }
+
+    /// Select the document `id` of the index at `index`, fetching it into the matches when it
+    /// isn't one of them. Returns whether it could be.
+    fn select_document(&mut self, index: usize, id: &str) -> bool {
+        let found = (0..self.matches.len())
+            .find(|&i| self.match_indexes[i] == index && self.matches[i].id == id);
+        let i = match found {
+            Some(i) => i,
+            None => {
+                let doc = self.prefetchers.get(index).and_then(|p| p.fetch(id));
+                let mut doc = match doc {
+                    Some(doc) => doc,
+                    None => {
+                        self.error = format!("No note {} in {}", id, self.indexes[index].0);
+                        return false;
+                    }
+                };
+                doc.serialization_type = document::SerializationType::Human;
+                self.matches.push(doc);
+                self.match_indexes.push(index);
+                self.match_titles.push(None);
+                self.matches.len() - 1
+            }
+        };
+        self.selected_state.select(Some(i));
+        self.show_selected();
+        self.prefetch_neighbors();
+        true
+    }
+
+    /// Image to show in the preview, a placeholder explaining why when it can't be drawn
+    fn preview_image(&self) -> Result<(Protocol, PathBuf), String> {
+        let reference = &self.images[self.image];
+        let protocol = self
+            .image_protocol
+            .ok_or_else(|| format!("[{}]\nThis terminal can't show images", reference))?;
+        let path = image::resolve(reference, &self.attachments_dir)
+            .ok_or_else(|| format!("[{}]\nNot a local image file", reference))?;
+        Ok((protocol, path))
+    }
+
+    /// Move the preview cursor by `delta` lines, scrolling to keep it in view. Wrapped lines
+    /// aren't accounted for, so a cursor below long paragraphs may sit past the bottom.
+    fn move_preview_cursor(&mut self, delta: isize) {
+        let last = self.preview.lines().count().saturating_sub(1);
+        let cursor = (self.preview_cursor as isize + delta).max(0) as usize;
+        self.preview_cursor = cursor.min(last);
+        let cursor = self.preview_cursor as u16;
+        if cursor < self.preview_scroll {
+            self.preview_scroll = cursor;
+        } else if cursor >= self.preview_scroll + self.preview_height.max(1) {
+            self.preview_scroll = cursor + 1 - self.preview_height.max(1);
+        }
+    }
+
+    /// Draw the image laid out by the last frame, unless it's already on the screen
+    fn draw_image<W: Write>(
+        &mut self,
+        protocol: Protocol,
+        tui: &mut tui::Terminal<TermionBackend<W>>,
+    ) {
+        if self.image_area == self.shown_image {
+            return;
+        }
+        let _ = protocol.clear(tui.backend_mut());
+        if self.shown_image.take().is_some() && protocol != Protocol::Kitty {
+            // The old image is in cells tui believes are blank, so have every cell redrawn and
+            // draw the new image over the next frame
+            let _ = tui.clear();
+            return;
+        }
+        if let Some((path, area)) = &self.image_area {
+            if let Err(e) = protocol.draw(tui.backend_mut(), path, *area) {
+                self.preview_status = e.to_string();
+            }
+        }
+        self.shown_image = self.image_area.clone();
+    }
+
+    /// Lines of the preview selected in visual mode, or just the one under the cursor
+    fn selected_lines(&self) -> Range<usize> {
+        let anchor = self.selection_anchor.unwrap_or(self.preview_cursor);
+        anchor.min(self.preview_cursor)..anchor.max(self.preview_cursor) + 1
+    }
+
+    /// Copy `lines` of the preview to the clipboard, leaving visual mode
+    fn yank(&mut self, lines: Range<usize>) {
+        let count = lines.len();
+        let text: Vec<&str> = self.preview.lines().skip(lines.start).take(count).collect();
+        self.preview_status = match clipboard::copy(&text.join("\n")) {
+            Ok(()) if count == 1 => String::from("Copied 1 line"),
+            Ok(()) => format!("Copied {} lines", count),
+            Err(e) => format!("Copy failed: {}", e),
+        };
+        self.selection_anchor = None;
+    }
+
+    /// Copy the body of the fenced code block around the preview cursor
+    fn yank_code_block(&mut self) {
+        let lines: Vec<&str> = self.preview.lines().collect();
+        match fenced_block(&lines, self.preview_cursor) {
+            Some(block) => self.yank(block),
+            None => self.preview_status = String::from("Not in a code block"),
         }
     }
 
@@ -88,67 +663,1000 @@ impl TerminalApp {
         self.selected_state.select(Some(i));
     }
 
+    /// Offer the tags fuzzy-matching the value being typed after `tags =` or `tags !=` at the
+    /// end of the filter input
+    pub fn update_completions(&mut self) {
+        self.completions = match tag_value_start(self.filter_input.as_str()) {
+            Some(start) => {
+                let partial = &self.filter_input.as_str()[start..];
+                let mut scored: Vec<(usize, usize, &String)> = self
+                    .tags
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, t)| fuzzy_score(partial, t).map(|score| (score, i, t)))
+                    .collect();
+                scored.sort();
+                scored.into_iter().map(|(_, _, t)| t.to_owned()).collect()
+            }
+            None => Vec::new(),
+        };
+        self.completion_state
+            .select(if self.completions.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+    }
+
+    /// Replace the tag being typed with the selected completion
+    pub fn accept_completion(&mut self) {
+        if let (Some(start), Some(i)) = (
+            tag_value_start(self.filter_input.as_str()),
+            self.completion_state.selected(),
+        ) {
+            let completion = &self.completions[i];
+            self.filter_input.edit(|input| {
+                input.truncate(start);
+                input.push_str(completion);
+                input.push(' ');
+                true
+            });
+        }
+        self.completions.clear();
+    }
+
+    /// Complete the field name being typed in the filter input
+    fn complete_field(&mut self) -> bool {
+        let fields = &self.fields;
+        self.filter_input
+            .edit(|input| complete_field(input, fields))
+    }
+
+    /// Apply an editing key to the focused input, if an input has the focus
+    fn edit_input(&mut self, key: Key) {
+        let ring = &mut self.kill_ring;
+        match self.focus {
+            Focus::Query => {
+                self.query_input.handle_key(key, ring);
+            }
+            Focus::Filter => {
+                if self.filter_input.handle_key(key, ring) {
+                    self.update_completions();
+                }
+            }
+            Focus::Results | Focus::Preview | Focus::Tags => {}
+        }
+    }
+
+    /// Border of the pane, highlighted when it has the focus
+    fn border_style(&self, pane: Focus) -> Style {
+        if self.focus == pane {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default()
+        }
+    }
+
+    pub fn next_completion(&mut self) {
+        let i = match self.completion_state.selected() {
+            Some(i) if i + 1 < self.completions.len() => i + 1,
+            _ => 0,
+        };
+        self.completion_state.select(Some(i));
+    }
+
+    pub fn previous_completion(&mut self) {
+        let i = match self.completion_state.selected() {
+            Some(i) if i > 0 => i - 1,
+            _ => self.completions.len().saturating_sub(1),
+        };
+        self.completion_state.select(Some(i));
+    }
+
+    /// Add `expr` to the end of the filter input, ANDed with whatever is already there
+    pub fn append_filter(&mut self, expr: &str) {
+        self.filter_input.edit(|input| {
+            let existing = input.trim_end();
+            *input = if existing.is_empty() {
+                expr.to_string()
+            } else if existing.ends_with(|c| c == '+' || c == '|') {
+                format!("{} {}", existing, expr)
+            } else {
+                format!("{} + {}", existing, expr)
+            };
+            true
+        });
+    }
+
+    /// Handle a key press while the date range picker is open
+    fn date_picker_input(&mut self, key: Key) {
+        let picker = match self.date_picker.as_mut() {
+            Some(p) => p,
+            None => return,
+        };
+        match key {
+            Key::Esc | Key::Ctrl('t') => self.date_picker = None,
+            Key::Down | Key::Ctrl('n') | Key::Char('\t') => picker.next(),
+            Key::Up | Key::Ctrl('p') => picker.previous(),
+            Key::Char('\n') => match picker.expression(Local::now().date_naive()) {
+                Ok(expr) => {
+                    self.date_picker = None;
+                    self.append_filter(&expr);
+                }
+                Err(e) => self.error = e,
+            },
+            key => {
+                if let Some(input) = picker.input() {
+                    input.handle_key(key, &mut self.kill_ring);
+                }
+            }
+        }
+    }
+
+    /// Search URL of the current index
+    fn uri(&self) -> &Url {
+        &self.indexes[self.index].1
+    }
+
+    /// Switch to the index at `index`, refreshing what's known about it
+    fn use_index(&mut self, client: &dyn Transport, index: usize) {
+        self.index = index;
+        self.matches.clear();
+        self.match_indexes.clear();
+        self.match_titles.clear();
+        self.selected_state.select(None);
+        self.show_selected();
+        self.fields.clear();
+        self.tags.clear();
+
+        let uri = self.uri().to_owned();
+        match query::filterable_attributes(client, &uri) {
+            Ok(fields) => self.fields = fields,
+            Err(e) => self.error = format!("Failed to fetch filterable attributes: {:?}", e),
+        }
+        match query::tag_facets(client, &uri) {
+            Ok(tags) => self.tags = tags,
+            Err(e) => self.error = format!("Failed to fetch tags: {:?}", e),
+        }
+    }
+
+    /// The search request for what's in the input boxes
+    fn api_query(&self) -> api::SearchRequest {
+        let mut q = api::SearchRequest::new()
+            .query(self.query_input.as_str())
+            .filter(self.filter_input.as_str())
+            .locales(&self.locales)
+            .facets(&["tags"]);
+        // Marking the matches in the body as well would bring it along in lazy mode
+        if !self.query_input.as_str().trim().is_empty() {
+            q = q.highlight(if self.lazy {
+                &["title"]
+            } else {
+                &["title", "body"]
+            });
+        }
+        if !self.show_drafts && self.fields.iter().any(|f| f == "status") {
+            q = q.and_raw_filter(status::NOT_DRAFT);
+        }
+        if self.lazy {
+            q.without_body();
+        }
+        q
+    }
+
+    /// The words the search matched in the selected note, for marking in the preview
+    fn selected_terms(&self) -> Vec<String> {
+        let formatted = self
+            .selected_state
+            .selected()
+            .and_then(|i| self.matches.get(i))
+            .and_then(|m| m.formatted.as_ref());
+        match formatted {
+            Some(formatted) => {
+                let mut terms = highlight::matched_terms(&formatted.title);
+                terms.extend(highlight::matched_terms(&formatted.body));
+                terms.sort();
+                terms.dedup();
+                terms
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// What decides the results: the search request and which indexes it's run against, and
+    /// how its hits are ordered
+    fn search_key(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            serde_json::to_string(&self.api_query()).unwrap(),
+            self.index,
+            self.search_all,
+            self.by_sequence
+        )
+    }
+
+    /// Search again once typing pauses, if the inputs changed what to search for. A new
+    /// search starts over from its first page.
+    fn schedule_search(&mut self) {
+        if self.search_key() != self.last_search {
+            self.page = 0;
+            self.pending_search = Some(Instant::now());
+        }
+    }
+
+    /// Show the next page of results, if there are more, or the previous one
+    fn turn_page(&mut self, cache: &mut SearchCache, searcher: &mut Searcher, forward: bool) {
+        let page = if forward {
+            if (self.page + 1) * RESULTS_PAGE >= self.total_hits {
+                return;
+            }
+            self.page + 1
+        } else if self.page > 0 {
+            self.page - 1
+        } else {
+            return;
+        };
+        self.page = page;
+        self.selected_state.select(None);
+        self.show_selected();
+        self.start_search(cache, searcher);
+    }
+
+    /// Which results are shown out of how many, e.g. `showing 1–100 of 2345 (estimated)`
+    fn results_range(&self) -> String {
+        if self.matches.is_empty() {
+            return String::from("no matches");
+        }
+        let first = self.page * RESULTS_PAGE;
+        format!(
+            "showing {}–{} of {}{}",
+            first + 1,
+            first + self.matches.len() as u32,
+            self.total_hits,
+            if self.exhaustive { "" } else { " (estimated)" }
+        )
+    }
+
+    /// Run the query against the current index, or against all of them, in the background.
+    /// Results in the cache are used right away.
+    fn start_search(&mut self, cache: &mut SearchCache, searcher: &mut Searcher) {
+        self.pending_search = None;
+        self.last_search = self.search_key();
+        let q = self
+            .api_query()
+            .page(self.page * RESULTS_PAGE, RESULTS_PAGE);
+        let searched: Vec<usize> = if self.search_all {
+            (0..self.indexes.len()).collect()
+        } else {
+            vec![self.index]
+        };
+        let indexes: Vec<_> = searched
+            .into_iter()
+            .map(|index| {
+                let uri = self.indexes[index].1.clone();
+                let cached = cache.get(&cache::key(&uri, &q));
+                (index, uri, cached)
+            })
+            .collect();
+        if indexes.iter().all(|(_, _, cached)| cached.is_some()) {
+            let results = indexes
+                .into_iter()
+                .map(|(index, _, cached)| (index, Ok(cached.unwrap_or_default())))
+                .collect();
+            self.finish_search(cache, &q, results);
+        } else {
+            searcher.send(q, indexes);
+        }
+    }
+
+    /// Show the hits of the responses to `q`, caching the ones that weren't. Hits from several
+    /// indexes are merged newest first, matching the default sort.
+    fn finish_search(
+        &mut self,
+        cache: &mut SearchCache,
+        q: &api::SearchRequest,
+        results: searcher::Results,
+    ) {
+        let mut hits = Vec::new();
+        let mut errors = Vec::new();
+        let mut tag_counts: HashMap<String, u32> = HashMap::new();
+        self.total_hits = 0;
+        self.exhaustive = true;
+        for (index, body) in results {
+            let (label, uri) = &self.indexes[index];
+            let resp = body.and_then(|body| {
+                let resp = query::parse_response(&body)?;
+                let key = cache::key(uri, q);
+                if cache.get(&key).is_none() {
+                    cache.insert(key, body);
+                }
+                Ok(resp)
+            });
+            match resp {
+                Ok(resp) => {
+                    self.total_hits += resp.num_hits;
+                    self.exhaustive &= resp.exhaustive_num_hits;
+                    let tags = resp
+                        .facets_distribution
+                        .and_then(|mut facets| facets.remove("tags"))
+                        .unwrap_or_default();
+                    for (tag, count) in tags {
+                        *tag_counts.entry(tag).or_default() += count;
+                    }
+                    hits.extend(resp.hits.into_iter().map(|m| (index, m)))
+                }
+                Err(e) => errors.push(format!("{}: {:?}", label, e)),
+            }
+        }
+        if self.search_all {
+            hits.sort_by(|a, b| b.1.date.cmp(&a.1.date));
+        }
+        if self.by_sequence {
+            hits.sort_by(|a, b| sequence::compare(&a.1.sequence, &b.1.sequence));
+        }
+        let (match_indexes, matches): (Vec<_>, Vec<_>) = hits
+            .into_iter()
+            .map(|(index, mut m)| {
+                m.serialization_type = document::SerializationType::Human;
+                (index, m)
+            })
+            .unzip();
+        self.match_indexes = match_indexes;
+        self.match_titles = vec![None; matches.len()];
+        self.matches = matches;
+        self.error = errors.join("\n");
+
+        let mut tag_counts: Vec<(String, u32)> = tag_counts.into_iter().collect();
+        tag_counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.tag_counts = tag_counts;
+        let selected = match self.tag_state.selected() {
+            Some(_) if self.tag_counts.is_empty() => None,
+            Some(i) => Some(i.min(self.tag_counts.len() - 1)),
+            None => None,
+        };
+        self.tag_state.select(selected);
+    }
+
+    /// Apply the settings of a reloaded config that can change while running. Nothing is
+    /// changed when any of them is invalid.
+    fn reload(
+        &mut self,
+        reload: &Reload,
+        keys: &mut Keys,
+        highlighter: &mut Highlighter,
+    ) -> Result<(), Report> {
+        let config = &reload.config;
+        let new_keys = Keys::new(&config.keys)?;
+        let new_highlighter = Highlighter::new(
+            config
+                .preview_theme
+                .as_deref()
+                .unwrap_or(highlight::DEFAULT_THEME),
+        )?;
+        *keys = new_keys;
+        *highlighter = new_highlighter;
+        self.locales = config.locales.to_owned();
+        Ok(())
+    }
+
+    /// Show why the filter input doesn't parse among the errors, or take that back once it
+    /// does. A filter that doesn't parse is left out of searches.
+    fn check_filter(&mut self) {
+        let message = match api::parse_filter(self.filter_input.as_str()) {
+            Ok(_) => String::new(),
+            Err(e) => format!("Filter: {}", e),
+        };
+        let previous = std::mem::replace(&mut self.filter_error, message);
+        if self.error.is_empty() || self.error == previous {
+            self.error = self.filter_error.to_owned();
+        }
+    }
+
+    /// Move the selection in the tag sidebar by `delta`, wrapping around at either end
+    fn move_tag_selection(&mut self, delta: isize) {
+        if self.tag_counts.is_empty() {
+            return;
+        }
+        let len = self.tag_counts.len() as isize;
+        let i = match self.tag_state.selected() {
+            Some(i) => (i as isize + delta).rem_euclid(len),
+            None if delta < 0 => len - 1,
+            None => 0,
+        };
+        self.tag_state.select(Some(i as usize));
+    }
+
+    /// Narrow the filter to the tag selected in the sidebar
+    fn filter_selected_tag(&mut self) {
+        let tag = match self
+            .tag_state
+            .selected()
+            .and_then(|i| self.tag_counts.get(i))
+        {
+            Some((tag, _)) => tag.to_owned(),
+            None => return,
+        };
+        let expr = tag_filter(&tag);
+        if self
+            .filter_input
+            .as_str()
+            .split(|c: char| c.is_whitespace() || c == '+' || c == '|')
+            .any(|word| word == expr)
+        {
+            return;
+        }
+        self.append_filter(&expr);
+    }
+
     fn new() -> TerminalApp {
         TerminalApp {
-            query_input: String::new(),
-            filter_input: String::new(),
+            query_input: TextInput::default(),
+            filter_input: TextInput::default(),
+            kill_ring: KillRing::default(),
             preview: String::new(),
             matches: Vec::new(),
             selected_state: ListState::default(),
             error: String::new(),
+            filter_error: String::new(),
             debug: String::new(),
-            inp_idx: 0,
-            inp_widths: [0, 0],
+            fields: Vec::new(),
+            tags: Vec::new(),
+            tag_counts: Vec::new(),
+            tag_state: ListState::default(),
+            completions: Vec::new(),
+            completion_state: ListState::default(),
+            date_picker: None,
+            prefetchers: Vec::new(),
+            indexes: Vec::new(),
+            index: 0,
+            search_all: false,
+            match_indexes: Vec::new(),
+            match_titles: Vec::new(),
+            list_offset: 0,
+            pending_search: None,
+            last_search: String::new(),
+            page: 0,
+            total_hits: 0,
+            exhaustive: true,
+            by_sequence: false,
+            show_drafts: false,
+            lazy: false,
+            locales: Vec::new(),
+            focus: Focus::Query,
+            preview_scroll: 0,
+            preview_cursor: 0,
+            selection_anchor: None,
+            preview_height: 0,
+            preview_status: String::new(),
+            image_protocol: None,
+            attachments_dir: PathBuf::new(),
+            images: Vec::new(),
+            image: 0,
+            image_area: None,
+            shown_image: None,
+            link: None,
+            link_history: Vec::new(),
+            recorder: None,
+        }
+    }
+}
+
+/// Complete the field name being typed at the end of `input` from `fields`, as far as it is
+/// unambiguous. Returns whether anything was completed.
+fn complete_field(input: &mut String, fields: &[String]) -> bool {
+    let start = input
+        .rfind(|c: char| c.is_whitespace() || "|+!(".contains(c))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &input[start..];
+    if word.is_empty() {
+        return false;
+    }
+
+    let candidates: Vec<&String> = fields.iter().filter(|f| f.starts_with(word)).collect();
+    let completion = match candidates.as_slice() {
+        [] => return false,
+        [only] => format!("{} ", only),
+        [first, rest @ ..] => {
+            // Longest prefix shared by all candidates
+            let mut prefix = first.to_string();
+            for c in rest {
+                while !c.starts_with(prefix.as_str()) {
+                    prefix.pop();
+                }
+            }
+            prefix
+        }
+    };
+    if completion.len() <= word.len() {
+        return false;
+    }
+
+    input.truncate(start);
+    input.push_str(&completion);
+    true
+}
+
+/// Number of results on either side of the selection to prefetch
+const PREFETCH_NEIGHBORS: usize = 3;
+
+/// Results fetched from each index at a time, PageDown and PageUp move between pages
+const RESULTS_PAGE: u32 = 100;
+
+/// Pause in typing before searching for what was typed
+const SEARCH_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+
+/// How often to check for the results of a search under way
+const SEARCH_POLL: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Ranges offered by the date range picker, ahead of its from/to inputs
+const DATE_PRESETS: [&str; 6] = [
+    "Today",
+    "This week",
+    "This month",
+    "This year",
+    "Last year",
+    "Due for a reminder or review",
+];
+
+/// Lines between the fences of the code block containing line `at`, if any. A cursor on either
+/// fence counts as inside the block.
+fn fenced_block(lines: &[&str], at: usize) -> Option<Range<usize>> {
+    // Line, character and length of the opening fence
+    let mut open: Option<(usize, char, usize)> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        match open {
+            None => {
+                let c = line.chars().next().unwrap_or(' ');
+                let len = line.chars().take_while(|&f| f == c).count();
+                if (c == '`' || c == '~') && len >= 3 {
+                    open = Some((i, c, len));
+                }
+            }
+            // Closing fences are at least as long as the opening one and have no info string
+            Some((start, c, len)) if line.len() >= len && line.chars().all(|f| f == c) => {
+                if at <= i {
+                    return Some(start + 1..i);
+                }
+                open = None;
+            }
+            Some(_) => {}
+        }
+        if i >= at && open.is_none() {
+            return None;
+        }
+    }
+    // An unclosed block runs to the end of the note
+    open.map(|(start, _, _)| start + 1..lines.len())
+}
+
+/// Panes that can have the keyboard focus, in the order tab cycles through them
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Focus {
+    Query,
+    Filter,
+    Results,
+    Preview,
+    /// Sidebar of the tags of the matches
+    Tags,
+}
+
+impl Focus {
+    const ORDER: [Focus; 5] = [
+        Focus::Query,
+        Focus::Filter,
+        Focus::Results,
+        Focus::Preview,
+        Focus::Tags,
+    ];
+
+    fn position(self) -> usize {
+        Self::ORDER.iter().position(|&f| f == self).unwrap_or(0)
+    }
+
+    fn next(self) -> Focus {
+        Self::ORDER[(self.position() + 1) % Self::ORDER.len()]
+    }
+
+    fn previous(self) -> Focus {
+        Self::ORDER[(self.position() + Self::ORDER.len() - 1) % Self::ORDER.len()]
+    }
+
+    /// Whether the pane is a text input, where letters are typed rather than used as keys
+    fn is_input(self) -> bool {
+        matches!(self, Focus::Query | Focus::Filter)
+    }
+}
+
+/// Popup composing a date range into the filter expression, from a preset or from/to dates
+struct DatePicker {
+    /// Selected row, the presets followed by the from and to inputs
+    state: ListState,
+    from: TextInput,
+    to: TextInput,
+}
+
+impl DatePicker {
+    const FROM_ROW: usize = DATE_PRESETS.len();
+    const TO_ROW: usize = DATE_PRESETS.len() + 1;
+
+    fn new() -> DatePicker {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        DatePicker {
+            state,
+            from: TextInput::default(),
+            to: TextInput::default(),
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.state.selected().unwrap_or(0)
+    }
+
+    fn next(&mut self) {
+        self.state
+            .select(Some((self.selected() + 1) % (Self::TO_ROW + 1)));
+    }
+
+    fn previous(&mut self) {
+        self.state
+            .select(Some((self.selected() + Self::TO_ROW) % (Self::TO_ROW + 1)));
+    }
+
+    /// The from/to input under the cursor, if one is selected
+    fn input(&mut self) -> Option<&mut TextInput> {
+        match self.selected() {
+            Self::FROM_ROW => Some(&mut self.from),
+            Self::TO_ROW => Some(&mut self.to),
+            _ => None,
         }
     }
+
+    fn items(&self) -> Vec<ListItem<'static>> {
+        DATE_PRESETS
+            .iter()
+            .map(|p| p.to_string())
+            .chain(vec![
+                format!("From: {}", self.from.as_str()),
+                format!("To:   {}", self.to.as_str()),
+            ])
+            .map(|s| ListItem::new(Span::raw(s)))
+            .collect()
+    }
+
+    /// Filter expression for the selected preset, or for the from/to inputs when either is
+    /// selected
+    fn expression(&self, today: NaiveDate) -> Result<String, String> {
+        let ymd = |d: NaiveDate| d.format("%Y-%m-%d").to_string();
+        match self.selected() {
+            0 => Ok(format!("date = {}", ymd(today))),
+            1 => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                Ok(format!("date >= {} + date <= {}", ymd(monday), ymd(today)))
+            }
+            2 => Ok(format!("date = {}", today.format("%Y-%m"))),
+            3 => Ok(format!("date = {}", today.year())),
+            4 => Ok(format!("date = {}", today.year() - 1)),
+            5 => Ok(reminder::filter(&ymd(today))),
+            _ => {
+                let (from, to) = (self.from.as_str(), self.to.as_str());
+                for d in &[from, to] {
+                    if !d.is_empty() && !is_filter_date(d) {
+                        return Err(format!("Not a YYYY, YYYY-MM or YYYY-MM-DD date: {}", d));
+                    }
+                }
+                match (from.is_empty(), to.is_empty()) {
+                    (false, false) => Ok(format!("date >= {} + date <= {}", from, to)),
+                    (false, true) => Ok(format!("date >= {}", from)),
+                    (true, false) => Ok(format!("date <= {}", to)),
+                    (true, true) => Err(String::from("Enter a from and/or to date")),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `s` is a date the filter grammar accepts
+fn is_filter_date(s: &str) -> bool {
+    match Filter::parse(Rule::date, s) {
+        Ok(mut pairs) => pairs.next().map(|p| p.as_str().len()) == Some(s.len()),
+        Err(_) => false,
+    }
 }
 
+/// Area of the given size centered in `area`
+fn centered_area(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Message for a change that couldn't be saved, saying why when the server is down: only
+/// searches fail over to replicas, changes aren't queued for the server to come back
+fn save_error(client: &dyn Transport, what: &str, e: Report) -> String {
+    match client.fallback_host() {
+        Some(_) => format!(
+            "Failed to save {}, the server is down and replicas are read-only: {}",
+            what, e
+        ),
+        None => format!("Failed to save {}: {}", what, e),
+    }
+}
+
+/// Filter expression matching notes tagged `tag`: the tag itself when the filter syntax reads
+/// it as one, a quoted comparison otherwise
+fn tag_filter(tag: &str) -> String {
+    if api::is_tag(tag) {
+        tag.to_owned()
+    } else {
+        format!("tags = \"{}\"", tag.replace('"', ""))
+    }
+}
+
+/// Byte offset of the tag value being typed when the filter input ends in `tags = <partial>`
+/// or `tags != <partial>`
+fn tag_value_start(input: &str) -> Option<usize> {
+    let start = input.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+    let before = input[..start].trim_end();
+    let before = before
+        .strip_suffix("!=")
+        .or_else(|| before.strip_suffix('='))?;
+    if before.trim_end().ends_with("tags") {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+/// Score how well `candidate` contains the characters of `pattern` in order, lower is better
+fn fuzzy_score(pattern: &str, candidate: &str) -> Option<usize> {
+    let candidate = candidate.to_lowercase();
+    let mut pos = 0;
+    let mut score = 0;
+    for (n, c) in pattern.to_lowercase().chars().enumerate() {
+        let i = candidate[pos..].find(c)?;
+        // Penalize where the match starts and every gap after that
+        score += if n == 0 { i } else { i * 2 };
+        pos += i + c.len_utf8();
+    }
+    Some(score)
+}
+
+/// Area for the completion popup, directly above the input box at `input`
+fn popup_area(input: Rect, items: usize) -> Rect {
+    let height = (items.min(8) as u16 + 2).min(input.y);
+    Rect::new(input.x, input.y - height, input.width.min(40), height)
+}
+
+/// Columns taken by the tag sidebar
+const TAG_SIDEBAR_WIDTH: u16 = 24;
+
+/// Smallest terminal the interface is drawn in, below this a placeholder is shown instead
+const MIN_WIDTH: u16 = 60;
+const MIN_HEIGHT: u16 = 30;
+
+/// Terminal settings from before the interface took over, restored by `restore_terminal`
+static ORIGINAL_TERMIOS: OnceCell<libc::termios> = OnceCell::new();
+
+/// Put the terminal back the way the shell expects it: cooked mode, main screen, cursor shown.
+/// Safe to call any number of times, from anywhere.
+fn restore_terminal() {
+    if let Some(termios) = ORIGINAL_TERMIOS.get() {
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios);
+        }
+    }
+    let mut out = stdout();
+    let _ = write!(
+        out,
+        "{}{}",
+        termion::cursor::Show,
+        termion::screen::ToMainScreen
+    );
+    let _ = out.flush();
+}
+
+/// Run `command`, which may carry arguments of its own like `bat --paging always`, on the file
+/// at `path`, waiting for it to exit
+fn run_on(command: &str, path: &Path) -> std::io::Result<std::process::ExitStatus> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "no command configured")
+    })?;
+    Command::new(program).args(words).arg(path).status()
+}
+
+/// Stop the process like the shell's job control does, returning once it's continued with `fg`.
+/// The terminal has to be restored beforehand.
+fn suspend() {
+    let _ = signal_hook::low_level::emulate_default_handler(SIGTSTP);
+}
+
+/// Restores the terminal however the interface exits: when dropped on return or on an error,
+/// from the panic hook, and on SIGINT, SIGTERM or SIGHUP, which would otherwise kill the
+/// process with the terminal still raw and on the alternate screen. Create it before the
+/// `tui::Terminal` so it's dropped after it.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn new() -> TerminalGuard {
+        if ORIGINAL_TERMIOS.get().is_none() {
+            let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } == 0 {
+                let _ = ORIGINAL_TERMIOS.set(termios);
+                // Only the first guard registers the handler, it outlives the guard harmlessly
+                if let Ok(mut signals) = Signals::new(&[SIGINT, SIGTERM, SIGHUP]) {
+                    std::thread::spawn(move || {
+                        if let Some(signal) = signals.forever().next() {
+                            restore_terminal();
+                            std::process::exit(128 + signal);
+                        }
+                    });
+                }
+            }
+        }
+        TerminalGuard
+    }
+}
+
+impl Default for TerminalGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Restore the terminal before the panic message is printed, so it's readable
 pub fn setup_panic() {
-    std::panic::set_hook(Box::new(move |_x| {
-        stdout()
-            .into_raw_mode()
-            .unwrap()
-            .suspend_raw_mode()
-            .unwrap();
-        write!(
-            stdout().into_raw_mode().unwrap(),
-            "{}",
-            termion::screen::ToMainScreen
-        )
-        .unwrap();
-        print!("");
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        report(info);
     }));
 }
 
-/// Interactive query interface
+/// Interactive query interface over `indexes`, labels and search URLs of the indexes that can
+/// be switched between, starting with the one at `index` and searching for `initial_query`.
+/// Notes edited in `editor` are saved back to their index unless `read_only`, and like the
+/// other changes made in the interface, recorded by `recorder`.
+/// With `trace`, drawing, input handling and searches are timed in a readout toggled with
+/// ctrl-g, and summarized on stderr on exit. Images referenced by relative paths are looked up
+/// in `attachments_dir`. The theme, keys and locales are picked up again from the config file
+/// at `config_path` whenever it changes.
+#[allow(clippy::too_many_arguments)]
 pub fn query(
-    client: reqwest::blocking::Client,
-    uri: Url,
+    cache: &mut SearchCache,
+    client: Arc<dyn Transport>,
+    indexes: Vec<(String, Url)>,
+    index: usize,
     verbosity: u8,
     pager: String,
     editor: String,
+    mut keys: Keys,
+    read_only: bool,
+    lazy: bool,
+    initial_query: String,
+    trace: bool,
+    attachments_dir: &Path,
+    theme: &str,
+    locales: &[String],
+    config_path: &str,
+    recorder: undo::Recorder,
 ) -> Result<Vec<String>, Report> {
+    // Before taking over the terminal, so a bad theme is reported like any other error
+    let mut highlighter = Highlighter::new(theme)?;
+    // Live reloading is a convenience, the interface works the same without it
+    let mut config_watcher = ConfigWatcher::new(config_path).ok();
+    // Preview text and how it was highlighted, redone only when the text changes
+    let mut highlighted = (String::new(), Text::default());
+
+    let _guard = TerminalGuard::new();
+    let mut trace = Trace::new(trace);
+    // When the last key was pressed, until the frame showing its effect is drawn
+    let mut input_at: Option<Instant> = None;
+
     let mut tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
         stdout().into_raw_mode().unwrap(),
     )))
     .unwrap();
 
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-
-    let syntax = ps.find_syntax_by_extension("md").unwrap();
-    let mut highlighter = HighlightLines::new(syntax, &ts.themes["Solarized (dark)"]);
-    //let mut highlighter = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-
     // Setup event handlers
     let mut events = event::Events::new();
 
     // Create default app state
     let mut app = TerminalApp::new();
+    app.lazy = lazy;
+    app.recorder = Some(recorder);
+    app.locales = locales.to_vec();
+    app.image_protocol = Protocol::detect();
+    app.attachments_dir = attachments_dir.to_owned();
+    app.prefetchers = indexes
+        .iter()
+        .map(|(_, uri)| {
+            let documents_uri = uri.join("documents/").expect("search URLs are absolute");
+            Prefetcher::new(client.clone(), documents_uri)
+        })
+        .collect();
+    app.indexes = indexes;
+    app.use_index(client.as_ref(), index);
+    let mut searcher = Searcher::new(client.clone());
+    if !initial_query.is_empty() {
+        app.query_input = TextInput::new(&initial_query);
+        app.start_search(cache, &mut searcher);
+    }
 
     loop {
+        if let Some(reload) = config_watcher.as_mut().and_then(|w| w.poll()) {
+            let reloaded = reload.and_then(|reload| {
+                app.reload(&reload, &mut keys, &mut highlighter)?;
+                Ok(reload)
+            });
+            match reloaded {
+                Ok(reload) => {
+                    // Highlighted again with the new theme
+                    highlighted.0.clear();
+                    app.schedule_search();
+                    let restart = reload.restart_needed();
+                    app.preview_status = if restart.is_empty() {
+                        String::from("Reloaded the config")
+                    } else {
+                        format!(
+                            "Reloaded the config, restart to apply {}",
+                            restart.join(", ")
+                        )
+                    };
+                }
+                Err(e) => app.error = format!("Failed to reload the config: {}", e),
+            }
+        }
+        if let Some(resp) = searcher.try_recv() {
+            trace.span("search", || app.finish_search(cache, &resp.q, resp.results));
+        }
+        if let Some(since) = app.pending_search {
+            if since.elapsed() >= SEARCH_DELAY {
+                app.start_search(cache, &mut searcher);
+            }
+        }
+
+        // Pick up the selected document's body once it has been prefetched
+        if app.awaiting_prefetch() {
+            app.preview = app.get_selected_contents();
+            app.images = image::references(&app.preview);
+        }
+
         // Draw UI
+        let readout = if trace.visible {
+            trace.lines()
+        } else {
+            Vec::new()
+        };
+        let draw_started = Instant::now();
+        app.image_area = None;
         if let Err(e) = tui.draw(|f| {
+            // The layout math below needs some room, tiny tmux splits get a placeholder
+            let size = f.size();
+            if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+                let message = Paragraph::new(format!(
+                    "Terminal too small, meilizet needs at least {}x{}",
+                    MIN_WIDTH, MIN_HEIGHT
+                ))
+                .wrap(Wrap { trim: true });
+                f.render_widget(message, size);
+                return;
+            }
+
             let main = if verbosity > 0 {
                 // Enable debug and error areas
                 Layout::default()
@@ -174,6 +1682,50 @@ pub fn query(
                     .split(f.size())
             };
 
+            // Tag sidebar, left out when the matches have no tags to narrow them down by
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    [
+                        Constraint::Length(if app.tag_counts.is_empty() {
+                            0
+                        } else {
+                            TAG_SIDEBAR_WIDTH
+                        }),
+                        Constraint::Min(0),
+                    ]
+                    .as_ref(),
+                )
+                .split(main[0]);
+            if !app.tag_counts.is_empty() {
+                let tags: Vec<ListItem> = app
+                    .tag_counts
+                    .iter()
+                    .map(|(tag, count)| {
+                        ListItem::new(Spans::from(vec![
+                            Span::styled(
+                                format!("{:>4} ", count),
+                                Style::default().fg(Color::DarkGray),
+                            ),
+                            Span::raw(highlight::printable(tag)),
+                        ]))
+                    })
+                    .collect();
+                let tags = List::new(tags)
+                    .block(
+                        Block::default()
+                            .title("Tags (enter filters)")
+                            .borders(Borders::ALL)
+                            .border_style(app.border_style(Focus::Tags)),
+                    )
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                let area = columns[0].inner(&Margin {
+                    vertical: 1,
+                    horizontal: 1,
+                });
+                f.render_stateful_widget(tags, area, &mut app.tag_state);
+            }
+
             let screen = Layout::default()
                 .direction(Direction::Horizontal)
                 .margin(1)
@@ -186,23 +1738,113 @@ pub fn query(
                     ]
                     .as_ref(),
                 )
-                .split(main[0]);
+                .split(columns[1]);
 
             // Preview area where content is displayed
-            let mut preview_text = String::from("");
-            for line in LinesWithEndings::from(app.preview.as_ref()) {
-                let ranges: Vec<(hStyle, &str)> = highlighter.highlight(line, &ps);
-                let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
-                preview_text.push_str(&escaped);
-            }
-            //let preview_text = Paragraph::new(app.preview.as_ref())
-            let preview_text = Paragraph::new(ansi_to_text(preview_text.bytes()).unwrap())
-                .block(Block::default().borders(Borders::NONE))
+            if highlighted.0 != app.preview {
+                let text = trace.span("highlight", || highlighter.markdown(&app.preview));
+                highlighted = (app.preview.to_owned(), text);
+            }
+            let mut preview_text = highlighted.1.clone();
+            highlight::mark(&mut preview_text, &app.selected_terms());
+            // Show the cursor line, or the visual selection, while the preview has the focus
+            if app.focus == Focus::Preview {
+                let selected = app.selected_lines();
+                for line in preview_text.lines.iter_mut().take(selected.end).skip(selected.start) {
+                    for span in line.0.iter_mut() {
+                        span.style = span.style.bg(Color::DarkGray);
+                    }
+                }
+            }
+            let pager_hint;
+            let link_hint;
+            let preview_title = match (&app.selection_anchor, app.preview_status.is_empty()) {
+                (_, false) => app.preview_status.as_str(),
+                (_, true) if app.link.is_some() => {
+                    link_hint = format!(
+                        "Link {}/{} (enter follows, esc cancels)",
+                        app.link.unwrap_or_default() + 1,
+                        app.selected_links().len()
+                    );
+                    link_hint.as_str()
+                }
+                (Some(_), true) => "Preview -- VISUAL (y copies, esc cancels)",
+                (None, true) if app.focus == Focus::Preview => {
+                    "Preview (v selects, y copies the line, b the code block)"
+                }
+                // Notes too long for the pane are easier read in the pager
+                (None, true) if app.preview.lines().count() > app.preview_height as usize => {
+                    pager_hint = format!("Preview ({} opens the pager)", keys::name(keys.view));
+                    pager_hint.as_str()
+                }
+                (None, true) => "Preview",
+            };
+            // Images the note refers to go below its text
+            let preview_area = if app.images.is_empty() {
+                screen[1]
+            } else {
+                let split = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                    .split(screen[1]);
+                let block = Block::default()
+                    .title(format!(
+                        "Image {}/{} (i shows the next)",
+                        app.image + 1,
+                        app.images.len()
+                    ))
+                    .borders(Borders::ALL);
+                let inner = block.inner(split[1]);
+                f.render_widget(block, split[1]);
+                match app.preview_image() {
+                    // Left blank for the image, which is drawn over it once tui is done
+                    Ok((_, path)) => {
+                        f.render_widget(Clear, inner);
+                        app.image_area = Some((path, inner));
+                    }
+                    Err(placeholder) => {
+                        let placeholder = Paragraph::new(placeholder)
+                            .style(Style::default().fg(Color::DarkGray))
+                            .wrap(Wrap { trim: true });
+                        f.render_widget(placeholder, inner);
+                    }
+                }
+                split[0]
+            };
+            // The selected note's fields go below its text, rather than in YAML at its top
+            let metadata = app.selected_metadata();
+            let preview_area = if metadata.is_empty() {
+                preview_area
+            } else {
+                let split = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(
+                        [
+                            Constraint::Min(3),
+                            Constraint::Length(metadata.len() as u16 + 2),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(preview_area);
+                let metadata = Paragraph::new(metadata)
+                    .block(Block::default().title("Metadata").borders(Borders::ALL))
+                    .wrap(Wrap { trim: true });
+                f.render_widget(metadata, split[1]);
+                split[0]
+            };
+            app.preview_height = preview_area.height.saturating_sub(2);
+            let preview_text = Paragraph::new(preview_text)
+                .block(
+                    Block::default()
+                        .title(preview_title)
+                        .borders(Borders::ALL)
+                        .border_style(app.border_style(Focus::Preview)),
+                )
+                .scroll((app.preview_scroll, 0))
                 .wrap(Wrap { trim: true });
-            f.render_widget(preview_text, screen[1]);
+            f.render_widget(preview_text, preview_area);
 
             // Output area where match titles are displayed
-            // TODO panes specifically for tag, weight, date, author, id, parentid
             let interactive = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(0)
@@ -220,45 +1862,128 @@ pub fn query(
                 .split(screen[0]);
 
             let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-            let matches: Vec<ListItem> = app
-                .matches
-                .iter()
-                .map(|m| ListItem::new(vec![Spans::from(Span::raw(m.title.to_string()))]))
+            // Only the matches on screen are made into list items, the list is handed just
+            // those and where the selection falls among them
+            let visible = app.visible_matches(interactive[0].height.saturating_sub(2) as usize);
+            let mut visible_state = ListState::default();
+            visible_state.select(
+                app.selected_state
+                    .selected()
+                    .filter(|i| visible.contains(i))
+                    .map(|i| i - visible.start),
+            );
+            let matches: Vec<ListItem> = visible
+                .map(|i| ListItem::new(app.match_title(i)))
                 .collect();
+            let title = if app.search_all {
+                format!("All vaults ({} toggles)", keys::name(keys.all_indexes))
+            } else if app.indexes.len() > 1 {
+                format!(
+                    "{} ({} switches, {} searches all)",
+                    app.indexes[app.index].0,
+                    keys::name(keys.next_index),
+                    keys::name(keys.all_indexes)
+                )
+            } else {
+                app.indexes[app.index].0.to_owned()
+            };
+            let title = if app.by_sequence {
+                format!("{}, by sequence ({} toggles)", title, keys::name(keys.sequence))
+            } else {
+                title
+            };
+            let title = if app.show_drafts {
+                format!("{}, with drafts ({} hides)", title, keys::name(keys.drafts))
+            } else {
+                title
+            };
+            let title = if searcher.busy() || app.pending_search.is_some() {
+                format!("{} -- searching…", title)
+            } else {
+                format!("{} -- {}", title, app.results_range())
+            };
+            // Whether results are coming from a replica while the server is down
+            let title = match client.fallback_host() {
+                Some(host) => format!(
+                    "{} -- from replica {}, maybe behind, read-only until the server is back",
+                    title, host
+                ),
+                None => title,
+            };
             let matches = List::new(matches)
-                .block(Block::default().borders(Borders::ALL))
+                .block(
+                    Block::default()
+                        .title(title)
+                        .borders(Borders::ALL)
+                        .border_style(app.border_style(Focus::Results)),
+                )
                 .highlight_style(selected_style)
                 .highlight_symbol("> ");
-            f.render_stateful_widget(matches, interactive[0], &mut app.selected_state);
+            f.render_stateful_widget(matches, interactive[0], &mut visible_state);
 
             // Input area where queries are entered
-            let query_input = Paragraph::new(app.query_input.as_ref())
+            let query_input = Paragraph::new(app.query_input.as_str())
+                .scroll((0, app.query_input.scroll(interactive[1].width.saturating_sub(2))))
                 .style(Style::default().fg(Color::Yellow))
                 .block(
                     Block::default()
                         .title("Query input")
-                        .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
+                        .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
+                        .border_style(app.border_style(Focus::Query)),
                 );
             f.render_widget(query_input, interactive[1]);
 
             // Input area where filters are entered
-            let filter_input = Paragraph::new(app.filter_input.as_ref())
+            let filter_input = Paragraph::new(app.filter_input.as_str())
+                .scroll((0, app.filter_input.scroll(interactive[2].width.saturating_sub(2))))
                 .style(Style::default().fg(Color::Yellow))
                 .block(
                     Block::default()
-                        .title("Filter input (e.g. 'vim | !bash')")
-                        .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
+                        .title("Filter input (e.g. 'vim | NOT (bash + status = draft)', tab completes fields)")
+                        .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
+                        .border_style(app.border_style(Focus::Filter)),
                 );
             f.render_widget(filter_input, interactive[2]);
 
-            // Make the cursor visible and ask tui-rs to put it at the specified
-            // coordinates after rendering
-            f.set_cursor(
-                // Put cursor past the end of the input text
-                // TODO refactor input area switching
-                interactive[app.inp_idx + 1].x + 1 + app.inp_widths[app.inp_idx] as u16,
-                interactive[app.inp_idx + 1].y + 1,
-            );
+            // Make the cursor visible in the focused input and ask tui-rs to put it at the
+            // specified coordinates after rendering, it stays hidden for the other panes
+            let input = match app.focus {
+                Focus::Query => Some((&app.query_input, interactive[1])),
+                Focus::Filter => Some((&app.filter_input, interactive[2])),
+                Focus::Results | Focus::Preview | Focus::Tags => None,
+            };
+            if let Some((input, area)) = input {
+                let scroll = input.scroll(area.width.saturating_sub(2));
+                f.set_cursor(area.x + 1 + input.cursor_width() - scroll, area.y + 1);
+            }
+
+            if !app.completions.is_empty() {
+                let area = popup_area(interactive[2], app.completions.len());
+                let completions: Vec<ListItem> = app
+                    .completions
+                    .iter()
+                    .map(|t| ListItem::new(Span::raw(t.to_string())))
+                    .collect();
+                let completions = List::new(completions)
+                    .block(Block::default().title("Tags").borders(Borders::ALL))
+                    .highlight_style(selected_style);
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(completions, area, &mut app.completion_state);
+            }
+
+            if let Some(picker) = app.date_picker.as_mut() {
+                let area = centered_area(44, DatePicker::TO_ROW as u16 + 3, f.size());
+                let items = List::new(picker.items())
+                    .block(
+                        Block::default()
+                            .title("Date range (enter applies, esc cancels)")
+                            .borders(Borders::ALL),
+                    )
+                    .highlight_style(selected_style)
+                    .highlight_symbol("> ");
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(items, area, &mut picker.state);
+            }
 
             if verbosity > 0 {
                 // Area to display debug messages
@@ -283,62 +2008,184 @@ pub fn query(
                     .wrap(Wrap { trim: true });
                 f.render_widget(error, main[2]);
             }
+
+            if !readout.is_empty() {
+                let size = f.size();
+                let widest = readout.iter().map(|l| l.width()).max().unwrap_or(0);
+                let width = (widest as u16 + 2).min(size.width);
+                let height = (readout.len() as u16 + 2).min(size.height);
+                let area = Rect::new(size.width - width, 0, width, height);
+                let lines: Vec<Spans> = readout.iter().map(|l| Spans::from(l.as_str())).collect();
+                let readout = Paragraph::new(lines)
+                    .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+                    .block(
+                        Block::default()
+                            .title("Timings (ctrl-g hides)")
+                            .borders(Borders::ALL),
+                    );
+                f.render_widget(Clear, area);
+                f.render_widget(readout, area);
+            }
         }) {
             tui.clear().unwrap();
             drop(tui);
             bail!("Failed to draw TUI App {}", e.to_string());
         }
+        if let Some(protocol) = app.image_protocol {
+            trace.span("image", || app.draw_image(protocol, &mut tui));
+        }
+        trace.record("draw", draw_started.elapsed());
+        if let Some(at) = input_at.take() {
+            trace.record("latency", at.elapsed());
+        }
 
-        // Handle input
-        match events.next() {
+        // Handle input, waking up in time to start the search typing paused for, or to show
+        // the results of the one under way
+        let next = match app.pending_search {
+            Some(since) => events.next_timeout(SEARCH_DELAY.saturating_sub(since.elapsed())),
+            None if searcher.busy() => events.next_timeout(SEARCH_POLL),
+            None => events.next(),
+        };
+        match next {
             Err(e) => {
                 tui.clear().unwrap();
                 drop(tui);
                 bail!("Failed to handle input {}", e.to_string());
             }
+            Ok(event::Event::Suspend) => {
+                // Give the terminal back to the shell while stopped, like the editor gets it
+                drop(events);
+                tui.clear().unwrap();
+                drop(tui);
+                suspend();
+                events = event::Events::new();
+                app.shown_image = None;
+                tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
+                    stdout().into_raw_mode().unwrap(),
+                )))
+                .unwrap();
+            }
             Ok(ev) => {
                 if let event::Event::Input(input) = ev {
+                    let input_started = Instant::now();
+                    input_at = Some(input_started);
                     // TODO add support for:
-                    //  - ctrl-e to open selected in $EDITOR, then submit on file close
                     //  - pageup/pagedn/home/end for navigating displayed selection
                     //  - ctrl-jkdu for navigating displayed selection
-                    //  - ctrl-hl for navigating between links
                     //  - Limit query and filter input box length
-                    //  - +/- (and return) to modify weight
                     //  - ctrl-m to toggle displaying frontmatter metadata (off by default)
+                    // Outside the inputs letters are free to move around the results and preview
+                    let input = match input {
+                        Key::Char('j') if !app.focus.is_input() => Key::Down,
+                        Key::Char('k') if !app.focus.is_input() => Key::Up,
+                        key if key == keys.next => Key::Down,
+                        key if key == keys.previous => Key::Up,
+                        input => input,
+                    };
                     match input {
-                        Key::Char('\n') => {
-                            // Select choice
-                            // TODO increment weight for selected doc
-                            break;
+                        _ if app.date_picker.is_some() => app.date_picker_input(input),
+                        key if key == keys.dates => app.date_picker = Some(DatePicker::new()),
+                        Key::Ctrl('g') if trace.enabled() => trace.visible = !trace.visible,
+                        key if key == keys.all_indexes && app.indexes.len() > 1 => {
+                            app.search_all = !app.search_all;
+                            app.selected_state.select(None);
+                            app.show_selected();
                         }
-                        Key::Ctrl('c') => {
-                            break;
+                        key if key == keys.sequence => {
+                            app.by_sequence = !app.by_sequence;
+                            app.selected_state.select(None);
+                            app.show_selected();
                         }
-                        Key::Left | Key::Right | Key::Char('\t') => {
-                            app.inp_idx = match app.inp_idx {
-                                1 => 0,
-                                _ => 1,
-                            };
+                        key if key == keys.drafts => {
+                            app.show_drafts = !app.show_drafts;
+                            app.selected_state.select(None);
+                            app.show_selected();
                         }
-                        Key::Char(c) => {
-                            if app.inp_idx == 0 {
-                                app.query_input.push(c);
-                            } else {
-                                app.filter_input.push(c);
+                        key if key == keys.back && !app.link_history.is_empty() => {
+                            app.link_back();
+                        }
+                        key if key == keys.next_link => app.move_link(1),
+                        key if key == keys.previous_link => app.move_link(-1),
+                        key if key == keys.next_index
+                            && app.indexes.len() > 1
+                            && !app.search_all =>
+                        {
+                            let next = (app.index + 1) % app.indexes.len();
+                            app.use_index(client.as_ref(), next);
+                        }
+                        Key::Char('\n') | Key::Char('\t') if !app.completions.is_empty() => {
+                            app.accept_completion();
+                        }
+                        Key::Down if !app.completions.is_empty() => {
+                            app.next_completion();
+                        }
+                        Key::Up if !app.completions.is_empty() => {
+                            app.previous_completion();
+                        }
+                        Key::Esc if !app.completions.is_empty() => {
+                            app.completions.clear();
+                        }
+                        Key::Char('\n') if app.link.is_some() => app.follow_link(),
+                        Key::Esc if app.link.is_some() => app.link = None,
+                        Key::Char('\n') if app.focus == Focus::Tags => app.filter_selected_tag(),
+                        Key::Char('\n') => {
+                            // Select choice. Not counting the view isn't worth holding up the
+                            // selection over, so failures are ignored.
+                            if !read_only {
+                                let _ =
+                                    app.change_selected(cache, client.as_ref(), "view", |doc| {
+                                        doc.views = doc.views.saturating_add(1)
+                                    });
                             }
-                            app.inp_widths[app.inp_idx] += 1;
+                            break;
                         }
-                        Key::Backspace => {
-                            // TODO prevent this from going to far back
-                            if app.inp_idx == 0 {
-                                app.query_input.pop();
+                        Key::Char('+') | Key::Char('-') if !app.focus.is_input() => {
+                            let delta = if input == Key::Char('+') { 1 } else { -1 };
+                            if read_only {
+                                app.error = String::from("Read-only mode, the weight is unchanged");
                             } else {
-                                app.filter_input.pop();
+                                match app.change_selected(cache, client.as_ref(), "weight", |doc| {
+                                    doc.weight = doc.weight.saturating_add(delta)
+                                }) {
+                                    Ok(Some(doc)) => {
+                                        app.preview_status = format!("Weight {}", doc.weight)
+                                    }
+                                    Ok(None) => {}
+                                    Err(e) => {
+                                        app.error = save_error(client.as_ref(), "the weight", e)
+                                    }
+                                }
+                            }
+                        }
+                        key if key == keys.quit => {
+                            break;
+                        }
+                        Key::Ctrl('z') => {
+                            // Raw mode turns ctrl-z into a key press instead of SIGTSTP
+                            drop(events);
+                            tui.clear().unwrap();
+                            drop(tui);
+                            suspend();
+                            events = event::Events::new();
+                            app.shown_image = None;
+                            tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
+                                stdout().into_raw_mode().unwrap(),
+                            )))
+                            .unwrap();
+                        }
+                        Key::Char('\t') => {
+                            // Completing a field name comes first, moving on once there's
+                            // nothing to complete
+                            if app.focus != Focus::Filter || !app.complete_field() {
+                                app.focus = app.focus.next();
                             }
-                            app.inp_widths[app.inp_idx] -= 1;
                         }
-                        Key::Ctrl('e') => {
+                        Key::BackTab => app.focus = app.focus.previous(),
+                        key if key == keys.edit => {
+                            let (i, mut doc) = match app.selected_document() {
+                                Some(selected) => selected,
+                                None => continue,
+                            };
                             // Temporarily drop the TUI app and event handling while
                             // we shell out to EDITOR, restore these on return
                             //events.tx.send("q");
@@ -352,23 +2199,56 @@ pub fn query(
                                 .suffix(".md")
                                 .rand_bytes(5)
                                 .tempfile()?;
-                            tf.write_all(app.get_selected_contents().as_bytes())?;
-                            let editor = editor.clone();
-                            let mut editor = editor.split_whitespace();
-                            let mut cmd = Command::new(editor.next().unwrap());
-                            for arg in editor {
-                                cmd.arg(arg);
-                            }
-                            cmd.arg(tf.path())
-                                .status()
-                                .expect("failed to execute process");
+                            // Laid out like the file it came from, frontmatter and all
+                            doc.serialization_type = document::SerializationType::Disk;
+                            tf.write_all(doc.to_string().as_bytes())?;
+                            let status = run_on(&editor, tf.path());
                             events = event::Events::new();
+                            app.shown_image = None;
                             tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
                                 stdout().into_raw_mode().unwrap(),
                             )))
                             .unwrap();
+
+                            // Editors exit with an error to abandon the changes, like git
+                            // commit messages
+                            let edited = match (status, std::fs::read_to_string(tf.path())) {
+                                (Ok(status), Ok(edited)) if status.success() => edited,
+                                (Ok(_), Ok(_)) => {
+                                    app.error = String::from("The editor failed, nothing saved");
+                                    continue;
+                                }
+                                (Err(e), _) => {
+                                    app.error = format!("Failed to run {}: {}", editor, e);
+                                    continue;
+                                }
+                                (_, Err(e)) => {
+                                    app.error = format!("Failed to read the edited note: {}", e);
+                                    continue;
+                                }
+                            };
+                            if read_only {
+                                app.error = String::from("Read-only mode, nothing saved");
+                                continue;
+                            }
+                            match app.save_edited(cache, client.as_ref(), i, &doc, &edited) {
+                                Ok(saved) => {
+                                    app.show_selected();
+                                    if saved {
+                                        app.preview_status = format!(
+                                            "Saved {} as revision {}",
+                                            doc.id, app.matches[i].id
+                                        );
+                                    }
+                                }
+                                Err(e) => app.error = save_error(client.as_ref(), &doc.id, e),
+                            }
                         }
-                        Key::Ctrl('v') => {
+                        key if key == keys.view => {
+                            let contents = match app.selected_document() {
+                                Some((_, doc)) => doc.to_string(),
+                                None => continue,
+                            };
                             // Temporarily drop the TUI app and event handling while
                             // we shell out to less, restore these on return
                             //events.tx.send("q");
@@ -382,94 +2262,86 @@ pub fn query(
                                 .suffix(".md")
                                 .rand_bytes(5)
                                 .tempfile()?;
-                            tf.write_all(app.get_selected_contents().as_bytes())?;
-                            let viewer = pager.clone();
+                            tf.write_all(contents.as_bytes())?;
                             // Support setting PAGER="bat --paging always"
-                            let mut viewer = viewer.split_whitespace();
-                            let mut cmd = Command::new(viewer.next().unwrap());
-                            for arg in viewer {
-                                cmd.arg(arg);
+                            if let Err(e) = run_on(&pager, tf.path()) {
+                                app.error = format!("Failed to run {}: {}", pager, e);
                             }
-                            cmd.arg(tf.path())
-                                .status()
-                                .expect("failed to execute process");
                             events = event::Events::new();
+                            app.shown_image = None;
                             tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
                                 stdout().into_raw_mode().unwrap(),
                             )))
                             .unwrap();
                         }
-                        Key::Down | Key::Ctrl('n') => {
-                            app.next();
-                            app.preview = app.get_selected_contents();
+                        Key::Down if app.focus == Focus::Tags => app.move_tag_selection(1),
+                        Key::Up if app.focus == Focus::Tags => app.move_tag_selection(-1),
+                        Key::Down if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(1);
                         }
-                        Key::Up | Key::Ctrl('p') => {
-                            app.previous();
-                            app.preview = app.get_selected_contents();
+                        Key::Up if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(-1);
                         }
-                        _ => {}
-                    }
-
-                    let mut q = api::ApiQuery::new();
-                    q.query = Some(app.query_input.to_owned());
-
-                    q.process_filter(app.filter_input.to_owned());
-
-                    app.debug = serde_json::to_string(&q).unwrap();
-
-                    // Split up the JSON decoding into two steps.
-                    // 1.) Get the text of the body.
-                    let response_body = match client
-                        .post(uri.as_ref())
-                        .body::<String>(serde_json::to_string(&q).unwrap())
-                        .header(CONTENT_TYPE, "application/json")
-                        .send()
-                    {
-                        Ok(resp) => {
-                            if !resp.status().is_success() {
-                                app.error = format!("Request failed: {:?}", resp);
-                                continue;
-                            }
-                            match resp.text() {
-                                Ok(text) => text,
-                                Err(e) => {
-                                    app.error = format!("resp.text() failed: {:?}", e);
-                                    continue;
-                                }
-                            }
+                        Key::PageDown if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(app.preview_height.max(1) as isize);
                         }
-                        Err(e) => {
-                            app.error = format!("Send failed: {:?}", e);
-                            continue;
+                        Key::PageUp if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(-(app.preview_height.max(1) as isize));
                         }
-                    };
-
-                    // 2.) Parse the results as JSON.
-                    match serde_json::from_str::<api::ApiResponse>(&response_body) {
-                        Ok(mut resp) => {
-                            app.matches = resp
-                                .hits
-                                .iter_mut()
-                                .map(|mut m| {
-                                    m.serialization_type = document::SerializationType::Human;
-                                    m.to_owned()
-                                })
-                                .collect::<Vec<_>>();
-                            app.error = String::from("");
+                        // Elsewhere they page through the results
+                        Key::PageDown => app.turn_page(cache, &mut searcher, true),
+                        Key::PageUp => app.turn_page(cache, &mut searcher, false),
+                        Key::Home | Key::Char('g') if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(-(app.preview_cursor as isize));
                         }
-                        Err(e) => {
-                            app.error = format!(
-                                "Could not deserialize body from: {}; error: {:?}",
-                                response_body, e
-                            )
+                        Key::End | Key::Char('G') if app.focus == Focus::Preview => {
+                            app.move_preview_cursor(app.preview.lines().count() as isize);
                         }
-                    };
+                        Key::Char('v') if app.focus == Focus::Preview => {
+                            app.selection_anchor = match app.selection_anchor {
+                                Some(_) => None,
+                                None => Some(app.preview_cursor),
+                            };
+                            app.preview_status.clear();
+                        }
+                        Key::Char('y') if app.focus == Focus::Preview => {
+                            app.yank(app.selected_lines());
+                        }
+                        Key::Char('b') if app.focus == Focus::Preview => app.yank_code_block(),
+                        Key::Char('i') if app.focus == Focus::Preview && !app.images.is_empty() => {
+                            app.image = (app.image + 1) % app.images.len();
+                        }
+                        Key::Esc if app.selection_anchor.is_some() => app.selection_anchor = None,
+                        Key::Down => {
+                            app.next();
+                            app.show_selected();
+                            app.prefetch_neighbors();
+                        }
+                        Key::Up => {
+                            app.previous();
+                            app.show_selected();
+                            app.prefetch_neighbors();
+                        }
+                        key => app.edit_input(key),
+                    }
+                    trace.record("input", input_started.elapsed());
+
+                    app.debug = serde_json::to_string(&app.api_query()).unwrap();
+                    app.check_filter();
+                    app.schedule_search();
                 }
             }
         }
     }
 
+    if let Some(protocol) = app.image_protocol {
+        let _ = protocol.clear(tui.backend_mut());
+    }
     tui.clear().unwrap();
+    drop(tui);
+    if trace.enabled() {
+        eprint!("{}", trace);
+    }
 
     Ok(app.get_selected())
 }
@@ -481,12 +2353,18 @@ pub mod event {
     use std::thread;
     use std::time::Duration;
 
+    use signal_hook::consts::{SIGTSTP, SIGWINCH};
+    use signal_hook::iterator::{Handle, Signals};
     use termion::event::Key;
     use termion::input::TermRead;
 
     pub enum Event<I> {
         Input(I),
         Tick,
+        /// The terminal was resized, redraw right away rather than on the next tick
+        Resize,
+        /// SIGTSTP was received, e.g. from `kill -TSTP`
+        Suspend,
     }
 
     /// A small event handler that wrap termion input and tick events. Each event
@@ -497,6 +2375,8 @@ pub mod event {
         input_handle: thread::JoinHandle<()>,
         #[allow(dead_code)]
         tick_handle: thread::JoinHandle<()>,
+        /// Stops the SIGWINCH and SIGTSTP thread when the events are dropped
+        signal_handle: Option<Handle>,
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -536,6 +2416,22 @@ pub mod event {
                     }
                 })
             };
+            let signal_handle = Signals::new(&[SIGWINCH, SIGTSTP]).ok().map(|mut signals| {
+                let handle = signals.handle();
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for signal in signals.forever() {
+                        let event = match signal {
+                            SIGTSTP => Event::Suspend,
+                            _ => Event::Resize,
+                        };
+                        if tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                });
+                handle
+            });
             let tick_handle = {
                 thread::spawn(move || loop {
                     if tx.send(Event::Tick).is_err() {
@@ -548,11 +2444,29 @@ pub mod event {
                 rx,
                 input_handle,
                 tick_handle,
+                signal_handle,
             }
         }
 
         pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
             self.rx.recv()
         }
+
+        /// Like `next`, with a `Tick` if nothing happens within `timeout`
+        pub fn next_timeout(&self, timeout: Duration) -> Result<Event<Key>, mpsc::RecvError> {
+            match self.rx.recv_timeout(timeout) {
+                Ok(event) => Ok(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => Ok(Event::Tick),
+                Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+            }
+        }
+    }
+
+    impl Drop for Events {
+        fn drop(&mut self) {
+            if let Some(handle) = &self.signal_handle {
+                handle.close();
+            }
+        }
     }
 }
@@ -0,0 +1,34 @@
+use color_eyre::Report;
+use eyre::Result;
+use once_cell::sync::OnceCell;
+use signal_hook::consts::SIGINT;
+use signal_hook::flag;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Set by the first ctrl-c once `catch` is called
+static INTERRUPTED: OnceCell<Arc<AtomicBool>> = OnceCell::new();
+
+/// Exit status of a process ended by SIGINT
+const EXIT_STATUS: i32 = 130;
+
+/// Have ctrl-c ask long-running commands to stop at the next point they can be resumed from,
+/// rather than killing them mid-request. They check `interrupted` between steps. Pressing
+/// ctrl-c a second time exits right away.
+pub fn catch() -> Result<(), Report> {
+    INTERRUPTED.get_or_try_init(|| -> Result<_, Report> {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        // Exits when the flag is already set, so it has to come before the handler setting it
+        flag::register_conditional_shutdown(SIGINT, EXIT_STATUS, Arc::clone(&interrupted))?;
+        flag::register(SIGINT, Arc::clone(&interrupted))?;
+        Ok(interrupted)
+    })?;
+    Ok(())
+}
+
+/// Whether ctrl-c was pressed since `catch` was called
+pub fn interrupted() -> bool {
+    INTERRUPTED
+        .get()
+        .is_some_and(|interrupted| interrupted.load(Ordering::Relaxed))
+}
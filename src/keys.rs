@@ -0,0 +1,173 @@
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::Deserialize;
+use termion::event::Key;
+
+/// `[keys]` section of the config file, rebinding commands of the interactive interface, e.g.
+/// `edit = "ctrl-x"`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyConfig {
+    /// Leave the interface, ctrl-c by default
+    pub quit: Option<String>,
+    /// Open the selected note in the editor, ctrl-e by default
+    pub edit: Option<String>,
+    /// Open the selected note in the pager, ctrl-v by default
+    pub view: Option<String>,
+    /// Pick a date range to filter on, ctrl-t by default
+    pub dates: Option<String>,
+    /// Move down the results or completions, ctrl-n by default, alongside the down arrow
+    pub next: Option<String>,
+    /// Move up the results or completions, ctrl-p by default, alongside the up arrow
+    pub previous: Option<String>,
+    /// Toggle searching every index at once, ctrl-a by default
+    pub all_indexes: Option<String>,
+    /// Switch to the next index, ctrl-o by default
+    pub next_index: Option<String>,
+    /// Toggle ordering the results by folgezettel sequence, ctrl-s by default
+    pub sequence: Option<String>,
+    /// Highlight the next of the selected note's links and backlinks, to follow with enter,
+    /// ctrl-l by default
+    pub next_link: Option<String>,
+    /// Highlight the previous link, ctrl-h by default
+    pub previous_link: Option<String>,
+    /// Go back to the note the last link was followed from, ctrl-o by default. Until a link
+    /// is followed, the key switches index if it's the same as `next_index`.
+    pub back: Option<String>,
+    /// Toggle showing drafts, which searches leave out, ctrl-d by default
+    pub drafts: Option<String>,
+}
+
+/// Keys bound to the commands of the interactive interface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keys {
+    pub quit: Key,
+    pub edit: Key,
+    pub view: Key,
+    pub dates: Key,
+    pub next: Key,
+    pub previous: Key,
+    pub all_indexes: Key,
+    pub next_index: Key,
+    pub sequence: Key,
+    pub next_link: Key,
+    pub previous_link: Key,
+    pub back: Key,
+    pub drafts: Key,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys {
+            quit: Key::Ctrl('c'),
+            edit: Key::Ctrl('e'),
+            view: Key::Ctrl('v'),
+            dates: Key::Ctrl('t'),
+            next: Key::Ctrl('n'),
+            previous: Key::Ctrl('p'),
+            all_indexes: Key::Ctrl('a'),
+            next_index: Key::Ctrl('o'),
+            sequence: Key::Ctrl('s'),
+            next_link: Key::Ctrl('l'),
+            previous_link: Key::Ctrl('h'),
+            back: Key::Ctrl('o'),
+            drafts: Key::Ctrl('d'),
+        }
+    }
+}
+
+impl Keys {
+    /// The default keys with the ones in `config` swapped in
+    pub fn new(config: &KeyConfig) -> Result<Keys, Report> {
+        let defaults = Keys::default();
+        let key = |name: &Option<String>, default: Key| match name {
+            Some(name) => parse(name),
+            None => Ok(default),
+        };
+        Ok(Keys {
+            quit: key(&config.quit, defaults.quit)?,
+            edit: key(&config.edit, defaults.edit)?,
+            view: key(&config.view, defaults.view)?,
+            dates: key(&config.dates, defaults.dates)?,
+            next: key(&config.next, defaults.next)?,
+            previous: key(&config.previous, defaults.previous)?,
+            all_indexes: key(&config.all_indexes, defaults.all_indexes)?,
+            next_index: key(&config.next_index, defaults.next_index)?,
+            sequence: key(&config.sequence, defaults.sequence)?,
+            next_link: key(&config.next_link, defaults.next_link)?,
+            previous_link: key(&config.previous_link, defaults.previous_link)?,
+            back: key(&config.back, defaults.back)?,
+            drafts: key(&config.drafts, defaults.drafts)?,
+        })
+    }
+}
+
+/// Parse a key written as a single character, `ctrl-` or `alt-` followed by one, or one of
+/// esc, enter, tab, backspace, delete, up, down, left, right, home, end, pageup, pagedown and
+/// f1 to f12
+pub fn parse(name: &str) -> Result<Key, Report> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let lower = name.to_ascii_lowercase();
+    let key = if let Some(c) = lower.strip_prefix("ctrl-").and_then(single) {
+        Key::Ctrl(c)
+    } else if let Some(c) = name
+        .get(4..)
+        .filter(|_| lower.starts_with("alt-"))
+        .and_then(single)
+    {
+        Key::Alt(c)
+    } else if let Some(c) = single(name) {
+        Key::Char(c)
+    } else {
+        match lower.as_str() {
+            "esc" | "escape" => Key::Esc,
+            "enter" | "return" => Key::Char('\n'),
+            "tab" => Key::Char('\t'),
+            "backspace" => Key::Backspace,
+            "delete" | "del" => Key::Delete,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            f => match f.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if (1..=12).contains(&n) => Key::F(n),
+                _ => return Err(eyre!("Unknown key {} in the [keys] config section", name)),
+            },
+        }
+    };
+    Ok(key)
+}
+
+/// How `key` is written in the config file, for hints in the interface
+pub fn name(key: Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::Char('\n') => String::from("enter"),
+        Key::Char('\t') => String::from("tab"),
+        Key::Char(c) => c.to_string(),
+        Key::Esc => String::from("esc"),
+        Key::Backspace => String::from("backspace"),
+        Key::Delete => String::from("delete"),
+        Key::Up => String::from("up"),
+        Key::Down => String::from("down"),
+        Key::Left => String::from("left"),
+        Key::Right => String::from("right"),
+        Key::Home => String::from("home"),
+        Key::End => String::from("end"),
+        Key::PageUp => String::from("pageup"),
+        Key::PageDown => String::from("pagedown"),
+        Key::F(n) => format!("f{}", n),
+        key => format!("{:?}", key),
+    }
+}
@@ -0,0 +1,122 @@
+use crate::date::Date;
+use serde_yaml::{Mapping, Value};
+use std::str::FromStr;
+
+/// The raw text of each top-level frontmatter field in the order it appeared in the source
+/// file. Writing a document back to disk reuses the original text of every field whose value
+/// didn't change, so rewriting a file keeps its field order, quoting and comments intact.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrontmatterLayout {
+    /// Comments and blank lines before the first field
+    preamble: String,
+    /// (field name, raw text including any continuation lines)
+    entries: Vec<(String, String)>,
+}
+
+/// Field names accepted as aliases when deserializing a `Document`
+fn canonical(key: &str) -> &str {
+    match key {
+        "author" => "authors",
+        "tag" => "tags",
+        k => k,
+    }
+}
+
+impl FrontmatterLayout {
+    /// Record the layout of the frontmatter block at the start of `s`, if there is one
+    pub fn parse(s: &str) -> FrontmatterLayout {
+        let mut layout = FrontmatterLayout::default();
+
+        let mut lines = s.trim_start().lines();
+        if lines.next().map(str::trim_end) != Some("---") {
+            return layout;
+        }
+        for line in lines {
+            if line.trim_end() == "---" || line.trim_end() == "..." {
+                break;
+            }
+            let starts_field = !line
+                .starts_with(|c: char| c.is_whitespace() || c == '-' || c == '#')
+                && line.contains(':');
+            if starts_field {
+                let key = line.split(':').next().unwrap().trim();
+                let key = key.trim_matches(|c| c == '"' || c == '\'');
+                layout.entries.push((key.to_string(), String::new()));
+            }
+            let raw = match layout.entries.last_mut() {
+                Some((_, raw)) => raw,
+                None => &mut layout.preamble,
+            };
+            raw.push_str(line);
+            raw.push('\n');
+        }
+
+        layout
+    }
+
+    /// Render `fields` as YAML, reusing the recorded text for every unchanged field. Fields
+    /// that weren't in the source are appended in the order they appear in `fields`.
+    pub fn render(&self, fields: &Mapping) -> String {
+        let mut out = self.preamble.to_owned();
+        let mut written = Vec::new();
+
+        for (key, raw) in &self.entries {
+            let name = canonical(key);
+            let value = match fields.get(&Value::from(name)) {
+                Some(v) => v,
+                // The field was dropped from the document
+                None => continue,
+            };
+            if written.contains(&name) {
+                continue;
+            }
+            written.push(name);
+
+            let original = serde_yaml::from_str::<Mapping>(raw)
+                .ok()
+                .and_then(|m| m.get(&Value::from(key.as_str())).cloned());
+            match original {
+                Some(original) if name == key && equivalent(name, &original, value) => {
+                    out.push_str(raw)
+                }
+                _ => out.push_str(&render_field(name, value)),
+            }
+        }
+
+        for (key, value) in fields {
+            match key.as_str() {
+                Some(k) if written.contains(&k) => {}
+                Some(k) => out.push_str(&render_field(k, value)),
+                None => {}
+            }
+        }
+
+        out
+    }
+}
+
+/// Compare field values semantically, so a date written in a different but equivalent format
+/// is still considered unchanged
+fn equivalent(key: &str, a: &Value, b: &Value) -> bool {
+    if a == b {
+        return true;
+    }
+    if key == "date" || key == "created" {
+        let parse = |v: &Value| match v {
+            Value::String(s) => Date::from_str(s).ok(),
+            Value::Number(n) => n.as_i64().map(Date::new),
+            _ => None,
+        };
+        if let (Some(a), Some(b)) = (parse(a), parse(b)) {
+            return a == b;
+        }
+    }
+    false
+}
+
+fn render_field(key: &str, value: &Value) -> String {
+    let mut m = Mapping::new();
+    m.insert(Value::from(key), value.clone());
+    let s = serde_yaml::to_string(&m).unwrap_or_default();
+    s.trim_start_matches("---\n").to_string()
+}
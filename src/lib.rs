@@ -1,5 +1,56 @@
 pub mod api;
+pub mod audit;
+pub mod authors;
+pub mod backup;
+pub mod cache;
+pub mod clipboard;
+pub mod config;
+pub mod confirm;
 pub mod date;
+pub mod diff;
 pub mod document;
+pub mod export;
+pub mod git;
+pub mod handler;
+pub mod highlight;
+pub mod ignore;
+pub mod image;
+pub mod import;
+pub mod interrupt;
 pub mod interactive;
+pub mod keys;
+pub mod layout;
+pub mod links;
+pub mod lock;
+pub mod migrate;
+pub mod note;
+pub mod output;
+pub mod permalink;
+pub mod picker;
+pub mod plugin;
+pub mod prefetch;
 pub mod query;
+pub mod reload;
+pub mod reminder;
+pub mod remote;
+pub mod replicate;
+pub mod report;
+pub mod revision;
+pub mod roundtrip;
+pub mod search;
+pub mod searcher;
+pub mod sequence;
+pub mod settings;
+pub mod share;
+pub mod state;
+pub mod status;
+pub mod tenant;
+pub mod textinput;
+pub mod titles;
+pub mod trace;
+pub mod triage;
+pub mod undo;
+pub mod validation;
+pub mod watch;
+pub mod webhook;
+pub mod zettel;
@@ -0,0 +1,247 @@
+use crate::document::Document;
+use crate::zettel;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Prefix of links to a note by ID, as the TUI copies them
+const NOTE_SCHEME: &str = "meilizet://note/";
+
+/// Where the links in a note's body point, as written: the targets of markdown links,
+/// `[text](target)`, and of Obsidian-style wiki-links, `[[target]]`, `[[target|text]]` or
+/// `[[target#heading]]`. Links to web pages and to headings in the same note are left out.
+pub fn targets(body: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("](") {
+        let after = &rest[start + 2..];
+        let end = match after.find(')') {
+            Some(end) => end,
+            None => break,
+        };
+        // A link title after the target, as in `[text](target "title")`, isn't part of it
+        let target = after[..end].split_whitespace().next().unwrap_or_default();
+        if let Some(target) = local(target) {
+            targets.push(target);
+        }
+        rest = &after[end..];
+    }
+    let mut rest = body;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let end = match after.find("]]") {
+            Some(end) => end,
+            None => break,
+        };
+        if let Some(target) = wiki(&after[..end]) {
+            targets.push(target);
+        }
+        rest = &after[end + 2..];
+    }
+    targets
+}
+
+/// The note a markdown link target names, by the last part of its path, unless it points
+/// elsewhere
+fn local(target: &str) -> Option<String> {
+    let target = target.trim_start_matches('<').trim_end_matches('>');
+    if let Some(id) = target.strip_prefix(NOTE_SCHEME) {
+        return Some(id.to_owned());
+    }
+    if target.is_empty() || target.starts_with('#') || target.contains(':') {
+        return None;
+    }
+    let path = target.split('#').next().unwrap_or_default();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.replace("%20", " "))
+    }
+}
+
+/// The note a wiki-link names, without the text shown for it or the heading it points at
+fn wiki(link: &str) -> Option<String> {
+    let target = link.split('|').next().unwrap_or_default();
+    let target = target.split('#').next().unwrap_or_default().trim();
+    if target.is_empty() {
+        None
+    } else {
+        Some(target.to_owned())
+    }
+}
+
+/// Note IDs by the names links use for notes: their ID, filename with or without the
+/// extension, slug and the zettel ID their filename starts with, and failing those, their
+/// title, ignoring case
+#[derive(Default)]
+pub struct Resolver {
+    ids: HashMap<String, String>,
+    titles: HashMap<String, String>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    /// Make `doc` known by its names. IDs win over the other names when they clash.
+    pub fn insert(&mut self, doc: &Document) {
+        if doc.id.is_empty() {
+            return;
+        }
+        self.ids.insert(doc.id.to_owned(), doc.id.to_owned());
+        let stem = doc.filename.rsplitn(2, '.').last().unwrap_or_default();
+        let mut names = vec![doc.filename.as_str(), stem, doc.slug.as_str()];
+        names.extend(zettel::from_filename(&doc.filename));
+        for name in names {
+            if !name.is_empty() {
+                self.ids
+                    .entry(name.to_owned())
+                    .or_insert_with(|| doc.id.to_owned());
+            }
+        }
+        let title = normalize(&doc.title);
+        if !title.is_empty() {
+            // Of notes sharing a title, which import warns about, links go to the first
+            self.titles
+                .entry(title)
+                .or_insert_with(|| doc.id.to_owned());
+        }
+    }
+
+    /// ID of the note `target` names, if it's one of them
+    pub fn resolve(&self, target: &str) -> Option<&str> {
+        self.ids
+            .get(target)
+            .or_else(|| self.titles.get(&normalize(target)))
+            .map(|id| id.as_str())
+    }
+}
+
+/// Lowercased, with runs of whitespace made single spaces
+fn normalize(title: &str) -> String {
+    title
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// What's known of one note while linking up an import
+struct Note {
+    /// File the note was imported from
+    filename: String,
+    /// Links as written, resolved to IDs where possible
+    links: Vec<String>,
+    /// Backlinks as stored in the index
+    backlinks: Vec<String>,
+    /// Whether the note was just imported, and needs its links and backlinks stored again
+    imported: bool,
+}
+
+/// The links between the notes of an index, with the notes of an import added as they're
+/// uploaded. Once they're all in, `updates` gives the changes to store, the links of the new
+/// notes and the backlinks of whatever they link to.
+#[derive(Default)]
+pub struct Graph {
+    resolver: Resolver,
+    notes: BTreeMap<String, Note>,
+}
+
+impl Graph {
+    /// `indexed` are the notes already in the index, with their `links` and `backlinks`
+    pub fn new(indexed: Vec<Document>) -> Graph {
+        let mut graph = Graph::default();
+        for doc in indexed {
+            graph.resolver.insert(&doc);
+            graph.notes.insert(
+                doc.id,
+                Note {
+                    filename: doc.filename,
+                    links: doc.links,
+                    backlinks: doc.backlinks,
+                    imported: false,
+                },
+            );
+        }
+        graph
+    }
+
+    /// Add a note being imported, linking to the notes named in its frontmatter and its body
+    pub fn add(&mut self, doc: &Document) {
+        self.resolver.insert(doc);
+        let mut links = doc.links.clone();
+        links.extend(targets(&doc.body));
+        self.notes.insert(
+            doc.id.to_owned(),
+            Note {
+                filename: doc.filename.to_owned(),
+                links,
+                // Uploading the note replaced the ones it had
+                backlinks: Vec::new(),
+                imported: true,
+            },
+        );
+    }
+
+    /// The links of the imported notes that don't name any note, by the file they're in
+    pub fn unresolved(&self) -> Vec<(&str, &str)> {
+        self.notes
+            .values()
+            .filter(|note| note.imported)
+            .flat_map(|note| {
+                note.links
+                    .iter()
+                    .filter(move |link| self.resolver.resolve(link).is_none())
+                    .map(move |link| (note.filename.as_str(), link.as_str()))
+            })
+            .collect()
+    }
+
+    /// Partial documents setting the `links` and `backlinks` that changed
+    pub fn updates(self) -> Vec<Value> {
+        let resolver = &self.resolver;
+        let links: BTreeMap<&str, Vec<String>> = self
+            .notes
+            .iter()
+            .map(|(id, note)| {
+                let mut seen = BTreeSet::new();
+                let links = note
+                    .links
+                    .iter()
+                    .map(|link| resolver.resolve(link).unwrap_or(link).to_owned())
+                    .filter(|link| link != id && seen.insert(link.to_owned()))
+                    .collect();
+                (id.as_str(), links)
+            })
+            .collect();
+        let mut backlinks: HashMap<&str, Vec<String>> = HashMap::new();
+        for (id, targets) in &links {
+            for target in targets {
+                if self.notes.contains_key(target) {
+                    backlinks
+                        .entry(target.as_str())
+                        .or_default()
+                        .push(id.to_string());
+                }
+            }
+        }
+
+        let mut updates = Vec::new();
+        for (id, note) in &self.notes {
+            let links = &links[id.as_str()];
+            let backlinks = backlinks.remove(id.as_str()).unwrap_or_default();
+            let mut update = json!({ "id": id });
+            if note.imported || *links != note.links {
+                update["links"] = json!(links);
+            }
+            if note.imported || backlinks != note.backlinks {
+                update["backlinks"] = json!(backlinks);
+            }
+            if update.as_object().map_or(0, |fields| fields.len()) > 1 {
+                updates.push(update);
+            }
+        }
+        updates
+    }
+}
@@ -0,0 +1,79 @@
+use color_eyre::Report;
+use eyre::{bail, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// An advisory lock on a file, held until dropped. Locks only keep out other meilizet
+/// processes, and go away with the process holding them, even when it's killed.
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Lock `path` for `activity`, e.g. `syncing vault work`, failing right away with what the
+    /// process holding it is doing when it's taken
+    pub fn acquire(path: &Path, activity: &str) -> Result<Lock, Report> {
+        let mut file = open(path)?;
+        if let Err(e) = flock(&file, libc::LOCK_EX | libc::LOCK_NB) {
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e.into());
+            }
+            let mut holder = String::new();
+            file.read_to_string(&mut holder)?;
+            match holder.trim() {
+                "" => bail!("Another meilizet process holds {}", path.display()),
+                holder => bail!("Another meilizet process is {}", holder),
+            }
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(activity.as_bytes())?;
+        Ok(Lock { file })
+    }
+
+    /// Lock `path`, waiting for the process holding it to let go, for short critical sections
+    pub fn wait(path: &Path) -> Result<Lock, Report> {
+        let file = open(path)?;
+        flock(&file, libc::LOCK_EX)?;
+        Ok(Lock { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Closing the file would release it too, this is just sooner and explicit
+        let _ = flock(&self.file, libc::LOCK_UN);
+    }
+}
+
+/// Lock file next to the state file `state_path` guarding changes to `index`
+pub fn index_path(state_path: &str, index: &str) -> PathBuf {
+    let state = PathBuf::from(shellexpand::tilde(state_path).as_ref());
+    state
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join("locks")
+        .join(format!("{}.lock", index))
+}
+
+fn open(path: &Path) -> Result<File, Report> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
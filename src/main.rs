@@ -1,14 +1,34 @@
 mod interactive;
 mod query;
 use color_eyre::Report;
+use eyre::{bail, eyre};
 use glob::{glob, Paths};
-use meilizet::{api, document};
-use reqwest::header::CONTENT_TYPE;
+use meilizet::{
+    api, audit, authors, backup, cache, clipboard, config, confirm, date, diff, document, export,
+    git, handler, highlight, ignore, image, import, interrupt, keys, links, lock, migrate, note,
+    output, permalink, picker, plugin, prefetch, reload, reminder, remote, replicate, report,
+    revision, roundtrip, search, searcher, sequence, settings, share, state, status, tenant,
+    textinput, titles, trace, triage, undo, validation, watch, webhook, zettel,
+};
+use notify::Watcher;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use std::{env, mem, thread};
+use structopt::clap::ArgMatches;
 use structopt::StructOpt;
 use url::Url;
 
+/// The optional cargo features, and whether this binary was built with each, for `meta`
+const FEATURES: &[(&str, bool)] = &[("integration", cfg!(feature = "integration"))];
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "meilizet",
@@ -28,8 +48,44 @@ struct Opt {
     )]
     host: String,
 
-    #[structopt(short, long, default_value = "", env = "MEILI_KEY")]
-    key: String,
+    /// Master key or API key of the server, sent with every request to it
+    #[structopt(short = "k", long, env = "MEILI_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Meilisearch index holding the notes, accepted before or after the subcommand
+    #[structopt(long, global = true, default_value = "notes", env = "MEILIZET_INDEX")]
+    index: String,
+
+    /// Use the index and server of this vault from the config file instead of --index and
+    /// --host
+    #[structopt(long, global = true, env = "MEILIZET_VAULT")]
+    vault: Option<String>,
+
+    /// Refuse to import or otherwise modify the index, for safely pointing at a shared one
+    #[structopt(long)]
+    read_only: bool,
+
+    /// Don't ask before changing many documents at once
+    #[structopt(short, long, global = true)]
+    yes: bool,
+
+    /// Print plain text without colors, also turned off by setting NO_COLOR
+    #[structopt(long, global = true)]
+    no_color: bool,
+
+    /// Time drawing, input handling and searches in the interactive interface, shown in a
+    /// readout toggled with ctrl-g and summarized when it exits
+    #[structopt(long, global = true)]
+    trace: bool,
+
+    /// Send requests uncompressed and don't ask for compressed responses
+    #[structopt(long)]
+    no_compression: bool,
+
+    /// Give up on requests taking longer than this many seconds, 0 waits however long they
+    /// take. Defaults to `timeout_secs` in the `[http]` config section.
+    #[structopt(long, global = true)]
+    request_timeout: Option<u64>,
 
     #[structopt(short, long, default_value = "less", env = "PAGER")]
     pager: String,
@@ -37,6 +93,34 @@ struct Opt {
     #[structopt(short, long, default_value = "vim", env = "EDITOR")]
     editor: String,
 
+    /// Path to the TOML config file
+    #[structopt(long = "config", default_value = config::DEFAULT_PATH, env = "MEILIZET_CONFIG")]
+    config_path: String,
+
+    #[structopt(skip)]
+    config: config::Config,
+
+    #[structopt(skip)]
+    keys: keys::Keys,
+
+    #[structopt(skip)]
+    client: Option<reqwest::blocking::Client>,
+
+    #[structopt(skip)]
+    external_client: Option<reqwest::blocking::Client>,
+
+    /// Path to the JSON file tracking state between runs
+    #[structopt(long = "state", default_value = state::DEFAULT_PATH, env = "MEILIZET_STATE")]
+    state_path: String,
+
+    /// Path to the log of changes that can be undone
+    #[structopt(long = "undo-log", default_value = undo::DEFAULT_PATH, env = "MEILIZET_UNDO_LOG")]
+    undo_log_path: String,
+
+    /// Path to the log of the imports run, see `log show`
+    #[structopt(long = "audit-log", default_value = audit::DEFAULT_PATH, env = "MEILIZET_AUDIT_LOG")]
+    audit_log_path: String,
+
     #[structopt(subcommand)]
     subcmd: Subcommands,
 }
@@ -44,24 +128,591 @@ struct Opt {
 #[derive(Debug, StructOpt)]
 enum Subcommands {
     /// Import markdown-fm-doc formatted files matching the unexpanded glob pattern
-    ImportLegacyMd { globpath: String },
+    ImportLegacyMd {
+        globpath: String,
+        #[structopt(flatten)]
+        opts: ImportOpts,
+    },
     /// Import meilizet/Document formatted files matching the unexpanded glob pattern
-    Import { globpath: String },
+    Import {
+        /// Files to import, relative to the repository with --git-url. Defaults to the `paths`
+        /// in the config file.
+        globpath: Option<String>,
+        /// Clone or pull this repository of notes and import it, only the files changed since
+        /// the commit imported last time after the first run
+        #[structopt(long)]
+        git_url: Option<String>,
+        /// Import an S3 bucket (s3://bucket/prefix) or WebDAV share (its http(s):// URL), only
+        /// the files whose ETags changed after the first run. S3 credentials are read from
+        /// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_REGION and AWS_ENDPOINT_URL.
+        #[structopt(long, conflicts_with = "git-url")]
+        remote: Option<String>,
+        #[structopt(flatten)]
+        opts: ImportOpts,
+    },
+    /// Import the files matching the unexpanded glob pattern, then keep the index in sync with
+    /// them until interrupted: files created or modified are imported, and the documents of
+    /// files removed are deleted
+    Watch {
+        /// Files to watch. Defaults to the `paths` in the config file.
+        globpath: Option<String>,
+        #[structopt(flatten)]
+        opts: ImportOpts,
+    },
+    /// Import the vault picked with --vault, or every vault in the config file, into its index
+    Sync {
+        /// Sync every vault
+        #[structopt(long)]
+        all: bool,
+        #[structopt(flatten)]
+        opts: ImportOpts,
+    },
     /// Interactively query the server
-    Query {},
+    ///
+    /// When stdin or stdout isn't a terminal, searches once and prints the ID and title of each
+    /// match instead, taking the query from stdin if it isn't given
+    Query {
+        /// What to search for to begin with
+        query: Option<String>,
+        /// Search without document bodies and fetch the body of the selected document on
+        /// demand, for broad queries over long notes
+        #[structopt(long)]
+        lazy: bool,
+        /// Choose from the matches with this fuzzy finder instead of the built-in interface,
+        /// e.g. `fzf` or `sk --multi`
+        #[structopt(long)]
+        picker: Option<String>,
+        /// Print the selected notes rather than just their IDs
+        #[structopt(long)]
+        contents: bool,
+    },
     /// Non-interactive query, specify all parameters from the command line
     StaticQuery {
         #[structopt(default_value = "")]
         query: String,
         #[structopt(default_value = "")]
         filter: String,
+        /// Sort expression, e.g. `modified:desc`; sortable fields are date, created, modified,
+        /// imported_at, weight, writes and views
+        #[structopt(short, long)]
+        sort: Option<String>,
+        /// Language of the query as an ISO 639-3 code, e.g. `deu`, instead of the `locales`
+        /// config setting; repeat for queries in one of several
+        #[structopt(long = "locale")]
+        locales: Vec<String>,
+    },
+    /// Print the note with the given ID
+    Get {
+        id: String,
+        /// markdown for frontmatter and body as in a note file, json for every field as stored
+        /// in the index, or body for just the body
+        #[structopt(long, default_value = "markdown")]
+        format: document::GetFormat,
+    },
+    /// List the revisions of a note, oldest first, as made by editing it in the interface.
+    /// The ID may be that of any of them.
+    History { id: String },
+    /// Mark a draft as published, firing the webhooks subscribed to the `published` event
+    Publish { id: String },
+    /// Set when to be reminded of a note, or with --review when it's due to be looked over
+    /// again, for `due` to list it from then on
+    Remind {
+        id: String,
+        /// How long from now, e.g. 3d, 2w, 6m or 1y
+        #[structopt(long = "in", conflicts_with = "at")]
+        after: Option<String>,
+        /// A date instead, e.g. 2025-01-31
+        #[structopt(long)]
+        at: Option<date::Date>,
+        /// Set the review date rather than the reminder
+        #[structopt(long)]
+        review: bool,
+        /// Remove the date
+        #[structopt(long, conflicts_with_all = &["after", "at"])]
+        clear: bool,
+    },
+    /// List the notes whose reminder or review date has come, longest overdue first
+    Due {
+        /// Also list the ones coming due within this long, e.g. 3d
+        #[structopt(long)]
+        within: Option<String>,
+    },
+    /// Create a search-only key for a public website, limited to the notes matching a filter,
+    /// and print a snippet searching the index with it through InstantSearch
+    PublishKey {
+        /// Notes the key finds, in the syntax of the filter input
+        #[structopt(long, default_value = tenant::DEFAULT_FILTER)]
+        filter: String,
+        /// Address the website reaches the server at, when it's not --host
+        #[structopt(long)]
+        public_host: Option<String>,
+        /// When the key stops working, e.g. 2025-12-31, never when unset
+        #[structopt(long)]
+        expires: Option<date::Date>,
+    },
+    /// Search once and print a line per match, for scripts and fuzzy finders like fzf
+    Search {
+        #[structopt(default_value = "")]
+        query: String,
+        /// Filter in the syntax of the filter input, e.g. `vim | !bash | status = draft`
+        #[structopt(short, long, default_value = "")]
+        filter: String,
+        /// Most matches to print
+        #[structopt(short, long, default_value = "20")]
+        limit: u32,
+        /// Sort expression, e.g. `modified:desc`, instead of newest first
+        #[structopt(short, long)]
+        sort: Option<String>,
+        /// json for the whole note as a JSON object, tsv for its ID, title, date, tags and
+        /// filename, or titles for its ID and title
+        #[structopt(long, default_value = "titles")]
+        format: search::SearchFormat,
     },
     /// Dump records to a local path
     Dump { path: String },
-    /// Opens $EDITOR on a template and then adds it when the editor is closed
-    New {},
+    /// Export records to a local directory in the given format
+    Export {
+        path: String,
+        /// One of: markdown, anki, opml
+        #[structopt(short, long, default_value = "markdown")]
+        format: export::ExportFormat,
+        /// Only export the notes matching this filter, written like in the interactive filter
+        /// input
+        #[structopt(long)]
+        filter: Option<String>,
+    },
+    /// Bundle a document and its linked neighborhood into a single file to share
+    Share {
+        id: String,
+        /// Number of link hops to follow from the shared document
+        #[structopt(short, long, default_value = "2")]
+        depth: usize,
+        /// One of: html, markdown
+        #[structopt(short, long, default_value = "html")]
+        format: share::ShareFormat,
+        /// Write the bundle here instead of to stdout
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Print the permalink of a note, see the `permalink_template` config setting
+    Url {
+        id: String,
+        /// Look up the ID of the note with the given permalink instead
+        #[structopt(short, long)]
+        reverse: bool,
+    },
+    /// Open the note a meilizet://note/<id> URL points to in $EDITOR
+    HandleUrl {
+        #[structopt(required_unless = "register")]
+        url: Option<String>,
+        /// Print the note instead
+        #[structopt(long)]
+        print: bool,
+        /// Make this the desktop's handler for meilizet:// URLs
+        #[structopt(long)]
+        register: bool,
+    },
+    /// Check that files matching the unexpanded glob pattern survive being parsed and written
+    /// back to disk unchanged
+    VerifyRoundtrip { globpath: String },
+    /// Check files matching the unexpanded glob pattern against the `[validation]` rules in
+    /// the config file
+    Lint { globpath: String },
+    /// Rewrite documents in the index to clean up inconsistencies
+    Normalize {
+        /// Print how each document changes
+        #[structopt(long)]
+        diff: bool,
+        /// Only print the changes, without applying them
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(subcommand)]
+        target: NormalizeTarget,
+    },
+    /// Bring the documents of an index stored by older versions up to the current schema,
+    /// applying the migrations not yet applied to it
+    Migrate {
+        /// List the migrations and whether each was applied instead
+        #[structopt(short, long)]
+        list: bool,
+        /// Only count the documents each migration would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Rebuild the index from scratch, the last resort when its settings or documents got
+    /// into a weird state: its settings and documents are saved to a file, the index is
+    /// deleted and created again with the same settings, and the documents are added back and
+    /// counted
+    Reindex {
+        /// JSON file of settings to apply instead of the current ones, in the format of the
+        /// Meilisearch settings endpoint
+        #[structopt(long)]
+        settings: Option<PathBuf>,
+        /// Where to save the settings and documents first. Defaults to a file named after the
+        /// index and the time, next to the state file.
+        #[structopt(long)]
+        backup: Option<PathBuf>,
+        /// Rebuild the index from a backup saved by an earlier reindex or index swap instead
+        /// of its current contents, e.g. after one failed halfway
+        #[structopt(long)]
+        restore: Option<PathBuf>,
+    },
+    /// Copy the documents and settings of the index to other servers, replacing what their
+    /// index of the same name held, then compare counts and a sample of documents. Servers
+    /// are copied to in parallel.
+    Replicate {
+        /// URL of a server to copy to, may be repeated
+        #[structopt(long, required = true, number_of_values = 1)]
+        to: Vec<String>,
+        /// API key of the servers copied to
+        #[structopt(long, env = "MEILIZET_REPLICA_API_KEY", hide_env_values = true)]
+        to_api_key: Option<String>,
+        /// Index to copy to, the same one as copied from by default
+        #[structopt(long)]
+        to_index: Option<String>,
+    },
+    /// Manage the indexes on the server
+    Index {
+        #[structopt(subcommand)]
+        action: IndexAction,
+    },
+    /// Manage which attributes of the index are searchable, filterable and sortable, and how
+    /// results are ranked. Filtering on an attribute fails until it's filterable.
+    Settings {
+        #[structopt(subcommand)]
+        action: SettingsAction,
+    },
+    /// Delete documents by ID, or every document matching a filter, after showing which
+    Delete {
+        ids: Vec<String>,
+        /// Meilisearch filter expression selecting the documents, e.g. `tags = draft`
+        #[structopt(long, conflicts_with = "ids", required_unless = "ids")]
+        filter: Option<String>,
+        /// List the documents that would be deleted, which are still looked up in the index,
+        /// without deleting them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Follow the asynchronous tasks Meilisearch applies changes with, whose IDs are printed by
+    /// import
+    Task {
+        #[structopt(subcommand)]
+        action: TaskAction,
+    },
+    /// Manage the config file
+    Config {
+        #[structopt(subcommand)]
+        action: ConfigAction,
+    },
+    /// Check that the server, the API key and the index are set up for meilizet. Unless in
+    /// read-only mode, a document is also added, searched for and deleted in a scratch index.
+    Selftest,
+    /// Print the version, the Meilisearch versions supported, the cargo features built in and
+    /// the configuration in effect as JSON, for scripts and editor plugins
+    Meta,
+    /// Revert the latest change made to the index, or the one with the given ID, by a command
+    /// or in the interactive interface. Documents changed since are only overwritten with
+    /// --yes.
+    Undo {
+        id: Option<u64>,
+        /// List the changes that can be undone instead
+        #[structopt(short, long)]
+        list: bool,
+    },
+    /// Review the imports and syncs run, as recorded in the import log
+    Log {
+        #[structopt(subcommand)]
+        action: LogAction,
+    },
+    /// List the notes waiting to be triaged, tagged `inbox` or not tagged at all, oldest first
+    Inbox {
+        /// Step through them instead, tagging, linking to a parent, archiving or deleting each
+        /// with a single key
+        #[structopt(long)]
+        triage: bool,
+    },
+    /// Find notes in need of attention
+    Report {
+        #[structopt(subcommand)]
+        kind: ReportKind,
+    },
+    /// Work with the folgezettel sequences of notes, e.g. `1a2b`
+    Sequence {
+        #[structopt(subcommand)]
+        action: SequenceAction,
+    },
+    /// Write a new note to the `notes_dir` set in the config file, open it in the editor and
+    /// import it once the editor is closed, unless it was left unchanged
+    New {
+        title: String,
+        /// Tags of the note, may be given more than once
+        #[structopt(short, long = "tag")]
+        tags: Vec<String>,
+        /// Wait for Meilisearch to finish indexing the note
+        #[structopt(long)]
+        wait: bool,
+    },
     /// Adds TOML-based document
     Add {},
+    /// List the plugins on the PATH: executables named `meilizet-<name>`, run as
+    /// `mz <name>` with the server, API key and index in MEILI_HOST, MEILI_API_KEY and
+    /// MEILIZET_INDEX
+    Plugins,
+    #[structopt(external_subcommand)]
+    Plugin(Vec<String>),
+}
+
+#[derive(Debug, StructOpt)]
+enum IndexAction {
+    /// Create an index
+    Create {
+        uid: String,
+        /// Document attribute identifying documents
+        #[structopt(long, default_value = "id")]
+        primary_key: String,
+    },
+    /// List the indexes on the server
+    List,
+    /// Delete an index along with all of its documents
+    Delete { uid: String },
+    /// Exchange the documents and settings of two indexes
+    Swap { first: String, second: String },
+}
+
+#[derive(Debug, StructOpt)]
+enum SettingsAction {
+    /// Print every setting, or the values of one
+    Get { setting: Option<settings::Setting> },
+    /// Replace the values of a setting: searchable, filterable, sortable, ranking-rules, or
+    /// localized-attributes as attribute patterns and their locales, e.g. `title_de,body_de=deu`
+    Set {
+        setting: settings::Setting,
+        #[structopt(required = true)]
+        values: Vec<String>,
+    },
+    /// Restore a setting to the server's default
+    Reset { setting: settings::Setting },
+    /// Configure the settings suiting the note schema
+    Init,
+}
+
+#[derive(Debug, StructOpt)]
+enum ConfigAction {
+    /// Write a commented template of every setting to the config file
+    Init {
+        /// Replace the config file if it exists
+        #[structopt(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum ReportKind {
+    /// Notes without any tags
+    Untagged {
+        #[structopt(flatten)]
+        opts: ReportOpts,
+    },
+    /// Notes that link to nothing, aren't linked to, and have neither a parent nor children
+    Unlinked {
+        #[structopt(flatten)]
+        opts: ReportOpts,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct ReportOpts {
+    /// Print the count and the notes as JSON
+    #[structopt(long, conflicts_with = "open")]
+    json: bool,
+    /// Step through the notes in the triage interface instead, tagging, linking, archiving or
+    /// deleting each
+    #[structopt(long)]
+    open: bool,
+}
+
+#[derive(Debug, StructOpt)]
+enum SequenceAction {
+    /// Print the next free branch off the sequence of a note, e.g. `1a3` when `1a1` and `1a2`
+    /// are taken
+    Next { id: String },
+}
+
+#[derive(Debug, StructOpt)]
+enum TaskAction {
+    /// Print the state of a task
+    Status { uid: u64 },
+    /// Wait for a task to finish, failing if it did
+    Wait {
+        uid: u64,
+        /// Give up after this many seconds
+        #[structopt(long)]
+        timeout: Option<u64>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum LogAction {
+    /// Print the imports run, oldest first, with the IDs they touched when verbose
+    Show {
+        /// Only the latest N
+        #[structopt(long)]
+        last: Option<usize>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum NormalizeTarget {
+    /// Replace author aliases with the canonical names from the `[authors]` config section
+    Authors,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+struct ImportOpts {
+    /// Wait for Meilisearch to finish indexing each batch, so the time it takes shows up in the
+    /// report
+    #[structopt(long)]
+    wait: bool,
+    /// Rough upper bound in MiB on memory used to buffer documents; larger values send fewer,
+    /// bigger batches
+    #[structopt(long, default_value = "32")]
+    max_memory: usize,
+    /// Send a batch once it holds this many documents, even when it's under the memory bound
+    #[structopt(long)]
+    batch_size: Option<usize>,
+    /// Number of threads reading and parsing files
+    #[structopt(short, long, default_value = "1")]
+    jobs: usize,
+    /// Pick up an interrupted import of the same glob pattern, skipping the batches it
+    /// already finished
+    #[structopt(long)]
+    resume: bool,
+    /// Refuse documents breaking the `[validation]` rules in the config file
+    #[structopt(long)]
+    strict: bool,
+    /// Print how each document differs from the one in the index
+    #[structopt(long)]
+    diff: bool,
+    /// Parse and check the files and list the documents that would be uploaded, without
+    /// contacting the server unless --diff is given too
+    #[structopt(long)]
+    dry_run: bool,
+    /// Import every file, including the ones unchanged since they were last imported
+    #[structopt(long)]
+    force: bool,
+}
+
+/// Sends the batches of one import, keeping track of how they went
+struct Uploader<'a> {
+    opt: &'a Opt,
+    opts: &'a ImportOpts,
+    client: reqwest::blocking::Client,
+    url: Url,
+    event: webhook::Event,
+    report: import::ImportReport,
+    state: state::State,
+    /// Entry in `state.imports` recording this import
+    key: String,
+    /// Content hash and document ID of each queued file, recorded in `state.imported_files`
+    /// once its batch is accepted
+    hashes: HashMap<String, (String, String)>,
+}
+
+impl Uploader<'_> {
+    /// Post one batch, recording the outcome in the report and the checkpoint
+    fn send(&mut self, batch: import::Batch, docs: &[document::Document]) -> Result<(), Report> {
+        let (opt, client, url) = (self.opt, &self.client, &self.url);
+        let report = &mut self.report;
+        let count = batch.count;
+        let files = batch.files.clone();
+        let ids = batch.ids.clone();
+        let body = import::timed(&mut report.serializing, || batch.finish())?;
+        let res = import::timed(&mut report.network, || {
+            opt.post_documents(client, url, body)
+        })?;
+        if !res.status().is_success() {
+            report.failed += count;
+            let e = format!("Batch of {} documents rejected: {:?}", count, res);
+            output::error(&e);
+            report.errors.push(e);
+            return Ok(());
+        }
+        if opt.verbosity > 0 {
+            output::ok(format_args!("{} documents {:?}", count, res));
+        }
+        let enqueued: api::task::EnqueuedTask = import::timed(&mut report.network, || res.json())?;
+        output::ok(format_args!(
+            "Batch of {} documents queued as task {}",
+            count, enqueued.uid
+        ));
+        report.tasks_queued.push(enqueued.uid);
+        let checkpoint = self
+            .state
+            .imports
+            .get_mut(&self.key)
+            .expect("checkpoint is created before uploading");
+        if self.opts.wait {
+            let task = import::timed(&mut report.tasks, || {
+                api::task::wait(client, &opt.url(""), enqueued.uid, None)
+            });
+            let task = match task {
+                Ok(task) => task,
+                Err(e) => {
+                    report.failed += count;
+                    output::error(&e);
+                    report.errors.push(e.to_string());
+                    return Ok(());
+                }
+            };
+            // Meilisearch rejects a batch as a whole, so every document in it is reported
+            if let Some(message) = task.error_message() {
+                report.failed += count;
+                let e = format!("Task {} failed: {}", task.uid, message);
+                output::error(&e);
+                report.errors.push(e);
+                for file in &files {
+                    output::detail(format_args!("{} was not indexed", file));
+                }
+                return Ok(());
+            }
+            let dropped = task.dropped_documents();
+            if dropped > 0 {
+                report.failed += dropped as usize;
+                output::warn(format_args!(
+                    "Task {} indexed {} of the {} documents",
+                    task.uid,
+                    count as u64 - dropped,
+                    count
+                ));
+                report
+                    .errors
+                    .push(format!("Task {} dropped {} documents", task.uid, dropped));
+            }
+            checkpoint.done.extend(files.iter().cloned());
+        } else {
+            checkpoint.pending.insert(enqueued.uid, files.clone());
+        }
+        // Until a later import sees it succeed, unless it was waited for already
+        let task = if self.opts.wait {
+            None
+        } else {
+            Some(enqueued.uid)
+        };
+        for file in files {
+            if let Some((hash, id)) = self.hashes.remove(&file) {
+                self.state.record_import(&opt.index, file, hash, id, task);
+            }
+        }
+        self.state.save()?;
+
+        report.documents += count;
+        report.ids.extend(ids);
+        let external = opt.external_client();
+        for doc in docs {
+            webhook::fire(&external, &opt.config.webhooks, self.event, doc);
+        }
+        Ok(())
+    }
 }
 
 impl Opt {
@@ -71,191 +722,3251 @@ impl Opt {
         url
     }
 
-    // TODO can I use a trait to define this function once for both Document and markdown_fm_doc?
-    fn import(&self, path: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/documents");
-        // Read the markdown files and post them to local Meilisearch
-        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    if let Ok(doc) = document::Document::parse_file(&path) {
-                        let doc: Vec<document::Document> = vec![doc];
-                        let res = client
-                            .post(url.as_ref())
-                            .body(serde_json::to_string(&doc).unwrap())
-                            .send()?;
-                        if self.verbosity > 0 {
-                            println!("✅ {} {:?}", doc[0], res);
-                        }
-                    } else {
-                        eprintln!("❌ Failed to load file {}", path.display());
-                    }
-                }
+    /// URL of `path` under the index's endpoints
+    fn index_url(&self, path: &str) -> Url {
+        self.url(&format!("indexes/{}/{}", self.index, path))
+    }
 
-                Err(e) => eprintln!("❌ {:?}", e),
+    /// Take the server, key, index and editor from the config file unless they were given on
+    /// the command line or in their environment variables. The editor in the file is meant
+    /// for meilizet alone, so it wins over `$EDITOR`.
+    fn apply_config(&mut self, matches: &ArgMatches) {
+        let given = |arg, var| matches.occurrences_of(arg) > 0 || env::var_os(var).is_some();
+        if let Some(host) = &self.config.host {
+            if !given("host", "MEILI_HOST") {
+                self.host = host.to_owned();
             }
         }
-        Ok(())
+        if let Some(index) = &self.config.index {
+            if !given("index", "MEILIZET_INDEX") {
+                self.index = index.to_owned();
+            }
+        }
+        if let Some(editor) = &self.config.editor {
+            if matches.occurrences_of("editor") == 0 {
+                self.editor = editor.to_owned();
+            }
+        }
+        if self.api_key.is_none() {
+            self.api_key = self.config.api_key.to_owned();
+        }
     }
 
-    fn legacy_import(&self, path: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/documents");
-        // Read the markdown files and post them to local Meilisearch
-        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    if let Ok(mdfm_doc) = markdown_fm_doc::parse_file(&path) {
-                        let doc: Vec<document::Document> = vec![mdfm_doc.into()];
-                        let res = client
-                            .post(url.as_ref())
-                            .body(serde_json::to_string(&doc).unwrap())
-                            .send()?;
-                        if self.verbosity > 0 {
-                            println!("✅ {} {:?}", doc[0], res);
-                        }
-                    } else {
-                        eprintln!("❌ Failed to load file {}", path.display());
-                    }
-                }
-
-                Err(e) => eprintln!("❌ {:?}", e),
+    /// Point every command at the vault's index and server
+    fn select_vault(&mut self, name: &str) -> Result<(), Report> {
+        let (vault, profile) = self.config.vault(name)?;
+        self.index = vault.index.to_owned();
+        if let Some(profile) = profile {
+            self.host = profile.host.to_owned();
+            self.read_only |= profile.read_only;
+            if self.api_key.is_none() {
+                self.api_key = profile.api_key.to_owned();
             }
         }
         Ok(())
     }
 
-    fn interactive_query(&self) -> Result<(), Report> {
-        interactive::setup_panic();
+    /// Fail in read-only mode. Every request modifying the index goes through here first.
+    fn check_writable(&self) -> Result<(), Report> {
+        if self.read_only {
+            bail!("Refusing to modify index {} in read-only mode", self.index);
+        }
+        Ok(())
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        match interactive::query(
-            client,
-            url,
-            self.verbosity,
-            self.pager.clone(),
-            self.editor.clone(),
-        ) {
-            Ok(res) => {
-                println!("Document IDs: {:?}", res);
-            }
-            Err(e) => {
-                eprintln!("❌ {:?}", e);
-                //std::panic::panic_any(e);
-            }
+    /// Keep other meilizet processes from importing into the index until the lock is dropped,
+    /// so they don't upload the same files twice or record them over each other
+    fn lock_index(&self) -> Result<lock::Lock, Report> {
+        let activity = match &self.vault {
+            Some(vault) => format!("syncing vault {}", vault),
+            None => format!("importing into index {}", self.index),
         };
+        lock::Lock::acquire(&lock::index_path(&self.state_path, &self.index), &activity)
+    }
+
+    /// Ask before `action` is applied to `docs` if there are many of them, see `confirm::bulk`
+    fn confirm(&self, action: &str, docs: &[&document::Document]) -> Result<(), Report> {
+        let threshold = self
+            .config
+            .confirm_threshold
+            .unwrap_or(confirm::DEFAULT_THRESHOLD);
+        if !confirm::bulk(action, docs, threshold, self.yes)? {
+            bail!("Aborted, nothing was changed");
+        }
         Ok(())
     }
 
-    fn static_query(&self, query: &str, filter: &str) -> Result<(), Report> {
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        match query::query(client, url, query.to_string(), filter.to_string()) {
-            Ok(res) => {
-                println!("Document IDs: {:?}", res);
-            }
-            Err(e) => {
-                eprintln!("❌ {:?}", e);
-                //std::panic::panic_any(e);
-            }
+    fn permalink_template(&self) -> &str {
+        self.config
+            .permalink_template
+            .as_deref()
+            .unwrap_or(permalink::DEFAULT_TEMPLATE)
+    }
+
+    /// HTTP client sending `api_key` with every request, in the header of current Meilisearch
+    /// versions as well as the one of versions before 0.25
+    fn build_client(&self, api_key: Option<&str>) -> Result<reqwest::blocking::Client, Report> {
+        let http = &self.config.http;
+        let keepalive = match http.tcp_keepalive_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
         };
-        Ok(())
+        let timeout = match self.request_timeout.unwrap_or(http.timeout_secs) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let mut headers = HeaderMap::new();
+        if let Some(key) = api_key {
+            let mut bearer = HeaderValue::from_str(&format!("Bearer {}", key))?;
+            bearer.set_sensitive(true);
+            headers.insert(AUTHORIZATION, bearer);
+            let mut legacy = HeaderValue::from_str(key)?;
+            legacy.set_sensitive(true);
+            headers.insert("X-Meili-API-Key", legacy);
+        }
+        Ok(reqwest::blocking::Client::builder()
+            .default_headers(headers)
+            .gzip(!self.no_compression)
+            .pool_max_idle_per_host(http.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(http.pool_idle_timeout_secs))
+            .tcp_keepalive(keepalive)
+            .timeout(timeout)
+            .build()?)
     }
 
-    fn dump(&self, path: &str) -> Result<(), Report> {
-        fs::create_dir_all(path)?;
+    /// The client shared by every request, so connections are pooled across the whole run
+    fn client(&self) -> reqwest::blocking::Client {
+        self.client
+            .clone()
+            .expect("client is built right after parsing arguments")
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let url = self.url("indexes/notes/search");
-        let q = api::ApiQuery::new();
+    /// Client for webhooks and remote imports, which must not be sent the API key
+    fn external_client(&self) -> reqwest::blocking::Client {
+        self.external_client
+            .clone()
+            .expect("client is built right after parsing arguments")
+    }
 
-        // Split up the JSON decoding into two steps.
-        // 1.) Get the text of the body.
-        let response_body = match client
+    /// POST an encoded batch of documents to the index. Search requests are left uncompressed,
+    /// they're too small to benefit.
+    fn post_documents(
+        &self,
+        client: &reqwest::blocking::Client,
+        url: &Url,
+        body: Vec<u8>,
+    ) -> Result<reqwest::blocking::Response, Report> {
+        self.check_writable()?;
+        let req = client
             .post(url.as_ref())
-            .body::<String>(serde_json::to_string(&q).unwrap())
-            .header(CONTENT_TYPE, "application/json")
-            .send()
-        {
-            Ok(resp) => {
-                if !resp.status().is_success() {
-                    eprintln!("Request failed: {:?}", resp);
-                }
-                match resp.text() {
-                    Ok(text) => text,
-                    Err(e) => {
-                        eprintln!("resp.text() failed: {:?}", e);
-                        String::from("")
-                    }
+            .header(CONTENT_TYPE, "application/json");
+        let req = if self.no_compression {
+            req
+        } else {
+            req.header(CONTENT_ENCODING, "gzip")
+        };
+        Ok(req.body(body).send()?)
+    }
+
+    /// Block until Meilisearch has applied the task to `index`, failing if it was rejected
+    fn wait_for_task(
+        &self,
+        client: &reqwest::blocking::Client,
+        index: &str,
+        uid: u64,
+    ) -> Result<(), Report> {
+        let task = api::task::wait(client, &self.url(""), uid, None)?;
+        match task.error_message() {
+            Some(message) => bail!("Task {} on index {} failed: {}", uid, index, message),
+            None => Ok(()),
+        }
+    }
+
+    // TODO can I use a trait to define this function once for both Document and markdown_fm_doc?
+    fn import(&self, path: &str, opts: &ImportOpts) -> Result<(), Report> {
+        let key = shellexpand::tilde(path).into_owned();
+        let ignore = Arc::new(ignore::Ignore::for_source(path)?);
+        let files = glob_paths(path, self.verbosity).filter({
+            let ignore = ignore.clone();
+            move |file| ignore.matching(file).is_none()
+        });
+        self.import_files(&key, files, opts, true, webhook::Event::Updated, |path| {
+            document::Document::parse_file(path)
+        })?;
+        if self.verbosity > 0 {
+            for line in ignore.summary() {
+                output::detail(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Import the files matching the patterns, then import each one created or modified and
+    /// delete the document of each one removed as it happens, until interrupted
+    fn watch(&self, patterns: &[String], opts: &ImportOpts) -> Result<(), Report> {
+        self.check_writable()?;
+        let cwd = env::current_dir()?;
+        // Events name files by absolute path, so the patterns are made to match those
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                let p = cwd.join(shellexpand::tilde(p).as_ref());
+                Ok((
+                    p.display().to_string(),
+                    glob::Pattern::new(&p.to_string_lossy())?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Report>>()?;
+        interrupt::catch()?;
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::watcher(tx, watch::DELAY)?;
+        for (pattern, _) in &patterns {
+            watcher.watch(watch::root(pattern), notify::RecursiveMode::Recursive)?;
+        }
+        // Catch up on what changed while nobody was watching
+        for (pattern, _) in &patterns {
+            if interrupt::interrupted() {
+                return Ok(());
+            }
+            self.import(pattern, opts)?;
+        }
+        output::ok("Watching for changes, press ctrl-c to stop");
+
+        let client = self.client();
+        // Imports go on with the config they started with, changes to it are only pointed out
+        let mut config_watcher = reload::ConfigWatcher::new(&self.config_path).ok();
+        while !interrupt::interrupted() {
+            match config_watcher.as_mut().and_then(|w| w.poll()) {
+                Some(Ok(reload)) => output::warn(format_args!(
+                    "{} changed, restart to apply {}",
+                    self.config_path,
+                    reload.changed.join(", ")
+                )),
+                Some(Err(e)) => output::warn(e),
+                None => {}
+            }
+            // Waking up now and then to notice ctrl-c
+            let event = match rx.recv_timeout(Duration::from_millis(200)) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if let notify::DebouncedEvent::Error(e, path) = &event {
+                match path {
+                    Some(path) => output::warn(format_args!("Watching {}: {}", path.display(), e)),
+                    None => output::warn(format_args!("Watching: {}", e)),
                 }
+                continue;
             }
-            Err(e) => {
-                eprintln!("Send failed: {:?}", e);
-                String::from("")
-            }
-        };
-
-        // 2.) Parse the results as JSON.
-        match serde_json::from_str::<api::ApiResponse>(&response_body) {
-            Ok(mut resp) => {
-                for entry in resp
-                    .hits
-                    .iter_mut()
-                    .map(|mut m| {
-                        m.serialization_type = document::SerializationType::Disk;
-                        m.to_owned()
-                    })
-                    .collect::<Vec<_>>()
-                {
-                    let f = Path::new(&path).join(&entry.filename);
-                    fs::write(f, entry.to_string())?;
+            for change in watch::changes(event) {
+                let matching = |path: &Path| {
+                    patterns
+                        .iter()
+                        .find(|(_, glob)| glob.matches_path(path))
+                        .map(|(pattern, _)| pattern)
+                };
+                // Failures are reported without interrupting the watch
+                let result = match change {
+                    watch::Change::Modified(path) => match matching(&path) {
+                        Some(pattern) if path.is_file() && !self.ignored(pattern, &path) => self
+                            .import_files(
+                                pattern,
+                                std::iter::once(path),
+                                opts,
+                                true,
+                                webhook::Event::Updated,
+                                |path| document::Document::parse_file(path),
+                            )
+                            .map(|_| ()),
+                        _ => Ok(()),
+                    },
+                    watch::Change::Removed(path) => match matching(&path) {
+                        Some(_) => self.delete_file(&client, &path),
+                        None => Ok(()),
+                    },
+                    watch::Change::Rescan => patterns
+                        .iter()
+                        .try_for_each(|(pattern, _)| self.import(pattern, opts)),
+                };
+                if let Err(e) = result {
+                    output::error(e);
                 }
             }
+        }
+        output::ok("Stopped watching");
+        Ok(())
+    }
+
+    /// Whether the ignore file of the vault `pattern` matches files of leaves out `path`
+    fn ignored(&self, pattern: &str, path: &Path) -> bool {
+        match ignore::Ignore::for_source(pattern) {
+            Ok(ignore) => ignore.matching(path).is_some(),
             Err(e) => {
-                eprintln!("Response not OK: {:?}", e);
+                output::warn(e);
+                false
+            }
+        }
+    }
+
+    /// Delete the document imported from a file that was removed
+    fn delete_file(&self, client: &reqwest::blocking::Client, path: &Path) -> Result<(), Report> {
+        let file = path.display().to_string();
+        let mut state = state::State::load(&self.state_path)?;
+        let imported = match state
+            .imported_files
+            .get_mut(&self.index)
+            .and_then(|files| files.remove(&file))
+        {
+            Some(imported) => imported,
+            None => {
+                if self.verbosity > 0 {
+                    output::warn(format_args!(
+                        "{} was never imported, nothing to delete",
+                        file
+                    ));
+                }
+                return Ok(());
             }
         };
+        let doc = query::get_document(
+            client,
+            &self.index_url(&format!("documents/{}", imported.id)),
+        )
+        .ok();
+        self.delete_documents(client, &[imported.id.to_owned()])?;
+        state.save()?;
+        if let Some(doc) = &doc {
+            webhook::fire(
+                &self.external_client(),
+                &self.config.webhooks,
+                webhook::Event::Deleted,
+                doc,
+            );
+        }
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!(
+            "Deleted {} as {} was removed",
+            imported.id, file
+        ));
         Ok(())
     }
-}
 
-pub fn glob_files(source: &str, verbosity: u8) -> Result<Paths, Box<dyn std::error::Error>> {
-    let glob_path = Path::new(&source);
-    let glob_str = shellexpand::tilde(glob_path.to_str().unwrap());
+    fn legacy_import(&self, path: &str, opts: &ImportOpts) -> Result<(), Report> {
+        let key = shellexpand::tilde(path).into_owned();
+        let files = glob_paths(path, self.verbosity);
+        // Legacy documents always get a freshly generated ID
+        self.import_files(&key, files, opts, true, webhook::Event::Created, |path| {
+            markdown_fm_doc::parse_file(path).map(document::Document::from)
+        })?;
+        Ok(())
+    }
 
-    if verbosity > 0 {
-        println!("Sourcing Markdown documents matching : {}", glob_str);
+    /// Import the notes in a remote repository, pulling it into a local clone first. The commit
+    /// imported is remembered, so later runs only import the files changed since.
+    fn git_import(
+        &self,
+        url: &str,
+        path: &Option<String>,
+        opts: &ImportOpts,
+    ) -> Result<(), Report> {
+        let (dir, head) = git::update(url)?;
+        let pattern = dir
+            .join(path.as_deref().unwrap_or("**/*.md"))
+            .display()
+            .to_string();
+        let last = state::State::load(&self.state_path)?
+            .git_commits
+            .get(url)
+            .cloned();
+        let changed = match &last {
+            Some(last) if *last == head => {
+                output::ok(format_args!("{} is already imported at {}", url, head));
+                return Ok(());
+            }
+            Some(last) => git::changed_files(&dir, last, &head),
+            None => None,
+        };
+        let ignore = ignore::Ignore::for_source(&pattern)?;
+        let files: Box<dyn Iterator<Item = PathBuf> + Send> = match changed {
+            Some(changed) => {
+                let pattern = glob::Pattern::new(&pattern)?;
+                Box::new(
+                    changed
+                        .into_iter()
+                        .filter(move |file| pattern.matches_path(file)),
+                )
+            }
+            None => Box::new(glob_paths(&pattern, self.verbosity)),
+        };
+        let files = files.filter(move |file| ignore.matching(file).is_none());
+        let report =
+            self.import_files(url, files, opts, true, webhook::Event::Updated, |path| {
+                document::Document::parse_file(path)
+            })?;
+        // Failed files are retried on the next run by leaving the last commit in place
+        if report.failed == 0 && !report.interrupted && !opts.dry_run {
+            let mut state = state::State::load(&self.state_path)?;
+            state.git_commits.insert(url.to_owned(), head);
+            state.save()?;
+        }
+        Ok(())
     }
 
-    Ok(glob(&glob_str).expect("Failed to read glob pattern"))
-}
+    /// Import vaults into their indexes, which means switching this to each vault in turn
+    fn sync(&mut self, all: bool, opts: &ImportOpts) -> Result<(), Report> {
+        let names: Vec<String> = if all {
+            self.config.vaults.keys().cloned().collect()
+        } else {
+            match &self.vault {
+                Some(name) => vec![name.to_owned()],
+                None => bail!("Pick a vault to sync with --vault, or sync them --all"),
+            }
+        };
+        if names.is_empty() {
+            bail!("No vaults are defined in {}", self.config_path);
+        }
+        for name in names {
+            if interrupt::interrupted() {
+                output::warn(format_args!("Interrupted before syncing vault {}", name));
+                break;
+            }
+            self.select_vault(&name)?;
+            self.vault = Some(name.to_owned());
+            let path = self.config.vaults[&name].path.to_owned();
+            println!("Syncing vault {} into index {}", name, self.index);
+            self.import(&path, opts)?;
+        }
+        Ok(())
+    }
 
-fn setup() -> Result<(), Report> {
-    if std::env::var("RUST_LIB_BACKTRACE").is_err() {
-        std::env::set_var("RUST_LIB_BACKTRACE", "1")
+    /// Import the notes in an S3 bucket or WebDAV share, fetching only the ones whose ETag
+    /// changed since the last run. Notes deleted remotely are left in the index.
+    fn remote_import(&self, url: &str, opts: &ImportOpts) -> Result<(), Report> {
+        let remote = remote::Remote::parse(url)?;
+        let client = self.external_client();
+        let entries: Vec<remote::Entry> = remote
+            .list(&client)?
+            .into_iter()
+            .filter(|entry| entry.key.ends_with(".md"))
+            .collect();
+        let etags = state::State::load(&self.state_path)?
+            .remote_etags
+            .remove(url)
+            .unwrap_or_default();
+        let changed: HashMap<String, remote::Entry> = entries
+            .iter()
+            .filter(|entry| etags.get(&entry.key) != Some(&entry.etag))
+            .map(|entry| (entry.key.to_owned(), entry.clone()))
+            .collect();
+        if changed.is_empty() {
+            output::ok(format_args!("{} has no changes to import", url));
+            return Ok(());
+        }
+
+        let files: Vec<PathBuf> = changed.keys().map(PathBuf::from).collect();
+        // Changed files are known from their ETags already
+        let report = self.import_files(
+            url,
+            files.into_iter(),
+            opts,
+            false,
+            webhook::Event::Updated,
+            move |path| {
+                let entry = &changed[&path.display().to_string()];
+                let content = remote
+                    .fetch(&client, &entry.key)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let filename = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut doc = document::Document::parse_str(&content, &filename)?;
+                doc.modified = entry.modified.clone();
+                if doc.created == date::Date::default() {
+                    doc.created = entry.modified.clone();
+                }
+                Ok(doc)
+            },
+        )?;
+        // Like with git, a failure means fetching everything that changed again next time
+        if report.failed == 0 && !report.interrupted && !opts.dry_run {
+            let mut state = state::State::load(&self.state_path)?;
+            let etags = entries
+                .into_iter()
+                .map(|entry| (entry.key, entry.etag))
+                .collect();
+            state.remote_etags.insert(url.to_owned(), etags);
+            state.save()?;
+        }
+        Ok(())
     }
-    color_eyre::install()?;
 
-    Ok(())
-}
+    /// Parse `files` with `parse` on `--jobs` background threads and post them to local
+    /// Meilisearch in batches, printing where the time went at the end. The queue between the
+    /// two is bounded, so memory use stays flat however large the vault is. `key` identifies the
+    /// import's checkpoint in the state file.
+    ///
+    /// Batches are posted one at a time: Meilisearch processes updates to an index one after
+    /// the other anyway, so posting several at once wouldn't get them indexed any sooner.
+    ///
+    /// When `incremental`, `files` are local files, and the ones whose contents are the same as
+    /// when they were last imported into the index are skipped unless `--force` is given.
+    fn import_files(
+        &self,
+        key: &str,
+        files: impl Iterator<Item = PathBuf> + Send + 'static,
+        opts: &ImportOpts,
+        incremental: bool,
+        event: webhook::Event,
+        parse: impl Fn(&Path) -> Result<document::Document, io::Error> + Send + Sync + 'static,
+    ) -> Result<import::ImportReport, Report> {
+        let run = audit::Run::new(key, &self.index, self.vault.as_deref());
+        let result = self.upload_files(key, files, opts, incremental, event, parse);
+        if opts.dry_run {
+            return result;
+        }
+        let run = match &result {
+            Ok(report) => run.report(report),
+            Err(e) => {
+                let mut run = run;
+                run.errors.push(e.to_string());
+                run
+            }
+        };
+        // The import itself went through either way
+        if let Err(e) = audit::AuditLog::new(&self.audit_log_path).record(&run) {
+            output::warn(format_args!(
+                "Failed to record the import in {}: {}",
+                self.audit_log_path, e
+            ));
+        }
+        result
+    }
 
-fn main() -> Result<(), Report> {
-    setup()?;
+    /// Parse the files and upload them in batches, see `import_files`
+    fn upload_files(
+        &self,
+        key: &str,
+        files: impl Iterator<Item = PathBuf> + Send + 'static,
+        opts: &ImportOpts,
+        incremental: bool,
+        event: webhook::Event,
+        parse: impl Fn(&Path) -> Result<document::Document, io::Error> + Send + Sync + 'static,
+    ) -> Result<import::ImportReport, Report> {
+        // Rather than parsing the whole vault only to fail on the first batch
+        let _lock = if opts.dry_run {
+            None
+        } else {
+            self.check_writable()?;
+            Some(self.lock_index()?)
+        };
+        interrupt::catch()?;
+        let client = self.client();
+        let mut state = state::State::load(&self.state_path)?;
+        let key = key.to_owned();
+        let mut checkpoint = if opts.resume {
+            state.imports.remove(&key).unwrap_or_default()
+        } else {
+            state::ImportCheckpoint::default()
+        };
+        // Batches that were accepted without being seen to finish only count if they did, which
+        // a dry run doesn't ask the server about
+        let pending = if opts.dry_run {
+            Default::default()
+        } else {
+            mem::take(&mut checkpoint.pending)
+        };
+        for (uid, files) in pending {
+            match api::task::get(&client, &self.url(""), uid) {
+                Ok(task) if task.succeeded() => checkpoint.done.extend(files),
+                _ => {}
+            }
+        }
+        let skip = checkpoint.done.clone();
+        state.imports.insert(key.to_owned(), checkpoint);
+        let unchanged = if !incremental || opts.force {
+            HashMap::new()
+        } else if opts.dry_run {
+            state.confirmed_hashes(&self.index)
+        } else {
+            self.imported_hashes(&client, &mut state)
+        };
 
-    let opt = Opt::from_args();
+        let (tx, rx) =
+            mpsc::sync_channel::<(String, Option<String>, document::Document)>(import::QUEUE_DEPTH);
+        let files = Arc::new(Mutex::new(files));
+        let parse = Arc::new(parse);
+        let skip = Arc::new(skip);
+        let unchanged = Arc::new(unchanged);
+        let aliases = Arc::new(authors::Aliases::new(&self.config.authors));
+        let statuses = Arc::new(status::Defaults::new(&self.config.status_defaults));
+        let template = Arc::new(self.permalink_template().to_owned());
+        let rules = Arc::new(if opts.strict {
+            Some(self.config.validation.clone())
+        } else {
+            None
+        });
+        let read = Arc::new(AtomicUsize::new(0));
+        let parsers: Vec<_> = (0..opts.jobs.max(1))
+            .map(|_| {
+                let (tx, files, parse, skip) =
+                    (tx.clone(), files.clone(), parse.clone(), skip.clone());
+                let unchanged = unchanged.clone();
+                let (aliases, template, rules) = (aliases.clone(), template.clone(), rules.clone());
+                let statuses = statuses.clone();
+                let read = read.clone();
+                thread::spawn(move || {
+                    let mut parsing = Duration::default();
+                    // What went wrong with each file that failed
+                    let mut failed = Vec::new();
+                    let mut skipped = 0;
+                    loop {
+                        // Holding the lock only while taking the next file
+                        let path = match files.lock().unwrap().next() {
+                            Some(path) => path,
+                            None => break,
+                        };
+                        read.fetch_add(1, Ordering::Relaxed);
+                        let file = path.display().to_string();
+                        if skip.contains(&file) {
+                            skipped += 1;
+                            continue;
+                        }
+                        let hash = if incremental {
+                            fs::read(&path)
+                                .ok()
+                                .map(|contents| hex::encode(Sha256::digest(&contents)))
+                        } else {
+                            None
+                        };
+                        if hash.is_some() && unchanged.get(&file) == hash.as_ref() {
+                            skipped += 1;
+                            continue;
+                        }
+                        match import::timed(&mut parsing, || parse(&path)) {
+                            Ok(mut doc) => {
+                                aliases.normalize(&mut doc);
+                                if doc.status.is_none() {
+                                    doc.status = statuses.get(&path);
+                                }
+                                doc.permalink = permalink::render(&template, &doc);
+                                let violations = rules
+                                    .as_ref()
+                                    .as_ref()
+                                    .map(|rules| validation::validate(rules, &doc))
+                                    .unwrap_or_default();
+                                if !violations.is_empty() {
+                                    let e = format!("{} breaks the validation rules", file);
+                                    output::error(&e);
+                                    for v in violations {
+                                        output::detail(v);
+                                    }
+                                    failed.push(e);
+                                } else if tx.send((file, hash, doc)).is_err() {
+                                    // The uploader gave up
+                                    break;
+                                }
+                            }
+                            Err(_) => {
+                                let e = format!("Failed to load file {}", path.display());
+                                output::error(&e);
+                                failed.push(e);
+                            }
+                        }
+                    }
+                    (parsing, failed, skipped)
+                })
+            })
+            .collect();
+        // The queue closes once every parser is done with its copy of the sender
+        drop(tx);
 
-    match opt.subcmd {
-        Subcommands::Import { ref globpath } => opt.import(globpath),
-        Subcommands::ImportLegacyMd { ref globpath } => opt.legacy_import(globpath),
-        Subcommands::Query {} => opt.interactive_query(),
-        Subcommands::Dump { ref path } => opt.dump(path),
-        Subcommands::StaticQuery {
-            ref query,
+        let mut uploader = Uploader {
+            opt: self,
+            opts,
+            url: self.index_url("documents"),
+            client,
+            event,
+            report: import::ImportReport::new(),
+            state,
+            key,
+            hashes: HashMap::new(),
+        };
+        // Half of the budget goes to the batch being built, the rest covers the queue and the
+        // copies made while sending
+        let batch_bytes = opts.max_memory * 1024 * 1024 / 2;
+        // Documents are only held on to after encoding when webhooks need them
+        let keep_docs = !self.config.webhooks.is_empty();
+        let mut batch = import::Batch::new(!self.no_compression);
+        let mut docs = Vec::new();
+        // Duplicate titles make links by title ambiguous, so new ones are pointed out. A dry run
+        // only has the ones among the files to go by.
+        let (mut titles, mut graph) = if opts.dry_run {
+            (titles::Titles::new(), links::Graph::default())
+        } else {
+            (
+                self.titles(&uploader.client),
+                self.link_graph(&uploader.client),
+            )
+        };
+        // What a dry run would have uploaded
+        let mut planned = output::Table::new(&["ID", "TITLE", "FILE"]);
+        let mut planned_count = 0;
+        let mut progress = import::Progress::new();
+        for (file, hash, mut doc) in rx {
+            // The documents already in the batch are sent, the parsers give up once the queue
+            // is gone
+            if interrupt::interrupted() {
+                uploader.report.interrupted = true;
+                break;
+            }
+            progress.update(read.load(Ordering::Relaxed), uploader.report.documents);
+            for conflict in titles.conflicts(&doc.id, &doc.title) {
+                output::warn(format_args!(
+                    "{} has {} title as {}: {}",
+                    file,
+                    if conflict.exact {
+                        "the same"
+                    } else {
+                        "nearly the same"
+                    },
+                    conflict.id,
+                    conflict.title
+                ));
+            }
+            titles.insert(&doc.id, &doc.title);
+            if opts.diff {
+                print!("{}", self.diff_against_index(&uploader.client, &doc));
+            }
+            if opts.dry_run {
+                planned.row(vec![doc.id, doc.title, file]);
+                planned_count += 1;
+                continue;
+            }
+            doc.imported_at = date::Date::now();
+            graph.add(&doc);
+            if let Some(hash) = hash {
+                uploader
+                    .hashes
+                    .insert(file.to_owned(), (hash, doc.id.to_owned()));
+            }
+            import::timed(&mut uploader.report.serializing, || batch.push(file, &doc))?;
+            if keep_docs {
+                docs.push(doc);
+            }
+            if batch.len >= batch_bytes || opts.batch_size.is_some_and(|n| batch.count >= n) {
+                let full = mem::replace(&mut batch, import::Batch::new(!self.no_compression));
+                uploader.send(full, &docs)?;
+                docs.clear();
+            }
+        }
+        if batch.count > 0 {
+            uploader.send(batch, &docs)?;
+        }
+
+        progress.finish();
+        if !opts.dry_run && uploader.report.documents > 0 {
+            if uploader.report.failed > 0 {
+                // Setting links on notes that weren't indexed would add them as empty documents
+                output::warn("Links were left alone, some documents weren't indexed");
+            } else {
+                if self.verbosity > 0 {
+                    for (file, link) in graph.unresolved() {
+                        output::warn(format_args!(
+                            "{} links to {}, which isn't a note",
+                            file, link
+                        ));
+                    }
+                }
+                let links = self.store_links(&uploader.client, graph.updates(), opts.wait)?;
+                uploader.report.tasks_queued.extend(links);
+            }
+        }
+
+        let mut report = uploader.report;
+        for parser in parsers {
+            let (parsing, failed, skipped) = parser.join().expect("Import parser thread panicked");
+            report.parsing += parsing;
+            report.failed += failed.len();
+            report.errors.extend(failed);
+            report.skipped += skipped;
+        }
+        if opts.dry_run {
+            if planned_count > 0 {
+                print!("{}", planned);
+            }
+            println!(
+                "Dry run, {} documents would be uploaded, {} files failed and {} were skipped, \
+                 the index was left alone",
+                planned_count, report.failed, report.skipped
+            );
+            return Ok(report);
+        }
+
+        // Keep the checkpoint around for retrying the failures with --resume
+        let mut state = uploader.state;
+        if report.failed == 0 && !report.interrupted {
+            state.imports.remove(&uploader.key);
+        }
+        state.save()?;
+
+        cache::invalidate(&self.config.cache);
+        print!("{}", report);
+        Ok(report)
+    }
+
+    /// The titles of the notes in the index, none if it can't be searched, e.g. because it
+    /// doesn't exist yet
+    fn titles(&self, client: &reqwest::blocking::Client) -> titles::Titles {
+        let mut titles = titles::Titles::new();
+        let mut q = api::SearchRequest::new();
+        q.attributes_to_retrieve = Some(
+            ["id", "title", "date"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        );
+        if let Ok(docs) = query::search_all(client, &self.index_url("search"), &q) {
+            for doc in docs {
+                titles.insert(&doc.id, &doc.title);
+            }
+        }
+        titles
+    }
+
+    /// The links between the notes in the index, empty if it can't be searched
+    fn link_graph(&self, client: &reqwest::blocking::Client) -> links::Graph {
+        let mut q = api::SearchRequest::new();
+        q.attributes_to_retrieve = Some(
+            [
+                "id",
+                "filename",
+                "slug",
+                "links",
+                "backlinks",
+                "title",
+                "date",
+            ]
+            .iter()
+            .map(|f| f.to_string())
+            .collect(),
+        );
+        links::Graph::new(
+            query::search_all(client, &self.index_url("search"), &q).unwrap_or_default(),
+        )
+    }
+
+    /// Second pass of an import: PATCH the links of the imported notes and the backlinks of the
+    /// notes they link to. Queued after the batches, so it's applied once they are. Returns the
+    /// task, if there was anything to change.
+    fn store_links(
+        &self,
+        client: &reqwest::blocking::Client,
+        updates: Vec<serde_json::Value>,
+        wait: bool,
+    ) -> Result<Option<u64>, Report> {
+        if updates.is_empty() {
+            return Ok(None);
+        }
+        let res = client
+            .put(self.index_url("documents").as_ref())
+            .json(&updates)
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Updating links failed: {:?}", res);
+        }
+        let enqueued: api::task::EnqueuedTask = res.json()?;
+        output::ok(format_args!(
+            "Links of {} documents queued as task {}",
+            updates.len(),
+            enqueued.uid
+        ));
+        if wait {
+            self.wait_for_task(client, &self.index, enqueued.uid)?;
+        }
+        Ok(Some(enqueued.uid))
+    }
+
+    /// Content hashes of the files imported into the index, by path. Files whose import task
+    /// hadn't finished are looked up again and forgotten unless it succeeded.
+    fn imported_hashes(
+        &self,
+        client: &reqwest::blocking::Client,
+        state: &mut state::State,
+    ) -> HashMap<String, String> {
+        let imported = state
+            .imported_files
+            .entry(self.index.to_owned())
+            .or_default();
+        let mut succeeded: HashMap<u64, bool> = HashMap::new();
+        imported.retain(|_, file| match file.task {
+            Some(uid) => {
+                let ok = *succeeded.entry(uid).or_insert_with(|| {
+                    api::task::get(client, &self.url(""), uid).is_ok_and(|task| task.succeeded())
+                });
+                file.task = None;
+                ok
+            }
+            None => true,
+        });
+        imported
+            .iter()
+            .map(|(path, file)| (path.to_owned(), file.hash.to_owned()))
+            .collect()
+    }
+
+    /// Labels and search URLs of the configured vaults, or of the selected index when there are
+    /// none, and which of them is selected
+    fn searchable_indexes(&self) -> Result<(Vec<(String, Url)>, usize), Report> {
+        if self.config.vaults.is_empty() {
+            return Ok((vec![(self.index.to_owned(), self.index_url("search"))], 0));
+        }
+        let mut indexes = Vec::new();
+        let mut current = 0;
+        for name in self.config.vaults.keys() {
+            let (vault, profile) = self.config.vault(name)?;
+            let mut url = Url::parse(profile.map_or(&self.host, |p| &p.host))?;
+            url.set_path(&format!("indexes/{}/search", vault.index));
+            if self.vault.as_ref() == Some(name) {
+                current = indexes.len();
+            }
+            indexes.push((name.to_owned(), url));
+        }
+        Ok((indexes, current))
+    }
+
+    /// Transport for searching, failing over to the `replicas` in the config file while the
+    /// server is down
+    fn search_transport(&self) -> Result<Arc<dyn api::Transport>, Report> {
+        if self.config.replicas.is_empty() {
+            return Ok(Arc::new(self.client()));
+        }
+        let hosts = std::iter::once(&self.host)
+            .chain(&self.config.replicas)
+            .map(|host| Url::parse(host).map_err(|e| eyre!("Bad host {}: {}", host, e)))
+            .collect::<Result<Vec<_>, Report>>()?;
+        Ok(Arc::new(api::Failover::new(self.client(), hosts)))
+    }
+
+    fn interactive_query(
+        &self,
+        query: &Option<String>,
+        lazy: bool,
+        picker: &Option<String>,
+        contents: bool,
+    ) -> Result<(), Report> {
+        let client = self.client();
+        let transport = self.search_transport()?;
+        let mut cache = cache::SearchCache::new(&self.config.cache);
+
+        let interactive = termion::is_tty(&io::stdin()) && termion::is_tty(&io::stdout());
+        let query = match query {
+            Some(query) => query.to_owned(),
+            None if !termion::is_tty(&io::stdin()) => {
+                let mut query = String::new();
+                io::stdin().read_to_string(&mut query)?;
+                query.trim().to_owned()
+            }
+            None => String::new(),
+        };
+        if let Some(picker) = picker {
+            let mut q = api::SearchRequest::new()
+                .query(&query)
+                .locales(&self.config.locales);
+            q.without_body();
+            let hits = query::cached_search(
+                &mut cache,
+                transport.as_ref(),
+                &self.index_url("search"),
+                &q,
+            )?
+            .hits;
+            cache.save()?;
+            self.print_selection(&client, &picker::pick(picker, &hits)?, contents)?;
+            return Ok(());
+        }
+        if !interactive {
+            let mut q = api::SearchRequest::new()
+                .query(&query)
+                .locales(&self.config.locales);
+            q.without_body();
+            for doc in query::cached_search(
+                &mut cache,
+                transport.as_ref(),
+                &self.index_url("search"),
+                &q,
+            )?
+            .hits
+            {
+                println!("{}\t{}", doc.id, doc.title);
+            }
+            cache.save()?;
+            return Ok(());
+        }
+
+        interactive::setup_panic();
+        let (indexes, current) = self.searchable_indexes()?;
+        match interactive::query(
+            &mut cache,
+            transport,
+            indexes,
+            current,
+            self.verbosity,
+            self.pager.clone(),
+            self.editor.clone(),
+            self.keys,
+            self.read_only,
+            lazy,
+            query,
+            self.trace,
+            &self.config.attachments_dir(),
+            self.config
+                .preview_theme
+                .as_deref()
+                .unwrap_or(highlight::DEFAULT_THEME),
+            &self.config.locales,
+            &self.config_path,
+            undo::Recorder {
+                log: undo::UndoLog::new(&self.undo_log_path),
+                webhooks: self.config.webhooks.clone(),
+                client: self.external_client(),
+            },
+        ) {
+            Ok(res) => {
+                self.print_selection(&self.client(), &res, contents)?;
+            }
+            Err(e) => {
+                output::error(format_args!("{:?}", e));
+                //std::panic::panic_any(e);
+            }
+        };
+        cache.save()?;
+        Ok(())
+    }
+
+    /// Print the IDs of the documents picked in a query, one per line so they can be piped on,
+    /// or the documents themselves as they'd be written to disk
+    fn print_selection(
+        &self,
+        client: &reqwest::blocking::Client,
+        ids: &[String],
+        contents: bool,
+    ) -> Result<(), Report> {
+        for id in ids {
+            if contents {
+                let mut doc = self.document(client, id)?;
+                doc.serialization_type = document::SerializationType::Disk;
+                println!("{}", doc);
+            } else {
+                println!("{}", id);
+            }
+        }
+        Ok(())
+    }
+
+    /// The document with the given ID. Zettel IDs also find notes named after them that were
+    /// given an ID of another kind.
+    fn document(
+        &self,
+        client: &reqwest::blocking::Client,
+        id: &str,
+    ) -> Result<document::Document, Report> {
+        let err = match query::get_document(client, &self.index_url(&format!("documents/{}", id))) {
+            Ok(doc) => return Ok(doc),
+            Err(e) => e,
+        };
+        if !zettel::is_id(id) {
+            return Err(err);
+        }
+        let mut q = api::SearchRequest::new();
+        q.attributes_to_retrieve = Some(
+            ["id", "title", "date", "filename"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        );
+        let found = query::search_all(client, &self.index_url("search"), &q)?
+            .into_iter()
+            .find(|doc| zettel::from_filename(&doc.filename) == Some(id));
+        match found {
+            Some(doc) => {
+                query::get_document(client, &self.index_url(&format!("documents/{}", doc.id)))
+            }
+            None => Err(err),
+        }
+    }
+
+    fn static_query(
+        &self,
+        query: &str,
+        filter: &str,
+        sort: &Option<String>,
+        locales: &[String],
+    ) -> Result<(), Report> {
+        let locales = if locales.is_empty() {
+            &self.config.locales
+        } else {
+            locales
+        };
+        let transport = self.search_transport()?;
+        let url = self.index_url("search");
+        let mut cache = cache::SearchCache::new(&self.config.cache);
+        match query::query(
+            &mut cache,
+            transport.as_ref(),
+            url,
+            query.to_string(),
+            filter.to_string(),
+            sort.to_owned(),
+            locales,
+        ) {
+            Ok(res) => {
+                println!("Document IDs: {:?}", res);
+            }
+            Err(e) => {
+                output::error(format_args!("{:?}", e));
+                //std::panic::panic_any(e);
+            }
+        };
+        cache.save()?;
+        Ok(())
+    }
+
+    fn get(&self, id: &str, format: document::GetFormat) -> Result<(), Report> {
+        let mut doc = self.document(&self.client(), id)?;
+        match format {
+            document::GetFormat::Markdown => {
+                doc.serialization_type = document::SerializationType::Disk;
+                print!("{}", doc);
+            }
+            document::GetFormat::Json => {
+                doc.serialization_type = document::SerializationType::Storage;
+                println!("{}", serde_json::to_string_pretty(&doc)?);
+            }
+            document::GetFormat::Body => print!("{}", doc.body),
+        }
+        Ok(())
+    }
+
+    fn history(&self, id: &str) -> Result<(), Report> {
+        let client = self.search_transport()?;
+        let mut q = api::SearchRequest::new().all_revisions();
+        q.without_body();
+        let docs = query::search_all(client.as_ref(), &self.index_url("search"), &q)?;
+        let mut table = output::Table::new(&["", "ID", "WRITES", "MODIFIED", "TITLE"]);
+        for doc in revision::history(docs, id)? {
+            table.row(vec![
+                // Marks the latest revision
+                if revision::is_superseded(&doc) {
+                    ""
+                } else {
+                    "*"
+                }
+                .to_owned(),
+                doc.id,
+                doc.writes.to_string(),
+                doc.modified.to_string(),
+                doc.title,
+            ]);
+        }
+        print!("{}", table);
+        Ok(())
+    }
+
+    fn publish(&self, id: &str) -> Result<(), Report> {
+        self.check_writable()?;
+        let client = self.client();
+        let before = self.document(&client, id)?;
+        if before.status == Some(status::Status::Published) {
+            output::ok(format_args!("{} is already published", before.id));
+            return Ok(());
+        }
+        let mut doc = before.clone();
+        doc.status = Some(status::Status::Published);
+        self.put_documents(&client, &[doc.clone()])?;
+        let undo_id = undo::UndoLog::new(&self.undo_log_path).record(
+            "publish",
+            &self.index,
+            vec![before],
+            Vec::new(),
+            vec![doc.clone()],
+        )?;
+        webhook::fire(
+            &self.external_client(),
+            &self.config.webhooks,
+            webhook::Event::Published,
+            &doc,
+        );
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!(
+            "Published {}, undo with `undo {}`",
+            doc.id, undo_id
+        ));
+        if !doc.filename.is_empty() {
+            output::detail(format_args!(
+                "Its next import takes the status from {} again, unless that's changed too",
+                doc.filename
+            ));
+        }
+        Ok(())
+    }
+
+    fn remind(
+        &self,
+        id: &str,
+        after: &Option<String>,
+        at: &Option<date::Date>,
+        review: bool,
+        clear: bool,
+    ) -> Result<(), Report> {
+        let when = match (after, at) {
+            (Some(after), _) => Some(date::Date::now().after(api::parse_duration(after)?)?),
+            (None, Some(at)) => Some(at.clone()),
+            (None, None) if clear => None,
+            (None, None) => bail!("Say when with --in or --at, or remove the date with --clear"),
+        };
+        self.check_writable()?;
+        let client = self.client();
+        let before = self.document(&client, id)?;
+        let mut doc = before.clone();
+        let (field, name) = if review {
+            (&mut doc.review_after, "review_after")
+        } else {
+            (&mut doc.remind_at, "remind_at")
+        };
+        *field = when.clone();
+        self.put_documents(&client, &[doc.clone()])?;
+        let undo_id = undo::UndoLog::new(&self.undo_log_path).record(
+            "remind",
+            &self.index,
+            vec![before],
+            Vec::new(),
+            vec![doc.clone()],
+        )?;
+        webhook::fire(
+            &self.external_client(),
+            &self.config.webhooks,
+            webhook::Event::Updated,
+            &doc,
+        );
+        cache::invalidate(&self.config.cache);
+        let what = if review { "Review of" } else { "Reminder of" };
+        match &when {
+            Some(when) => output::ok(format_args!(
+                "{} {} set for {}, undo with `undo {}`",
+                what, doc.id, when, undo_id
+            )),
+            None => output::ok(format_args!(
+                "{} {} removed, undo with `undo {}`",
+                what, doc.id, undo_id
+            )),
+        }
+        // Importing the file again replaces the document, the date with it
+        if let (Some(when), false) = (&when, doc.filename.is_empty()) {
+            output::detail(format_args!(
+                "Add `{}: {}` to the frontmatter of {} to keep it when it's imported again",
+                name,
+                when.format("%+"),
+                doc.filename
+            ));
+        }
+        Ok(())
+    }
+
+    fn due(&self, within: &Option<String>) -> Result<(), Report> {
+        let until = match within {
+            Some(within) => date::Date::now().after(api::parse_duration(within)?)?,
+            None => date::Date::now(),
+        };
+        let client = self.search_transport()?;
+        let mut q = api::SearchRequest::new();
+        q.without_body();
+        let docs = query::search_all(client.as_ref(), &self.index_url("search"), &q)?;
+        let due = reminder::due(docs, &until);
+        if due.is_empty() {
+            output::ok("Nothing is due");
+            return Ok(());
+        }
+        let mut table = output::Table::new(&["DUE", "FOR", "ID", "TITLE"]);
+        for due in due {
+            table.row(vec![
+                due.at.to_string(),
+                due.what.to_owned(),
+                due.doc.id,
+                due.doc.title,
+            ]);
+        }
+        print!("{}", table);
+        Ok(())
+    }
+
+    /// Create a key that can only search the index, sign a tenant token with it that only
+    /// finds the notes matching `filter`, and check the token works before printing it
+    fn publish_key(
+        &self,
+        filter: &str,
+        public_host: &Option<String>,
+        expires: &Option<date::Date>,
+    ) -> Result<(), Report> {
+        self.check_writable()?;
+        // A filter that doesn't parse mustn't turn into a key finding every note, drafts included
+        let filter = match api::parse_filter(filter) {
+            Ok(Some(filter)) => filter,
+            Ok(None) => bail!("The filter {:?} filters nothing", filter),
+            Err(e) => bail!("Couldn't parse the filter {:?}: {}", filter, e),
+        };
+        let description = format!("Public search of {} where {}", self.index, filter);
+        let client = self.client();
+        let res = client
+            .post(self.url("keys").as_ref())
+            .json(&serde_json::json!({
+                "description": description,
+                "actions": ["search"],
+                "indexes": [self.index],
+                "expiresAt": expires.as_ref().map(|d| d.format("%+")),
+            }))
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Failed to create the key: {}", res.text()?);
+        }
+        let created: serde_json::Value = res.json()?;
+        let key = created["key"]
+            .as_str()
+            .ok_or_else(|| eyre!("No key in the response: {}", created))?;
+        let uid = created["uid"].as_str();
+        let token = tenant::token(key, uid, &self.index, &filter);
+
+        // Rather than handing out a token that only fails once it's on the website, e.g. when
+        // the filter names a field the index can't filter on
+        let res = self
+            .external_client()
+            .post(self.index_url("search").as_ref())
+            .bearer_auth(&token)
+            .json(&api::SearchRequest::new().page(0, 0))
+            .send()?;
+        let status = res.status();
+        let body = res.text()?;
+        if !status.is_success() {
+            let revoked = client
+                .delete(self.url(&format!("keys/{}", uid.unwrap_or(key))).as_ref())
+                .send();
+            if !matches!(revoked, Ok(res) if res.status().is_success()) {
+                output::warn(format_args!("Failed to delete the key {} again", key));
+            }
+            bail!("Searching with the key failed: {}", body);
+        }
+        let found = query::parse_response(&body)?.num_hits;
+
+        let host = public_host.as_deref().unwrap_or(&self.host);
+        output::ok(format_args!(
+            "Created a key finding {} notes of {}, revoke it with DELETE /keys/{}",
+            found,
+            self.index,
+            uid.unwrap_or(key)
+        ));
+        output::detail(format_args!("Host  {}", host));
+        output::detail(format_args!("Index {}", self.index));
+        output::detail(format_args!("Key   {}", token));
+        println!();
+        print!("{}", tenant::snippet(host, &self.index, &token));
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        query: &str,
+        filter: &str,
+        limit: u32,
+        sort: &Option<String>,
+        format: search::SearchFormat,
+    ) -> Result<(), Report> {
+        let transport = self.search_transport()?;
+        let mut cache = cache::SearchCache::new(&self.config.cache);
+        let mut q = api::SearchRequest::new()
+            .query(query)
+            .filter(filter)
+            .locales(&self.config.locales)
+            .page(0, limit);
+        if let Some(sort) = sort {
+            q = q.sort(sort);
+        }
+        if !format.needs_body() {
+            q.without_body();
+        }
+        let hits = query::cached_search(
+            &mut cache,
+            transport.as_ref(),
+            &self.index_url("search"),
+            &q,
+        )?
+        .hits;
+        cache.save()?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for doc in hits {
+            match writeln!(out, "{}", search::line(format, &doc)?) {
+                Ok(()) => {}
+                // The reader, a pager or `head`, has seen enough
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn dump(&self, path: &str) -> Result<(), Report> {
+        fs::create_dir_all(path)?;
+
+        let client = self.client();
+        let url = self.index_url("search");
+        let q = api::SearchRequest::new();
+        for mut entry in query::search_all(&client, &url, &q)? {
+            entry.serialization_type = document::SerializationType::Disk;
+            let f = Path::new(&path).join(&entry.filename);
+            fs::write(f, entry.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn export(
+        &self,
+        path: &str,
+        format: &export::ExportFormat,
+        filter: &Option<String>,
+    ) -> Result<(), Report> {
+        let client = self.client();
+        let url = self.index_url("search");
+        let mut q = api::SearchRequest::new();
+        if let Some(filter) = filter {
+            q.filter = match api::parse_filter(filter) {
+                Ok(Some(filter)) => Some(filter),
+                Ok(None) => bail!("The filter {:?} filters nothing", filter),
+                Err(e) => bail!("Couldn't parse the filter {:?}: {}", filter, e),
+            };
+        }
+        let docs = query::search_all(&client, &url, &q)?;
+        let dir = Path::new(path);
+
+        match format {
+            export::ExportFormat::Markdown => export::markdown(&docs, dir)?,
+            export::ExportFormat::Opml => export::opml(&docs, dir)?,
+            export::ExportFormat::Anki => {
+                let mut state = state::State::load(&self.state_path)?;
+                let count = export::anki(&docs, dir, &mut state)?;
+                state.save()?;
+                if self.verbosity > 0 {
+                    output::ok(format_args!("Exported {} new flashcards", count));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn share(
+        &self,
+        id: &str,
+        depth: usize,
+        format: &share::ShareFormat,
+        output: &Option<String>,
+    ) -> Result<(), Report> {
+        let client = self.client();
+        let docs = share::neighborhood(id, depth, |id| self.document(&client, id))?;
+
+        let bundle = match format {
+            share::ShareFormat::Html => share::html(&docs),
+            share::ShareFormat::Markdown => share::markdown(&docs),
+        };
+        match output {
+            Some(path) => fs::write(shellexpand::tilde(path).as_ref(), bundle)?,
+            None => print!("{}", bundle),
+        }
+        if self.verbosity > 0 {
+            output::ok(format_args!("Shared {} documents", docs.len()));
+        }
+        Ok(())
+    }
+
+    fn permalink(&self, id: &str, reverse: bool) -> Result<(), Report> {
+        let client = self.client();
+        if reverse {
+            let mut q = api::SearchRequest::new();
+            q.filter = Some(format!("permalink = \"{}\"", id.replace('"', "\\\"")));
+            q.without_body();
+            let hits = query::search(&client, &self.index_url("search"), &q)?.hits;
+            if hits.is_empty() {
+                bail!("No note has the permalink {}", id);
+            }
+            for doc in hits {
+                println!("{}", doc.id);
+            }
+            return Ok(());
+        }
+
+        let doc = self.document(&client, id)?;
+        println!("{}", permalink::render(self.permalink_template(), &doc));
+        Ok(())
+    }
+
+    fn handle_url(&self, url: &Option<String>, print: bool, register: bool) -> Result<(), Report> {
+        if register {
+            let entry = handler::register(&std::env::current_exe()?)?;
+            output::ok(format_args!("Registered {}", entry.display()));
+            return Ok(());
+        }
+        let id = handler::note_id(url.as_deref().unwrap_or_default())?;
+        let mut doc = self.document(&self.client(), &id)?;
+        doc.serialization_type = document::SerializationType::Disk;
+        if print {
+            print!("{}", doc);
+            return Ok(());
+        }
+
+        let mut tf = tempfile::Builder::new()
+            .prefix(&format!("meilizet-{}-", id))
+            .suffix(".md")
+            .tempfile()?;
+        tf.write_all(doc.to_string().as_bytes())?;
+        let mut editor = self.editor.split_whitespace();
+        let mut cmd = Command::new(editor.next().unwrap_or("vim"));
+        cmd.args(editor).arg(tf.path()).status()?;
+        Ok(())
+    }
+
+    /// Write a new note to the notes directory, open it in the editor and import it, or remove
+    /// it again when it's closed without changes
+    fn new_note(&self, title: &str, tags: &[String], wait: bool) -> Result<(), Report> {
+        // Rather than losing what was written to a server that won't take it
+        self.check_writable()?;
+        let template = match &self.config.note_template {
+            Some(path) => Some(
+                fs::read_to_string(shellexpand::tilde(path).as_ref())
+                    .map_err(|e| eyre!("Failed to read note template {}: {}", path, e))?,
+            ),
+            None => None,
+        };
+        let doc = note::create(title, tags);
+        let contents = note::render(template.as_deref(), &doc);
+        let dir = self.config.notes_dir();
+        let path = dir.join(note::filename(&doc));
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(&dir)?;
+        }
+        fs::write(&path, &contents)?;
+
+        let mut editor = self.editor.split_whitespace();
+        let status = Command::new(editor.next().unwrap_or("vim"))
+            .args(editor)
+            .arg(&path)
+            .status()?;
+        if !status.success() {
+            bail!(
+                "The editor exited with {}, {} was kept but not imported",
+                status,
+                path.display()
+            );
+        }
+        if fs::read_to_string(&path)? == contents {
+            fs::remove_file(&path)?;
+            output::warn(format_args!(
+                "{} was left unchanged, removed it",
+                path.display()
+            ));
+            return Ok(());
+        }
+        let opts = ImportOpts {
+            wait,
+            max_memory: 32,
+            batch_size: None,
+            jobs: 1,
+            resume: false,
+            strict: false,
+            diff: false,
+            dry_run: false,
+            force: false,
+        };
+        let key = path.display().to_string();
+        self.import_files(
+            &key,
+            std::iter::once(path),
+            &opts,
+            true,
+            webhook::Event::Created,
+            |path| document::Document::parse_file(path),
+        )?;
+        Ok(())
+    }
+
+    fn verify_roundtrip(&self, path: &str) -> Result<(), Report> {
+        let mut failed = 0;
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            match entry {
+                Ok(path) => {
+                    let diffs = match document::Document::parse_file(&path) {
+                        Ok(doc) => roundtrip::verify(&doc),
+                        Err(e) => Err(e),
+                    };
+                    match diffs {
+                        Ok(diffs) if diffs.is_empty() => {
+                            if self.verbosity > 0 {
+                                output::ok(path.display());
+                            }
+                        }
+                        Ok(diffs) => {
+                            failed += 1;
+                            output::bad(path.display());
+                            for d in diffs {
+                                output::detail(format_args!(
+                                    "{}: {} => {}",
+                                    d.field, d.before, d.after
+                                ));
+                            }
+                        }
+                        Err(e) => {
+                            failed += 1;
+                            output::error(format_args!(
+                                "Failed to load file {}: {}",
+                                path.display(),
+                                e
+                            ));
+                        }
+                    }
+                }
+
+                Err(e) => output::error(format_args!("{:?}", e)),
+            }
+        }
+        if failed > 0 {
+            bail!("{} files did not round-trip cleanly", failed);
+        }
+        Ok(())
+    }
+
+    fn normalize_authors(&self, diff: bool, dry_run: bool) -> Result<(), Report> {
+        let client = self.client();
+        let docs = query::search_all(
+            &client,
+            &self.index_url("search"),
+            &api::SearchRequest::new(),
+        )?;
+        let aliases = authors::Aliases::new(&self.config.authors);
+        let (before, changed): (Vec<_>, Vec<_>) = docs
+            .into_iter()
+            .filter_map(|doc| {
+                let mut normalized = doc.clone();
+                if aliases.normalize(&mut normalized) {
+                    Some((doc, normalized))
+                } else {
+                    None
+                }
+            })
+            .unzip();
+        if changed.is_empty() {
+            if self.verbosity > 0 {
+                output::ok("Authors are already normalized");
+            }
+            return Ok(());
+        }
+
+        if diff || dry_run {
+            let colored = output::stdout_colored();
+            for (old, new) in before.iter().zip(changed.iter()) {
+                print!("{}", diff::unified(Some(old), new, colored));
+            }
+        }
+        if dry_run {
+            println!("Dry run, {} documents would change", changed.len());
+            return Ok(());
+        }
+        self.confirm(
+            "normalize the authors of",
+            &changed.iter().collect::<Vec<_>>(),
+        )?;
+        self.put_documents(&client, &changed)?;
+        let id = undo::UndoLog::new(&self.undo_log_path).record(
+            "normalize-authors",
+            &self.index,
+            before,
+            Vec::new(),
+            changed.clone(),
+        )?;
+        let external = self.external_client();
+        for doc in &changed {
+            webhook::fire(
+                &external,
+                &self.config.webhooks,
+                webhook::Event::Updated,
+                doc,
+            );
+        }
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!(
+            "Normalized the authors of {} documents, undo with `undo {}`",
+            changed.len(),
+            id
+        ));
+        Ok(())
+    }
+
+    /// Apply the migrations not yet recorded in the index's metadata document to every document
+    /// in the index, then record them. Documents are handled as raw JSON, as those in need of
+    /// migrating may no longer deserialize into a `Document`.
+    fn migrate(&self, list: bool, dry_run: bool) -> Result<(), Report> {
+        let client = self.client();
+        let metadata_url = self.url(&format!(
+            "indexes/{}/documents/{}",
+            migrate::METADATA_INDEX,
+            self.index
+        ));
+        // Indexes never migrated, including every index before migrations existed, have no
+        // metadata document
+        let mut metadata = match client.get(metadata_url.as_ref()).send() {
+            Ok(res) if res.status().is_success() => res.json()?,
+            _ => migrate::Metadata {
+                id: self.index.to_owned(),
+                ..Default::default()
+            },
+        };
+
+        if list {
+            let mut table = output::Table::new(&["VERSION", "NAME", "APPLIED", "DESCRIPTION"]);
+            for m in migrate::MIGRATIONS {
+                let applied = metadata.migrations.contains(&m.version);
+                table.row(vec![
+                    m.version.to_string(),
+                    m.name.to_owned(),
+                    String::from(if applied { "yes" } else { "no" }),
+                    m.description.to_owned(),
+                ]);
+            }
+            print!("{}", table);
+            return Ok(());
+        }
+
+        let pending = migrate::pending(&metadata.migrations);
+        if pending.is_empty() {
+            output::ok(format_args!("Index {} is up to date", self.index));
+            return Ok(());
+        }
+        let mut docs = self.raw_documents(&client, &self.index)?;
+        let mut changed = vec![false; docs.len()];
+        for m in &pending {
+            let mut count = 0;
+            for (doc, changed) in docs.iter_mut().zip(changed.iter_mut()) {
+                if let Some(fields) = doc.as_object_mut() {
+                    if (m.apply)(fields) {
+                        *changed = true;
+                        count += 1;
+                    }
+                }
+            }
+            output::ok(format_args!(
+                "{} {}: {} of {} documents {}",
+                m.version,
+                m.name,
+                count,
+                docs.len(),
+                if dry_run { "would change" } else { "change" }
+            ));
+        }
+        let changed: Vec<_> = docs
+            .into_iter()
+            .zip(changed)
+            .filter_map(|(doc, changed)| if changed { Some(doc) } else { None })
+            .collect();
+        if dry_run {
+            println!("Dry run, {} documents would change", changed.len());
+            return Ok(());
+        }
+
+        self.check_writable()?;
+        let threshold = self
+            .config
+            .confirm_threshold
+            .unwrap_or(confirm::DEFAULT_THRESHOLD);
+        if changed.len() > threshold
+            && !confirm::typed_yes(
+                &format!("migrate {} documents in {}", changed.len(), self.index),
+                self.yes,
+            )?
+        {
+            bail!("Aborted, nothing was changed");
+        }
+        let url = self.index_url("documents");
+        for chunk in changed.chunks(migrate::BATCH) {
+            let res = client.post(url.as_ref()).json(chunk).send()?;
+            if !res.status().is_success() {
+                bail!("Updating documents failed: {}", res.text()?);
+            }
+            let task: api::task::EnqueuedTask = res.json()?;
+            self.wait_for_task(&client, &self.index, task.uid)?;
+        }
+
+        // Only recorded once every document is updated, so a failed run is simply run again
+        metadata
+            .migrations
+            .extend(pending.iter().map(|m| m.version));
+        let res = client
+            .post(
+                self.url(&format!("indexes/{}/documents", migrate::METADATA_INDEX))
+                    .as_ref(),
+            )
+            .json(&[&metadata])
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Recording the migrations failed: {}", res.text()?);
+        }
+        let task: api::task::EnqueuedTask = res.json()?;
+        self.wait_for_task(&client, migrate::METADATA_INDEX, task.uid)?;
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!(
+            "Applied {} migrations to {}, changing {} documents",
+            pending.len(),
+            self.index,
+            changed.len()
+        ));
+        Ok(())
+    }
+
+    /// Every document in index `uid` as stored, paging through the documents endpoint
+    fn raw_documents(
+        &self,
+        client: &reqwest::blocking::Client,
+        uid: &str,
+    ) -> Result<Vec<serde_json::Value>, Report> {
+        let page_size = query::PAGE_SIZE as usize;
+        let mut docs = Vec::new();
+        loop {
+            let mut url = self.url(&format!("indexes/{}/documents", uid));
+            url.query_pairs_mut()
+                .append_pair("offset", &docs.len().to_string())
+                .append_pair("limit", &page_size.to_string());
+            let res = client.get(url.as_ref()).send()?;
+            if !res.status().is_success() {
+                bail!("Fetching documents failed: {}", res.text()?);
+            }
+            // A bare array before Meilisearch 0.28, wrapped in `results` since
+            let page = match res.json()? {
+                serde_json::Value::Array(page) => page,
+                serde_json::Value::Object(mut wrapped) => match wrapped.remove("results") {
+                    Some(serde_json::Value::Array(page)) => page,
+                    _ => bail!("Unexpected response listing documents"),
+                },
+                _ => bail!("Unexpected response listing documents"),
+            };
+            let last = page.len() < page_size;
+            docs.extend(page);
+            if last {
+                return Ok(docs);
+            }
+        }
+    }
+
+    /// Diff of the document against the version of it in the index
+    fn diff_against_index(
+        &self,
+        client: &reqwest::blocking::Client,
+        doc: &document::Document,
+    ) -> String {
+        let url = self.index_url(&format!("documents/{}", doc.id));
+        // Not being able to fetch it is taken to mean it's new
+        let current = query::get_document(client, &url).ok();
+        diff::unified(current.as_ref(), doc, output::stdout_colored())
+    }
+
+    /// Add or replace documents in the index in one request
+    fn put_documents(
+        &self,
+        client: &reqwest::blocking::Client,
+        docs: &[document::Document],
+    ) -> Result<(), Report> {
+        let mut batch = import::Batch::new(!self.no_compression);
+        for doc in docs {
+            batch.push(doc.filename.to_owned(), doc)?;
+        }
+        let url = self.index_url("documents");
+        let res = self.post_documents(client, &url, batch.finish()?)?;
+        if !res.status().is_success() {
+            bail!("Request failed: {:?}", res);
+        }
+        Ok(())
+    }
+
+    fn delete_documents(
+        &self,
+        client: &reqwest::blocking::Client,
+        ids: &[String],
+    ) -> Result<(), Report> {
+        self.check_writable()?;
+        let res = client
+            .post(self.index_url("documents/delete-batch").as_ref())
+            .json(ids)
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Request failed: {:?}", res);
+        }
+        Ok(())
+    }
+
+    fn index(&self, action: &IndexAction) -> Result<(), Report> {
+        let client = self.client();
+        match action {
+            IndexAction::List => {
+                let indexes: Vec<api::IndexInfo> = query::get_json(&client, &self.url("indexes"))?;
+                let mut table =
+                    output::Table::new(&["", "UID", "PRIMARY KEY", "DOCUMENTS", "UPDATED"]);
+                for index in indexes {
+                    let stats: api::IndexStats = query::get_json(
+                        &client,
+                        &self.url(&format!("indexes/{}/stats", index.uid)),
+                    )?;
+                    table.row(vec![
+                        // Marks the index the other commands use
+                        if index.uid == self.index { "*" } else { "" }.to_owned(),
+                        index.uid,
+                        index.primary_key.unwrap_or_default(),
+                        stats.number_of_documents.to_string(),
+                        index.updated_at,
+                    ]);
+                }
+                print!("{}", table);
+            }
+            IndexAction::Create { uid, primary_key } => {
+                self.check_writable()?;
+                let res = client
+                    .post(self.url("indexes").as_ref())
+                    .json(&serde_json::json!({ "uid": uid, "primaryKey": primary_key }))
+                    .send()?;
+                if !res.status().is_success() {
+                    bail!("Creating index {} failed: {}", uid, res.text()?);
+                }
+                output::ok(format_args!("Created index {}", uid));
+            }
+            IndexAction::Delete { uid } => {
+                self.check_writable()?;
+                let stats: api::IndexStats =
+                    query::get_json(&client, &self.url(&format!("indexes/{}/stats", uid)))?;
+                let action = format!(
+                    "delete index {} and its {} documents",
+                    uid, stats.number_of_documents
+                );
+                if !confirm::typed_yes(&action, self.yes)? {
+                    bail!("Aborted, nothing was changed");
+                }
+                let res = client
+                    .delete(self.url(&format!("indexes/{}", uid)).as_ref())
+                    .send()?;
+                if !res.status().is_success() {
+                    bail!("Deleting index {} failed: {}", uid, res.text()?);
+                }
+                let mut state = state::State::load(&self.state_path)?;
+                state.imported_files.remove(uid);
+                state.save()?;
+                cache::invalidate(&self.config.cache);
+                output::ok(format_args!("Deleted index {}", uid));
+            }
+            IndexAction::Swap { first, second } => self.swap_indexes(&client, first, second)?,
+        }
+        Ok(())
+    }
+
+    /// Run the plugin named by the first of `args` with the rest, exiting with its status.
+    /// It's told where things are through the variables meilizet itself reads, so it can run
+    /// meilizet, found in MEILIZET, in turn against the same index. MEILIZET_READ_ONLY is set in
+    /// read-only mode, which plugins are trusted to honor.
+    fn plugin(&self, args: &[String]) -> Result<(), Report> {
+        let (name, args) = match args.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let mut vars = vec![
+            ("MEILI_HOST", self.host.to_owned()),
+            ("MEILIZET_INDEX", self.index.to_owned()),
+            ("MEILIZET_CONFIG", self.config_path.to_owned()),
+            ("MEILIZET_STATE", self.state_path.to_owned()),
+            ("MEILIZET_UNDO_LOG", self.undo_log_path.to_owned()),
+            ("MEILIZET_AUDIT_LOG", self.audit_log_path.to_owned()),
+        ];
+        if let Some(api_key) = &self.api_key {
+            vars.push(("MEILI_API_KEY", api_key.to_owned()));
+        }
+        if self.read_only {
+            vars.push(("MEILIZET_READ_ONLY", String::from("1")));
+        }
+        if self.no_color {
+            vars.push(("NO_COLOR", String::from("1")));
+        }
+        if let Ok(exe) = env::current_exe() {
+            vars.push(("MEILIZET", exe.display().to_string()));
+        }
+        let code = plugin::run(name, args, &vars)?;
+        if code != 0 {
+            std::process::exit(code);
+        }
+        Ok(())
+    }
+
+    fn meta(&self) -> Result<(), Report> {
+        let features: Vec<&str> = FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        let meta = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "meilisearch": {
+                "min": api::MIN_SERVER_VERSION,
+                "below": api::MAX_SERVER_VERSION,
+            },
+            "features": features,
+            "config": {
+                "path": self.config_path,
+                "host": self.host,
+                "index": self.index,
+                "vault": self.vault,
+                // Whether there is one, not the key itself
+                "api_key": self.api_key.is_some(),
+                "read_only": self.read_only,
+                "editor": self.editor,
+                "pager": self.pager,
+                "state": self.state_path,
+                "undo_log": self.undo_log_path,
+                "audit_log": self.audit_log_path,
+                "file": config::Config::redacted(&self.config_path)?,
+            },
+        });
+        println!("{}", serde_json::to_string_pretty(&meta)?);
+        Ok(())
+    }
+
+    fn selftest(&self) -> Result<(), Report> {
+        let client = self.client();
+        let mut checks = vec![
+            ("Server", self.check_server(&client)),
+            ("API key", self.check_api_key(&client)),
+            ("Index", self.check_index(&client)),
+            ("Settings", self.check_settings(&client)),
+        ];
+        if self.read_only {
+            output::detail("Skipping the write checks in read-only mode");
+        } else {
+            checks.push(("Indexing", self.check_round_trip(&client)));
+        }
+        let mut failed = 0;
+        for (name, result) in &checks {
+            match result {
+                Ok(detail) => output::ok(format_args!("{}: {}", name, detail)),
+                Err(e) => {
+                    failed += 1;
+                    output::bad(format_args!("{}: {}", name, e));
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("{} of {} checks failed", failed, checks.len());
+        }
+        Ok(())
+    }
+
+    /// The health endpoint answers without a key, so this tells a server that's down from a key
+    /// that's wrong
+    fn check_server(&self, client: &reqwest::blocking::Client) -> Result<String, Report> {
+        let res = client
+            .get(self.url("health").as_ref())
+            .send()
+            .map_err(|e| eyre!("{} is unreachable: {}", self.host, e))?;
+        if !res.status().is_success() {
+            bail!("{} is unhealthy: {}", self.host, res.status());
+        }
+        Ok(format!("{} is up", self.host))
+    }
+
+    fn check_api_key(&self, client: &reqwest::blocking::Client) -> Result<String, Report> {
+        let version: serde_json::Value = query::get_json(client, &self.url("version"))
+            .map_err(|e| eyre!("Rejected, set --api-key or MEILI_API_KEY: {}", e))?;
+        Ok(format!(
+            "Accepted by Meilisearch {}",
+            version["pkgVersion"]
+                .as_str()
+                .unwrap_or("of an unknown version")
+        ))
+    }
+
+    fn check_index(&self, client: &reqwest::blocking::Client) -> Result<String, Report> {
+        let stats: api::IndexStats =
+            query::get_json(client, &self.index_url("stats")).map_err(|e| {
+                eyre!(
+                    "Index {} is missing, import some notes first: {}",
+                    self.index,
+                    e
+                )
+            })?;
+        Ok(format!(
+            "{} holds {} documents",
+            self.index, stats.number_of_documents
+        ))
+    }
+
+    /// Filters on fields that aren't filterable fail, which the interface can only show as no
+    /// matches
+    fn check_settings(&self, client: &reqwest::blocking::Client) -> Result<String, Report> {
+        let filterable: Vec<String> =
+            query::get_json(client, &self.index_url("settings/filterable-attributes"))?;
+        let missing: Vec<&str> = ["tags", "date", "created", "modified", "imported_at"]
+            .iter()
+            .copied()
+            .filter(|field| !filterable.iter().any(|f| f == field))
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "{} can't be filtered on, run `mz settings init`",
+                missing.join(", ")
+            );
+        }
+        Ok(String::from("Tags and dates can be filtered on"))
+    }
+
+    /// Add a document to a scratch index, find it and delete the index again
+    fn check_round_trip(&self, client: &reqwest::blocking::Client) -> Result<String, Report> {
+        let uid = format!("meilizet-selftest-{}", uuid::Uuid::new_v4().to_simple());
+        let result = self.round_trip(client, &uid);
+        // Index deletion is itself a task on newer servers, which can finish on its own
+        let deleted = client
+            .delete(self.url(&format!("indexes/{}", uid)).as_ref())
+            .send();
+        if let Err(e) = deleted {
+            output::warn(format_args!(
+                "Failed to delete scratch index {}: {}",
+                uid, e
+            ));
+        }
+        result
+    }
+
+    fn round_trip(&self, client: &reqwest::blocking::Client, uid: &str) -> Result<String, Report> {
+        let res = client
+            .post(self.url("indexes").as_ref())
+            .json(&serde_json::json!({ "uid": uid, "primaryKey": "id" }))
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Creating scratch index {} failed: {}", uid, res.text()?);
+        }
+        // Created right away before 0.28, by a task since
+        if let Ok(task) = res.json::<api::task::EnqueuedTask>() {
+            self.wait_for_task(client, uid, task.uid)?;
+        }
+        let res = client
+            .post(self.url(&format!("indexes/{}/documents", uid)).as_ref())
+            .json(&serde_json::json!([{
+                "id": "selftest",
+                "title": "meilizet selftest",
+                "body": "quokka",
+            }]))
+            .send()?;
+        if !res.status().is_success() {
+            bail!("Adding a document failed: {}", res.text()?);
+        }
+        let task: api::task::EnqueuedTask = res.json()?;
+        self.wait_for_task(client, uid, task.uid)?;
+        let mut q = api::SearchRequest::new().query("quokka");
+        // The scratch index has no sortable attributes
+        q.sort = None;
+        let url = self.url(&format!("indexes/{}/search", uid));
+        let hits = query::search(client, &url, &q)?.hits;
+        if hits.len() != 1 {
+            bail!("Searching found {} documents instead of 1", hits.len());
+        }
+        Ok(String::from("Documents can be added and searched"))
+    }
+
+    /// List the notes in the inbox, or triage them one at a time
+    fn inbox(&self, triage: bool) -> Result<(), Report> {
+        let client = self.client();
+        let mut q = api::SearchRequest::new();
+        if !triage {
+            q.without_body();
+        }
+        let docs = triage::inbox(query::search_all(&client, &self.index_url("search"), &q)?);
+        if docs.is_empty() {
+            output::ok("The inbox is empty");
+            return Ok(());
+        }
+        if !triage {
+            let mut table = output::Table::new(&["ID", "DATE", "TITLE", "TAGS"]);
+            for doc in &docs {
+                table.row(vec![
+                    doc.id.to_owned(),
+                    doc.date.to_string(),
+                    doc.title.to_owned(),
+                    doc.tags.join(", "),
+                ]);
+            }
+            print!("{}", table);
+            return Ok(());
+        }
+        self.triage(&client, docs)
+    }
+
+    /// Step through `docs` in the triage interface. Each action is applied and recorded for
+    /// `undo` as soon as it's picked.
+    fn triage(
+        &self,
+        client: &reqwest::blocking::Client,
+        docs: Vec<document::Document>,
+    ) -> Result<(), Report> {
+        self.check_writable()?;
+        let undo_log = undo::UndoLog::new(&self.undo_log_path);
+        let external = self.external_client();
+        let done = triage::run(docs, |action| {
+            let triage::Action {
+                kind,
+                before,
+                after,
+            } = action;
+            let (id, message) = match after {
+                Some(after) => {
+                    self.put_documents(client, &[after.clone()])?;
+                    let id = undo_log.record(
+                        kind,
+                        &self.index,
+                        vec![before],
+                        Vec::new(),
+                        vec![after.clone()],
+                    )?;
+                    webhook::fire(
+                        &external,
+                        &self.config.webhooks,
+                        webhook::Event::Updated,
+                        &after,
+                    );
+                    (id, format!("Filed {}", after.title))
+                }
+                None => {
+                    let ids = vec![before.id.to_owned()];
+                    self.delete_documents(client, &ids)?;
+                    let mut state = state::State::load(&self.state_path)?;
+                    state.forget_imports(&self.index, &ids);
+                    state.save()?;
+                    let id = undo_log.record(
+                        kind,
+                        &self.index,
+                        vec![before.clone()],
+                        Vec::new(),
+                        Vec::new(),
+                    )?;
+                    webhook::fire(
+                        &external,
+                        &self.config.webhooks,
+                        webhook::Event::Deleted,
+                        &before,
+                    );
+                    (id, format!("Deleted {}", before.title))
+                }
+            };
+            cache::invalidate(&self.config.cache);
+            Ok(format!("{}, undo with `undo {}`", message, id))
+        })?;
+        output::ok(format_args!("Triaged {} notes", done));
+        Ok(())
+    }
+
+    /// List the notes a hygiene report turns up, or step through them in the triage interface
+    fn report(&self, kind: &ReportKind) -> Result<(), Report> {
+        let (name, opts) = match kind {
+            ReportKind::Untagged { opts } => ("untagged", opts),
+            ReportKind::Unlinked { opts } => ("unlinked", opts),
+        };
+        let client = self.client();
+        let mut q = api::SearchRequest::new();
+        if !opts.open {
+            q.without_body();
+        }
+        let docs = query::search_all(&client, &self.index_url("search"), &q)?;
+        let found = match kind {
+            ReportKind::Untagged { .. } => report::untagged(&docs),
+            ReportKind::Unlinked { .. } => report::unlinked(&docs),
+        };
+        if opts.json {
+            println!("{}", report::json(&found));
+            return Ok(());
+        }
+        if found.is_empty() {
+            output::ok(format_args!("No {} notes", name));
+            return Ok(());
+        }
+        if opts.open {
+            return self.triage(&client, found.into_iter().cloned().collect());
+        }
+        let mut table = output::Table::new(&["ID", "DATE", "TITLE", "FILENAME"]);
+        for doc in &found {
+            table.row(vec![
+                doc.id.to_owned(),
+                doc.date.to_string(),
+                doc.title.to_owned(),
+                doc.filename.to_owned(),
+            ]);
+        }
+        print!("{}", table);
+        output::ok(format_args!(
+            "{} of {} notes are {}",
+            found.len(),
+            docs.len(),
+            name
+        ));
+        Ok(())
+    }
+
+    fn sequence(&self, action: &SequenceAction) -> Result<(), Report> {
+        let client = self.client();
+        match action {
+            SequenceAction::Next { id } => {
+                let doc = self.document(&client, id)?;
+                if doc.sequence.is_empty() {
+                    bail!("Note {} has no sequence", id);
+                }
+                let mut q = api::SearchRequest::new();
+                q.attributes_to_retrieve = Some(
+                    ["id", "title", "date", "sequence"]
+                        .iter()
+                        .map(|f| f.to_string())
+                        .collect(),
+                );
+                let docs = query::search_all(&client, &self.index_url("search"), &q)?;
+                let next = sequence::next_branch(
+                    &doc.sequence,
+                    docs.iter().map(|doc| doc.sequence.as_str()),
+                )
+                .ok_or_else(|| eyre!("Note {} has an invalid sequence {}", id, doc.sequence))?;
+                println!("{}", next);
+            }
+        }
+        Ok(())
+    }
+
+    fn task(&self, action: &TaskAction) -> Result<(), Report> {
+        let client = self.client();
+        let host = self.url("");
+        let task = match action {
+            TaskAction::Status { uid } => api::task::get(&client, &host, *uid)?,
+            TaskAction::Wait { uid, timeout } => {
+                api::task::wait(&client, &host, *uid, timeout.map(Duration::from_secs))?
+            }
+        };
+        let mut table = output::Table::new(&["TASK", "TYPE", "STATUS", "ENQUEUED", "FINISHED"]);
+        table.row(vec![
+            task.uid.to_string(),
+            task.kind(),
+            task.status.to_owned(),
+            task.enqueued_at.clone().unwrap_or_default(),
+            task.finished_at.clone().unwrap_or_default(),
+        ]);
+        print!("{}", table);
+        if let Some(message) = task.error_message() {
+            bail!("Task {} failed: {}", task.uid, message);
+        }
+        if task.dropped_documents() > 0 {
+            output::warn(format_args!(
+                "{} documents weren't indexed",
+                task.dropped_documents()
+            ));
+        }
+        Ok(())
+    }
+
+    fn settings(&self, action: &SettingsAction) -> Result<(), Report> {
+        let client = self.client();
+        let (req, done) = match action {
+            SettingsAction::Get { setting: None } => {
+                let current: serde_json::Value =
+                    query::get_json(&client, &self.index_url("settings"))?;
+                let mut table = output::Table::new(&["SETTING", "VALUES"]);
+                for setting in settings::Setting::ALL.iter() {
+                    table.row(vec![
+                        setting.path().to_owned(),
+                        setting_values(&current[setting.key()]),
+                    ]);
+                }
+                print!("{}", table);
+                return Ok(());
+            }
+            SettingsAction::Get {
+                setting: Some(setting),
+            } => {
+                let url = self.index_url(&format!("settings/{}", setting.path()));
+                let current: serde_json::Value = query::get_json(&client, &url)?;
+                for value in current.as_array().into_iter().flatten() {
+                    println!("{}", settings::describe(value));
+                }
+                return Ok(());
+            }
+            SettingsAction::Set { setting, values } => (
+                client
+                    .post(
+                        self.index_url(&format!("settings/{}", setting.path()))
+                            .as_ref(),
+                    )
+                    .json(&setting.body(values)?),
+                format!("Set {} to {}", setting.path(), values.join(", ")),
+            ),
+            SettingsAction::Reset { setting } => (
+                client.delete(
+                    self.index_url(&format!("settings/{}", setting.path()))
+                        .as_ref(),
+                ),
+                format!("Reset {}", setting.path()),
+            ),
+            SettingsAction::Init => (
+                client
+                    .post(self.index_url("settings").as_ref())
+                    .json(&settings::defaults()),
+                String::from("Configured the settings for notes"),
+            ),
+        };
+        self.check_writable()?;
+        let res = req.send()?;
+        if !res.status().is_success() {
+            bail!(
+                "Updating the settings of {} failed: {}",
+                self.index,
+                res.text()?
+            );
+        }
+        let task: api::task::EnqueuedTask = res.json()?;
+        // Searches would be answered with the old settings until the task is processed
+        self.wait_for_task(&client, &self.index, task.uid)?;
+        cache::invalidate(&self.config.cache);
+        output::ok(done);
+        Ok(())
+    }
+
+    /// Delete and recreate the index, apply `settings` or its current ones, and add its
+    /// documents back, or the settings and documents of the backup `restore`. What the index
+    /// holds is saved to `backup` before anything is changed, and the index is counted at the
+    /// end to make sure none went missing.
+    fn reindex(
+        &self,
+        settings: &Option<PathBuf>,
+        backup: &Option<PathBuf>,
+        restore: &Option<PathBuf>,
+    ) -> Result<(), Report> {
+        self.check_writable()?;
+        let uid = &self.index;
+        let _lock = lock::Lock::acquire(
+            &lock::index_path(&self.state_path, uid),
+            &format!("rebuilding index {}", uid),
+        )?;
+        let client = self.client();
+        let restored = restore.as_deref().map(backup::Backup::load).transpose()?;
+        // Restoring is what's left to do when a rebuild failed after deleting the index
+        let info: Option<api::IndexInfo> =
+            match query::get_json(&client, &self.url(&format!("indexes/{}", uid))) {
+                Ok(info) => Some(info),
+                Err(_) if restored.is_some() => None,
+                Err(e) => return Err(e),
+            };
+        let current = match info {
+            Some(_) => Some(backup::Backup {
+                index: uid.to_owned(),
+                settings: query::get_json(&client, &self.index_url("settings"))?,
+                documents: self.raw_documents(&client, uid)?,
+            }),
+            None => None,
+        };
+        let settings = match (settings, &restored, &current) {
+            (Some(path), _, _) => serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| eyre!("Failed to parse settings {}: {}", path.display(), e))?,
+            (None, Some(restored), _) if !restored.settings.is_null() => restored.settings.clone(),
+            (None, _, Some(current)) => current.settings.clone(),
+            (None, _, None) => settings::defaults(),
+        };
+
+        let backup = match backup {
+            Some(path) => path.to_owned(),
+            None => backup::Backup::default_path(&self.state_path, "reindex", uid),
+        };
+        if let Some(current) = &current {
+            current.save(&backup)?;
+            output::ok(format_args!(
+                "Saved the settings and {} documents of {} to {}",
+                current.documents.len(),
+                uid,
+                backup.display()
+            ));
+        }
+        let docs = match (restored, current) {
+            (Some(restored), _) => restored.documents,
+            (None, Some(current)) => current.documents,
+            (None, None) => unreachable!("the index exists unless restoring"),
+        };
+        if !confirm::typed_yes(
+            &format!(
+                "delete and rebuild index {} with {} documents",
+                uid,
+                docs.len()
+            ),
+            self.yes,
+        )? {
+            bail!("Aborted, nothing was changed");
+        }
+
+        // From here on a failure leaves the index incomplete, to be restored from the backup
+        let rebuild = || -> Result<(), Report> {
+            if info.is_some() {
+                let res = client
+                    .delete(self.url(&format!("indexes/{}", uid)).as_ref())
+                    .send()?;
+                if !res.status().is_success() {
+                    bail!("Deleting index {} failed: {}", uid, res.text()?);
+                }
+                // Deleted and created right away before 0.28, by a task since
+                if let Ok(task) = res.json::<api::task::EnqueuedTask>() {
+                    self.wait_for_task(&client, uid, task.uid)?;
+                }
+                output::ok(format_args!("Deleted index {}", uid));
+            }
+            let primary_key = info
+                .as_ref()
+                .and_then(|info| info.primary_key.as_deref())
+                .unwrap_or("id");
+            let res = client
+                .post(self.url("indexes").as_ref())
+                .json(&serde_json::json!({ "uid": uid, "primaryKey": primary_key }))
+                .send()?;
+            if !res.status().is_success() {
+                bail!("Creating index {} failed: {}", uid, res.text()?);
+            }
+            if let Ok(task) = res.json::<api::task::EnqueuedTask>() {
+                self.wait_for_task(&client, uid, task.uid)?;
+            }
+            let res = client
+                .post(self.index_url("settings").as_ref())
+                .json(&settings)
+                .send()?;
+            if !res.status().is_success() {
+                bail!("Applying the settings failed: {}", res.text()?);
+            }
+            let task: api::task::EnqueuedTask = res.json()?;
+            self.wait_for_task(&client, uid, task.uid)?;
+            output::ok("Applied the settings");
+            let mut added = 0;
+            for chunk in docs.chunks(query::PAGE_SIZE as usize) {
+                let res = client
+                    .post(self.index_url("documents").as_ref())
+                    .json(chunk)
+                    .send()?;
+                if !res.status().is_success() {
+                    bail!("Adding documents failed: {}", res.text()?);
+                }
+                let task: api::task::EnqueuedTask = res.json()?;
+                self.wait_for_task(&client, uid, task.uid)?;
+                added += chunk.len();
+                output::detail(format_args!(
+                    "Added {} of {} documents in task {}",
+                    added,
+                    docs.len(),
+                    task.uid
+                ));
+            }
+            let stats: api::IndexStats =
+                query::get_json(&client, &self.url(&format!("indexes/{}/stats", uid)))?;
+            if stats.number_of_documents != docs.len() as u64 {
+                bail!(
+                    "Index {} holds {} documents instead of {}",
+                    uid,
+                    stats.number_of_documents,
+                    docs.len()
+                );
+            }
+            Ok(())
+        };
+        let result = rebuild();
+        cache::invalidate(&self.config.cache);
+        if let Err(e) = result {
+            bail!(
+                "{}. The index may be missing documents, rebuild it with --restore {}",
+                e,
+                restore.as_ref().unwrap_or(&backup).display()
+            );
+        }
+        output::ok(format_args!(
+            "Rebuilt index {} with its {} documents",
+            uid,
+            docs.len()
+        ));
+        Ok(())
+    }
+
+    /// Copy the index to the same or `to_index` on every host in `to`, a thread per host
+    fn replicate(
+        &self,
+        to: &[String],
+        to_api_key: &Option<String>,
+        to_index: &Option<String>,
+    ) -> Result<(), Report> {
+        let client = self.client();
+        let info: api::IndexInfo =
+            query::get_json(&client, &self.url(&format!("indexes/{}", self.index)))?;
+        let primary_key = info.primary_key.unwrap_or_else(|| String::from("id"));
+        let settings: serde_json::Value = query::get_json(&client, &self.index_url("settings"))?;
+        let docs = Arc::new(self.raw_documents(&client, &self.index)?);
+        let replicas = to
+            .iter()
+            .map(|host| {
+                Ok(replicate::Replica {
+                    client: self.build_client(to_api_key.as_deref())?,
+                    host: Url::parse(host).map_err(|e| eyre!("Bad host {}: {}", host, e))?,
+                    index: to_index.clone().unwrap_or_else(|| self.index.to_owned()),
+                })
+            })
+            .collect::<Result<Vec<_>, Report>>()?;
+        let action = format!(
+            "replace the documents of {} with the {} of {}",
+            replicas
+                .iter()
+                .map(|r| format!("{} on {}", r.index, r.host))
+                .collect::<Vec<_>>()
+                .join(", "),
+            docs.len(),
+            self.index
+        );
+        if !confirm::typed_yes(&action, self.yes)? {
+            bail!("Aborted, nothing was changed");
+        }
+
+        let threads: Vec<_> = replicas
+            .into_iter()
+            .map(|replica| {
+                let (primary_key, settings, docs) =
+                    (primary_key.clone(), settings.clone(), docs.clone());
+                thread::spawn(move || {
+                    let summary = replica.replace(&primary_key, &settings, &docs);
+                    (replica, summary)
+                })
+            })
+            .collect();
+        let mut failed = 0;
+        for thread in threads {
+            let (replica, summary) = thread.join().expect("Replication thread panicked");
+            match summary {
+                Ok(summary) => output::ok(format_args!(
+                    "Copied {} documents to {} on {}, {} spot checks passed",
+                    summary.documents, replica.index, replica.host, summary.checked
+                )),
+                Err(e) => {
+                    failed += 1;
+                    output::error(format_args!("Copying to {} failed: {}", replica.host, e));
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("{} of {} replicas failed", failed, to.len());
+        }
+        Ok(())
+    }
+
+    /// Exchange the documents and settings of two indexes. Meilisearch 1.0 and later swap them
+    /// in a single task. Earlier versions can't, so their contents are copied across instead,
+    /// after saving both to backups: it takes a while for large indexes, during which both are
+    /// incomplete.
+    fn swap_indexes(
+        &self,
+        client: &reqwest::blocking::Client,
+        first: &str,
+        second: &str,
+    ) -> Result<(), Report> {
+        self.check_writable()?;
+        let version: serde_json::Value = query::get_json(client, &self.url("version"))?;
+        let native = version["pkgVersion"]
+            .as_str()
+            .and_then(|v| v.split('.').next()?.parse::<u64>().ok())
+            .is_some_and(|major| major >= 1);
+        if native {
+            let count = |uid: &str| -> Result<u64, Report> {
+                let stats: api::IndexStats =
+                    query::get_json(client, &self.url(&format!("indexes/{}/stats", uid)))?;
+                Ok(stats.number_of_documents)
+            };
+            if !confirm::typed_yes(
+                &format!(
+                    "swap the {} documents of {} with the {} of {}",
+                    count(first)?,
+                    first,
+                    count(second)?,
+                    second
+                ),
+                self.yes,
+            )? {
+                bail!("Aborted, nothing was changed");
+            }
+            let res = client
+                .post(self.url("swap-indexes").as_ref())
+                .json(&serde_json::json!([{ "indexes": [first, second] }]))
+                .send()?;
+            if !res.status().is_success() {
+                bail!(
+                    "Swapping indexes {} and {} failed: {}",
+                    first,
+                    second,
+                    res.text()?
+                );
+            }
+            let task: api::task::EnqueuedTask = res.json()?;
+            self.wait_for_task(client, first, task.uid)?;
+            cache::invalidate(&self.config.cache);
+            output::ok(format_args!("Swapped indexes {} and {}", first, second));
+            return Ok(());
+        }
+
+        let contents = |uid: &str| -> Result<backup::Backup, Report> {
+            Ok(backup::Backup {
+                index: uid.to_owned(),
+                settings: query::get_json(client, &self.url(&format!("indexes/{}/settings", uid)))?,
+                documents: self.raw_documents(client, uid)?,
+            })
+        };
+        let (first_backup, second_backup) = (contents(first)?, contents(second)?);
+        if !confirm::typed_yes(
+            &format!(
+                "swap the {} documents of {} with the {} of {}",
+                first_backup.documents.len(),
+                first,
+                second_backup.documents.len(),
+                second
+            ),
+            self.yes,
+        )? {
+            bail!("Aborted, nothing was changed");
+        }
+        // Both are on disk before either index is touched, a failure halfway loses nothing
+        let mut paths = Vec::new();
+        for backup in &[&first_backup, &second_backup] {
+            let path = backup::Backup::default_path(&self.state_path, "swap", &backup.index);
+            backup.save(&path)?;
+            output::ok(format_args!(
+                "Saved the {} documents of {} to {}",
+                backup.documents.len(),
+                backup.index,
+                path.display()
+            ));
+            paths.push(path);
+        }
+
+        let copy = || -> Result<(), Report> {
+            for (uid, backup) in &[(first, &second_backup), (second, &first_backup)] {
+                let url = |path: &str| self.url(&format!("indexes/{}/{}", uid, path));
+                let requests = vec![
+                    client.delete(url("documents").as_ref()),
+                    client.post(url("settings").as_ref()).json(&backup.settings),
+                    client
+                        .post(url("documents").as_ref())
+                        .json(&backup.documents),
+                ];
+                for req in requests {
+                    let res = req.send()?;
+                    if !res.status().is_success() {
+                        bail!("Swapping into index {} failed: {}", uid, res.text()?);
+                    }
+                    let task: api::task::EnqueuedTask = res.json()?;
+                    self.wait_for_task(client, uid, task.uid)?;
+                }
+            }
+            Ok(())
+        };
+        let result = copy();
+        cache::invalidate(&self.config.cache);
+        if let Err(e) = result {
+            bail!(
+                "{}. The indexes may be missing documents, they're saved in {} and {}",
+                e,
+                paths[0].display(),
+                paths[1].display()
+            );
+        }
+        output::ok(format_args!("Swapped indexes {} and {}", first, second));
+        Ok(())
+    }
+
+    /// Delete the documents with `ids`, or the ones matching `filter`. The filter is resolved to
+    /// IDs up front, so what's previewed, what's deleted and what `undo` restores all agree.
+    fn delete(&self, ids: &[String], filter: &Option<String>, dry_run: bool) -> Result<(), Report> {
+        if !dry_run {
+            self.check_writable()?;
+        }
+        let client = self.client();
+        let docs = match filter {
+            Some(filter) => {
+                let mut q = api::SearchRequest::new();
+                q.filter = Some(filter.to_owned());
+                query::search_all(&client, &self.index_url("search"), &q)?
+            }
+            None => ids
+                .iter()
+                .map(|id| {
+                    self.document(&client, id)
+                        .map_err(|e| eyre!("No document {} to delete: {}", id, e))
+                })
+                .collect::<Result<Vec<_>, Report>>()?,
+        };
+        if docs.is_empty() {
+            output::ok("No documents match, nothing to delete");
+            return Ok(());
+        }
+        if dry_run {
+            let mut table = output::Table::new(&["ID", "TITLE", "FILE"]);
+            for doc in &docs {
+                table.row(vec![
+                    doc.id.to_owned(),
+                    doc.title.to_owned(),
+                    doc.filename.to_owned(),
+                ]);
+            }
+            print!("{}", table);
+            println!(
+                "Dry run, {} documents would be deleted, the index was left alone",
+                docs.len()
+            );
+            return Ok(());
+        }
+
+        // Deleting always asks, however few documents are affected
+        if !confirm::bulk("delete", &docs.iter().collect::<Vec<_>>(), 0, self.yes)? {
+            bail!("Aborted, nothing was changed");
+        }
+        let ids: Vec<String> = docs.iter().map(|doc| doc.id.to_owned()).collect();
+        self.delete_documents(&client, &ids)?;
+        // Their files are imported again even if they haven't changed
+        let mut state = state::State::load(&self.state_path)?;
+        state.forget_imports(&self.index, &ids);
+        state.save()?;
+        let id = undo::UndoLog::new(&self.undo_log_path).record(
+            "delete",
+            &self.index,
+            docs.clone(),
+            Vec::new(),
+            Vec::new(),
+        )?;
+        let external = self.external_client();
+        for doc in &docs {
+            webhook::fire(
+                &external,
+                &self.config.webhooks,
+                webhook::Event::Deleted,
+                doc,
+            );
+        }
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!(
+            "Deleted {} documents, undo with `undo {}`",
+            docs.len(),
+            id
+        ));
+        Ok(())
+    }
+
+    fn log(&self, action: &LogAction) -> Result<(), Report> {
+        match action {
+            LogAction::Show { last } => {
+                let runs = audit::AuditLog::new(&self.audit_log_path).runs()?;
+                let skip = last.map_or(0, |n| runs.len().saturating_sub(n));
+                for run in runs.into_iter().skip(skip) {
+                    let mut line = format!("{} {} into index {}", run.at, run.source, run.index);
+                    if let Some(vault) = &run.vault {
+                        line.push_str(&format!(" (vault {})", vault));
+                    }
+                    if run.failed() {
+                        output::bad(line);
+                    } else {
+                        output::ok(line);
+                    }
+                    output::detail(format_args!(
+                        "{} documents, {} failed, {} skipped{}",
+                        run.documents,
+                        run.failed,
+                        run.skipped,
+                        if run.interrupted { ", interrupted" } else { "" }
+                    ));
+                    if !run.tasks.is_empty() {
+                        let tasks: Vec<String> = run.tasks.iter().map(|t| t.to_string()).collect();
+                        output::detail(format_args!("Tasks {}", tasks.join(", ")));
+                    }
+                    if self.verbosity > 0 && !run.ids.is_empty() {
+                        output::detail(format_args!("IDs {}", run.ids.join(", ")));
+                    }
+                    for e in &run.errors {
+                        output::detail(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn undo(&self, id: Option<u64>, list: bool) -> Result<(), Report> {
+        let log = undo::UndoLog::new(&self.undo_log_path);
+        if list {
+            let mut table = output::Table::new(&["ID", "WHEN", "WHAT", "INDEX", "DOCUMENTS"]);
+            for op in log.operations()? {
+                table.row(vec![
+                    op.id.to_string(),
+                    op.at.to_string(),
+                    op.kind,
+                    op.index,
+                    (op.before.len() + op.created.len()).to_string(),
+                ]);
+            }
+            print!("{}", table);
+            return Ok(());
+        }
+
+        let op = log.find(id)?;
+        if op.index != self.index {
+            bail!(
+                "Operation {} changed index {}, pick it with --index or --vault",
+                op.id,
+                op.index
+            );
+        }
+        let client = self.client();
+        // Putting `before` back overwrites whatever was changed since, so the documents have
+        // to be as the operation left them: the ones in `after` unchanged, views aside, and
+        // the ones it deleted still gone
+        let current = |id: &str| {
+            query::get_document(&client, &self.index_url(&format!("documents/{}", id))).ok()
+        };
+        let mut changed: Vec<&str> = op
+            .after
+            .iter()
+            .filter(|doc| match current(&doc.id) {
+                Some(now) => roundtrip::diff(doc, &now)
+                    .iter()
+                    .any(|diff| diff.field != "views"),
+                None => true,
+            })
+            .map(|doc| doc.id.as_str())
+            .collect();
+        changed.extend(
+            op.before
+                .iter()
+                .filter(|doc| !op.after.iter().any(|after| after.id == doc.id))
+                .filter(|doc| current(&doc.id).is_some())
+                .map(|doc| doc.id.as_str()),
+        );
+        if !changed.is_empty() {
+            if !self.yes {
+                bail!(
+                    "{} changed since operation {}, undoing it would lose that. Pass --yes to \
+                     undo it anyway.",
+                    changed.join(", "),
+                    op.id
+                );
+            }
+            output::warn(format_args!(
+                "Overwriting the changes made since to {}",
+                changed.join(", ")
+            ));
+        }
+
+        let created: Vec<&document::Document> = op
+            .after
+            .iter()
+            .filter(|d| op.created.contains(&d.id))
+            .collect();
+        self.confirm(
+            &format!("undo {} {} on", op.id, op.kind),
+            &op.before
+                .iter()
+                .chain(created.iter().cloned())
+                .collect::<Vec<_>>(),
+        )?;
+        if !op.before.is_empty() {
+            self.put_documents(&client, &op.before)?;
+        }
+        if !op.created.is_empty() {
+            self.delete_documents(&client, &op.created)?;
+        }
+        log.remove(op.id)?;
+
+        let external = self.external_client();
+        for doc in &op.before {
+            webhook::fire(
+                &external,
+                &self.config.webhooks,
+                webhook::Event::Updated,
+                doc,
+            );
+        }
+        for doc in created {
+            webhook::fire(
+                &external,
+                &self.config.webhooks,
+                webhook::Event::Deleted,
+                doc,
+            );
+        }
+        cache::invalidate(&self.config.cache);
+        output::ok(format_args!("Undid {} {}", op.id, op.kind));
+        Ok(())
+    }
+
+    fn lint(&self, path: &str) -> Result<(), Report> {
+        let mut failed = 0;
+        let ignore = ignore::Ignore::for_source(path)?;
+        for entry in glob_files(path, self.verbosity).expect("Failed to read glob pattern") {
+            match entry {
+                Ok(path) if ignore.matching(&path).is_some() => {}
+                Ok(path) => match document::Document::parse_file(&path) {
+                    Ok(doc) => {
+                        let violations = validation::validate(&self.config.validation, &doc);
+                        if violations.is_empty() {
+                            if self.verbosity > 0 {
+                                output::ok(path.display());
+                            }
+                        } else {
+                            failed += 1;
+                            output::bad(path.display());
+                            for v in violations {
+                                output::detail(v);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        output::error(format_args!(
+                            "Failed to load file {}: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                },
+
+                Err(e) => output::error(format_args!("{:?}", e)),
+            }
+        }
+        // Files missing from search are less of a mystery when it's said why
+        for line in ignore.summary() {
+            output::detail(line);
+        }
+        if failed > 0 {
+            bail!("{} files broke the validation rules", failed);
+        }
+        Ok(())
+    }
+}
+
+/// Values of a setting as shown by `settings get`
+fn setting_values(setting: &serde_json::Value) -> String {
+    match setting {
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(settings::describe)
+            .collect::<Vec<_>>()
+            .join(", "),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Paths matching the glob, reporting the ones that can't be read
+fn glob_paths(source: &str, verbosity: u8) -> impl Iterator<Item = PathBuf> + Send {
+    glob_files(source, verbosity)
+        .expect("Failed to read glob pattern")
+        .filter_map(|entry| match entry {
+            Ok(path) => Some(path),
+            Err(e) => {
+                output::error(format_args!("{:?}", e));
+                None
+            }
+        })
+}
+
+pub fn glob_files(source: &str, verbosity: u8) -> Result<Paths, Box<dyn std::error::Error>> {
+    let glob_path = Path::new(&source);
+    let glob_str = shellexpand::tilde(glob_path.to_str().unwrap());
+
+    if verbosity > 0 {
+        println!("Sourcing Markdown documents matching : {}", glob_str);
+    }
+
+    Ok(glob(&glob_str).expect("Failed to read glob pattern"))
+}
+
+fn setup() -> Result<(), Report> {
+    if std::env::var("RUST_LIB_BACKTRACE").is_err() {
+        std::env::set_var("RUST_LIB_BACKTRACE", "1")
+    }
+    color_eyre::install()?;
+
+    Ok(())
+}
+
+fn main() -> Result<(), Report> {
+    setup()?;
+
+    let matches = Opt::clap().get_matches();
+    let mut opt = Opt::from_clap(&matches);
+    output::init(opt.no_color);
+    if let Subcommands::Config {
+        action: ConfigAction::Init { force },
+    } = opt.subcmd
+    {
+        // The file may not parse yet, which is why it's being replaced
+        let path = config::Config::init(&opt.config_path, force)?;
+        output::ok(format_args!("Wrote {}", path.display()));
+        return Ok(());
+    }
+    opt.config = config::Config::load(&opt.config_path)?;
+    opt.keys = keys::Keys::new(&opt.config.keys)?;
+    opt.apply_config(&matches);
+    if let Some(format) = &opt.config.date_display_format {
+        date::set_display_format(format);
+    }
+    zettel::set_scheme(opt.config.id_scheme);
+    if let Some(name) = opt.vault.to_owned() {
+        opt.select_vault(&name)?;
+    }
+    opt.client = Some(opt.build_client(opt.api_key.as_deref())?);
+    opt.external_client = Some(opt.build_client(None)?);
+
+    match opt.subcmd {
+        Subcommands::Import {
+            ref globpath,
+            ref git_url,
+            ref remote,
+            ref opts,
+        } => match (git_url, remote, globpath) {
+            (Some(url), _, _) => opt.git_import(url, globpath, opts),
+            (None, Some(url), _) => opt.remote_import(url, opts),
+            (None, None, Some(globpath)) => opt.import(globpath, opts),
+            (None, None, None) if !opt.config.paths.is_empty() => opt
+                .config
+                .paths
+                .iter()
+                .try_for_each(|path| opt.import(path, opts)),
+            (None, None, None) => Err(eyre!(
+                "Give a glob of files to import, a --git-url, a --remote or `paths` in the config \
+                 file"
+            )),
+        },
+        Subcommands::Watch {
+            ref globpath,
+            ref opts,
+        } => match globpath {
+            Some(globpath) => opt.watch(&[globpath.to_owned()], opts),
+            None if !opt.config.paths.is_empty() => opt.watch(&opt.config.paths, opts),
+            None => Err(eyre!(
+                "Give a glob of files to watch or `paths` in the config file"
+            )),
+        },
+        Subcommands::ImportLegacyMd {
+            ref globpath,
+            ref opts,
+        } => opt.legacy_import(globpath, opts),
+        Subcommands::Sync { all, ref opts } => {
+            let opts = opts.clone();
+            opt.sync(all, &opts)
+        }
+        Subcommands::Query {
+            ref query,
+            lazy,
+            ref picker,
+            contents,
+        } => opt.interactive_query(query, lazy, picker, contents),
+        Subcommands::Dump { ref path } => opt.dump(path),
+        Subcommands::Export {
+            ref path,
+            ref format,
             ref filter,
-        } => opt.static_query(query, filter),
-        Subcommands::New {} => unimplemented!("not yet"),
+        } => opt.export(path, format, filter),
+        Subcommands::StaticQuery {
+            ref query,
+            ref filter,
+            ref sort,
+            ref locales,
+        } => opt.static_query(query, filter, sort, locales),
+        Subcommands::Get { ref id, format } => opt.get(id, format),
+        Subcommands::History { ref id } => opt.history(id),
+        Subcommands::Publish { ref id } => opt.publish(id),
+        Subcommands::Remind {
+            ref id,
+            ref after,
+            ref at,
+            review,
+            clear,
+        } => opt.remind(id, after, at, review, clear),
+        Subcommands::Due { ref within } => opt.due(within),
+        Subcommands::PublishKey {
+            ref filter,
+            ref public_host,
+            ref expires,
+        } => opt.publish_key(filter, public_host, expires),
+        Subcommands::Search {
+            ref query,
+            ref filter,
+            limit,
+            ref sort,
+            format,
+        } => opt.search(query, filter, limit, sort, format),
+        Subcommands::Share {
+            ref id,
+            depth,
+            ref format,
+            ref output,
+        } => opt.share(id, depth, format, output),
+        Subcommands::Url { ref id, reverse } => opt.permalink(id, reverse),
+        Subcommands::HandleUrl {
+            ref url,
+            print,
+            register,
+        } => opt.handle_url(url, print, register),
+        Subcommands::VerifyRoundtrip { ref globpath } => opt.verify_roundtrip(globpath),
+        Subcommands::Lint { ref globpath } => opt.lint(globpath),
+        Subcommands::Normalize {
+            diff,
+            dry_run,
+            target: NormalizeTarget::Authors,
+        } => opt.normalize_authors(diff, dry_run),
+        Subcommands::Migrate { list, dry_run } => opt.migrate(list, dry_run),
+        Subcommands::Reindex {
+            ref settings,
+            ref backup,
+            ref restore,
+        } => opt.reindex(settings, backup, restore),
+        Subcommands::Replicate {
+            ref to,
+            ref to_api_key,
+            ref to_index,
+        } => opt.replicate(to, to_api_key, to_index),
+        Subcommands::Index { ref action } => opt.index(action),
+        Subcommands::Settings { ref action } => opt.settings(action),
+        Subcommands::Delete {
+            ref ids,
+            ref filter,
+            dry_run,
+        } => opt.delete(ids, filter, dry_run),
+        Subcommands::Task { ref action } => opt.task(action),
+        Subcommands::Sequence { ref action } => opt.sequence(action),
+        Subcommands::Inbox { triage } => opt.inbox(triage),
+        Subcommands::Report { ref kind } => opt.report(kind),
+        Subcommands::Config { .. } => unreachable!("config init runs before the config loads"),
+        Subcommands::Selftest => opt.selftest(),
+        Subcommands::Meta => opt.meta(),
+        Subcommands::Plugins => {
+            for name in plugin::list() {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        Subcommands::Plugin(ref args) => opt.plugin(args),
+        Subcommands::Undo { id, list } => opt.undo(id, list),
+        Subcommands::Log { ref action } => opt.log(action),
+        Subcommands::New {
+            ref title,
+            ref tags,
+            wait,
+        } => opt.new_note(title, tags, wait),
         Subcommands::Add {} => unimplemented!("not yet"),
     }
 }
@@ -0,0 +1,146 @@
+use crate::date::Date;
+use crate::permalink::slugify;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// Index holding the metadata document of each index, under the index's uid. Kept apart so
+/// the metadata never turns up among the notes.
+pub const METADATA_INDEX: &str = "meilizet-metadata";
+
+/// Documents fetched and updated per request while migrating
+pub const BATCH: usize = 1000;
+
+/// Document in `METADATA_INDEX` recording what was done to an index
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Metadata {
+    /// uid of the index described
+    pub id: String,
+    /// Versions of the migrations applied to the index
+    #[serde(default)]
+    pub migrations: Vec<u32>,
+}
+
+/// A versioned transformation of the documents in an index, bringing ones stored by older
+/// versions up to the current schema. Only documents still in the old shape are changed, so
+/// applying a migration to an index that never needed it changes nothing.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Change the document in place, returning whether anything changed
+    pub apply: fn(&mut Map<String, Value>) -> bool,
+}
+
+/// Every migration, oldest first. Versions are never reused or reordered once released.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "authors-list",
+        description: "Rename `author` to `authors` and make it a list",
+        apply: authors_list,
+    },
+    Migration {
+        version: 2,
+        name: "tags-list",
+        description: "Rename `tag` to `tags` and split tags stored as one string into a list",
+        apply: tags_list,
+    },
+    Migration {
+        version: 3,
+        name: "epoch-dates",
+        description: "Store dates written as text as epoch seconds, so they can be filtered and \
+                      sorted on",
+        apply: epoch_dates,
+    },
+    Migration {
+        version: 4,
+        name: "slugs",
+        description: "Fill in missing slugs from the titles",
+        apply: slugs,
+    },
+];
+
+/// Migrations not among the `applied` versions, oldest first
+pub fn pending(applied: &[u32]) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect()
+}
+
+/// Turn the string or list under `key` into a list of strings, merging in `alias`
+fn list_field(doc: &mut Map<String, Value>, key: &str, alias: &str, split: bool) -> bool {
+    let mut changed = false;
+    let mut values = match doc.get(key) {
+        Some(Value::Array(values)) => values.to_owned(),
+        Some(Value::String(s)) => {
+            changed = true;
+            split_list(s, split)
+        }
+        _ => Vec::new(),
+    };
+    match doc.remove(alias) {
+        Some(Value::Array(more)) => values.extend(more),
+        Some(Value::String(s)) => values.extend(split_list(&s, split)),
+        Some(_) => {}
+        None if !changed => return false,
+        None => {}
+    }
+    doc.insert(key.to_owned(), Value::Array(values));
+    true
+}
+
+/// Values of a list written as one string, separated by commas or else whitespace when `split`
+fn split_list(s: &str, split: bool) -> Vec<Value> {
+    let parts: Vec<&str> = if !split {
+        vec![s]
+    } else if s.contains(',') {
+        s.split(',').collect()
+    } else {
+        s.split_whitespace().collect()
+    };
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| Value::String(p.to_owned()))
+        .collect()
+}
+
+fn authors_list(doc: &mut Map<String, Value>) -> bool {
+    // Names contain spaces, so a single string is a single author
+    list_field(doc, "authors", "author", false)
+}
+
+fn tags_list(doc: &mut Map<String, Value>) -> bool {
+    list_field(doc, "tags", "tag", true)
+}
+
+fn epoch_dates(doc: &mut Map<String, Value>) -> bool {
+    let mut changed = false;
+    for field in &["date", "created", "modified", "imported_at"] {
+        let date = match doc.get(*field) {
+            Some(Value::String(s)) => Date::from_str(s).ok(),
+            _ => None,
+        };
+        if let Some(date) = date {
+            doc.insert(field.to_string(), serde_json::to_value(date).unwrap());
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn slugs(doc: &mut Map<String, Value>) -> bool {
+    let has_slug = matches!(doc.get("slug"), Some(Value::String(s)) if !s.is_empty());
+    let slug = match doc.get("title") {
+        Some(Value::String(title)) if !has_slug => slugify(title),
+        _ => return false,
+    };
+    if slug.is_empty() {
+        return false;
+    }
+    doc.insert("slug".to_owned(), Value::String(slug));
+    true
+}
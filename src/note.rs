@@ -0,0 +1,59 @@
+use crate::date::Date;
+use crate::document::{Document, SerializationType};
+use crate::permalink;
+use crate::zettel;
+
+/// A fresh note titled `title`, dated now, with an ID in the configured scheme
+pub fn create(title: &str, tags: &[String]) -> Document {
+    let id = zettel::new_id();
+    Document {
+        id: id.to_owned(),
+        parentid: id,
+        date: Date::now(),
+        created: Date::now(),
+        title: title.to_owned(),
+        tags: tags.to_vec(),
+        writes: 1,
+        serialization_type: SerializationType::Disk,
+        ..Default::default()
+    }
+}
+
+/// Name of the file `doc` is written to: its slug, after its ID when that's a zettel ID so the
+/// ID is picked up again on import, or just its ID when the title has nothing to slugify
+pub fn filename(doc: &Document) -> String {
+    let slug = permalink::slugify(&doc.title);
+    if slug.is_empty() {
+        format!("{}.md", doc.id)
+    } else if zettel::is_id(&doc.id) {
+        format!("{}-{}.md", doc.id, slug)
+    } else {
+        format!("{}.md", slug)
+    }
+}
+
+/// Contents of the file of a new note: the frontmatter fields laid out like the other notes
+/// when there's no `template`, or else the template with the placeholders of
+/// `permalink::render` filled in along with `{title}`, `{tags}` and `{datetime}`. Title and
+/// tags are quoted so any title makes valid YAML, e.g.
+///
+/// ```text
+/// ---
+/// id: {id}
+/// title: {title}
+/// date: {datetime}
+/// tags: {tags}
+/// ---
+/// ```
+pub fn render(template: Option<&str>, doc: &Document) -> String {
+    let template = match template {
+        Some(template) => template,
+        None => return doc.to_string(),
+    };
+    let title = serde_json::to_string(&doc.title).unwrap_or_default();
+    let tags = serde_json::to_string(&doc.tags).unwrap_or_default();
+    permalink::render(template, doc)
+        .replace("{title}", &title)
+        .replace("{tags}", &tags)
+        .replace("{datetime}", &doc.date.format("%+"))
+}
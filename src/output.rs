@@ -0,0 +1,118 @@
+use once_cell::sync::OnceCell;
+use std::{env, fmt, io};
+use termion::{color, style};
+use unicode_width::UnicodeWidthStr;
+
+/// Whether color was turned off with `--no-color` or `NO_COLOR`
+static DISABLED: OnceCell<bool> = OnceCell::new();
+
+/// Turn color off for the rest of the run when `no_color` is set or `NO_COLOR` is in the
+/// environment. Only the first call has any effect.
+pub fn init(no_color: bool) {
+    let _ = DISABLED.set(no_color || env::var_os("NO_COLOR").is_some());
+}
+
+fn enabled() -> bool {
+    !*DISABLED.get_or_init(|| env::var_os("NO_COLOR").is_some())
+}
+
+/// Whether what's printed to stdout should be colored, which it isn't when piped
+pub fn stdout_colored() -> bool {
+    enabled() && termion::is_tty(&io::stdout())
+}
+
+fn stderr_colored() -> bool {
+    enabled() && termion::is_tty(&io::stderr())
+}
+
+fn paint<C: color::Color>(c: C, s: &str, colored: bool) -> String {
+    if colored {
+        format!("{}{}{}", color::Fg(c), s, color::Fg(color::Reset))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Something worked
+pub fn ok(msg: impl fmt::Display) {
+    println!("{} {}", paint(color::Green, "✓", stdout_colored()), msg);
+}
+
+/// An item that failed a check, as part of a report on stdout
+pub fn bad(msg: impl fmt::Display) {
+    println!("{} {}", paint(color::Red, "✗", stdout_colored()), msg);
+}
+
+/// Further lines about the item printed last
+pub fn detail(msg: impl fmt::Display) {
+    println!("    {}", msg);
+}
+
+/// Something went wrong
+pub fn error(msg: impl fmt::Display) {
+    eprintln!("{} {}", paint(color::Red, "✗", stderr_colored()), msg);
+}
+
+/// Something looks off but isn't fatal
+pub fn warn(msg: impl fmt::Display) {
+    eprintln!("{} {}", paint(color::Yellow, "!", stderr_colored()), msg);
+}
+
+/// Rows of text printed in aligned columns under a header
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: &[&str]) -> Self {
+        Table {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    fn write_row(f: &mut fmt::Formatter<'_>, cells: &[String], widths: &[usize]) -> fmt::Result {
+        let mut line = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            line.push_str(cell);
+            // No trailing whitespace after the last column
+            if i + 1 < cells.len() {
+                let pad = widths[i].saturating_sub(cell.width()) + 2;
+                line.push_str(&" ".repeat(pad));
+            }
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths: Vec<usize> = self.header.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                if i < widths.len() {
+                    widths[i] = widths[i].max(cell.width());
+                } else {
+                    widths.push(cell.width());
+                }
+            }
+        }
+
+        if stdout_colored() {
+            write!(f, "{}", style::Bold)?;
+            Table::write_row(f, &self.header, &widths)?;
+            write!(f, "{}", style::Reset)?;
+        } else {
+            Table::write_row(f, &self.header, &widths)?;
+        }
+        for row in &self.rows {
+            Table::write_row(f, row, &widths)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use crate::document::Document;
+
+/// Permalink template unless the `permalink_template` config setting says otherwise
+pub const DEFAULT_TEMPLATE: &str = "meilizet://note/{id}";
+
+/// Lowercase alphanumeric words joined by dashes
+pub fn slugify(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Fill in the template's `{id}`, `{slug}`, `{date}`, `{year}`, `{month}` and `{day}`
+/// placeholders for the document. The slug falls back to one made from the title, dates are
+/// the document's `date` in local time.
+pub fn render(template: &str, doc: &Document) -> String {
+    let slug = if doc.slug.is_empty() {
+        slugify(&doc.title)
+    } else {
+        doc.slug.to_owned()
+    };
+    template
+        .replace("{id}", &doc.id)
+        .replace("{slug}", &slug)
+        .replace("{date}", &doc.date.format("%Y-%m-%d"))
+        .replace("{year}", &doc.date.format("%Y"))
+        .replace("{month}", &doc.date.format("%m"))
+        .replace("{day}", &doc.date.format("%d"))
+}
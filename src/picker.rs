@@ -0,0 +1,60 @@
+use crate::document::Document;
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// One candidate line per document: ID, title and tags, tab separated
+fn candidate(doc: &Document) -> String {
+    let clean = |s: &str| s.replace(&['\t', '\n'][..], " ");
+    format!(
+        "{}\t{}\t{}",
+        doc.id,
+        clean(&doc.title),
+        clean(&doc.tags.join(" "))
+    )
+}
+
+/// Let the user choose from `docs` with an external fuzzy finder such as fzf or skim, given as
+/// a command line like `fzf --multi`. Returns the IDs of the chosen documents, none when the
+/// finder was cancelled.
+pub fn pick(command: &str, docs: &[Document]) -> Result<Vec<String>, Report> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| eyre!("Empty picker command"))?;
+    let mut cmd = Command::new(program);
+    cmd.args(words);
+    // Both understand these: search and show titles and tags, but not IDs
+    if let Some("fzf") | Some("sk") = Path::new(program).file_name().and_then(|n| n.to_str()) {
+        cmd.args(["--delimiter", "\t", "--with-nth", "2.."]);
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("Failed to run picker {}: {}", program, e))?;
+    {
+        let mut stdin = child.stdin.take().unwrap();
+        for doc in docs {
+            // The picker may quit before reading everything
+            if writeln!(stdin, "{}", candidate(doc)).is_err() {
+                break;
+            }
+        }
+    }
+    let mut chosen = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut chosen)?;
+    let status = child.wait()?;
+    // fzf and skim exit with 1 for no match and 130 when cancelled
+    if !status.success() && !matches!(status.code(), Some(1) | Some(130)) {
+        bail!("Picker {} failed: {}", program, status);
+    }
+
+    Ok(chosen
+        .lines()
+        .filter_map(|l| l.split('\t').next())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_owned())
+        .collect())
+}
@@ -0,0 +1,73 @@
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Executables named with this prefix on the PATH run as subcommands, `meilizet-foo` as
+/// `mz foo`
+pub const PREFIX: &str = "meilizet-";
+
+/// The executable of the plugin `name`, the first one found on the PATH
+pub fn find(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}", PREFIX, name);
+    path_dirs()
+        .map(|dir| dir.join(&file))
+        .find(|path| is_executable(path))
+}
+
+/// Names of the plugins on the PATH, without the prefix
+pub fn list() -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for dir in path_dirs() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file = entry.file_name().to_string_lossy().into_owned();
+            if let Some(name) = file.strip_prefix(PREFIX) {
+                if !name.is_empty() && is_executable(&entry.path()) {
+                    names.insert(name.to_owned());
+                }
+            }
+        }
+    }
+    names.into_iter().collect()
+}
+
+/// Run the plugin `name` with `args` and the environment variables `env`, which tell it the
+/// server and index to use, with the terminal handed over to it. Returns its exit status.
+pub fn run(name: &str, args: &[String], env: &[(&str, String)]) -> Result<i32, Report> {
+    let path = find(name).ok_or_else(|| {
+        eyre!(
+            "No subcommand {} and no {}{} on the PATH",
+            name,
+            PREFIX,
+            name
+        )
+    })?;
+    let status = Command::new(&path)
+        .args(args)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .status()
+        .map_err(|e| eyre!("Failed to run {}: {}", path.display(), e))?;
+    // Killed by a signal, like a shell reports it
+    Ok(status.code().unwrap_or(128))
+}
+
+fn path_dirs() -> impl Iterator<Item = PathBuf> {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,85 @@
+use crate::api::Transport;
+use crate::document::Document;
+use crate::query;
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use url::Url;
+
+/// Fetches documents by ID on a background thread, so they're already at hand by the time
+/// they're navigated to
+pub struct Prefetcher {
+    client: Arc<dyn Transport>,
+    documents_uri: Url,
+    tx: mpsc::Sender<String>,
+    docs: Arc<Mutex<HashMap<String, Document>>>,
+}
+
+impl Prefetcher {
+    /// `documents_uri` is the index's documents endpoint, ending in a slash
+    pub fn new(client: Arc<dyn Transport>, documents_uri: Url) -> Prefetcher {
+        let (tx, rx) = mpsc::channel::<String>();
+        let docs = Arc::new(Mutex::new(HashMap::new()));
+
+        let fetched = Arc::clone(&docs);
+        let (thread_client, thread_uri) = (Arc::clone(&client), documents_uri.clone());
+        thread::spawn(move || {
+            let (client, documents_uri) = (thread_client, thread_uri);
+            let mut attempted = HashSet::new();
+            // Runs until the Prefetcher, and with it the sender, is dropped
+            for id in rx {
+                if !attempted.insert(id.to_owned()) {
+                    continue;
+                }
+                let uri = match documents_uri.join(&id) {
+                    Ok(uri) => uri,
+                    Err(_) => continue,
+                };
+                // Failures are fine, the document is simply fetched again when it's opened
+                if let Ok(doc) = query::get_document(client.as_ref(), &uri) {
+                    fetched.lock().unwrap().insert(id, doc);
+                }
+            }
+        });
+
+        Prefetcher {
+            client,
+            documents_uri,
+            tx,
+            docs,
+        }
+    }
+
+    /// Queue documents to be fetched in the background
+    pub fn request<I: IntoIterator<Item = String>>(&self, ids: I) {
+        for id in ids {
+            if self.tx.send(id).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// The prefetched document, if it has arrived
+    pub fn get(&self, id: &str) -> Option<Document> {
+        self.docs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Replace the copy of a document that was changed
+    pub fn insert(&self, doc: Document) {
+        self.docs.lock().unwrap().insert(doc.id.to_owned(), doc);
+    }
+
+    /// The document, fetching it right away if it hasn't been prefetched
+    pub fn fetch(&self, id: &str) -> Option<Document> {
+        if let Some(doc) = self.get(id) {
+            return Some(doc);
+        }
+        let uri = self.documents_uri.join(id).ok()?;
+        let doc = query::get_document(self.client.as_ref(), &uri).ok()?;
+        self.docs
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), doc.clone());
+        Some(doc)
+    }
+}
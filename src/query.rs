@@ -1,58 +1,177 @@
-use crate::{api, document};
+use crate::api::Transport;
+use crate::cache::{self, SearchCache};
+use crate::{api, document, revision};
 use color_eyre::Report;
 use eyre::bail;
-use reqwest::header::CONTENT_TYPE;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Mutex;
 use url::Url;
 
-pub fn query(
-    client: reqwest::blocking::Client,
-    uri: Url,
-    query_input: String,
-    filter_input: String,
-) -> Result<(), Report> {
-    let mut q = api::ApiQuery::new();
-    q.query = Some(query_input);
-
-    q.process_filter(filter_input);
-
-    // Split up the JSON decoding into two steps.
-    // 1.) Get the text of the body.
-    let response_body = match client
-        .post(uri.as_ref())
-        .body::<String>(serde_json::to_string(&q).unwrap())
-        .header(CONTENT_TYPE, "application/json")
-        .send()
-    {
-        Ok(resp) => {
-            if !resp.status().is_success() {
-                bail!("Request failed: {:?}", resp);
-            }
-            match resp.text() {
-                Ok(text) => text,
-                Err(e) => {
-                    bail!("resp.text() failed: {:?}", e);
-                }
-            }
+/// Hits fetched per request when paging through all of them
+pub const PAGE_SIZE: u32 = 1000;
+
+/// Send a single search request and parse the response
+pub fn search(
+    client: &dyn Transport,
+    uri: &Url,
+    q: &api::SearchRequest,
+) -> Result<api::ApiResponse, Report> {
+    parse_response(&send_search(client, uri, q)?)
+}
+
+/// Every hit of the query rather than the first `q.limit`, fetched a page at a time. Fails
+/// rather than coming back short when the hits reach the index's `pagination.maxTotalHits`
+/// setting, the most a search goes through, which Meilisearch caps the hits at without saying.
+pub fn search_all(
+    client: &dyn Transport,
+    uri: &Url,
+    q: &api::SearchRequest,
+) -> Result<Vec<document::Document>, Report> {
+    let mut q = q.clone().page(0, PAGE_SIZE);
+    let mut hits = Vec::new();
+    loop {
+        let page = search(client, uri, &q)?.hits;
+        let last = page.len() < PAGE_SIZE as usize;
+        hits.extend(page);
+        if last {
+            break;
         }
-        Err(e) => {
-            bail!("Send failed: {:?}", e);
+        q.offset += PAGE_SIZE;
+    }
+    if let Some(max) = max_total_hits(client, uri) {
+        if hits.len() as u64 >= max {
+            bail!(
+                "{} or more notes match, as many as a search of the index goes through. Raise \
+                 its pagination.maxTotalHits setting, e.g. with `settings init`.",
+                max
+            );
+        }
+    }
+    Ok(hits)
+}
+
+/// The index's `pagination.maxTotalHits` setting, given its search URL. Servers before 0.30
+/// don't have it.
+fn max_total_hits(client: &dyn Transport, uri: &Url) -> Option<u64> {
+    let pagination: serde_json::Value =
+        get_json(client, &uri.join("settings/pagination").ok()?).ok()?;
+    pagination["maxTotalHits"].as_u64()
+}
+
+/// Like `search`, answering from `cache` when the same request was made recently
+pub fn cached_search(
+    cache: &mut SearchCache,
+    client: &dyn Transport,
+    uri: &Url,
+    q: &api::SearchRequest,
+) -> Result<api::ApiResponse, Report> {
+    let key = cache::key(uri, q);
+    let response_body = match cache.get(&key) {
+        Some(body) => body,
+        None => {
+            let body = send_search(client, uri, q)?;
+            cache.insert(key, body.to_owned());
+            body
         }
     };
+    parse_response(&response_body)
+}
 
-    // 2.) Parse the results as JSON.
-    match serde_json::from_str::<api::ApiResponse>(&response_body) {
-        Ok(mut resp) => {
-            println!(
-                "Matches: {:?}",
-                resp.hits
-                    .iter_mut()
-                    .map(|mut m| {
-                        m.serialization_type = document::SerializationType::Human;
-                        m.to_owned()
-                    })
-                    .collect::<Vec<_>>()
-            );
+// Split up the JSON decoding into two steps.
+// 1.) Get the text of the body. Every search is sent from here, so this is where the
+// revisions replaced by newer ones are left out.
+pub fn send_search(
+    client: &dyn Transport,
+    uri: &Url,
+    q: &api::SearchRequest,
+) -> Result<String, Report> {
+    let q = latest_only(client, uri, q);
+    let mut body = serde_json::to_value(&q).unwrap();
+    if q.facets.is_some() && facets_distribution(client, uri) {
+        let body = body.as_object_mut().unwrap();
+        let facets = body.remove("facets").unwrap();
+        body.insert(String::from("facetsDistribution"), facets);
+    }
+    client.post(uri, body.to_string())
+}
+
+/// Whether the server behind each search URL is older than 0.28, looked up once
+static BEFORE_0_28: Lazy<Mutex<HashMap<Url, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether the server asks for facets with `facetsDistribution`, as it did before 0.28. Search
+/// keys may not read the version, those servers are taken to be recent.
+fn facets_distribution(client: &dyn Transport, uri: &Url) -> bool {
+    if let Some(&before) = BEFORE_0_28.lock().unwrap().get(uri) {
+        return before;
+    }
+    let before = uri
+        .join("/version")
+        .ok()
+        .and_then(|version| get_json::<serde_json::Value>(client, &version).ok())
+        .and_then(|version| {
+            let version = version["pkgVersion"].as_str()?.to_owned();
+            let mut parts = version.split('.').map(|n| n.parse::<u64>().ok());
+            Some((parts.next()??, parts.next()??) < (0, 28))
+        })
+        .unwrap_or(false);
+    BEFORE_0_28.lock().unwrap().insert(uri.clone(), before);
+    before
+}
+
+/// Whether the index at each search URL can filter on `latest`, looked up once
+static FILTERS_LATEST: Lazy<Mutex<HashMap<Url, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// `q` narrowed down to the latest revision of every note, unless it asks for all of them.
+/// Indexes set up before there were revisions can't filter on `latest` until `settings init`,
+/// they're searched as they are.
+fn latest_only(client: &dyn Transport, uri: &Url, q: &api::SearchRequest) -> api::SearchRequest {
+    if q.all_revisions {
+        return q.clone();
+    }
+    let known = FILTERS_LATEST.lock().unwrap().get(uri).cloned();
+    let filters_latest = match known {
+        Some(filters_latest) => filters_latest,
+        None => match filterable_attributes(client, uri) {
+            Ok(fields) => {
+                let filters_latest = fields.iter().any(|f| f == "latest");
+                FILTERS_LATEST
+                    .lock()
+                    .unwrap()
+                    .insert(uri.clone(), filters_latest);
+                filters_latest
+            }
+            Err(_) => false,
+        },
+    };
+    if filters_latest {
+        q.clone().and_raw_filter(revision::LATEST)
+    } else {
+        q.clone()
+    }
+}
+
+// 2.) Parse the results as JSON.
+pub fn parse_response(response_body: &str) -> Result<api::ApiResponse, Report> {
+    match serde_json::from_str::<api::ApiResponse>(response_body) {
+        Ok(resp) => Ok(resp),
+        Err(e) => {
+            bail!(
+                "Could not deserialize body from: {}; error: {:?}",
+                response_body,
+                e
+            )
         }
+    }
+}
+
+/// GET `uri` and parse the JSON response
+pub fn get_json<T: DeserializeOwned>(client: &dyn Transport, uri: &Url) -> Result<T, Report> {
+    let response_body = client.get(uri)?;
+
+    match serde_json::from_str::<T>(&response_body) {
+        Ok(v) => Ok(v),
         Err(e) => {
             bail!(
                 "Could not deserialize body from: {}; error: {:?}",
@@ -60,6 +179,60 @@ pub fn query(
                 e
             )
         }
-    };
+    }
+}
+
+/// Fetch a single document from its documents endpoint URL
+pub fn get_document(client: &dyn Transport, uri: &Url) -> Result<document::Document, Report> {
+    get_json(client, uri)
+}
+
+/// Fetch the attributes that can be used in filters, given the index's search URL
+pub fn filterable_attributes(client: &dyn Transport, uri: &Url) -> Result<Vec<String>, Report> {
+    get_json(client, &uri.join("settings/filterable-attributes")?)
+}
+
+/// Fetch every tag in the index, most used first
+pub fn tag_facets(client: &dyn Transport, uri: &Url) -> Result<Vec<String>, Report> {
+    let q = api::SearchRequest::new().facets(&["tags"]).page(0, 0);
+
+    let mut tags: Vec<(String, u32)> = search(client, uri, &q)?
+        .facets_distribution
+        .and_then(|mut f| f.remove("tags"))
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(tags.into_iter().map(|(tag, _)| tag).collect())
+}
+
+pub fn query(
+    cache: &mut SearchCache,
+    client: &dyn Transport,
+    uri: Url,
+    query_input: String,
+    filter_input: String,
+    sort: Option<String>,
+    locales: &[String],
+) -> Result<(), Report> {
+    let mut q = api::SearchRequest::new()
+        .query(&query_input)
+        .filter(&filter_input)
+        .locales(locales);
+    if let Some(sort) = sort {
+        q = q.sort(&sort);
+    }
+
+    let mut resp = cached_search(cache, client, &uri, &q)?;
+    println!(
+        "Matches: {:?}",
+        resp.hits
+            .iter_mut()
+            .map(|mut m| {
+                m.serialization_type = document::SerializationType::Human;
+                m.to_owned()
+            })
+            .collect::<Vec<_>>()
+    );
     Ok(())
 }
@@ -0,0 +1,118 @@
+use crate::config::Config;
+use color_eyre::Report;
+use eyre::Result;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Settings the interactive interface applies as soon as the config file changes, the others
+/// only take effect on the next start
+pub const LIVE: [&str; 3] = ["keys", "locales", "preview_theme"];
+
+/// How long the config file has to be left alone before it's read again
+const DELAY: Duration = Duration::from_millis(200);
+
+/// The config file as read again after it changed
+pub struct Reload {
+    pub config: Config,
+    /// Top-level settings whose values changed, e.g. `keys` or `host`
+    pub changed: Vec<String>,
+}
+
+impl Reload {
+    /// Changed settings that can't be applied without restarting
+    pub fn restart_needed(&self) -> Vec<&str> {
+        self.changed
+            .iter()
+            .map(String::as_str)
+            .filter(|setting| !LIVE.contains(setting))
+            .collect()
+    }
+}
+
+/// Notices changes to the config file while a long-running command is going
+pub struct ConfigWatcher {
+    path: PathBuf,
+    /// Top-level settings of the file as last read, to tell which ones a change touched
+    settings: toml::value::Table,
+    rx: mpsc::Receiver<DebouncedEvent>,
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// Watch the config file at `path`, which may not exist yet
+    pub fn new(path: &str) -> Result<ConfigWatcher, Report> {
+        let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::watcher(tx, DELAY)?;
+        // The directory rather than the file, as editors often save by replacing the file
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(ConfigWatcher {
+            settings: settings(&path),
+            path,
+            rx,
+            _watcher: watcher,
+        })
+    }
+
+    /// The config as it is now, when the file changed since the last call. A file that no
+    /// longer parses is reported each time it's saved, until it's fixed.
+    pub fn poll(&mut self) -> Option<Result<Reload, Report>> {
+        let mut touched = false;
+        while let Ok(event) = self.rx.try_recv() {
+            touched |= self.concerns(&event);
+        }
+        if !touched {
+            return None;
+        }
+        let settings = settings(&self.path);
+        let mut changed: Vec<String> = self
+            .settings
+            .keys()
+            .chain(settings.keys())
+            .filter(|key| self.settings.get(*key) != settings.get(*key))
+            .cloned()
+            .collect();
+        changed.sort();
+        changed.dedup();
+        if changed.is_empty() {
+            return None;
+        }
+        match Config::load(&self.path.to_string_lossy()) {
+            Ok(config) => {
+                self.settings = settings;
+                Some(Ok(Reload { config, changed }))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Whether `event` is about the config file rather than something else in its directory
+    fn concerns(&self, event: &DebouncedEvent) -> bool {
+        let is_config = |path: &Path| path.file_name() == self.path.file_name();
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => is_config(path),
+            DebouncedEvent::Rename(from, to) => is_config(from) || is_config(to),
+            DebouncedEvent::Rescan => true,
+            _ => false,
+        }
+    }
+}
+
+/// Top-level settings of the config file, none when it's missing or doesn't parse. Secrets are
+/// left unresolved, this is only for comparing.
+fn settings(path: &Path) -> toml::value::Table {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,39 @@
+use crate::date::Date;
+use crate::document::Document;
+
+/// A note whose reminder or review date has come
+pub struct Due {
+    pub at: Date,
+    /// `reminder` or `review`
+    pub what: &'static str,
+    pub doc: Document,
+}
+
+/// The notes among `docs` with a reminder or review date up to `until`, longest overdue first.
+/// A note due for both shows up for the earlier one.
+pub fn due(docs: Vec<Document>, until: &Date) -> Vec<Due> {
+    let mut due: Vec<Due> = docs
+        .into_iter()
+        .filter_map(|doc| {
+            let reminder = doc.remind_at.clone().map(|at| (at, "reminder"));
+            let review = doc.review_after.clone().map(|at| (at, "review"));
+            let (at, what) = match (reminder, review) {
+                (Some(reminder), Some(review)) => reminder.min(review),
+                (reminder, review) => reminder.or(review)?,
+            };
+            if at <= *until {
+                Some(Due { at, what, doc })
+            } else {
+                None
+            }
+        })
+        .collect();
+    due.sort_by(|a, b| a.at.cmp(&b.at).then_with(|| a.doc.id.cmp(&b.doc.id)));
+    due
+}
+
+/// Expression in the syntax of the filter input matching the notes due by the end of `day`,
+/// e.g. `2024-05-14`
+pub fn filter(day: &str) -> String {
+    format!("(remind_at <= {} | review_after <= {})", day, day)
+}
@@ -0,0 +1,343 @@
+use crate::date::Date;
+use chrono::{DateTime, Utc};
+use color_eyre::Report;
+use eyre::{bail, eyre, Result};
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::str::FromStr;
+use url::Url;
+
+/// Characters left alone when encoding S3 canonical query strings
+const S3_QUERY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Characters left alone when encoding S3 object keys
+const S3_PATH: &AsciiSet = &S3_QUERY.remove(b'/');
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:getetag/><d:getlastmodified/><d:resourcetype/></d:prop>
+</d:propfind>"#;
+
+/// A file stored remotely
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Object key in the bucket, or path on the WebDAV server
+    pub key: String,
+    /// Changes whenever the file's content does
+    pub etag: String,
+    pub modified: Date,
+}
+
+/// Where notes can be imported from besides the local filesystem
+pub enum Remote {
+    S3(S3),
+    WebDav(WebDav),
+}
+
+impl Remote {
+    /// `s3://bucket/prefix`, or the `http(s)://` URL of a WebDAV directory. S3 credentials come
+    /// from the usual `AWS_*` environment variables, WebDAV ones from the URL.
+    pub fn parse(s: &str) -> Result<Remote, Report> {
+        let url = Url::parse(s)?;
+        match url.scheme() {
+            "s3" => Ok(Remote::S3(S3::from_env(&url)?)),
+            "http" | "https" => Ok(Remote::WebDav(WebDav::new(url))),
+            scheme => bail!(
+                "Unsupported remote {}, use s3:// or http(s):// for WebDAV",
+                scheme
+            ),
+        }
+    }
+
+    /// Every file under the remote location
+    pub fn list(&self, client: &Client) -> Result<Vec<Entry>, Report> {
+        match self {
+            Remote::S3(s3) => s3.list(client),
+            Remote::WebDav(dav) => dav.list(client),
+        }
+    }
+
+    /// Content of the file with `key` as listed
+    pub fn fetch(&self, client: &Client, key: &str) -> Result<String, Report> {
+        match self {
+            Remote::S3(s3) => s3.get(client, key, &[]),
+            Remote::WebDav(dav) => dav.fetch(client, key),
+        }
+    }
+}
+
+/// A bucket, or a prefix within one, on AWS or an S3-compatible store
+pub struct S3 {
+    /// Scheme and host, followed by the bucket for path-style endpoints
+    endpoint: Url,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3 {
+    fn from_env(url: &Url) -> Result<S3, Report> {
+        let bucket = url
+            .host_str()
+            .ok_or_else(|| eyre!("No bucket in {}", url))?;
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| String::from("us-east-1"));
+        let endpoint = match env::var("AWS_ENDPOINT_URL") {
+            // S3-compatible stores like MinIO address buckets by path
+            Ok(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+            Err(_) => format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+        };
+        let var = |name| env::var(name).map_err(|_| eyre!("Set {} to import from S3", name));
+        Ok(S3 {
+            endpoint: Url::parse(&endpoint)?,
+            prefix: url.path().trim_start_matches('/').to_owned(),
+            region,
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    fn list(&self, client: &Client) -> Result<Vec<Entry>, Report> {
+        let mut entries = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let xml = self.get(client, "", &query)?;
+            let doc = roxmltree::Document::parse(&xml)?;
+            for contents in doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "Contents")
+            {
+                entries.push(Entry {
+                    key: text(contents, "Key"),
+                    etag: text(contents, "ETag"),
+                    modified: Date::from_str(&text(contents, "LastModified")).unwrap_or_default(),
+                });
+            }
+            let root = doc.root_element();
+            token = match text(root, "IsTruncated").as_str() {
+                "true" => Some(text(root, "NextContinuationToken")),
+                _ => return Ok(entries),
+            };
+        }
+    }
+
+    /// GET the object `key`, or the bucket itself when empty, signed with AWS Signature
+    /// Version 4
+    fn get(&self, client: &Client, key: &str, query: &[(&str, &str)]) -> Result<String, Report> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let day = now.format("%Y%m%d").to_string();
+
+        let path = format!(
+            "{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            utf8_percent_encode(key, S3_PATH)
+        );
+        let mut params: Vec<String> = query
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(k, S3_QUERY),
+                    utf8_percent_encode(v, S3_QUERY)
+                )
+            })
+            .collect();
+        params.sort();
+        let query = params.join("&");
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), port),
+            None => self.endpoint.host_str().unwrap_or_default().to_owned(),
+        };
+
+        // Signed headers, sorted by name
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", String::from("UNSIGNED-PAYLOAD")),
+            ("x-amz-date", amz_date.to_owned()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.to_owned()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical = format!(
+            "GET\n{}\n{}\n{}\n{}\nUNSIGNED-PAYLOAD",
+            path,
+            query,
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers
+        );
+        let scope = format!("{}/{}/s3/aws4_request", day, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical.as_bytes()))
+        );
+        let mut signing_key = hmac(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            day.as_bytes(),
+        );
+        for part in &[self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query(Some(&query).filter(|q| !q.is_empty()).map(String::as_str));
+        let mut req = client.get(url.as_ref());
+        // reqwest sets the host header itself
+        for (name, value) in headers.iter().skip(1) {
+            req = req.header(*name, value);
+        }
+        let req = req.header(
+            AUTHORIZATION,
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ),
+        );
+        let resp = req.send()?;
+        if !resp.status().is_success() {
+            bail!("S3 request for {} failed: {}", url, resp.status());
+        }
+        Ok(resp.text()?)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A directory on a WebDAV server, such as a Nextcloud share
+pub struct WebDav {
+    url: Url,
+    user: String,
+    password: Option<String>,
+}
+
+impl WebDav {
+    fn new(mut url: Url) -> WebDav {
+        let user = decode(url.username());
+        let password = url.password().map(decode);
+        // Credentials go in a header rather than the URL
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        WebDav {
+            url,
+            user,
+            password,
+        }
+    }
+
+    fn request(&self, client: &Client, method: Method, path: &str) -> RequestBuilder {
+        let mut url = self.url.clone();
+        url.set_path(path);
+        let req = client.request(method, url.as_ref());
+        if self.user.is_empty() {
+            req
+        } else {
+            req.basic_auth(&self.user, self.password.as_ref())
+        }
+    }
+
+    /// Walk the share one directory at a time, servers like Nextcloud refuse `Depth: infinity`
+    fn list(&self, client: &Client) -> Result<Vec<Entry>, Report> {
+        let propfind = Method::from_bytes(b"PROPFIND")?;
+        let mut entries = Vec::new();
+        let mut dirs = vec![decode(self.url.path())];
+        while let Some(dir) = dirs.pop() {
+            let resp = self
+                .request(client, propfind.clone(), &dir)
+                .header("Depth", "1")
+                .header(CONTENT_TYPE, "application/xml")
+                .body(PROPFIND_BODY)
+                .send()?;
+            if !resp.status().is_success() {
+                bail!("Listing {} failed: {}", dir, resp.status());
+            }
+            let xml = resp.text()?;
+            let doc = roxmltree::Document::parse(&xml)?;
+            for response in doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "response")
+            {
+                // Servers send either absolute URLs or paths
+                let href = self.url.join(&text(response, "href"))?;
+                let path = decode(href.path());
+                // The directory itself is listed along with its contents
+                if path.trim_end_matches('/') == dir.trim_end_matches('/') {
+                    continue;
+                }
+                if response
+                    .descendants()
+                    .any(|n| n.tag_name().name() == "collection")
+                {
+                    dirs.push(path);
+                } else {
+                    entries.push(Entry {
+                        key: path,
+                        etag: text(response, "getetag"),
+                        modified: DateTime::parse_from_rfc2822(&text(response, "getlastmodified"))
+                            .map(|d| Date::new(d.timestamp()))
+                            .unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn fetch(&self, client: &Client, path: &str) -> Result<String, Report> {
+        let resp = self.request(client, Method::GET, path).send()?;
+        if !resp.status().is_success() {
+            bail!("Fetching {} failed: {}", path, resp.status());
+        }
+        Ok(resp.text()?)
+    }
+}
+
+/// Paths and credentials are kept decoded, `Url` encodes them again as needed
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+/// Text of the first element under `node` named `name`, ignoring namespaces
+fn text(node: roxmltree::Node, name: &str) -> String {
+    node.descendants()
+        .find(|n| n.tag_name().name() == name)
+        .and_then(|n| n.text())
+        .unwrap_or_default()
+        .to_owned()
+}
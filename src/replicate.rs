@@ -0,0 +1,156 @@
+use crate::api::{self, task::EnqueuedTask};
+use crate::query;
+use color_eyre::Report;
+use eyre::{bail, Result};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Documents sent per request
+const BATCH: usize = 1000;
+
+/// Documents compared between the source and a replica after copying
+pub const SPOT_CHECKS: usize = 20;
+
+/// An index on a server documents are copied to
+#[derive(Clone)]
+pub struct Replica {
+    pub client: Client,
+    pub host: Url,
+    pub index: String,
+}
+
+/// What was copied to a replica and how it checked out
+#[derive(Debug)]
+pub struct Summary {
+    pub documents: u64,
+    /// Documents compared by hash, all matching
+    pub checked: usize,
+}
+
+impl Replica {
+    fn url(&self, path: &str) -> Url {
+        let mut url = self.host.clone();
+        url.set_path(path);
+        url
+    }
+
+    fn index_url(&self, path: &str) -> Url {
+        self.url(&format!("indexes/{}/{}", self.index, path))
+    }
+
+    /// Send the request and wait for the task it enqueues, if it does: before Meilisearch
+    /// 0.28 some changes were made right away
+    fn apply(&self, req: RequestBuilder, what: &str) -> Result<(), Report> {
+        let res = req.send()?;
+        if !res.status().is_success() {
+            bail!("{} on {} failed: {}", what, self.host, res.text()?);
+        }
+        if let Ok(task) = res.json::<EnqueuedTask>() {
+            self.wait(task.uid)?;
+        }
+        Ok(())
+    }
+
+    fn wait(&self, uid: u64) -> Result<(), Report> {
+        let task = api::task::wait(&self.client, &self.url(""), uid, None)?;
+        match task.error_message() {
+            Some(message) => bail!("Task {} on {} failed: {}", uid, self.host, message),
+            None => Ok(()),
+        }
+    }
+
+    /// Make the replica hold `docs` with `settings` and nothing else, creating its index if
+    /// need be
+    pub fn replace(
+        &self,
+        primary_key: &str,
+        settings: &Value,
+        docs: &[Value],
+    ) -> Result<Summary, Report> {
+        let exists = self
+            .client
+            .get(self.url(&format!("indexes/{}", self.index)).as_ref())
+            .send()?
+            .status()
+            .is_success();
+        if exists {
+            self.apply(
+                self.client.delete(self.index_url("documents").as_ref()),
+                "Clearing the documents",
+            )?;
+        } else {
+            self.apply(
+                self.client
+                    .post(self.url("indexes").as_ref())
+                    .json(&serde_json::json!({ "uid": self.index, "primaryKey": primary_key })),
+                "Creating the index",
+            )?;
+        }
+        // Documents are indexed for the settings they arrive under, so those go first
+        self.apply(
+            self.client
+                .post(self.index_url("settings").as_ref())
+                .json(settings),
+            "Applying the settings",
+        )?;
+
+        // Enqueued all at once and then waited for, Meilisearch works through them in order
+        let mut tasks = Vec::new();
+        for chunk in docs.chunks(BATCH) {
+            let res = self
+                .client
+                .post(self.index_url("documents").as_ref())
+                .json(chunk)
+                .send()?;
+            if !res.status().is_success() {
+                bail!("Adding documents to {} failed: {}", self.host, res.text()?);
+            }
+            tasks.push(res.json::<EnqueuedTask>()?.uid);
+        }
+        for uid in tasks {
+            self.wait(uid)?;
+        }
+
+        let stats: api::IndexStats = query::get_json(&self.client, &self.index_url("stats"))?;
+        if stats.number_of_documents != docs.len() as u64 {
+            bail!(
+                "{} holds {} documents instead of {}",
+                self.host,
+                stats.number_of_documents,
+                docs.len()
+            );
+        }
+        let mut checked = 0;
+        for doc in spot_checks(docs) {
+            let id = match doc.get(primary_key) {
+                Some(Value::String(id)) => id.to_owned(),
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            let copy: Value =
+                query::get_json(&self.client, &self.index_url(&format!("documents/{}", id)))?;
+            if hash(&copy) != hash(doc) {
+                bail!("Document {} differs on {}", id, self.host);
+            }
+            checked += 1;
+        }
+        Ok(Summary {
+            documents: stats.number_of_documents,
+            checked,
+        })
+    }
+}
+
+/// Up to `SPOT_CHECKS` documents spread evenly over `docs`
+fn spot_checks(docs: &[Value]) -> impl Iterator<Item = &Value> {
+    let step = (docs.len() / SPOT_CHECKS).max(1);
+    docs.iter().step_by(step).take(SPOT_CHECKS)
+}
+
+/// SHA-256 of the document as JSON, hex encoded. Fields are always written in the same order,
+/// so equal documents hash the same whichever server they came from.
+pub fn hash(doc: &Value) -> String {
+    hex::encode(Sha256::digest(doc.to_string().as_bytes()))
+}
@@ -0,0 +1,50 @@
+use crate::document::Document;
+use crate::zettel;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// Notes without any tags
+pub fn untagged(docs: &[Document]) -> Vec<&Document> {
+    docs.iter().filter(|doc| doc.tags.is_empty()).collect()
+}
+
+/// Notes outside the link graph: linking to nothing, linked to by nothing, with neither a
+/// parent nor children
+pub fn unlinked(docs: &[Document]) -> Vec<&Document> {
+    // Everything some note points at, however it names its target
+    let mut targets: HashSet<&str> = HashSet::new();
+    for doc in docs {
+        targets.extend(doc.links.iter().map(|l| l.as_str()));
+        if !doc.parentid.is_empty() && doc.parentid != doc.id {
+            targets.insert(&doc.parentid);
+        }
+    }
+    let linked_to = |doc: &Document| {
+        let mut names = vec![doc.id.as_str(), doc.filename.as_str(), doc.slug.as_str()];
+        names.extend(zettel::from_filename(&doc.filename));
+        names
+            .into_iter()
+            .any(|name| !name.is_empty() && targets.contains(name))
+    };
+    docs.iter()
+        .filter(|doc| doc.links.is_empty())
+        .filter(|doc| doc.parentid.is_empty() || doc.parentid == doc.id)
+        .filter(|doc| !linked_to(doc))
+        .collect()
+}
+
+/// The notes of a report and how many there are, for `--json`
+pub fn json(docs: &[&Document]) -> Value {
+    json!({
+        "count": docs.len(),
+        "notes": docs
+            .iter()
+            .map(|doc| json!({
+                "id": doc.id,
+                "title": doc.title,
+                "filename": doc.filename,
+                "date": doc.date,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
@@ -0,0 +1,75 @@
+use crate::document::Document;
+use crate::zettel;
+use color_eyre::Report;
+use eyre::{bail, Result};
+use std::collections::{HashMap, HashSet};
+
+/// `edited` as a new revision of `original`: a document of its own, with a new ID, that
+/// records which one it replaces and counts one more write. The original is kept, marked as
+/// no longer the latest.
+pub fn revise(original: &Document, mut edited: Document) -> Document {
+    edited.id = zettel::new_id();
+    edited.revises = original.id.to_owned();
+    edited.writes = original.writes.saturating_add(1);
+    edited.latest = Some(true);
+    edited
+}
+
+/// Whether `doc` has been replaced by a newer revision, and is left out of searches
+pub fn is_superseded(doc: &Document) -> bool {
+    doc.latest == Some(false)
+}
+
+/// Point the links and backlinks of `doc` that are to `from` at `to` instead, returning whether
+/// there were any
+pub fn repoint(doc: &mut Document, from: &str, to: &str) -> bool {
+    let mut changed = false;
+    for id in doc.links.iter_mut().chain(doc.backlinks.iter_mut()) {
+        if id == from {
+            *id = to.to_owned();
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Meilisearch filter leaving out the revisions replaced by newer ones, and nothing else, notes
+/// never edited in the interface included
+pub const LATEST: &str = "NOT latest = false";
+
+/// The revisions of the note `id` is a revision of, oldest first, picked out of `docs`
+pub fn history(docs: Vec<Document>, id: &str) -> Result<Vec<Document>, Report> {
+    let mut by_id: HashMap<String, Document> = docs
+        .into_iter()
+        .map(|doc| (doc.id.to_owned(), doc))
+        .collect();
+    if !by_id.contains_key(id) {
+        bail!("No document with ID {}", id);
+    }
+    let replaced_by: HashMap<String, String> = by_id
+        .values()
+        .filter(|doc| !doc.revises.is_empty())
+        .map(|doc| (doc.revises.to_owned(), doc.id.to_owned()))
+        .collect();
+
+    // Back to the first revision still around. Cycles only come from hand-edited `revises`.
+    let mut first = id.to_owned();
+    let mut seen = HashSet::new();
+    while let Some(previous) = by_id.get(&first).map(|doc| doc.revises.to_owned()) {
+        if !by_id.contains_key(&previous) || !seen.insert(previous.to_owned()) {
+            break;
+        }
+        first = previous;
+    }
+    let mut chain = Vec::new();
+    let mut next = Some(first);
+    while let Some(id) = next {
+        let doc = match by_id.remove(&id) {
+            Some(doc) => doc,
+            None => break,
+        };
+        next = replaced_by.get(&doc.id).cloned();
+        chain.push(doc);
+    }
+    Ok(chain)
+}
@@ -0,0 +1,67 @@
+use crate::document::{Document, SerializationType};
+use std::io;
+
+/// A field whose value changed after writing a document to disk and reading it back
+#[derive(Debug, PartialEq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+macro_rules! compare_fields {
+    ($diffs:ident, $a:ident, $b:ident, $($field:ident),+) => {
+        $(
+            if $a.$field != $b.$field {
+                $diffs.push(FieldDiff {
+                    field: stringify!($field),
+                    before: format!("{:?}", $a.$field),
+                    after: format!("{:?}", $b.$field),
+                });
+            }
+        )+
+    };
+}
+
+/// Compare every field of two documents that is stored in the file itself, `modified` and
+/// `imported_at` are derived when importing
+pub fn diff(a: &Document, b: &Document) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+    compare_fields!(
+        diffs,
+        a,
+        b,
+        id,
+        parentid,
+        authors,
+        body,
+        date,
+        created,
+        title,
+        background_img,
+        links,
+        slug,
+        sequence,
+        subtitle,
+        tags,
+        status,
+        remind_at,
+        review_after,
+        weight,
+        writes,
+        revises,
+        views,
+        filename,
+        extra
+    );
+    diffs
+}
+
+/// Serialize the document the way it's written to disk, parse the result back and report any
+/// fields that didn't survive the trip
+pub fn verify(doc: &Document) -> Result<Vec<FieldDiff>, io::Error> {
+    let mut disk = doc.clone();
+    disk.serialization_type = SerializationType::Disk;
+    let reparsed = Document::parse_str(&disk.to_string(), &doc.filename)?;
+    Ok(diff(doc, &reparsed))
+}
@@ -0,0 +1,60 @@
+use crate::document::Document;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use std::str::FromStr;
+
+/// Output formats of the `search` subcommand, one line per match in each
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFormat {
+    /// The whole note as a JSON object
+    Json,
+    /// ID, title, date, tags and filename, tab separated
+    Tsv,
+    /// ID and title, tab separated, for fuzzy finders
+    Titles,
+}
+
+impl SearchFormat {
+    /// Whether the format shows the bodies of the notes, which are otherwise left out of the
+    /// search
+    pub fn needs_body(self) -> bool {
+        self == SearchFormat::Json
+    }
+}
+
+impl FromStr for SearchFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<SearchFormat, Self::Err> {
+        match s {
+            "json" | "jsonl" => Ok(SearchFormat::Json),
+            "tsv" => Ok(SearchFormat::Tsv),
+            "titles" | "plain" => Ok(SearchFormat::Titles),
+            _ => Err(eyre!(
+                "Unknown search format {}, expected json, tsv or titles",
+                s
+            )),
+        }
+    }
+}
+
+/// The line printed for `doc`
+pub fn line(format: SearchFormat, doc: &Document) -> Result<String, Report> {
+    Ok(match format {
+        SearchFormat::Json => serde_json::to_string(doc)?,
+        SearchFormat::Tsv => [
+            field(&doc.id),
+            field(&doc.title),
+            doc.date.to_string(),
+            field(&doc.tags.join(",")),
+            field(&doc.filename),
+        ]
+        .join("\t"),
+        SearchFormat::Titles => format!("{}\t{}", field(&doc.id), field(&doc.title)),
+    })
+}
+
+/// `s` made safe for a tab separated column, with tabs and line breaks turned into spaces
+fn field(s: &str) -> String {
+    s.replace(|c| c == '\t' || c == '\n' || c == '\r', " ")
+}
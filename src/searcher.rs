@@ -0,0 +1,103 @@
+use crate::api::{SearchRequest, Transport};
+use crate::query;
+use color_eyre::Report;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use url::Url;
+
+/// Response body of each index searched, by its position in the interface's list of indexes
+pub type Results = Vec<(usize, Result<String, Report>)>;
+
+struct Request {
+    generation: u64,
+    q: SearchRequest,
+    /// Search URL of each index, with the response body when the cache already had it
+    indexes: Vec<(usize, Url, Option<String>)>,
+}
+
+/// What came back for a search
+pub struct Response {
+    pub q: SearchRequest,
+    pub results: Results,
+}
+
+/// Sends searches from a background thread, so typing isn't held up by a slow network. Only
+/// the latest search counts: searches queued behind it are skipped, and responses to earlier
+/// ones are dropped when they arrive.
+pub struct Searcher {
+    tx: mpsc::Sender<Request>,
+    rx: mpsc::Receiver<(u64, Response)>,
+    /// Generation of the latest search sent
+    latest: u64,
+    /// Generation of the latest response received
+    received: u64,
+}
+
+impl Searcher {
+    pub fn new(client: Arc<dyn Transport>) -> Searcher {
+        let (tx, requests) = mpsc::channel::<Request>();
+        let (responses, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // Runs until the Searcher, and with it the sender, is dropped
+            while let Ok(mut req) = requests.recv() {
+                while let Ok(newer) = requests.try_recv() {
+                    req = newer;
+                }
+                let Request {
+                    generation,
+                    q,
+                    indexes,
+                } = req;
+                let results = indexes
+                    .into_iter()
+                    .map(|(index, uri, cached)| {
+                        let body = match cached {
+                            Some(body) => Ok(body),
+                            None => query::send_search(client.as_ref(), &uri, &q),
+                        };
+                        (index, body)
+                    })
+                    .collect();
+                if responses
+                    .send((generation, Response { q, results }))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+        Searcher {
+            tx,
+            rx,
+            latest: 0,
+            received: 0,
+        }
+    }
+
+    /// Queue `q` against `indexes`, given as their position and search URL along with the
+    /// cached response body if there is one
+    pub fn send(&mut self, q: SearchRequest, indexes: Vec<(usize, Url, Option<String>)>) {
+        self.latest += 1;
+        let _ = self.tx.send(Request {
+            generation: self.latest,
+            q,
+            indexes,
+        });
+    }
+
+    /// The response to the latest search, once it has arrived
+    pub fn try_recv(&mut self) -> Option<Response> {
+        while let Ok((generation, response)) = self.rx.try_recv() {
+            if generation == self.latest {
+                self.received = generation;
+                return Some(response);
+            }
+        }
+        None
+    }
+
+    /// Whether the latest search is still waiting for its response
+    pub fn busy(&self) -> bool {
+        self.received != self.latest
+    }
+}
@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+
+/// Part of a folgezettel sequence: sequences alternate between numbers and letters, as in
+/// `1a2b`, each part branching off the note before it
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Segment {
+    Number(u64),
+    /// Letters counted like spreadsheet columns, `a` to `z` then `aa`, so this is that count
+    Letters(u64),
+}
+
+/// Parse a sequence like `1a2b` into its parts. `/`, `.` and `,` may separate the parts, as in
+/// `21/3d7a`. `None` when there's anything else in it.
+pub fn parse(sequence: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut chars = sequence.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            let mut n: u64 = 0;
+            while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                n = n.checked_mul(10)?.checked_add(d.into())?;
+                chars.next();
+            }
+            segments.push(Segment::Number(n));
+        } else if c.is_ascii_alphabetic() {
+            let mut n: u64 = 0;
+            while let Some(c) = chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+                let d = (c.to_ascii_lowercase() as u8 - b'a' + 1) as u64;
+                n = n.checked_mul(26)?.checked_add(d)?;
+                chars.next();
+            }
+            segments.push(Segment::Letters(n));
+        } else if matches!(c, '/' | '.' | ',') {
+            chars.next();
+        } else {
+            return None;
+        }
+    }
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments)
+    }
+}
+
+/// Write parts back out, without separators
+pub fn render(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Number(n) => out.push_str(&n.to_string()),
+            Segment::Letters(n) => out.push_str(&letters(*n)),
+        }
+    }
+    out
+}
+
+fn letters(mut n: u64) -> String {
+    let mut out = Vec::new();
+    while n > 0 {
+        n -= 1;
+        out.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap()
+}
+
+/// Order sequences the way the notes follow each other: each note before its branches and
+/// branches in numeric order, so `1a9` comes before `1a10`. Empty and unparseable sequences
+/// go last.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// The branch off `parent` after the last one any sequence in `taken` uses, either itself or
+/// through branches of its own: `1a` has branches `1a1`, `1a2` and so on, `1a1` has `1a1a`,
+/// `1a1b`...
+pub fn next_branch<'a, I>(parent: &str, taken: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let parent = parse(parent)?;
+    let depth = parent.len();
+    let used: Vec<u64> = taken
+        .into_iter()
+        .filter_map(parse)
+        .filter(|s| s.len() > depth && s[..depth] == parent[..])
+        .map(|s| match s[depth] {
+            Segment::Number(n) | Segment::Letters(n) => n,
+        })
+        .collect();
+    let next = used.iter().max().map_or(1, |n| n + 1);
+    let mut branch = parent;
+    branch.push(match branch.last() {
+        Some(Segment::Number(_)) => Segment::Letters(next),
+        _ => Segment::Number(next),
+    });
+    Some(render(&branch))
+}
@@ -0,0 +1,143 @@
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// Index settings managed by the `settings` subcommand
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    Searchable,
+    Filterable,
+    Sortable,
+    RankingRules,
+    LocalizedAttributes,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 5] = [
+        Setting::Searchable,
+        Setting::Filterable,
+        Setting::Sortable,
+        Setting::RankingRules,
+        Setting::LocalizedAttributes,
+    ];
+
+    /// Path of the setting under `indexes/:index/settings/`
+    pub fn path(self) -> &'static str {
+        match self {
+            Setting::Searchable => "searchable-attributes",
+            Setting::Filterable => "filterable-attributes",
+            Setting::Sortable => "sortable-attributes",
+            Setting::RankingRules => "ranking-rules",
+            Setting::LocalizedAttributes => "localized-attributes",
+        }
+    }
+
+    /// Key of the setting in the object returned by `indexes/:index/settings`
+    pub fn key(self) -> &'static str {
+        match self {
+            Setting::Searchable => "searchableAttributes",
+            Setting::Filterable => "filterableAttributes",
+            Setting::Sortable => "sortableAttributes",
+            Setting::RankingRules => "rankingRules",
+            Setting::LocalizedAttributes => "localizedAttributes",
+        }
+    }
+
+    /// Request body setting this to `values`. Localized attributes are given as
+    /// `patterns=locales`, e.g. `title_de,body_de=deu` or `*=deu,eng`, the rest as they are.
+    pub fn body(self, values: &[String]) -> Result<Value, Report> {
+        match self {
+            Setting::LocalizedAttributes => values
+                .iter()
+                .map(|value| localized_attributes(value))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            _ => Ok(json!(values)),
+        }
+    }
+}
+
+/// A rule of the localized attributes setting from `patterns=locales`
+fn localized_attributes(value: &str) -> Result<Value, Report> {
+    let list = |s: &str| -> Vec<String> {
+        s.split(',')
+            .map(|x| x.trim().to_owned())
+            .filter(|x| !x.is_empty())
+            .collect()
+    };
+    let (patterns, locales) = match value.split_once('=') {
+        Some((patterns, locales)) => (list(patterns), list(locales)),
+        None => (Vec::new(), Vec::new()),
+    };
+    if patterns.is_empty() || locales.is_empty() {
+        return Err(eyre!(
+            "Expected attribute patterns and locales like title,body=deu,eng, got {}",
+            value
+        ));
+    }
+    Ok(json!({ "attributePatterns": patterns, "locales": locales }))
+}
+
+/// A value of a setting as it's given to `settings set`
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        Value::Object(rule) => {
+            let list = |key: &str| {
+                rule.get(key)
+                    .and_then(|v| v.as_array())
+                    .into_iter()
+                    .flatten()
+                    .map(|v| v.as_str().unwrap_or_default())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            format!("{}={}", list("attributePatterns"), list("locales"))
+        }
+        other => other.to_string(),
+    }
+}
+
+impl FromStr for Setting {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Setting, Self::Err> {
+        match s {
+            "searchable" | "searchable-attributes" => Ok(Setting::Searchable),
+            "filterable" | "filterable-attributes" => Ok(Setting::Filterable),
+            "sortable" | "sortable-attributes" => Ok(Setting::Sortable),
+            "ranking" | "ranking-rules" => Ok(Setting::RankingRules),
+            "localized" | "localized-attributes" => Ok(Setting::LocalizedAttributes),
+            _ => Err(eyre!(
+                "Unknown setting {}, expected searchable, filterable, sortable, ranking-rules or \
+                 localized-attributes",
+                s
+            )),
+        }
+    }
+}
+
+/// Most hits a search goes through with the default settings, Meilisearch's own default of 1000
+/// would keep exports, bulk deletes and the like from reaching every note of a larger vault
+pub const MAX_TOTAL_HITS: u64 = 100_000;
+
+/// Settings suiting `Document`: searching the text fields with titles ranked first, filtering
+/// on the fields the filter input and `--filter` options use, sorting on dates and weight,
+/// which also breaks ties between equally relevant notes, and paging through every note
+pub fn defaults() -> Value {
+    json!({
+        "searchableAttributes": ["title", "subtitle", "tags", "authors", "body"],
+        "filterableAttributes": [
+            "id", "parentid", "tags", "authors", "status", "date", "created", "modified",
+            "imported_at", "weight", "revises", "latest", "remind_at", "review_after"
+        ],
+        "sortableAttributes": [
+            "date", "created", "modified", "imported_at", "weight", "writes", "views", "title"
+        ],
+        "rankingRules": [
+            "words", "typo", "proximity", "attribute", "sort", "exactness", "weight:desc"
+        ],
+        "pagination": { "maxTotalHits": MAX_TOTAL_HITS },
+    })
+}
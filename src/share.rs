@@ -0,0 +1,176 @@
+use crate::document::Document;
+use crate::zettel;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+/// Output formats supported by the `share` subcommand
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShareFormat {
+    /// Single self-contained HTML page
+    Html,
+    /// Single markdown file with every note concatenated
+    Markdown,
+}
+
+impl FromStr for ShareFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<ShareFormat, Self::Err> {
+        match s {
+            "html" => Ok(ShareFormat::Html),
+            "markdown" | "md" => Ok(ShareFormat::Markdown),
+            _ => Err(eyre!("Unknown share format {}", s)),
+        }
+    }
+}
+
+/// Collect the document `id` plus everything reachable through `links` and `parentid` within
+/// `depth` hops, in breadth-first order. `fetch` looks up a single document by ID.
+pub fn neighborhood<F>(id: &str, depth: usize, mut fetch: F) -> Result<Vec<Document>, Report>
+where
+    F: FnMut(&str) -> Result<Document, Report>,
+{
+    let mut docs = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(id.to_string());
+    queue.push_back((id.to_string(), 0));
+
+    while let Some((id, hops)) = queue.pop_front() {
+        let doc = match fetch(&id) {
+            Ok(doc) => doc,
+            // The starting document must exist, dangling links further out are skipped
+            Err(e) if hops == 0 => return Err(e),
+            Err(_) => continue,
+        };
+        if hops < depth {
+            let mut neighbors = doc.links.clone();
+            if !doc.parentid.is_empty() && doc.parentid != doc.id {
+                neighbors.push(doc.parentid.to_owned());
+            }
+            for n in neighbors {
+                if seen.insert(n.to_owned()) {
+                    queue.push_back((n, hops + 1));
+                }
+            }
+        }
+        docs.push(doc);
+    }
+
+    Ok(docs)
+}
+
+fn anchor(doc: &Document) -> String {
+    format!("note-{}", doc.id)
+}
+
+/// Point markdown links at any of the bundled documents (by ID, filename, zettel ID, slug or
+/// note URL) to the document's anchor within the bundle
+fn rewrite_links(body: &str, targets: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find("](") {
+        let (before, after) = rest.split_at(start + 2);
+        out.push_str(before);
+        match after.find(')') {
+            Some(end) => {
+                let target = &after[..end];
+                let key = target.trim_start_matches("meilizet://note/");
+                match targets.get(key) {
+                    Some(anchor) => {
+                        out.push('#');
+                        out.push_str(anchor);
+                    }
+                    None => out.push_str(target),
+                }
+                rest = &after[end..];
+            }
+            None => {
+                rest = after;
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn link_targets(docs: &[Document]) -> HashMap<String, String> {
+    let mut targets = HashMap::new();
+    for doc in docs {
+        for key in &[&doc.id, &doc.filename, &doc.slug] {
+            if !key.is_empty() {
+                targets.insert(key.to_string(), anchor(doc));
+            }
+        }
+        if let Some(id) = zettel::from_filename(&doc.filename) {
+            targets.insert(id.to_string(), anchor(doc));
+        }
+    }
+    targets
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = "body{max-width:48em;margin:2em auto;padding:0 1em;font-family:sans-serif;\
+line-height:1.5}section{border-top:1px solid #ccc;margin-top:2em}pre{background:#f4f4f4;\
+padding:1em;overflow-x:auto}.meta{color:#666;font-size:.9em}";
+
+/// Render the documents as a single HTML page with a table of contents, the first document
+/// being the one that was shared
+pub fn html(docs: &[Document]) -> String {
+    let targets = link_targets(docs);
+    let title = docs.first().map(|d| d.title.as_str()).unwrap_or("meilizet");
+
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<nav>\n<ul>\n",
+        html_escape(title),
+        STYLE
+    );
+    for doc in docs {
+        out.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a></li>\n",
+            anchor(doc),
+            html_escape(&doc.title)
+        ));
+    }
+    out.push_str("</ul>\n</nav>\n");
+
+    for doc in docs {
+        out.push_str(&format!(
+            "<section id=\"{}\">\n<h1>{}</h1>\n<p class=\"meta\">{} &middot; {}</p>\n",
+            anchor(doc),
+            html_escape(&doc.title),
+            doc.date,
+            html_escape(&doc.tags.join(", "))
+        ));
+        let body = rewrite_links(&doc.body, &targets);
+        html::push_html(&mut out, Parser::new_ext(&body, Options::all()));
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Render the documents as a single markdown file with an anchor before each note
+pub fn markdown(docs: &[Document]) -> String {
+    let targets = link_targets(docs);
+    let mut out = String::new();
+    for doc in docs {
+        out.push_str(&format!(
+            "<a id=\"{}\"></a>\n\n# {}\n\n{}\n\n",
+            anchor(doc),
+            doc.title,
+            rewrite_links(doc.body.trim(), &targets)
+        ));
+    }
+    out
+}
@@ -0,0 +1,192 @@
+use crate::lock::Lock;
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location of the state file unless overridden on the command line
+pub const DEFAULT_PATH: &str = "~/.local/share/meilizet/state.json";
+
+/// Bookkeeping persisted between runs of the tool. Several processes may have it loaded at
+/// once, see `save`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    #[serde(skip)]
+    path: PathBuf,
+    /// The file as it was loaded or last saved, to tell the entries changed since from the
+    /// ones left alone
+    #[serde(skip)]
+    loaded: Value,
+    /// Keys of the flashcards already written out by `export --format anki`
+    #[serde(default)]
+    pub anki_exported: BTreeSet<String>,
+    /// Progress of imports that haven't finished cleanly, by glob pattern
+    #[serde(default)]
+    pub imports: BTreeMap<String, ImportCheckpoint>,
+    /// Commit last imported from each remote repository, by URL
+    #[serde(default)]
+    pub git_commits: BTreeMap<String, String>,
+    /// ETags of the files imported from each S3 bucket or WebDAV share, by URL and then key
+    #[serde(default)]
+    pub remote_etags: BTreeMap<String, BTreeMap<String, String>>,
+    /// Local files as they were when last imported, by index and then path, so imports can
+    /// skip the ones that haven't changed since
+    #[serde(default)]
+    pub imported_files: BTreeMap<String, BTreeMap<String, ImportedFile>>,
+}
+
+/// A file that was imported, see `State::imported_files`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImportedFile {
+    /// SHA-256 of the file's contents, hex encoded
+    pub hash: String,
+    /// ID of the document made from the file
+    pub id: String,
+    /// Task adding the document when the import didn't wait for it, until a later import sees
+    /// it succeed. The file only counts as imported once it has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<u64>,
+}
+
+/// Progress of one import, saved after every batch so `import --resume` can pick up where an
+/// interrupted run left off
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ImportCheckpoint {
+    /// Files in batches Meilisearch finished indexing
+    #[serde(default)]
+    pub done: BTreeSet<String>,
+    /// Files in batches that were accepted but not yet seen to finish, by task ID
+    #[serde(default)]
+    pub pending: BTreeMap<u64, Vec<String>>,
+}
+
+impl State {
+    /// Load the state file at `path`, starting from an empty state when it doesn't exist yet
+    pub fn load(path: &str) -> Result<State, Report> {
+        let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+        let loaded = read(&path)?;
+        let mut state: State = serde_json::from_value(loaded.clone())
+            .map_err(|e| eyre!("Failed to parse state {}: {}", path.display(), e))?;
+        state.path = path;
+        state.loaded = loaded;
+        Ok(state)
+    }
+
+    /// Remember that `file`, with contents hashing to `hash`, was imported into `index` as the
+    /// document `id`
+    pub fn record_import(
+        &mut self,
+        index: &str,
+        file: String,
+        hash: String,
+        id: String,
+        task: Option<u64>,
+    ) {
+        self.imported_files
+            .entry(index.to_owned())
+            .or_default()
+            .insert(file, ImportedFile { hash, id, task });
+    }
+
+    /// Content hash of each file imported into `index`, leaving out the ones whose task hasn't
+    /// been seen to succeed yet
+    pub fn confirmed_hashes(&self, index: &str) -> HashMap<String, String> {
+        self.imported_files
+            .get(index)
+            .map(|files| {
+                files
+                    .iter()
+                    .filter(|(_, file)| file.task.is_none())
+                    .map(|(path, file)| (path.to_owned(), file.hash.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Forget the files imported as the documents `ids`, so they're imported again even if
+    /// they haven't changed
+    pub fn forget_imports(&mut self, index: &str, ids: &[String]) {
+        if let Some(files) = self.imported_files.get_mut(index) {
+            files.retain(|_, file| !ids.contains(&file.id));
+        }
+    }
+
+    /// Write the entries changed since loading, e.g. the checkpoint of one import or the
+    /// files of one index, over the file as it is now, so other processes' changes to the
+    /// rest aren't lost. The file is replaced whole, a crash never leaves half of it.
+    pub fn save(&mut self) -> Result<(), Report> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = Lock::wait(&self.path.with_extension("lock"))?;
+        let mut current = read(&self.path)?;
+        let ours = serde_json::to_value(&*self)?;
+        merge(&mut current, &self.loaded, ours.clone());
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&current)?)?;
+        fs::rename(&tmp, &self.path)?;
+        // Later saves only write what changed after this one
+        self.loaded = ours;
+        Ok(())
+    }
+}
+
+/// The state file at `path` as JSON, an empty object when it doesn't exist yet
+fn read(path: &Path) -> Result<Value, Report> {
+    if !path.exists() {
+        return Ok(Value::Object(Default::default()));
+    }
+    let s = fs::read_to_string(path)?;
+    serde_json::from_str(&s).map_err(|e| eyre!("Failed to parse state {}: {}", path.display(), e))
+}
+
+/// Apply to `current` the entries of each field that differ between `loaded` and `ours`,
+/// removing the ones removed since loading
+fn merge(current: &mut Value, loaded: &Value, ours: Value) {
+    let (current, ours) = match (current, ours) {
+        (Value::Object(current), Value::Object(ours)) => (current, ours),
+        (current, ours) => {
+            *current = ours;
+            return;
+        }
+    };
+    for (field, ours) in ours {
+        // A field missing from the file was empty
+        let loaded = loaded.get(&field).cloned().unwrap_or_else(|| match ours {
+            Value::Object(_) => Value::Object(Default::default()),
+            Value::Array(_) => Value::Array(Vec::new()),
+            _ => Value::Null,
+        });
+        let entries = match (&loaded, ours) {
+            (Value::Object(loaded), Value::Object(ours)) => (loaded, ours),
+            // Sets and the like are taken whole, if they changed at all
+            (loaded, ours) => {
+                if *loaded != ours {
+                    current.insert(field, ours);
+                }
+                continue;
+            }
+        };
+        let (loaded, ours) = entries;
+        let target = current
+            .entry(field)
+            .or_insert_with(|| Value::Object(Default::default()));
+        if !target.is_object() {
+            *target = Value::Object(Default::default());
+        }
+        let target = target.as_object_mut().expect("made an object above");
+        for key in loaded.keys() {
+            if !ours.contains_key(key) {
+                target.remove(key);
+            }
+        }
+        for (key, value) in ours {
+            if loaded.get(&key) != Some(&value) {
+                target.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where a note is in its life, set by the `status` frontmatter field. Notes without one count
+/// as published.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Half-formed, left out of the interface's searches until shown
+    Draft,
+    Published,
+    /// Kept for reference rather than worked on
+    Archived,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Draft => "draft",
+            Status::Published => "published",
+            Status::Archived => "archived",
+        })
+    }
+}
+
+/// Meilisearch filter leaving out drafts, and nothing else, notes without a status included
+pub const NOT_DRAFT: &str = "NOT status = draft";
+
+/// Status of the notes that don't set one, by the directory they're in, from the
+/// `[status_defaults]` config section
+pub struct Defaults {
+    /// Absolute, deepest first so the closest directory wins
+    dirs: Vec<(PathBuf, Status)>,
+    /// What relative paths are relative to
+    cwd: PathBuf,
+}
+
+impl Defaults {
+    pub fn new(config: &BTreeMap<String, Status>) -> Defaults {
+        let cwd = env::current_dir().unwrap_or_default();
+        let mut dirs: Vec<_> = config
+            .iter()
+            .map(|(dir, status)| (cwd.join(shellexpand::tilde(dir).as_ref()), *status))
+            .collect();
+        dirs.sort_by_key(|(dir, _)| Reverse(dir.components().count()));
+        Defaults { dirs, cwd }
+    }
+
+    /// Status of the file at `path` when its frontmatter leaves it out. Relative paths, here
+    /// and in the config, are taken from the current directory.
+    pub fn get(&self, path: &Path) -> Option<Status> {
+        let path = self.cwd.join(path);
+        self.dirs
+            .iter()
+            .find(|(dir, _)| path.starts_with(dir))
+            .map(|(_, status)| *status)
+    }
+}
@@ -0,0 +1,71 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+
+/// Filter of the keys made by `publish-key` unless told otherwise, so only published notes show
+/// up on a website, those without a status included
+pub const DEFAULT_FILTER: &str = "status != draft AND status != archived";
+
+/// A tenant token: a JSON web token signed with the search key `key`, only searching `index`,
+/// and in it only the documents matching `filter`, a Meilisearch filter expression. Whoever
+/// holds it can't change these rules without the key itself. `key_uid` is the key's `uid`,
+/// which servers before 1.0 don't have, they take the start of the key instead.
+pub fn token(key: &str, key_uid: Option<&str>, index: &str, filter: &str) -> String {
+    let mut rules = Map::new();
+    rules.insert(index.to_owned(), json!({ "filter": filter }));
+    let mut claims = json!({ "searchRules": rules });
+    match key_uid {
+        Some(uid) => claims["apiKeyUid"] = json!(uid),
+        None => claims["apiKeyPrefix"] = json!(key.chars().take(8).collect::<String>()),
+    }
+    let signed = format!(
+        "{}.{}",
+        encode(&json!({ "alg": "HS256", "typ": "JWT" })),
+        encode(&claims)
+    );
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(signed.as_bytes());
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+fn encode(value: &Value) -> String {
+    URL_SAFE_NO_PAD.encode(value.to_string())
+}
+
+/// HTML for a search box over `index` on `host` with `token`, listing the hits as links to
+/// their permalinks, to paste into a page of a website
+pub fn snippet(host: &str, index: &str, token: &str) -> String {
+    format!(
+        r##"<div id="searchbox"></div>
+<div id="hits"></div>
+<script src="https://cdn.jsdelivr.net/npm/@meilisearch/instant-meilisearch/dist/instant-meilisearch.umd.min.js"></script>
+<script src="https://cdn.jsdelivr.net/npm/instantsearch.js@4"></script>
+<script>
+  const search = instantsearch({{
+    indexName: {index},
+    searchClient: instantMeiliSearch({host}, {token}).searchClient,
+  }});
+  search.addWidgets([
+    instantsearch.widgets.searchBox({{ container: "#searchbox" }}),
+    instantsearch.widgets.hits({{
+      container: "#hits",
+      templates: {{
+        item: (hit, {{ html }}) => html`<a href="${{hit.permalink}}">${{hit.title}}</a>`,
+      }},
+    }}),
+  ]);
+  search.start();
+</script>
+"##,
+        index = json!(index),
+        host = json!(host),
+        token = json!(token),
+    )
+}
@@ -0,0 +1,222 @@
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Kills remembered before the oldest ones are dropped
+const KILL_RING_SIZE: usize = 30;
+
+/// Text removed with the kill keys, shared by every input so text can be moved between them
+#[derive(Debug, Default)]
+pub struct KillRing {
+    /// Oldest first
+    kills: Vec<String>,
+}
+
+impl KillRing {
+    fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.kills.len() == KILL_RING_SIZE {
+            self.kills.remove(0);
+        }
+        self.kills.push(text);
+    }
+
+    fn last(&self) -> Option<&str> {
+        self.kills.last().map(String::as_str)
+    }
+}
+
+/// Kind of the last change, so a run of typing or deleting is undone in one go
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// Single line of editable text with a cursor and undo history, behind every text input of the
+/// interactive interface. Keys follow readline where the interface doesn't already use them.
+#[derive(Debug, Default)]
+pub struct TextInput {
+    text: String,
+    /// Byte offset of the cursor, always between grapheme clusters so an emoji or a letter
+    /// with combining accents is stepped over and deleted as one
+    cursor: usize,
+    /// Text and cursor before each change, most recent last
+    undo: Vec<(String, usize)>,
+    redo: Vec<(String, usize)>,
+    last_edit: Option<Edit>,
+}
+
+impl TextInput {
+    /// Input holding `text` with the cursor at its end
+    pub fn new(text: &str) -> TextInput {
+        TextInput {
+            text: text.to_owned(),
+            cursor: text.len(),
+            ..Default::default()
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Display width of the text before the cursor, for placing the terminal cursor
+    pub fn cursor_width(&self) -> u16 {
+        self.text[..self.cursor].width() as u16
+    }
+
+    /// Columns to scroll the text left by so the cursor stays inside an input `width` columns
+    /// wide, keeping as much of the text before it in view as fits
+    pub fn scroll(&self, width: u16) -> u16 {
+        self.cursor_width().saturating_sub(width.saturating_sub(1))
+    }
+
+    /// Apply a key press, returning whether it was an editing key
+    pub fn handle_key(&mut self, key: Key, ring: &mut KillRing) -> bool {
+        match key {
+            Key::Char(c) => self.insert(c),
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::Ctrl('b') | Key::Left => self.cursor = self.previous(),
+            Key::Ctrl('f') | Key::Right => self.cursor = self.next(),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.text.len(),
+            Key::Ctrl('k') => self.kill_to_end(ring),
+            Key::Ctrl('u') => self.kill_to_start(ring),
+            Key::Ctrl('w') => self.kill_word(ring),
+            Key::Ctrl('y') => self.yank(ring),
+            // Terminals send ctrl-_ as 0x1f, which termion reads as ctrl-7
+            Key::Ctrl('_') | Key::Ctrl('7') => self.undo(),
+            Key::Ctrl('r') => self.redo(),
+            _ => return false,
+        }
+        if let Key::Ctrl('b') | Key::Ctrl('f') | Key::Left | Key::Right | Key::Home | Key::End = key
+        {
+            // Typing somewhere else starts a new undo step
+            self.last_edit = None;
+        }
+        true
+    }
+
+    /// Change the text with `f`, as one undoable step leaving the cursor at the end. Returns
+    /// what `f` does, which is whether it changed anything.
+    pub fn edit(&mut self, f: impl FnOnce(&mut String) -> bool) -> bool {
+        let mut text = self.text.clone();
+        if !f(&mut text) {
+            return false;
+        }
+        self.checkpoint(Edit::Other);
+        self.cursor = text.len();
+        self.text = text;
+        true
+    }
+
+    /// Offset of the grapheme cluster before the cursor
+    fn previous(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Offset of the end of the grapheme cluster after the cursor
+    fn next(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// Remember the text before a change, unless it continues a run of the same kind
+    fn checkpoint(&mut self, edit: Edit) {
+        if edit == Edit::Other || self.last_edit != Some(edit) {
+            self.undo.push((self.text.to_owned(), self.cursor));
+        }
+        self.redo.clear();
+        self.last_edit = Some(edit);
+    }
+
+    fn insert(&mut self, c: char) {
+        self.checkpoint(Edit::Insert);
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.checkpoint(Edit::Delete);
+        let start = self.previous();
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn delete(&mut self) {
+        if self.cursor == self.text.len() {
+            return;
+        }
+        self.checkpoint(Edit::Delete);
+        let end = self.next();
+        self.text.drain(self.cursor..end);
+    }
+
+    /// Remove the text between the byte offsets `start` and `end`, adding it to the kill ring
+    fn kill(&mut self, start: usize, end: usize, ring: &mut KillRing) {
+        if start == end {
+            return;
+        }
+        self.checkpoint(Edit::Other);
+        ring.push(self.text.drain(start..end).collect());
+        self.cursor = start;
+    }
+
+    fn kill_to_end(&mut self, ring: &mut KillRing) {
+        self.kill(self.cursor, self.text.len(), ring);
+    }
+
+    fn kill_to_start(&mut self, ring: &mut KillRing) {
+        self.kill(0, self.cursor, ring);
+    }
+
+    /// Kill the word before the cursor along with the whitespace after it
+    fn kill_word(&mut self, ring: &mut KillRing) {
+        let before = &self.text[..self.cursor];
+        let word = before.trim_end_matches(char::is_whitespace);
+        let start = word.rfind(char::is_whitespace).map_or(0, |i| {
+            i + word[i..].chars().next().map_or(0, char::len_utf8)
+        });
+        self.kill(start, self.cursor, ring);
+    }
+
+    /// Insert the most recent kill at the cursor
+    fn yank(&mut self, ring: &KillRing) {
+        if let Some(text) = ring.last() {
+            self.checkpoint(Edit::Other);
+            self.text.insert_str(self.cursor, text);
+            self.cursor += text.len();
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some((text, cursor)) = self.undo.pop() {
+            let current = std::mem::replace(&mut self.text, text);
+            self.redo.push((current, self.cursor));
+            self.cursor = cursor;
+            self.last_edit = None;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some((text, cursor)) = self.redo.pop() {
+            let current = std::mem::replace(&mut self.text, text);
+            self.undo.push((current, self.cursor));
+            self.cursor = cursor;
+            self.last_edit = None;
+        }
+    }
+}
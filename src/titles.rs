@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+/// Titles shorter than this, once normalized, are only compared exactly: short titles a letter
+/// apart, like `Todo` and `Todos`, are usually different notes
+const MIN_FUZZY_LEN: usize = 8;
+
+/// Another note whose title is the same as, or a typo away from, the one checked
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub id: String,
+    pub title: String,
+    /// Same title once case and spacing are ignored, rather than one edit away from it
+    pub exact: bool,
+}
+
+/// The titles of the notes in an index, for spotting duplicates, which make links by title
+/// ambiguous. Near duplicates are found through the titles with one character deleted, so
+/// they're looked up rather than compared with every title.
+#[derive(Default)]
+pub struct Titles {
+    /// ID, title and normalized title of each note
+    notes: Vec<(String, String, String)>,
+    /// Positions in `notes` by normalized title and by each of its single deletions
+    variants: HashMap<String, Vec<usize>>,
+}
+
+impl Titles {
+    pub fn new() -> Titles {
+        Titles::default()
+    }
+
+    /// Remember the title of the note `id`
+    pub fn insert(&mut self, id: &str, title: &str) {
+        let normalized = normalize(title);
+        if normalized.is_empty() {
+            return;
+        }
+        let at = self.notes.len();
+        for variant in variants(&normalized) {
+            self.variants.entry(variant).or_default().push(at);
+        }
+        self.notes
+            .push((id.to_owned(), title.to_owned(), normalized));
+    }
+
+    /// Notes other than `id` whose title matches `title` or is one edit away from it
+    pub fn conflicts(&self, id: &str, title: &str) -> Vec<Conflict> {
+        let normalized = normalize(title);
+        if normalized.is_empty() {
+            return Vec::new();
+        }
+        let mut candidates: Vec<usize> = variants(&normalized)
+            .filter_map(|v| self.variants.get(&v))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+            .into_iter()
+            .map(|at| &self.notes[at])
+            .filter(|(other, _, _)| other != id)
+            .filter_map(|(other, other_title, other_normalized)| {
+                let exact = *other_normalized == normalized;
+                if exact || distance(&normalized, other_normalized) == 1 {
+                    Some(Conflict {
+                        id: other.to_owned(),
+                        title: other_title.to_owned(),
+                        exact,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Lowercased, with runs of whitespace made single spaces
+fn normalize(title: &str) -> String {
+    title
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The title itself, and when it's long enough, the title with each of its characters deleted
+fn variants(normalized: &str) -> impl Iterator<Item = String> + '_ {
+    let chars: Vec<char> = normalized.chars().collect();
+    let deletions = if chars.len() >= MIN_FUZZY_LEN {
+        chars.len()
+    } else {
+        0
+    };
+    std::iter::once(normalized.to_owned()).chain(
+        (0..deletions).map(move |i| chars[..i].iter().chain(chars[i + 1..].iter()).collect()),
+    )
+}
+
+/// Levenshtein distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
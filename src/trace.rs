@@ -0,0 +1,95 @@
+use crate::output;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Timings of the phases of the interactive interface, recorded with `--trace` to find out
+/// where a sluggish terminal spends its time
+#[derive(Default)]
+pub struct Trace {
+    enabled: bool,
+    /// Whether the readout is drawn over the interface
+    pub visible: bool,
+    spans: BTreeMap<&'static str, Span>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Span {
+    count: u32,
+    last: Duration,
+    total: Duration,
+    max: Duration,
+}
+
+impl Span {
+    fn average(&self) -> Duration {
+        self.total / self.count.max(1)
+    }
+}
+
+impl Trace {
+    /// Nothing is recorded unless `enabled`
+    pub fn new(enabled: bool) -> Trace {
+        Trace {
+            enabled,
+            visible: enabled,
+            spans: BTreeMap::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Run `f`, timing it as `name`
+    pub fn span<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let ret = f();
+        self.record(name, start.elapsed());
+        ret
+    }
+
+    /// Record a phase timed elsewhere, for phases that can't be wrapped in a closure
+    pub fn record(&mut self, name: &'static str, elapsed: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let span = self.spans.entry(name).or_default();
+        span.count += 1;
+        span.last = elapsed;
+        span.total += elapsed;
+        span.max = span.max.max(elapsed);
+    }
+
+    /// One line per phase, for the readout
+    pub fn lines(&self) -> Vec<String> {
+        self.spans
+            .iter()
+            .map(|(name, span)| {
+                format!(
+                    "{:<9} {:>9.1?} avg {:>9.1?} max {:>9.1?}",
+                    name,
+                    span.last,
+                    span.average(),
+                    span.max
+                )
+            })
+            .collect()
+    }
+}
+
+/// Summary of every phase, printed when the interface exits
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = output::Table::new(&["Phase", "Count", "Average", "Max"]);
+        for (name, span) in &self.spans {
+            table.row(vec![
+                name.to_string(),
+                span.count.to_string(),
+                format!("{:.1?}", span.average()),
+                format!("{:.1?}", span.max),
+            ]);
+        }
+        write!(f, "{}", table)
+    }
+}
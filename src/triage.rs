@@ -0,0 +1,253 @@
+use crate::document::{Document, SerializationType};
+use crate::interactive::event::{Event, Events};
+use crate::interactive::TerminalGuard;
+use crate::textinput::{KillRing, TextInput};
+use color_eyre::Report;
+use eyre::Result;
+use std::io::stdout;
+use termion::{event::Key, raw::IntoRawMode, screen::AlternateScreen};
+use tui::{
+    backend::TermionBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+
+/// Tag of notes waiting to be triaged
+pub const INBOX_TAG: &str = "inbox";
+
+/// Tag archived notes are given in place of `INBOX_TAG`
+pub const ARCHIVE_TAG: &str = "archive";
+
+/// Whether `doc` still needs triaging: tagged `inbox`, or not tagged at all
+pub fn in_inbox(doc: &Document) -> bool {
+    doc.tags.is_empty() || doc.tags.iter().any(|t| t == INBOX_TAG)
+}
+
+/// The notes of `docs` in the inbox, oldest first
+pub fn inbox(docs: Vec<Document>) -> Vec<Document> {
+    let mut inbox: Vec<Document> = docs.into_iter().filter(in_inbox).collect();
+    inbox.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.id.cmp(&b.id)));
+    inbox
+}
+
+/// `doc` out of the inbox with `tags` added
+pub fn tag(doc: &Document, tags: &[String]) -> Document {
+    let mut doc = doc.clone();
+    doc.tags.retain(|t| t != INBOX_TAG);
+    for tag in tags {
+        if !doc.tags.contains(tag) {
+            doc.tags.push(tag.to_owned());
+        }
+    }
+    doc
+}
+
+/// `doc` out of the inbox under the note `parent`
+pub fn link(doc: &Document, parent: &str) -> Document {
+    let mut doc = tag(doc, &[]);
+    doc.parentid = parent.to_owned();
+    doc
+}
+
+/// A change decided on while triaging, applied right away so nothing is lost on quitting
+#[derive(Debug)]
+pub struct Action {
+    /// Name of the change for the undo log, e.g. `triage-archive`
+    pub kind: &'static str,
+    pub before: Document,
+    /// The changed note, `None` when it's to be deleted
+    pub after: Option<Document>,
+}
+
+/// What's being typed or confirmed at the bottom of the screen
+enum Prompt {
+    Tags(TextInput),
+    Parent(TextInput),
+    Delete,
+}
+
+const HELP: &str = "t tag  p link to parent  a archive  d delete  n/→ skip  b/← back  q quit";
+
+/// Step through `docs` one note at a time, applying the action picked for each with `apply`,
+/// which returns a message to show for it. Returns how many notes were dealt with.
+pub fn run<F>(docs: Vec<Document>, mut apply: F) -> Result<usize, Report>
+where
+    F: FnMut(Action) -> Result<String, Report>,
+{
+    if docs.is_empty() {
+        return Ok(0);
+    }
+    let _guard = TerminalGuard::new();
+    let mut tui = tui::Terminal::new(TermionBackend::new(AlternateScreen::from(
+        stdout().into_raw_mode()?,
+    )))?;
+    let events = Events::new();
+    let mut ring = KillRing::default();
+
+    // Notes dealt with drop out, so this stays on the next one
+    let mut docs = docs;
+    let mut current = 0;
+    let mut done = 0;
+    let mut prompt: Option<Prompt> = None;
+    let mut status = String::new();
+
+    while current < docs.len() {
+        let doc = &docs[current];
+        tui.draw(|f| {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints(
+                    [
+                        Constraint::Length(5),
+                        Constraint::Min(3),
+                        Constraint::Length(3),
+                    ]
+                    .as_ref(),
+                )
+                .split(f.size());
+
+            let label =
+                |s: &str| Span::styled(format!("{:<8}", s), Style::default().fg(Color::DarkGray));
+            let header = Paragraph::new(vec![
+                Spans::from(Span::styled(
+                    doc.title.to_owned(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+                Spans::from(vec![label("Date"), Span::raw(doc.date.to_string())]),
+                Spans::from(vec![label("Tags"), Span::raw(doc.tags.join(", "))]),
+            ])
+            .block(
+                Block::default()
+                    .title(format!("Inbox {}/{}", current + 1, docs.len()))
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(header, rows[0]);
+
+            let mut body = doc.clone();
+            body.serialization_type = SerializationType::Human;
+            let body = Paragraph::new(body.to_string())
+                .block(Block::default().borders(Borders::ALL))
+                .wrap(Wrap { trim: false });
+            f.render_widget(body, rows[1]);
+
+            let (title, line) = match &prompt {
+                Some(Prompt::Tags(input)) => (
+                    "Tags, comma separated (enter applies, esc cancels)",
+                    input.as_str().to_owned(),
+                ),
+                Some(Prompt::Parent(input)) => (
+                    "ID of the parent note (enter applies, esc cancels)",
+                    input.as_str().to_owned(),
+                ),
+                Some(Prompt::Delete) => ("Delete this note? (y/n)", String::new()),
+                None if status.is_empty() => (HELP, String::new()),
+                None => (HELP, status.to_owned()),
+            };
+            let bottom = Paragraph::new(line)
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().title(title).borders(Borders::ALL));
+            f.render_widget(bottom, rows[2]);
+        })?;
+
+        let key = match events.next()? {
+            Event::Input(key) => key,
+            _ => continue,
+        };
+        let action = match (&mut prompt, key) {
+            (_, Key::Ctrl('c')) => break,
+            (Some(_), Key::Esc) | (Some(Prompt::Delete), Key::Char('n')) => {
+                prompt = None;
+                None
+            }
+            (Some(Prompt::Tags(input)), Key::Char('\n')) => {
+                let tags: Vec<String> = input
+                    .as_str()
+                    .split(',')
+                    .map(|t| t.trim().to_owned())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                prompt = None;
+                if tags.is_empty() {
+                    None
+                } else {
+                    Some(Action {
+                        kind: "triage-tag",
+                        before: doc.clone(),
+                        after: Some(tag(doc, &tags)),
+                    })
+                }
+            }
+            (Some(Prompt::Parent(input)), Key::Char('\n')) => {
+                let parent = input.as_str().trim().to_owned();
+                prompt = None;
+                if parent.is_empty() {
+                    None
+                } else {
+                    Some(Action {
+                        kind: "triage-link",
+                        before: doc.clone(),
+                        after: Some(link(doc, &parent)),
+                    })
+                }
+            }
+            (Some(Prompt::Tags(input)), key) | (Some(Prompt::Parent(input)), key) => {
+                input.handle_key(key, &mut ring);
+                None
+            }
+            (Some(Prompt::Delete), Key::Char('y')) => {
+                prompt = None;
+                Some(Action {
+                    kind: "triage-delete",
+                    before: doc.clone(),
+                    after: None,
+                })
+            }
+            (Some(Prompt::Delete), _) => None,
+            (None, Key::Char('q')) | (None, Key::Esc) => break,
+            (None, Key::Char('t')) => {
+                prompt = Some(Prompt::Tags(TextInput::default()));
+                None
+            }
+            (None, Key::Char('p')) => {
+                prompt = Some(Prompt::Parent(TextInput::default()));
+                None
+            }
+            (None, Key::Char('d')) => {
+                prompt = Some(Prompt::Delete);
+                None
+            }
+            (None, Key::Char('a')) => Some(Action {
+                kind: "triage-archive",
+                before: doc.clone(),
+                after: Some(tag(doc, &[ARCHIVE_TAG.to_owned()])),
+            }),
+            (None, Key::Char('n')) | (None, Key::Char(' ')) | (None, Key::Right) => {
+                current += 1;
+                status.clear();
+                None
+            }
+            (None, Key::Char('b')) | (None, Key::Left) => {
+                current = current.saturating_sub(1);
+                status.clear();
+                None
+            }
+            _ => None,
+        };
+        if let Some(action) = action {
+            match apply(action) {
+                Ok(message) => {
+                    docs.remove(current);
+                    done += 1;
+                    status = message;
+                }
+                Err(e) => status = format!("Failed: {}", e),
+            }
+        }
+    }
+
+    tui.clear()?;
+    Ok(done)
+}
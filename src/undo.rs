@@ -0,0 +1,152 @@
+use crate::date::Date;
+use crate::document::Document;
+use crate::webhook::{self, Webhook};
+use color_eyre::Report;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Location of the undo log unless overridden on the command line
+pub const DEFAULT_PATH: &str = "~/.local/share/meilizet/undo.jsonl";
+
+/// One change made to an index, with what's needed to revert it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Operation {
+    /// Sequence number, to undo it by
+    pub id: u64,
+    /// What was done, e.g. `normalize-authors`
+    pub kind: String,
+    pub index: String,
+    pub at: Date,
+    /// Documents as they were before, put back on undo
+    #[serde(default)]
+    pub before: Vec<Document>,
+    /// IDs of documents that didn't exist before, deleted on undo
+    #[serde(default)]
+    pub created: Vec<String>,
+    /// Documents as the operation left them
+    #[serde(default)]
+    pub after: Vec<Document>,
+}
+
+/// Append-only log of the operations that can be undone, one JSON object per line, oldest
+/// first. Imports aren't undoable, they're recorded in the import log instead, see `audit`.
+pub struct UndoLog {
+    path: PathBuf,
+}
+
+impl UndoLog {
+    pub fn new(path: &str) -> Self {
+        UndoLog {
+            path: PathBuf::from(shellexpand::tilde(path).as_ref()),
+        }
+    }
+
+    pub fn operations(&self) -> Result<Vec<Operation>, Report> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                serde_json::from_str(l)
+                    .map_err(|e| eyre!("Failed to parse undo log {}: {}", self.path.display(), e))
+            })
+            .collect()
+    }
+
+    /// Log an operation, returning its ID
+    pub fn record(
+        &self,
+        kind: &str,
+        index: &str,
+        before: Vec<Document>,
+        created: Vec<String>,
+        after: Vec<Document>,
+    ) -> Result<u64, Report> {
+        let id = self.operations()?.last().map_or(1, |op| op.id + 1);
+        let op = Operation {
+            id,
+            kind: kind.to_owned(),
+            index: index.to_owned(),
+            at: Date::now(),
+            before,
+            created,
+            after,
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&op)?)?;
+        Ok(id)
+    }
+
+    /// The operation with the given ID, or the latest one
+    pub fn find(&self, id: Option<u64>) -> Result<Operation, Report> {
+        let ops = self.operations()?;
+        match id {
+            Some(id) => ops
+                .into_iter()
+                .find(|op| op.id == id)
+                .ok_or_else(|| eyre!("No operation {} in the undo log", id)),
+            None => ops
+                .into_iter()
+                .last()
+                .ok_or_else(|| eyre!("Nothing to undo")),
+        }
+    }
+
+    /// Drop an operation from the log once it's been undone
+    pub fn remove(&self, id: u64) -> Result<(), Report> {
+        let mut s = String::new();
+        for op in self.operations()?.iter().filter(|op| op.id != id) {
+            s.push_str(&serde_json::to_string(op)?);
+            s.push('\n');
+        }
+        fs::write(&self.path, s)?;
+        Ok(())
+    }
+}
+
+/// Records the changes the interactive interface makes the way the commands record theirs:
+/// logged to be undone and sent to the webhooks. The interface drops the cached searches
+/// itself, with its `SearchCache`.
+pub struct Recorder {
+    pub log: UndoLog,
+    pub webhooks: Vec<Webhook>,
+    /// Client for the webhooks, as opposed to the Meilisearch servers
+    pub client: reqwest::blocking::Client,
+}
+
+impl Recorder {
+    /// Log a change to `index` like `UndoLog::record`, then fire the webhooks for each of the
+    /// `after` documents, as created if it's among `created`. Returns the ID to undo it by.
+    pub fn record(
+        &self,
+        kind: &str,
+        index: &str,
+        before: Vec<Document>,
+        created: Vec<String>,
+        after: Vec<Document>,
+    ) -> Result<u64, Report> {
+        let id = self
+            .log
+            .record(kind, index, before, created.clone(), after.clone())?;
+        for doc in &after {
+            let event = if created.contains(&doc.id) {
+                webhook::Event::Created
+            } else {
+                webhook::Event::Updated
+            };
+            webhook::fire(&self.client, &self.webhooks, event, doc);
+        }
+        Ok(id)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::date::Date;
+use crate::document::Document;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// `[validation]` section of the config file. Every rule is off unless set.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ValidationConfig {
+    /// Fields every document has to set to a non-empty value
+    pub required: Vec<String>,
+    /// The only tags documents may use
+    pub tags: Vec<String>,
+    /// Longest title allowed, in characters
+    pub max_title_length: Option<usize>,
+    /// Dates before this are assumed to be typos
+    pub earliest_date: Option<Date>,
+    /// Dates after this are assumed to be typos
+    pub latest_date: Option<Date>,
+}
+
+/// A convention a document breaks
+#[derive(Debug)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn is_empty(v: &Value) -> bool {
+    match v {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        // Dates are stored as epochs, 0 meaning unset
+        Value::Number(n) => n.as_f64() == Some(0.0),
+        Value::Bool(_) => false,
+    }
+}
+
+/// Check `doc` against the configured rules, returning every rule it breaks
+pub fn validate(config: &ValidationConfig, doc: &Document) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violation = |field: &str, message: String| {
+        violations.push(Violation {
+            field: field.to_owned(),
+            message,
+        })
+    };
+
+    if !config.required.is_empty() {
+        let fields = serde_json::to_value(doc).unwrap_or(Value::Null);
+        for field in &config.required {
+            if fields.get(field).is_none_or(is_empty) {
+                violation(field, "required but not set".to_owned());
+            }
+        }
+    }
+
+    if !config.tags.is_empty() {
+        for tag in doc.tags.iter().filter(|t| !config.tags.contains(t)) {
+            violation("tags", format!("\"{}\" is not an allowed tag", tag));
+        }
+    }
+
+    if let Some(max) = config.max_title_length {
+        let len = doc.title.chars().count();
+        if len > max {
+            violation(
+                "title",
+                format!("{} characters long, at most {} allowed", len, max),
+            );
+        }
+    }
+
+    for (field, date) in &[("date", &doc.date), ("created", &doc.created)] {
+        // Unset
+        if **date == Date::default() {
+            continue;
+        }
+        if let Some(earliest) = &config.earliest_date {
+            if *date < earliest {
+                violation(field, format!("{} is before {}", date, earliest));
+            }
+        }
+        if let Some(latest) = &config.latest_date {
+            if *date > latest {
+                violation(field, format!("{} is after {}", date, latest));
+            }
+        }
+    }
+
+    violations
+}
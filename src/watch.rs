@@ -0,0 +1,57 @@
+use notify::DebouncedEvent;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// How long a file has to be left alone before its changes are acted on, so an editor saving
+/// in several steps causes one import
+pub const DELAY: Duration = Duration::from_millis(500);
+
+/// What a filesystem event means for the index
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// The file was created or modified and needs importing
+    Modified(PathBuf),
+    /// The file is gone and its document should go too
+    Removed(PathBuf),
+    /// Events were missed, everything needs importing again
+    Rescan,
+}
+
+/// The changes an event stands for. Notices of changes still in progress are left out, the
+/// event that follows once the file settles is what counts.
+pub fn changes(event: DebouncedEvent) -> Vec<Change> {
+    match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+            vec![Change::Modified(path)]
+        }
+        DebouncedEvent::Remove(path) => vec![Change::Removed(path)],
+        DebouncedEvent::Rename(from, to) => vec![Change::Removed(from), Change::Modified(to)],
+        DebouncedEvent::Rescan => vec![Change::Rescan],
+        DebouncedEvent::NoticeWrite(_)
+        | DebouncedEvent::NoticeRemove(_)
+        | DebouncedEvent::Chmod(_)
+        | DebouncedEvent::Error(..) => Vec::new(),
+    }
+}
+
+/// Deepest directory holding every file the glob pattern can match: its leading components
+/// without wildcards
+pub fn root(pattern: &str) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if let Component::Normal(name) = component {
+            if name.to_string_lossy().contains(&['*', '?', '['][..]) {
+                break;
+            }
+        }
+        root.push(component);
+    }
+    // A pattern naming a single file
+    if root.is_file() {
+        root.pop();
+    }
+    if root.as_os_str().is_empty() {
+        root.push(".");
+    }
+    root
+}
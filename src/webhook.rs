@@ -0,0 +1,63 @@
+use crate::document::Document;
+use crate::output;
+use serde::{Deserialize, Serialize};
+
+/// Kinds of document changes a webhook can subscribe to
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Created,
+    Updated,
+    Deleted,
+    /// A note was published with `publish`
+    Published,
+}
+
+/// A single `[[webhooks]]` entry from the config file, e.g.
+///
+/// ```toml
+/// [[webhooks]]
+/// url = "http://localhost:8080/rebuild"
+/// events = ["created", "updated"]
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Events to fire on, every event when left empty
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+impl Webhook {
+    fn wants(&self, event: Event) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: Event,
+    document: &'a Document,
+}
+
+/// POST the document JSON to every webhook subscribed to `event`. Failures are only reported,
+/// a broken downstream automation shouldn't abort the operation that triggered it.
+pub fn fire(client: &reqwest::blocking::Client, hooks: &[Webhook], event: Event, doc: &Document) {
+    for hook in hooks.iter().filter(|h| h.wants(event)) {
+        match client
+            .post(hook.url.as_str())
+            .json(&Payload {
+                event,
+                document: doc,
+            })
+            .send()
+        {
+            Ok(resp) => {
+                if !resp.status().is_success() {
+                    output::error(format_args!("Webhook {} failed: {:?}", hook.url, resp));
+                }
+            }
+            Err(e) => output::error(format_args!("Webhook {} failed: {:?}", hook.url, e)),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use chrono::{Duration, Local, NaiveDateTime, Timelike};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+use std::sync::Mutex;
+use uuid_b64::UuidB64;
+
+/// strftime-style format of zettel IDs, e.g. `202405141230`
+pub const FORMAT: &str = "%Y%m%d%H%M";
+
+/// How the IDs of notes that don't have one are generated, see the `id_scheme` config setting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    /// Random, base64 encoded UUIDs
+    #[default]
+    Uuid,
+    /// Classic Zettelkasten timestamps, e.g. `202405141230`
+    Zettel,
+}
+
+static SCHEME: OnceCell<IdScheme> = OnceCell::new();
+
+/// Minute of the last zettel ID handed out, so notes created within the same minute still get
+/// IDs of their own
+static LAST: Lazy<Mutex<Option<NaiveDateTime>>> = Lazy::new(|| Mutex::new(None));
+
+/// Set the scheme new IDs are generated with. Only the first call has any effect.
+pub fn set_scheme(scheme: IdScheme) {
+    let _ = SCHEME.set(scheme);
+}
+
+/// A fresh ID for a new note, in the configured scheme
+pub fn new_id() -> String {
+    match SCHEME.get().copied().unwrap_or_default() {
+        IdScheme::Uuid => UuidB64::new().to_string(),
+        IdScheme::Zettel => generate(),
+    }
+}
+
+/// A zettel ID for the current minute, or the minute after the last one generated if that's
+/// already been taken
+pub fn generate() -> String {
+    let now = Local::now().naive_local();
+    let now = now
+        - Duration::seconds(now.second().into())
+        - Duration::nanoseconds(now.nanosecond().into());
+    let mut last = LAST.lock().unwrap();
+    let minute = match *last {
+        Some(last) if last >= now => last + Duration::minutes(1),
+        _ => now,
+    };
+    *last = Some(minute);
+    minute.format(FORMAT).to_string()
+}
+
+/// Whether `s` is a zettel ID, 12 digits down to the minute or 14 down to the second
+pub fn is_id(s: &str) -> bool {
+    let format = match s.len() {
+        12 => FORMAT,
+        14 => "%Y%m%d%H%M%S",
+        _ => return false,
+    };
+    s.bytes().all(|b| b.is_ascii_digit()) && NaiveDateTime::parse_from_str(s, format).is_ok()
+}
+
+/// The zettel ID a filename starts with, as in `202405141230 Some title.md` or
+/// `202405141230-some-title.md`
+pub fn from_filename(filename: &str) -> Option<&str> {
+    let name = filename.rsplit('/').next().unwrap_or(filename);
+    let end = name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(name.len());
+    let id = &name[..end];
+    if is_id(id) {
+        Some(id)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,132 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use testcontainers::clients::Cli;
+use testcontainers::images::generic::GenericImage;
+use testcontainers::Container;
+
+/// Meilisearch release the tests run against, the one meilizet targets
+const IMAGE_TAG: &str = "v0.24.0";
+const MASTER_KEY: &str = "meilizet-integration";
+const PORT: u16 = 7700;
+
+/// A Meilisearch server and an index of its own for one test. The server is started in a
+/// container unless `MEILIZET_TEST_HOST` (and `MEILIZET_TEST_API_KEY`) point at a running one.
+pub struct Server<'d> {
+    container: Option<Container<'d, GenericImage>>,
+    pub host: String,
+    pub api_key: String,
+    pub index: String,
+    /// Holds the state, undo log and config file, so no test touches the user's own
+    dir: TempDir,
+}
+
+impl<'d> Server<'d> {
+    pub fn start(docker: &'d Cli) -> Server<'d> {
+        let (container, host, api_key) = match env::var("MEILIZET_TEST_HOST") {
+            Ok(host) => (
+                None,
+                host,
+                env::var("MEILIZET_TEST_API_KEY").unwrap_or_default(),
+            ),
+            Err(_) => {
+                let image = GenericImage::new("getmeili/meilisearch", IMAGE_TAG)
+                    .with_env_var("MEILI_MASTER_KEY", MASTER_KEY)
+                    .with_env_var("MEILI_NO_ANALYTICS", "true")
+                    .with_exposed_port(PORT);
+                let container = docker.run(image);
+                let host = format!("http://127.0.0.1:{}", container.get_host_port_ipv4(PORT));
+                (Some(container), host, MASTER_KEY.to_owned())
+            }
+        };
+        wait_until_healthy(&host);
+        let server = Server {
+            container,
+            host,
+            api_key,
+            index: format!("meilizet-test-{}", uuid::Uuid::new_v4().to_simple()),
+            dir: tempfile::tempdir().expect("creating a temporary directory"),
+        };
+        server.mz_ok(&["settings", "init"]);
+        server
+    }
+
+    /// Run `mz` against this server and index, failing the test if it exits unsuccessfully.
+    /// Returns what it printed on stdout.
+    pub fn mz_ok(&self, args: &[&str]) -> String {
+        let output = self.mz(args);
+        assert!(
+            output.status.success(),
+            "mz {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).expect("mz printed invalid UTF-8")
+    }
+
+    pub fn mz(&self, args: &[&str]) -> Output {
+        let path = |name| self.dir.path().join(name);
+        let mut mz = Command::new(env!("CARGO_BIN_EXE_mz"));
+        if !self.api_key.is_empty() {
+            mz.arg("--api-key").arg(&self.api_key);
+        }
+        mz.arg("--host")
+            .arg(&self.host)
+            .arg("--index")
+            .arg(&self.index)
+            .arg("--config")
+            .arg(path("config.toml"))
+            .arg("--state")
+            .arg(path("state.json"))
+            .arg("--undo-log")
+            .arg(path("undo.jsonl"))
+            .arg("--yes")
+            .args(args)
+            .env("NO_COLOR", "1")
+            .env_remove("MEILIZET_VAULT")
+            .env_remove("MEILI_API_KEY")
+            .output()
+            .expect("running mz")
+    }
+
+    /// Search non-interactively, returning the IDs of the matches
+    pub fn search(&self, query: &str) -> Vec<String> {
+        self.mz_ok(&["query", query])
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl Drop for Server<'_> {
+    fn drop(&mut self) {
+        // A container goes away with its index, a shared server keeps it otherwise
+        if self.container.is_none() {
+            let _ = self.mz(&["index", "delete", &self.index]);
+        }
+    }
+}
+
+/// Glob pattern matching the notes under `tests/fixtures`
+pub fn fixtures() -> String {
+    let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    format!("{}/*.md", dir.display())
+}
+
+fn wait_until_healthy(host: &str) {
+    let started = Instant::now();
+    let url = format!("{}/health", host);
+    while started.elapsed() < Duration::from_secs(60) {
+        if let Ok(resp) = reqwest::blocking::get(&url) {
+            if resp.status().is_success() {
+                return;
+            }
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    panic!("Meilisearch at {} didn't become healthy", host);
+}
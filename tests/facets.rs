@@ -0,0 +1,63 @@
+// Counting tag values, which Meilisearch asks for and answers with under different names
+// depending on its version, against canned responses instead of a server
+
+use meilizet::api::MockTransport;
+use meilizet::query;
+use serde_json::json;
+use url::Url;
+
+/// Transport for a server at `host` running `version`, whose index of notes has `tags` with
+/// the counts given, named as `distribution` in its search responses
+fn server(host: &str, version: &str, distribution: &str) -> (MockTransport, Url) {
+    let response = json!({
+        "hits": [],
+        "query": "",
+        "limit": 0,
+        "offset": 0,
+        "processingTimeMs": 1,
+        distribution: { "tags": { "vim": 2, "rust": 5 } },
+    });
+    let client = MockTransport::new()
+        .on_get("/version", &json!({ "pkgVersion": version }).to_string())
+        .on_get(
+            "/indexes/notes/settings/filterable-attributes",
+            r#"["tags"]"#,
+        )
+        .on_post("/indexes/notes/search", &response.to_string());
+    let uri = Url::parse(&format!("{}/indexes/notes/search", host)).unwrap();
+    (client, uri)
+}
+
+/// Body of the search request sent through `client`
+fn search_body(client: &MockTransport) -> serde_json::Value {
+    let search = client
+        .requests()
+        .into_iter()
+        .find(|r| r.method == "POST")
+        .expect("a search was sent");
+    serde_json::from_str(&search.body.unwrap()).unwrap()
+}
+
+#[test]
+fn facets_are_asked_for_by_their_current_name() {
+    let (client, uri) = server("http://current:7700", "1.3.0", "facetDistribution");
+    assert_eq!(
+        query::tag_facets(&client, &uri).unwrap(),
+        vec!["rust", "vim"]
+    );
+    let body = search_body(&client);
+    assert_eq!(body["facets"], json!(["tags"]));
+    assert!(body.get("facetsDistribution").is_none(), "{}", body);
+}
+
+#[test]
+fn servers_before_0_28_are_asked_for_facets_distribution() {
+    let (client, uri) = server("http://old:7700", "0.27.2", "facetsDistribution");
+    assert_eq!(
+        query::tag_facets(&client, &uri).unwrap(),
+        vec!["rust", "vim"]
+    );
+    let body = search_body(&client);
+    assert_eq!(body["facetsDistribution"], json!(["tags"]));
+    assert!(body.get("facets").is_none(), "{}", body);
+}
@@ -0,0 +1,417 @@
+// Property tests of the filter syntax typed into the filter input: generated expressions are
+// rendered to text, parsed back and translated, and arbitrary text is thrown at the parser.
+
+use chrono::{Local, NaiveDate, TimeZone};
+use meilizet::api::{is_tag, parse_duration, parse_filter, translate_filter, Filter, Rule};
+use meilizet::date::Date;
+use pest::Parser;
+use proptest::prelude::*;
+
+/// One term of a filter expression
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Tag(String),
+    NotTag(String),
+    Field {
+        name: String,
+        op: String,
+        value: String,
+        quoted: bool,
+    },
+    Date {
+        field: Option<String>,
+        comparator: Option<String>,
+        date: String,
+    },
+    Duration {
+        field: Option<String>,
+        comparator: Option<String>,
+        amount: u32,
+        unit: char,
+    },
+}
+
+/// Terms joined by `|` or `+`, `ops[i]` sitting between `terms[i]` and `terms[i + 1]`
+#[derive(Clone, Debug, PartialEq)]
+struct Expression {
+    terms: Vec<Term>,
+    ops: Vec<String>,
+}
+
+impl Term {
+    fn render(&self) -> String {
+        let prefix = |field: &Option<String>, comparator: &Option<String>| {
+            let field = field.as_ref().map(|f| format!("{}:", f));
+            format!(
+                "{}{}",
+                field.unwrap_or_default(),
+                comparator.clone().unwrap_or_default()
+            )
+        };
+        match self {
+            Term::Tag(tag) => tag.to_owned(),
+            Term::NotTag(tag) => format!("!{}", tag),
+            Term::Field {
+                name,
+                op,
+                value,
+                quoted,
+            } => {
+                if *quoted {
+                    format!("{} {} \"{}\"", name, op, value)
+                } else {
+                    format!("{}{}{}", name, op, value)
+                }
+            }
+            Term::Date {
+                field,
+                comparator,
+                date,
+            } => format!("{}{}", prefix(field, comparator), date),
+            Term::Duration {
+                field,
+                comparator,
+                amount,
+                unit,
+            } => format!("{}{}{}", prefix(field, comparator), amount, unit),
+        }
+    }
+}
+
+impl Expression {
+    fn render(&self, spaced: bool) -> String {
+        let mut out = self.terms[0].render();
+        for (op, term) in self.ops.iter().zip(&self.terms[1..]) {
+            if spaced {
+                out.push_str(&format!(" {} ", op));
+            } else {
+                out.push_str(op);
+            }
+            out.push_str(&term.render());
+        }
+        out
+    }
+
+    /// Read an expression back from the parse tree of `input`
+    fn parse(input: &str) -> Option<Expression> {
+        let expression = Filter::parse(Rule::expression, input).ok()?.next()?;
+        let mut terms = Vec::new();
+        let mut ops = Vec::new();
+        let mut field = None;
+        let mut comparator = None;
+        for token in expression.into_inner() {
+            match token.as_rule() {
+                Rule::date_field => field = Some(token.as_str().to_owned()),
+                Rule::comparator => comparator = Some(token.as_str().to_owned()),
+                Rule::date => terms.push(Term::Date {
+                    field: field.take(),
+                    comparator: comparator.take(),
+                    date: token.as_str().to_owned(),
+                }),
+                Rule::duration => {
+                    let text = token.as_str();
+                    let (amount, unit) = text.split_at(text.len() - 1);
+                    terms.push(Term::Duration {
+                        field: field.take(),
+                        comparator: comparator.take(),
+                        amount: amount.parse().ok()?,
+                        unit: unit.chars().next()?,
+                    });
+                }
+                Rule::field_comparison => {
+                    let mut inner = token.into_inner();
+                    let name = inner.next()?.as_str().to_owned();
+                    let op = inner.next()?.as_str().to_owned();
+                    let value = inner.next()?;
+                    let quoted = value.as_rule() == Rule::quoted_value;
+                    terms.push(Term::Field {
+                        name,
+                        op,
+                        value: value.as_str().trim_matches('"').to_owned(),
+                        quoted,
+                    });
+                }
+                Rule::tag => terms.push(Term::Tag(token.as_str().to_owned())),
+                Rule::not_tag => {
+                    terms.push(Term::NotTag(token.into_inner().next()?.as_str().to_owned()))
+                }
+                Rule::operator => ops.push(token.as_str().to_owned()),
+                Rule::EOI => break,
+                _ => return None,
+            }
+        }
+        Some(Expression { terms, ops })
+    }
+}
+
+/// Tags start with a letter, tags starting with digits being read as dates and durations
+fn tag() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9._/-]{0,10}"
+}
+
+fn date() -> impl Strategy<Value = String> {
+    (1000..3000u32, 1..13u32, 1..29u32, 0..3u8).prop_map(|(y, m, d, precision)| match precision {
+        0 => format!("{}", y),
+        1 => format!("{}-{:02}", y, m),
+        _ => format!("{}-{}-{}", y, m, d),
+    })
+}
+
+fn date_prefix() -> impl Strategy<Value = (Option<String>, Option<String>)> {
+    (
+        prop::option::of(prop::sample::select(vec![
+            "created",
+            "modified",
+            "imported",
+            "imported_at",
+        ])),
+        prop::option::of(prop::sample::select(vec![">", "<"])),
+    )
+        .prop_map(|(field, comparator)| (field.map(String::from), comparator.map(String::from)))
+}
+
+fn term(durations: bool) -> BoxedStrategy<Term> {
+    let field = (
+        "[a-z][a-z0-9_]{0,8}",
+        prop::sample::select(vec!["=", "!="]),
+        prop_oneof![
+            "[a-z0-9._/-]{1,8}".prop_map(|v| (v, false)),
+            "[^\"]{0,8}".prop_map(|v| (v, true)),
+        ],
+    )
+        .prop_map(|(name, op, (value, quoted))| Term::Field {
+            name,
+            op: op.to_owned(),
+            value,
+            quoted,
+        });
+    let date = (date_prefix(), date()).prop_map(|((field, comparator), date)| Term::Date {
+        field,
+        comparator,
+        date,
+    });
+    // Amounts of four digits would be read as a year followed by a tag
+    let duration = (
+        date_prefix(),
+        0..1000u32,
+        prop::sample::select(vec!['h', 'd', 'w', 'm', 'y']),
+    )
+        .prop_map(|((field, comparator), amount, unit)| Term::Duration {
+            field,
+            comparator,
+            amount,
+            unit,
+        });
+    if durations {
+        prop_oneof![
+            tag().prop_map(Term::Tag),
+            tag().prop_map(Term::NotTag),
+            field,
+            date,
+            duration,
+        ]
+        .boxed()
+    } else {
+        prop_oneof![
+            tag().prop_map(Term::Tag),
+            tag().prop_map(Term::NotTag),
+            field,
+            date,
+        ]
+        .boxed()
+    }
+}
+
+fn expression(durations: bool) -> impl Strategy<Value = Expression> {
+    prop::collection::vec(
+        (term(durations), prop::sample::select(vec!["|", "+"])),
+        1..6,
+    )
+    .prop_map(|pairs| {
+        let ops = pairs[1..].iter().map(|(_, op)| op.to_string()).collect();
+        let terms = pairs.into_iter().map(|(term, _)| term).collect();
+        Expression { terms, ops }
+    })
+}
+
+proptest! {
+    #[test]
+    fn rendered_expressions_parse_back_identically(
+        expr in expression(true),
+        spaced in any::<bool>(),
+    ) {
+        let text = expr.render(spaced);
+        prop_assert_eq!(Expression::parse(&text), Some(expr), "{}", text);
+    }
+
+    #[test]
+    fn rendered_expressions_translate(
+        expr in expression(true),
+        spaced in any::<bool>(),
+    ) {
+        prop_assert!(translate_filter(&expr.render(spaced)).is_some());
+    }
+
+    /// Durations are left out, they translate to the time of translation
+    #[test]
+    fn expressions_translate_term_by_term(expr in expression(false)) {
+        let mut expected = translate_filter(&expr.terms[0].render()).unwrap();
+        for (op, term) in expr.ops.iter().zip(&expr.terms[1..]) {
+            expected.push_str(if op == "|" { " OR " } else { " AND " });
+            expected.push_str(&translate_filter(&term.render()).unwrap());
+        }
+        prop_assert_eq!(translate_filter(&expr.render(true)), Some(expected));
+    }
+
+    /// A date is the whole of its period whichever way the comparison is written
+    #[test]
+    fn date_shorthands_match_comparisons(
+        (field, comparator) in date_prefix(),
+        date in date(),
+    ) {
+        let prefix = field.as_ref().map_or(String::new(), |field| format!("{}:", field));
+        // `imported:` is short for the field's own name
+        let field = match field.as_deref() {
+            Some("imported") => "imported_at",
+            Some(field) => field,
+            None => "date",
+        };
+        // A date on its own is the same as comparing for equality
+        prop_assert_eq!(
+            translate_filter(&format!("{}{}{}", prefix, comparator.as_deref().unwrap_or(""), date)),
+            translate_filter(&format!("{} {} {}", field, comparator.as_deref().unwrap_or("="), date))
+        );
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics(input in "\\PC{0,40}") {
+        let _ = translate_filter(&input);
+    }
+
+    /// Input made of the characters the syntax uses gets much further into the parser
+    #[test]
+    fn input_near_the_syntax_never_panics(input in "[0-9a-z!<>:|+=\" ._/-]{0,40}") {
+        let _ = translate_filter(&input);
+    }
+
+    #[test]
+    fn huge_durations_never_panic(amount in "[0-9]{4,30}", unit in "[hdwmy]") {
+        let _ = translate_filter(&format!(">{}{}", amount, unit));
+    }
+}
+
+#[test]
+fn impossible_dates_filter_nothing() {
+    for input in &[
+        "2021-02-31",
+        "2021-1-0",
+        "2021-04-31",
+        "created:>2021-02-30",
+    ] {
+        assert_eq!(translate_filter(input), None, "{}", input);
+    }
+}
+
+#[test]
+fn overflowing_durations_filter_nothing() {
+    for input in &[
+        ">99999999999999999999d",
+        "<9223372036854775807y",
+        "9999999999999h",
+    ] {
+        assert_eq!(translate_filter(input), None, "{}", input);
+    }
+}
+
+#[test]
+fn durations_past_the_last_date_fail_and_stored_ones_still_display() {
+    let far = parse_duration("1000000y").unwrap();
+    assert!(Date::now().after(far).is_err());
+    assert!(Date::now().after(parse_duration("2d").unwrap()).is_ok());
+    for stored in &[i64::MAX, i64::MIN] {
+        assert!(!Date::new(*stored).to_string().is_empty());
+    }
+}
+
+#[test]
+fn keywords_and_groups_combine_terms() {
+    assert_eq!(
+        parse_filter("vim AND (rust OR !bash)").unwrap(),
+        Some(String::from("tags = vim AND (tags = rust OR tags != bash)"))
+    );
+    assert_eq!(
+        parse_filter("NOT (vim | rust)").unwrap(),
+        Some(String::from("NOT (tags = vim OR tags = rust)"))
+    );
+    assert_eq!(
+        parse_filter("vim bash").unwrap(),
+        Some(String::from("tags = vim AND tags = bash"))
+    );
+    // Keywords are upper case, lower case words are tags
+    assert_eq!(
+        parse_filter("not and or").unwrap(),
+        Some(String::from("tags = not AND tags = and AND tags = or"))
+    );
+}
+
+#[test]
+fn tags_read_as_something_else_are_compared() {
+    assert!(is_tag("vim"));
+    assert!(is_tag("not"));
+    for tag in &["2021", "3d", "2023-05", "2021abc", "NOT", "OR"] {
+        assert!(!is_tag(tag), "{}", tag);
+        assert_eq!(
+            parse_filter(&format!("tags = \"{}\"", tag)).unwrap(),
+            Some(format!("tags = \"{}\"", tag))
+        );
+    }
+}
+
+#[test]
+fn comparisons_understand_fields() {
+    assert_eq!(
+        parse_filter("weight >= 3 + tag = vim + author = steve").unwrap(),
+        Some(String::from(
+            "weight >= 3 AND tags = \"vim\" AND authors = \"steve\""
+        ))
+    );
+    // After the last second of the day, in local time like the dates of notes
+    assert_eq!(
+        parse_filter("date > 2023-01-01").unwrap(),
+        Some(format!("date > {}", midnight(2023, 1, 2) - 1))
+    );
+    assert_eq!(
+        parse_filter("created = 2021").unwrap(),
+        Some(format!(
+            "(created >= {} AND created <= {})",
+            midnight(2021, 1, 1),
+            midnight(2022, 1, 1) - 1
+        ))
+    );
+}
+
+/// Timestamp of the start of a day in local time
+fn midnight(y: i32, m: u32, d: u32) -> i64 {
+    let day = NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    Local
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+        .unwrap()
+        .timestamp()
+}
+
+#[test]
+fn dangling_operators_are_dropped() {
+    assert_eq!(
+        parse_filter("vim |").unwrap(),
+        Some(String::from("tags = vim"))
+    );
+    assert_eq!(parse_filter(" ").unwrap(), None);
+}
+
+#[test]
+fn errors_say_where_the_input_went_wrong() {
+    let e = parse_filter("(vim").unwrap_err().to_string();
+    assert!(e.contains(')') && e.ends_with("at column 5"), "{}", e);
+    let e = parse_filter("date = 2021-02-31").unwrap_err().to_string();
+    assert!(e.contains("No such date"), "{}", e);
+}
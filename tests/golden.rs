@@ -0,0 +1,118 @@
+// Golden-file tests of the importers: every note in tests/golden is parsed and compared with
+// the JSON snapshot next to it. After an intended change to parsing, rewrite the snapshots with
+// `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+use meilizet::document::Document;
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Stand-in for IDs generated on import, which differ on every run
+const GENERATED: &str = "<generated>";
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut notes: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("reading tests/golden")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "md"))
+        .collect();
+    notes.sort();
+    notes
+}
+
+/// What importing `source` gave, as it's kept in the snapshots
+fn snapshot(source: &str, result: Result<Document, std::io::Error>) -> Value {
+    match result {
+        Ok(doc) => {
+            let mut value = serde_json::to_value(&doc).unwrap();
+            if !has_id(source) {
+                value["id"] = json!(GENERATED);
+                value["parentid"] = json!(GENERATED);
+            }
+            value
+        }
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+fn has_id(source: &str) -> bool {
+    source.lines().any(|line| line.starts_with("id:"))
+}
+
+#[test]
+fn importing_matches_the_snapshots() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatched = Vec::new();
+    for path in corpus() {
+        let source = fs::read_to_string(&path).unwrap();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let actual = snapshot(&source, Document::parse_str(&source, filename));
+        let snapshot_path = path.with_extension("json");
+        if update {
+            let pretty = serde_json::to_string_pretty(&actual).unwrap();
+            fs::write(&snapshot_path, pretty + "\n").unwrap();
+            continue;
+        }
+        let expected: Value = match fs::read_to_string(&snapshot_path) {
+            Ok(s) => serde_json::from_str(&s).unwrap(),
+            Err(_) => panic!(
+                "{} has no snapshot, create it with UPDATE_GOLDEN=1",
+                path.display()
+            ),
+        };
+        if actual != expected {
+            mismatched.push(format!(
+                "{}:\nexpected {}\nactual   {}",
+                filename,
+                serde_json::to_string_pretty(&expected).unwrap(),
+                serde_json::to_string_pretty(&actual).unwrap()
+            ));
+        }
+    }
+    assert!(mismatched.is_empty(), "{}", mismatched.join("\n\n"));
+}
+
+/// Local and git imports read files, remote imports parse what they fetched. Both must agree
+/// on everything but the timestamps taken from the file.
+#[test]
+fn files_and_fetched_contents_import_alike() {
+    for path in corpus() {
+        let source = fs::read_to_string(&path).unwrap();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        match (
+            Document::parse_file(&path),
+            Document::parse_str(&source, filename),
+        ) {
+            (Ok(mut from_file), Ok(from_str)) => {
+                assert_ne!(from_file.modified, from_str.modified, "{}", filename);
+                from_file.created = from_str.created.clone();
+                from_file.modified = from_str.modified.clone();
+                if !has_id(&source) {
+                    from_file.id = from_str.id.clone();
+                    from_file.parentid = from_str.parentid.clone();
+                }
+                assert_eq!(from_file, from_str, "{}", filename);
+            }
+            (Err(_), Err(_)) => {}
+            (from_file, from_str) => panic!(
+                "{}: reading the file gave {:?}, parsing its contents {:?}",
+                filename, from_file, from_str
+            ),
+        }
+    }
+}
+
+#[test]
+fn huge_bodies_import_whole() {
+    let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. 日本語 🚀\n\n";
+    let body = paragraph.repeat(64 * 1024);
+    let source = format!(
+        "---\nid: huge\ntitle: Huge\ndate: 2021-01-01T00:00:00+00:00\n---\n{}",
+        body
+    );
+    let doc = Document::parse_str(&source, "huge.md").unwrap();
+    assert_eq!(doc.body.len(), body.len());
+    assert!(doc.body == body);
+}
@@ -0,0 +1,104 @@
+// End-to-end tests against a real Meilisearch, started with Docker through testcontainers. Run
+// them with `cargo test --features integration`, setting MEILIZET_TEST_HOST (and
+// MEILIZET_TEST_API_KEY) to use a running server instead.
+#![cfg(feature = "integration")]
+
+mod common;
+
+use common::{fixtures, Server};
+use testcontainers::clients::Cli;
+
+#[test]
+fn imported_notes_can_be_searched() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+    server.mz_ok(&["import", &fixtures(), "--wait"]);
+
+    assert_eq!(server.search("macros"), vec!["vim-macros"]);
+    assert_eq!(server.search("sourdough"), vec!["sourdough"]);
+    // Typos are tolerated
+    assert_eq!(server.search("sourdouhg"), vec!["sourdough"]);
+    assert!(server.search("kubernetes").is_empty());
+}
+
+#[test]
+fn deleted_notes_stop_matching() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+    server.mz_ok(&["import", &fixtures(), "--wait"]);
+
+    server.mz_ok(&["delete", "bash-traps"]);
+    assert!(server.search("trap").is_empty());
+    assert_eq!(server.search("macros"), vec!["vim-macros"]);
+
+    server.mz_ok(&["delete", "--filter", "tags = baking"]);
+    assert!(server.search("sourdough").is_empty());
+}
+
+#[test]
+fn deleting_a_missing_note_fails() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+    server.mz_ok(&["import", &fixtures(), "--wait"]);
+
+    assert!(!server.mz(&["delete", "no-such-note"]).status.success());
+    assert_eq!(server.search("macros"), vec!["vim-macros"]);
+}
+
+#[test]
+fn settings_can_be_changed_and_reset() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+
+    let filterable = server.mz_ok(&["settings", "get", "filterable"]);
+    assert!(filterable.lines().any(|line| line == "tags"));
+
+    server.mz_ok(&["settings", "set", "searchable", "title"]);
+    assert_eq!(server.mz_ok(&["settings", "get", "searchable"]), "title\n");
+
+    server.mz_ok(&["settings", "reset", "searchable"]);
+    assert_eq!(server.mz_ok(&["settings", "get", "searchable"]), "*\n");
+}
+
+#[test]
+fn searchable_attributes_limit_what_matches() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+    server.mz_ok(&["import", &fixtures(), "--wait"]);
+
+    // "register" only appears in the body of the vim note
+    assert_eq!(server.search("register"), vec!["vim-macros"]);
+    server.mz_ok(&["settings", "set", "searchable", "title"]);
+    assert!(server.search("register").is_empty());
+}
+
+#[test]
+fn selftest_passes_against_a_fresh_server() {
+    let docker = Cli::default();
+    let server = Server::start(&docker);
+    server.mz_ok(&["import", &fixtures(), "--wait"]);
+
+    let output = server.mz(&["selftest"]);
+    assert!(
+        output.status.success(),
+        "selftest failed: {}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn selftest_reports_a_wrong_api_key() {
+    let docker = Cli::default();
+    let mut server = Server::start(&docker);
+    if server.api_key.is_empty() {
+        // Servers without a master key accept any key
+        return;
+    }
+    server.api_key = String::from("wrong");
+
+    let output = server.mz(&["selftest"]);
+    assert!(!output.status.success());
+    let printed = String::from_utf8_lossy(&output.stdout);
+    assert!(printed.contains("MEILI_API_KEY"), "{}", printed);
+}
@@ -0,0 +1,142 @@
+// Round-trip tests of the Disk serializer: a document written the way notes are saved and
+// parsed back must come out the same, whether it's generated or one of the golden notes.
+
+use meilizet::date::Date;
+use meilizet::document::{Document, SerializationType};
+use meilizet::roundtrip;
+use meilizet::status::Status;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Name every generated note is parsed back as
+const FILENAME: &str = "note.md";
+
+/// Text of a single-line frontmatter value, quotes, colons and YAML keywords included
+fn line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "\\PC{0,20}",
+        prop::sample::select(vec!["yes", "null", "~", "1.5", "- x", "a: b", "#c", "'q'"])
+            .prop_map(String::from),
+    ]
+}
+
+fn word() -> impl Strategy<Value = String> {
+    "[a-z0-9][a-z0-9_-]{0,10}"
+}
+
+fn words() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(word(), 0..4)
+}
+
+/// Between 1970 and 2100, to the second like the files have them
+fn date() -> impl Strategy<Value = Date> {
+    (0i64..4_102_444_800).prop_map(Date::new)
+}
+
+fn body() -> impl Strategy<Value = String> {
+    prop::collection::vec("\\PC{0,30}", 0..5).prop_map(|lines| lines.join("\n"))
+}
+
+/// Frontmatter fields outside of the schema, named so they can't clash with it
+fn extra() -> impl Strategy<Value = BTreeMap<String, serde_yaml::Value>> {
+    let value = prop_oneof![
+        line().prop_map(serde_yaml::Value::from),
+        any::<i64>().prop_map(serde_yaml::Value::from),
+        any::<bool>().prop_map(serde_yaml::Value::from),
+    ];
+    prop::collection::btree_map("x_[a-z]{1,8}", value, 0..3)
+}
+
+/// Documents as they're parsed from files, leaving out the fields only kept in the index
+fn document() -> impl Strategy<Value = Document> {
+    let ids = (word(), word(), "([a-z0-9]{1,8})?");
+    let text = (line(), line(), body(), "([a-z0-9/._-]{1,12})?");
+    let lists = (words(), words(), words());
+    let dates = (
+        date(),
+        date(),
+        prop::option::of(date()),
+        prop::option::of(date()),
+    );
+    let fields = (
+        prop::option::of(prop::sample::select(vec![
+            Status::Draft,
+            Status::Published,
+            Status::Archived,
+        ])),
+        any::<i32>(),
+        any::<u16>(),
+        "([a-z0-9-]{1,10})?",
+        "([0-9][a-z0-9]{0,5})?",
+        extra(),
+    );
+    (ids, text, lists, dates, fields).prop_map(
+        |(
+            (id, parentid, revises),
+            (title, subtitle, body, background_img),
+            (authors, tags, links),
+            (date, created, remind_at, review_after),
+            (status, weight, writes, slug, sequence, extra),
+        )| Document {
+            id,
+            parentid,
+            revises,
+            title,
+            subtitle,
+            body,
+            background_img,
+            authors,
+            tags,
+            links,
+            date,
+            created,
+            remind_at,
+            review_after,
+            status,
+            weight,
+            writes,
+            slug,
+            sequence,
+            extra,
+            filename: FILENAME.to_owned(),
+            serialization_type: SerializationType::Disk,
+            ..Default::default()
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn documents_survive_disk_serialization(doc in document()) {
+        let written = doc.to_string();
+        let reparsed = Document::parse_str(&written, FILENAME)
+            .map_err(|e| TestCaseError::fail(format!("{}\n{}", e, written)))?;
+        prop_assert_eq!(roundtrip::diff(&doc, &reparsed), Vec::new(), "{}", written);
+    }
+}
+
+#[test]
+fn golden_notes_survive_disk_serialization() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut failed = Vec::new();
+    for entry in fs::read_dir(&dir).expect("reading tests/golden") {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "md") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        // Notes that don't import at all are covered by the snapshots
+        let doc = match Document::parse_str(&source, filename) {
+            Ok(doc) => doc,
+            Err(_) => continue,
+        };
+        let diffs = roundtrip::verify(&doc).unwrap();
+        if !diffs.is_empty() {
+            failed.push(format!("{}: {:?}", filename, diffs));
+        }
+    }
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
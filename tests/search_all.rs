@@ -0,0 +1,68 @@
+// Paging through every match of a search, against canned responses instead of a server
+
+use meilizet::api::{MockTransport, SearchRequest};
+use meilizet::query;
+use serde_json::json;
+use url::Url;
+
+/// A search response holding the documents `ids`
+fn response(ids: &[&str]) -> String {
+    let hits: Vec<_> = ids
+        .iter()
+        .map(|id| json!({ "id": id, "title": id, "date": 1_600_000_000 }))
+        .collect();
+    json!({
+        "hits": hits,
+        "estimatedTotalHits": ids.len(),
+        "query": "",
+        "limit": query::PAGE_SIZE,
+        "offset": 0,
+        "processingTimeMs": 1,
+    })
+    .to_string()
+}
+
+/// Transport for the index `index` holding `ids`, which can filter on `latest` and goes
+/// through `max_total_hits` of them per search
+fn index(index: &str, ids: &[&str], max_total_hits: u64) -> (MockTransport, Url) {
+    let path = format!("/indexes/{}", index);
+    let client = MockTransport::new()
+        .on_post(&format!("{}/search", path), &response(ids))
+        .on_get(
+            &format!("{}/settings/filterable-attributes", path),
+            r#"["tags", "latest"]"#,
+        )
+        .on_get(
+            &format!("{}/settings/pagination", path),
+            &json!({ "maxTotalHits": max_total_hits }).to_string(),
+        );
+    let uri = Url::parse(&format!("http://localhost:7700{}/search", path)).unwrap();
+    (client, uri)
+}
+
+#[test]
+fn every_match_comes_back_without_superseded_revisions() {
+    let (client, uri) = index("complete", &["a", "b", "c"], 1000);
+    let docs = query::search_all(&client, &uri, &SearchRequest::new()).unwrap();
+    assert_eq!(
+        docs.iter().map(|doc| doc.id.as_str()).collect::<Vec<_>>(),
+        vec!["a", "b", "c"]
+    );
+
+    let searches: Vec<serde_json::Value> = client
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .map(|r| serde_json::from_str(&r.body.unwrap()).unwrap())
+        .collect();
+    // A short page is the last one
+    assert_eq!(searches.len(), 1);
+    assert_eq!(searches[0]["filter"], json!("NOT latest = false"));
+}
+
+#[test]
+fn reaching_max_total_hits_fails_instead_of_truncating() {
+    let (client, uri) = index("capped", &["a", "b"], 2);
+    let err = query::search_all(&client, &uri, &SearchRequest::new()).unwrap_err();
+    assert!(err.to_string().contains("maxTotalHits"), "{}", err);
+}